@@ -12,6 +12,13 @@ use der::{Decode, Encode, Error, ErrorKind, Length, Sequence, Tag, Tagged, Value
 
 use crate::{MAX_CSR_ATV, MAX_CSR_ATV_LEN, MAX_CSR_ATV_VALUE, MAX_CSR_ATV_VALUE_LEN};
 
+/// X.501 `AttributeType` as defined in [RFC 5280 Appendix A.1].
+///
+/// ```text
+/// AttributeType           ::= OBJECT IDENTIFIER
+/// ```
+///
+/// [RFC 5280 Appendix A.1]: https://datatracker.ietf.org/doc/html/rfc5280#appendix-A.1
 pub type AttributeType = ObjectIdentifier;
 
 /// X.501 `AttributeValue` as defined in [RFC 5280 Appendix A.1].
@@ -0,0 +1,359 @@
+// Copyright (c) 2021 The RustCrypto Project Developers
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use der::asn1::{BitStringRef, GeneralizedTime, ObjectIdentifier, OctetStringRef, SequenceOf};
+use der::asn1::{UIntRef, UtcTime};
+use der::{Choice, Decode, Encode, Enumerated, Sequence};
+use spki::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+use crate::name::Name;
+use crate::signer::{CertSigner, MAX_SIGNATURE_LEN};
+use crate::{Error, Result};
+
+/// Maximum number of extensions in a certificate.
+pub const MAX_CERT_EXTENSIONS: usize = 8;
+
+/// Certificate `Version` as defined in [RFC 5280 Section 4.1].
+///
+/// ```text
+/// Version  ::=  INTEGER  {  v1(0), v2(1), v3(2)  }
+/// ```
+///
+/// [RFC 5280 Section 4.1]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Enumerated)]
+#[asn1(type = "INTEGER")]
+#[repr(u8)]
+pub enum Version {
+    /// Version 1 (default)
+    V1 = 0,
+
+    /// Version 2
+    V2 = 1,
+
+    /// Version 3
+    V3 = 2,
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Self::V1
+    }
+}
+
+/// X.509 `Time` as defined in [RFC 5280 Section 4.1.2.5].
+///
+/// ```text
+/// Time ::= CHOICE {
+///      utcTime        UTCTime,
+///      generalTime    GeneralizedTime }
+/// ```
+///
+/// [RFC 5280 Section 4.1.2.5]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1.2.5
+#[derive(Choice, Copy, Clone, Debug, Eq, PartialEq)]
+#[allow(missing_docs)]
+pub enum Time {
+    #[asn1(type = "UTCTime")]
+    UtcTime(UtcTime),
+
+    #[asn1(type = "GeneralizedTime")]
+    GeneralTime(GeneralizedTime),
+}
+
+/// X.509 `Validity` as defined in [RFC 5280 Section 4.1.2.5].
+///
+/// ```text
+/// Validity ::= SEQUENCE {
+///     notBefore      Time,
+///     notAfter       Time  }
+/// ```
+///
+/// [RFC 5280 Section 4.1.2.5]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1.2.5
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct Validity {
+    /// The time before which the certificate isn't valid.
+    pub not_before: Time,
+
+    /// The time after which the certificate isn't valid.
+    pub not_after: Time,
+}
+
+/// X.509 `Extension` as defined in [RFC 5280 Section 4.1.2.9].
+///
+/// ```text
+/// Extension  ::=  SEQUENCE  {
+///      extnID      OBJECT IDENTIFIER,
+///      critical    BOOLEAN DEFAULT FALSE,
+///      extnValue   OCTET STRING
+///                  -- contains the DER encoding of an ASN.1 value
+///                  -- corresponding to the extension type identified
+///                  -- by extnID
+///      }
+/// ```
+///
+/// [RFC 5280 Section 4.1.2.9]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1.2.9
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct Extension<'a> {
+    /// Extension identifier.
+    pub extn_id: ObjectIdentifier,
+
+    /// Whether a relying party must reject the certificate if it doesn't recognize the extension.
+    #[asn1(default = "Default::default")]
+    pub critical: bool,
+
+    /// DER encoding of the extension value.
+    pub extn_value: OctetStringRef<'a>,
+}
+
+/// X.509 `Extensions` as defined in [RFC 5280 Section 4.1.2.9].
+///
+/// ```text
+/// Extensions  ::=  SEQUENCE SIZE (1..MAX) OF Extension
+/// ```
+///
+/// [RFC 5280 Section 4.1.2.9]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1.2.9
+pub type Extensions<'a> = SequenceOf<Extension<'a>, MAX_CERT_EXTENSIONS>;
+
+/// X.509 `TBSCertificate` as defined in [RFC 5280 Section 4.1].
+///
+/// ```text
+/// TBSCertificate  ::=  SEQUENCE  {
+///      version         [0]  Version DEFAULT v1,
+///      serialNumber         CertificateSerialNumber,
+///      signature            AlgorithmIdentifier{SIGNATURE-ALGORITHM, {SignatureAlgorithms}},
+///      issuer               Name,
+///      validity             Validity,
+///      subject              Name,
+///      subjectPublicKeyInfo SubjectPublicKeyInfo,
+///      ... ,
+///      [[2:               -- If present, version MUST be v2
+///      issuerUniqueID  [1]  IMPLICIT UniqueIdentifier OPTIONAL,
+///      subjectUniqueID [2]  IMPLICIT UniqueIdentifier OPTIONAL
+///      ]],
+///      [[3:               -- If present, version MUST be v3 --
+///      extensions      [3]  Extensions{{CertExtensions}} OPTIONAL
+///      ]], ... }
+/// ```
+///
+/// [RFC 5280 Section 4.1]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct TbsCertificate<'a> {
+    /// Certificate version.
+    #[asn1(context_specific = "0", default = "Default::default")]
+    pub version: Version,
+
+    /// Serial number, unique among the certificates issued by `issuer`.
+    pub serial_number: UIntRef<'a>,
+
+    /// Algorithm used by the issuer to sign the certificate.
+    pub signature: AlgorithmIdentifier<'a>,
+
+    /// Issuer name.
+    pub issuer: Name<'a>,
+
+    /// Validity period.
+    pub validity: Validity,
+
+    /// Subject name.
+    pub subject: Name<'a>,
+
+    /// Subject public key info.
+    pub subject_public_key_info: SubjectPublicKeyInfo<'a>,
+
+    /// Issuer unique identifier.
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", optional = "true")]
+    pub issuer_unique_id: Option<BitStringRef<'a>>,
+
+    /// Subject unique identifier.
+    #[asn1(context_specific = "2", tag_mode = "IMPLICIT", optional = "true")]
+    pub subject_unique_id: Option<BitStringRef<'a>>,
+
+    /// Certificate extensions.
+    #[asn1(context_specific = "3", tag_mode = "EXPLICIT", optional = "true")]
+    pub extensions: Option<Extensions<'a>>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for TbsCertificate<'a> {
+    type Error = der::Error;
+
+    fn try_from(bytes: &'a [u8]) -> core::result::Result<Self, Self::Error> {
+        Self::from_der(bytes)
+    }
+}
+
+/// X.509 `Certificate` as defined in [RFC 5280 Section 4.1].
+///
+/// ```text
+/// Certificate  ::=  SEQUENCE  {
+///      tbsCertificate       TBSCertificate,
+///      signatureAlgorithm   AlgorithmIdentifier,
+///      signature            BIT STRING  }
+/// ```
+///
+/// [RFC 5280 Section 4.1]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct Certificate<'a> {
+    /// The signed part of the certificate.
+    pub tbs_certificate: TbsCertificate<'a>,
+
+    /// Signature algorithm identifier.
+    pub signature_algorithm: AlgorithmIdentifier<'a>,
+
+    /// Signature over the DER encoding of `tbs_certificate`.
+    pub signature: BitStringRef<'a>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for Certificate<'a> {
+    type Error = der::Error;
+
+    fn try_from(bytes: &'a [u8]) -> core::result::Result<Self, Self::Error> {
+        Self::from_der(bytes)
+    }
+}
+
+/// Builds and signs a version 3 `Certificate` without allocating.
+pub struct TbsCertificateBuilder<'a> {
+    serial_number: UIntRef<'a>,
+    issuer: Name<'a>,
+    validity: Validity,
+    subject: Name<'a>,
+    subject_public_key_info: SubjectPublicKeyInfo<'a>,
+    extensions: Option<Extensions<'a>>,
+}
+
+impl<'a> TbsCertificateBuilder<'a> {
+    /// Creates a builder for a certificate with serial number `serial`, issued by `issuer` to
+    /// `subject` for the public key in `subject_public_key_info`. Use `add_extension()` to add
+    /// certificate extensions.
+    pub fn new(
+        serial: &'a [u8],
+        issuer: Name<'a>,
+        validity: Validity,
+        subject: Name<'a>,
+        subject_public_key_info: SubjectPublicKeyInfo<'a>,
+    ) -> Result<'static, Self> {
+        Ok(Self {
+            serial_number: UIntRef::new(serial).map_err(Error::InvalidDer)?,
+            issuer,
+            validity,
+            subject,
+            subject_public_key_info,
+            extensions: None,
+        })
+    }
+
+    /// Adds `extension` to the certificate extensions.
+    pub fn add_extension(mut self, extension: Extension<'a>) -> Result<'static, Self> {
+        self.extensions
+            .get_or_insert_with(Extensions::new)
+            .add(extension)
+            .map_err(Error::InvalidDer)?;
+        Ok(self)
+    }
+
+    /// Returns the `TbsCertificate` to be signed with the algorithm identified by `signature`.
+    pub fn build(self, signature: AlgorithmIdentifier<'a>) -> TbsCertificate<'a> {
+        TbsCertificate {
+            version: Version::V3,
+            serial_number: self.serial_number,
+            signature,
+            issuer: self.issuer,
+            validity: self.validity,
+            subject: self.subject,
+            subject_public_key_info: self.subject_public_key_info,
+            issuer_unique_id: None,
+            subject_unique_id: None,
+            extensions: self.extensions,
+        }
+    }
+
+    /// Signs the certificate with `signer`, the issuer's key. The `Certificate` is DER-encoded to
+    /// `cert_buf`, and the encoded bytes are returned.
+    pub fn sign<'b>(
+        self,
+        signer: &dyn CertSigner,
+        cert_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        let tbs_certificate = self.build(signer.algorithm());
+
+        // `cert_buf` holds the encoded `TbsCertificate` until it's signed, and is then overwritten
+        // with the encoded `Certificate`.
+        let mut sig_bytes = [0u8; MAX_SIGNATURE_LEN];
+        let tbs_der = tbs_certificate
+            .encode_to_slice(cert_buf)
+            .map_err(Error::InvalidDer)?;
+        let signature = signer.sign(tbs_der, &mut sig_bytes)?;
+
+        let cert = Certificate {
+            tbs_certificate,
+            signature_algorithm: signer.algorithm(),
+            signature: BitStringRef::from_bytes(signature).map_err(Error::InvalidDer)?,
+        };
+        cert.encode_to_slice(cert_buf).map_err(Error::InvalidDer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::Ed25519Signer;
+    use crate::MAX_CERT_LEN;
+    use const_oid::db::rfc4519::CN;
+    use der::DateTime;
+    use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+    #[test]
+    fn build_ed25519_certificate() {
+        let issuer_signer = Ed25519Signer::from_secret(&[1u8; 32]).unwrap();
+        let subject_signer = Ed25519Signer::from_secret(&[2u8; 32]).unwrap();
+        let mut issuer = Name::default();
+        issuer.add_utf8_string(CN, "Test Issuer").unwrap();
+        let mut subject = Name::default();
+        subject.add_utf8_string(CN, "Test Subject").unwrap();
+        let not_before = UtcTime::from_date_time(DateTime::new(2022, 1, 1, 0, 0, 0).unwrap());
+        let not_after = UtcTime::from_date_time(DateTime::new(2032, 1, 1, 0, 0, 0).unwrap());
+        let validity = Validity {
+            not_before: Time::UtcTime(not_before.unwrap()),
+            not_after: Time::UtcTime(not_after.unwrap()),
+        };
+        let extension = Extension {
+            extn_id: ObjectIdentifier::new_unwrap("1.2.3.4"),
+            critical: false,
+            extn_value: OctetStringRef::new(&[0x04, 0x01, 0xaa]).unwrap(),
+        };
+
+        let mut cert_bytes = [0u8; MAX_CERT_LEN];
+        let cert_der = TbsCertificateBuilder::new(
+            &[0x01, 0x02],
+            issuer.clone(),
+            validity,
+            subject.clone(),
+            subject_signer.public_key_info(),
+        )
+        .unwrap()
+        .add_extension(extension.clone())
+        .unwrap()
+        .sign(&issuer_signer, &mut cert_bytes)
+        .unwrap();
+
+        let cert = Certificate::try_from(cert_der).unwrap();
+        let tbs = &cert.tbs_certificate;
+        assert_eq!(tbs.version, Version::V3);
+        assert_eq!(tbs.serial_number.as_bytes(), &[0x01, 0x02]);
+        assert_eq!(tbs.issuer, issuer);
+        assert_eq!(tbs.subject, subject);
+        assert_eq!(tbs.validity, validity);
+        assert_eq!(
+            tbs.subject_public_key_info,
+            subject_signer.public_key_info()
+        );
+        assert_eq!(tbs.extensions.as_ref().unwrap().get(0), Some(&extension));
+
+        let mut tbs_bytes = [0u8; MAX_CERT_LEN];
+        let tbs_der = tbs.encode_to_slice(&mut tbs_bytes).unwrap();
+        let public_key = PublicKey::from_bytes(issuer_signer.public_key()).unwrap();
+        let signature = Signature::try_from(cert.signature.raw_bytes()).unwrap();
+        assert!(public_key.verify(tbs_der, &signature).is_ok());
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use der::asn1::{ObjectIdentifier, OctetStringRef, SequenceOf};
+use der::{Decode, Sequence};
+
+/// Maximum number of FWIDs carried in a `DiceTcbInfo` extension.
+pub const MAX_FWIDS: usize = 8;
+
+/// The `tcg-dice-TcbInfo` certificate extension OID, from the [TCG DICE Attestation
+/// Architecture].
+///
+/// [TCG DICE Attestation Architecture]: https://trustedcomputinggroup.org/resource/dice-attestation-architecture/
+pub const TCG_DICE_TCB_INFO_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.23.133.5.4.1");
+
+/// The `id-sha256` hash algorithm OID, as used in `Fwid::hash_alg`.
+pub const SHA256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+
+/// The `id-sha384` hash algorithm OID, as used in `Fwid::hash_alg`.
+pub const SHA384_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+
+/// The `id-sha512` hash algorithm OID, as used in `Fwid::hash_alg`.
+pub const SHA512_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+
+/// TCG DICE `FWID`, a digest of a measured component of the TCB.
+///
+/// ```text
+/// FWID ::== SEQUENCE {
+///     hashAlg OBJECT IDENTIFIER,
+///     digest OCTET STRING
+/// }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct Fwid<'a> {
+    /// The algorithm used to compute `digest`.
+    pub hash_alg: ObjectIdentifier,
+
+    /// The measurement digest.
+    pub digest: OctetStringRef<'a>,
+}
+
+/// TCG DICE `FWIDLIST`.
+///
+/// ```text
+/// FWIDLIST ::== SEQUENCE SIZE (1..MAX) OF FWID
+/// ```
+pub type FwidList<'a> = SequenceOf<Fwid<'a>, MAX_FWIDS>;
+
+/// TCG DICE `DiceTcbInfo`, carried in the `tcg-dice-TcbInfo` certificate extension. Only the
+/// fields used by Salus are supported.
+///
+/// ```text
+/// DiceTcbInfo ::== SEQUENCE {
+///     vendor [0] IMPLICIT UTF8String OPTIONAL,
+///     model [1] IMPLICIT UTF8String OPTIONAL,
+///     version [2] IMPLICIT UTF8String OPTIONAL,
+///     svn [3] IMPLICIT INTEGER OPTIONAL,
+///     layer [4] IMPLICIT INTEGER OPTIONAL,
+///     index [5] IMPLICIT INTEGER OPTIONAL,
+///     fwids [6] IMPLICIT FWIDLIST OPTIONAL,
+///     flags [7] IMPLICIT OperationalFlags OPTIONAL,
+///     vendorInfo [8] IMPLICIT OCTET STRING OPTIONAL,
+///     type [9] IMPLICIT OCTET STRING OPTIONAL
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq, Sequence)]
+pub struct DiceTcbInfo<'a> {
+    /// The measurements of the TCB component.
+    #[asn1(context_specific = "6", tag_mode = "IMPLICIT", optional = "true")]
+    pub fwids: Option<FwidList<'a>>,
+}
+
+impl<'a> TryFrom<&'a [u8]> for DiceTcbInfo<'a> {
+    type Error = der::Error;
+
+    fn try_from(bytes: &'a [u8]) -> core::result::Result<Self, Self::Error> {
+        Self::from_der(bytes)
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use der::asn1::ObjectIdentifier;
+
+use crate::dice::{SHA256_OID, SHA384_OID, SHA512_OID};
+use crate::signer::{CertSigner, ED25519_ALGORITHM, MAX_SIGNATURE_LEN};
+use crate::{Error, Result, MAX_EVIDENCE_NONCE_LEN};
+
+/// The `eat_nonce` claim key from [RFC 9711].
+///
+/// [RFC 9711]: https://datatracker.ietf.org/doc/html/rfc9711
+pub const EAT_NONCE_CLAIM: i64 = 10;
+
+/// The `swname` claim key from [RFC 9711].
+///
+/// [RFC 9711]: https://datatracker.ietf.org/doc/html/rfc9711
+pub const EAT_SW_NAME_CLAIM: i64 = 270;
+
+/// The `swversion` claim key from [RFC 9711].
+///
+/// [RFC 9711]: https://datatracker.ietf.org/doc/html/rfc9711
+pub const EAT_SW_VERSION_CLAIM: i64 = 271;
+
+/// The private-use claim key for the name of the hash algorithm the TVM measurements were made
+/// with, from the IANA Named Information Hash Algorithm Registry (e.g. "sha-256").
+pub const SALUS_MEASUREMENT_ALG_CLAIM: i64 = -75000;
+
+/// The private-use claim key for the TVM's initial measurement.
+pub const SALUS_TVM_MEASUREMENT_CLAIM: i64 = -75001;
+
+/// The private-use claim key for the TVM's runtime measurement registers.
+pub const SALUS_RUNTIME_MEASUREMENTS_CLAIM: i64 = -75002;
+
+// The software name carried in the `swname` claim.
+const SALUS_SW_NAME: &str = "Salus";
+
+// The COSE_Sign1 CBOR tag.
+const COSE_SIGN1_TAG: u64 = 18;
+
+// The COSE `alg` and `x5chain` header labels.
+const COSE_ALG_LABEL: i64 = 1;
+const COSE_X5CHAIN_LABEL: i64 = 33;
+
+// The COSE algorithm identifier for EdDSA.
+const COSE_ALG_EDDSA: i64 = -8;
+
+// Maximum length of the encoded protected header: a map with a single `alg` entry.
+const MAX_PROTECTED_HEADER_LEN: usize = 8;
+
+// Maximum length of the `Sig_structure` that precedes the payload.
+const MAX_SIG_STRUCTURE_PREFIX_LEN: usize = 32;
+
+// CBOR major types.
+const MAJOR_UINT: u8 = 0;
+const MAJOR_NINT: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+
+/// Writes CBOR data items to a buffer.
+struct CborWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> CborWriter<'b> {
+    fn new(buf: &'b mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<'static, ()> {
+        let end = self
+            .len
+            .checked_add(bytes.len())
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(Error::BufferTooSmall)?;
+        self.buf[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Skips over `len` bytes that were already written to the buffer.
+    fn skip(&mut self, len: usize) -> Result<'static, ()> {
+        let end = self
+            .len
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .ok_or(Error::BufferTooSmall)?;
+        self.len = end;
+        Ok(())
+    }
+
+    /// Writes the head of a data item of type `major`, with the argument `arg`.
+    fn head(&mut self, major: u8, arg: u64) -> Result<'static, ()> {
+        let major = major << 5;
+        match head_len(arg) {
+            1 => self.write(&[major | arg as u8]),
+            2 => self.write(&[major | 24, arg as u8]),
+            3 => {
+                self.write(&[major | 25])?;
+                self.write(&(arg as u16).to_be_bytes())
+            }
+            5 => {
+                self.write(&[major | 26])?;
+                self.write(&(arg as u32).to_be_bytes())
+            }
+            _ => {
+                self.write(&[major | 27])?;
+                self.write(&arg.to_be_bytes())
+            }
+        }
+    }
+
+    fn int(&mut self, val: i64) -> Result<'static, ()> {
+        match u64::try_from(val) {
+            Ok(val) => self.head(MAJOR_UINT, val),
+            // Negative integers are encoded as -1 - val.
+            Err(_) => self.head(MAJOR_NINT, !val as u64),
+        }
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Result<'static, ()> {
+        self.head(MAJOR_BYTES, bytes.len() as u64)?;
+        self.write(bytes)
+    }
+
+    fn text(&mut self, text: &str) -> Result<'static, ()> {
+        self.head(MAJOR_TEXT, text.len() as u64)?;
+        self.write(text.as_bytes())
+    }
+
+    fn array(&mut self, len: usize) -> Result<'static, ()> {
+        self.head(MAJOR_ARRAY, len as u64)
+    }
+
+    fn map(&mut self, len: usize) -> Result<'static, ()> {
+        self.head(MAJOR_MAP, len as u64)
+    }
+
+    fn tag(&mut self, tag: u64) -> Result<'static, ()> {
+        self.head(MAJOR_TAG, tag)
+    }
+
+    /// Returns the written bytes.
+    fn finish(self) -> &'b [u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Returns the length of the head of a data item with the argument `arg`.
+fn head_len(arg: u64) -> usize {
+    match arg {
+        0..=23 => 1,
+        24..=0xff => 2,
+        0x100..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+/// Returns the name of the hash algorithm identified by `oid` in the IANA Named Information Hash
+/// Algorithm Registry.
+fn hash_algorithm_name(oid: ObjectIdentifier) -> Option<&'static str> {
+    match oid {
+        SHA256_OID => Some("sha-256"),
+        SHA384_OID => Some("sha-384"),
+        SHA512_OID => Some("sha-512"),
+        _ => None,
+    }
+}
+
+/// The claims of an Entity Attestation Token (EAT) for a TVM, as defined in [RFC 9711]. The claims
+/// set is encoded as a CBOR map with the following entries, in deterministic order:
+///
+/// ```text
+/// {
+///     ? eat_nonce (10) => bstr,
+///     swname (270) => "Salus",
+///     swversion (271) => [ salus_version: tstr ],
+///     measurement_alg (-75000) => tstr,
+///     tvm_measurement (-75001) => bstr,
+///     runtime_measurements (-75002) => [ * bstr ],
+/// }
+/// ```
+///
+/// [RFC 9711]: https://datatracker.ietf.org/doc/html/rfc9711
+pub struct EatClaims<'a> {
+    /// OID of the hash algorithm the measurements were made with.
+    pub hash_alg: ObjectIdentifier,
+
+    /// The TVM's initial measurement.
+    pub tvm_measurement: &'a [u8],
+
+    /// The TVM's runtime measurement registers.
+    pub runtime_measurements: &'a [&'a [u8]],
+
+    /// The version of Salus.
+    pub salus_version: &'a str,
+
+    /// Nonce of up to `MAX_EVIDENCE_NONCE_LEN` bytes passed by the relying party.
+    pub nonce: Option<&'a [u8]>,
+}
+
+impl EatClaims<'_> {
+    /// Signs the claims with `signer`, producing a tagged COSE_Sign1 token as defined in
+    /// [RFC 9052]. If `x5chain` is given, it's carried in the unprotected header as the
+    /// DER-encoded certificate of the signing key. The token is encoded to `token_buf`, and the
+    /// encoded bytes are returned.
+    ///
+    /// [RFC 9052]: https://datatracker.ietf.org/doc/html/rfc9052
+    pub fn sign<'b>(
+        &self,
+        signer: &dyn CertSigner,
+        x5chain: Option<&[u8]>,
+        token_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        let algorithm = signer.algorithm();
+        if algorithm != ED25519_ALGORITHM {
+            return Err(Error::UnsupportedAlgorithm(algorithm));
+        }
+        let mut protected_bytes = [0u8; MAX_PROTECTED_HEADER_LEN];
+        let mut protected = CborWriter::new(&mut protected_bytes);
+        protected.map(1)?;
+        protected.int(COSE_ALG_LABEL)?;
+        protected.int(COSE_ALG_EDDSA)?;
+        let protected = protected.finish();
+
+        // `token_buf` holds the payload and then the `Sig_structure` while they're signed, and is
+        // then overwritten with the token, moving the payload into place at each step.
+        let mut payload = CborWriter::new(token_buf);
+        self.encode(&mut payload)?;
+        let payload_len = payload.finish().len();
+
+        let mut prefix_bytes = [0u8; MAX_SIG_STRUCTURE_PREFIX_LEN];
+        let mut prefix = CborWriter::new(&mut prefix_bytes);
+        prefix.array(4)?;
+        prefix.text("Signature1")?;
+        prefix.bytes(protected)?;
+        prefix.bytes(&[])?;
+        prefix.head(MAJOR_BYTES, payload_len as u64)?;
+        let prefix = prefix.finish();
+        let sig_structure = move_payload(token_buf, 0, prefix.len(), payload_len)?;
+        sig_structure[..prefix.len()].copy_from_slice(prefix);
+        let mut sig_bytes = [0u8; MAX_SIGNATURE_LEN];
+        let signature = signer.sign(sig_structure, &mut sig_bytes)?;
+
+        let unprotected_len = match x5chain {
+            Some(cert) => {
+                1 + head_len(COSE_X5CHAIN_LABEL as u64) + head_len(cert.len() as u64) + cert.len()
+            }
+            None => 1,
+        };
+        let token_prefix_len = head_len(COSE_SIGN1_TAG)
+            + head_len(4)
+            + head_len(protected.len() as u64)
+            + protected.len()
+            + unprotected_len
+            + head_len(payload_len as u64);
+        move_payload(token_buf, prefix.len(), token_prefix_len, payload_len)?;
+
+        let mut token = CborWriter::new(token_buf);
+        token.tag(COSE_SIGN1_TAG)?;
+        token.array(4)?;
+        token.bytes(protected)?;
+        match x5chain {
+            Some(cert) => {
+                token.map(1)?;
+                token.int(COSE_X5CHAIN_LABEL)?;
+                token.bytes(cert)?;
+            }
+            None => token.map(0)?,
+        }
+        token.head(MAJOR_BYTES, payload_len as u64)?;
+        // The payload was already moved into place.
+        token.skip(payload_len)?;
+        token.bytes(signature)?;
+        Ok(token.finish())
+    }
+
+    fn encode(&self, w: &mut CborWriter) -> Result<'static, ()> {
+        let hash_alg_name =
+            hash_algorithm_name(self.hash_alg).ok_or(Error::UnsupportedHashAlgorithm)?;
+        match self.nonce {
+            Some(nonce) => {
+                if nonce.len() > MAX_EVIDENCE_NONCE_LEN {
+                    return Err(Error::InvalidNonce);
+                }
+                w.map(6)?;
+                w.int(EAT_NONCE_CLAIM)?;
+                w.bytes(nonce)?;
+            }
+            None => w.map(5)?,
+        }
+        w.int(EAT_SW_NAME_CLAIM)?;
+        w.text(SALUS_SW_NAME)?;
+        w.int(EAT_SW_VERSION_CLAIM)?;
+        w.array(1)?;
+        w.text(self.salus_version)?;
+        w.int(SALUS_MEASUREMENT_ALG_CLAIM)?;
+        w.text(hash_alg_name)?;
+        w.int(SALUS_TVM_MEASUREMENT_CLAIM)?;
+        w.bytes(self.tvm_measurement)?;
+        w.int(SALUS_RUNTIME_MEASUREMENTS_CLAIM)?;
+        w.array(self.runtime_measurements.len())?;
+        for register in self.runtime_measurements {
+            w.bytes(register)?;
+        }
+        Ok(())
+    }
+}
+
+/// Moves the `len` byte payload at offset `from` of `buf` to offset `to`, returning the bytes of
+/// `buf` up to the end of the moved payload.
+fn move_payload(buf: &mut [u8], from: usize, to: usize, len: usize) -> Result<'static, &mut [u8]> {
+    let end = to
+        .checked_add(len)
+        .filter(|&end| end <= buf.len())
+        .ok_or(Error::BufferTooSmall)?;
+    buf.copy_within(from..from + len, to);
+    Ok(&mut buf[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::Ed25519Signer;
+    use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+    const TEST_SECRET: [u8; 32] = [3u8; 32];
+
+    // The claims of `test_claims()`, encoded by hand.
+    const TEST_PAYLOAD: &[u8] = &[
+        0xa6, // map(6)
+        0x0a, 0x42, 0x09, 0x09, // eat_nonce: h'0909'
+        0x19, 0x01, 0x0e, 0x65, b'S', b'a', b'l', b'u', b's', // swname: "Salus"
+        0x19, 0x01, 0x0f, 0x81, 0x63, b'0', b'.', b'1', // swversion: ["0.1"]
+        0x3a, 0x00, 0x01, 0x24, 0xf7, 0x67, b's', b'h', b'a', b'-', b'2', b'5',
+        b'6', // "sha-256"
+        0x3a, 0x00, 0x01, 0x24, 0xf8, 0x42, 0x01, 0x02, // tvm_measurement: h'0102'
+        0x3a, 0x00, 0x01, 0x24, 0xf9, 0x82, 0x41, 0x03, 0x41, 0x04, // [h'03', h'04']
+    ];
+
+    // The protected header: {1: -8}.
+    const TEST_PROTECTED: &[u8] = &[0xa1, 0x01, 0x27];
+
+    fn test_claims<'a>(runtime_measurements: &'a [&'a [u8]]) -> EatClaims<'a> {
+        EatClaims {
+            hash_alg: SHA256_OID,
+            tvm_measurement: &[0x01, 0x02],
+            runtime_measurements,
+            salus_version: "0.1",
+            nonce: Some(&[0x09, 0x09]),
+        }
+    }
+
+    #[test]
+    fn sign_eat() {
+        let signer = Ed25519Signer::from_secret(&TEST_SECRET).unwrap();
+        let runtime_measurements: [&[u8]; 2] = [&[0x03], &[0x04]];
+        let claims = test_claims(&runtime_measurements);
+        let cert = [0x30, 0x00];
+        let mut token_bytes = [0u8; 256];
+        let token = claims
+            .sign(&signer, Some(&cert[..]), &mut token_bytes)
+            .unwrap();
+
+        // 18([protected, {33: cert}, payload, signature])
+        let (head, token) = token.split_at(3);
+        assert_eq!(head, &[0xd2, 0x84, 0x43]);
+        let (protected, token) = token.split_at(TEST_PROTECTED.len());
+        assert_eq!(protected, TEST_PROTECTED);
+        let (unprotected, token) = token.split_at(6);
+        assert_eq!(unprotected, &[0xa1, 0x18, 0x21, 0x42, 0x30, 0x00]);
+        let (payload_head, token) = token.split_at(2);
+        assert_eq!(payload_head, &[0x58, TEST_PAYLOAD.len() as u8]);
+        let (payload, token) = token.split_at(TEST_PAYLOAD.len());
+        assert_eq!(payload, TEST_PAYLOAD);
+        let (signature_head, token) = token.split_at(2);
+        assert_eq!(signature_head, &[0x58, 0x40]);
+        let signature = Signature::try_from(token).unwrap();
+
+        // ["Signature1", protected, h'', payload]
+        let mut sig_structure_bytes = [0u8; 256];
+        let mut sig_structure = CborWriter::new(&mut sig_structure_bytes);
+        sig_structure.write(&[0x84, 0x6a]).unwrap();
+        sig_structure.write(b"Signature1").unwrap();
+        sig_structure.write(&[0x43]).unwrap();
+        sig_structure.write(TEST_PROTECTED).unwrap();
+        sig_structure
+            .write(&[0x40, 0x58, TEST_PAYLOAD.len() as u8])
+            .unwrap();
+        sig_structure.write(TEST_PAYLOAD).unwrap();
+        let public_key = PublicKey::from_bytes(signer.public_key()).unwrap();
+        assert!(public_key
+            .verify(sig_structure.finish(), &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn reject_small_buffer() {
+        let signer = Ed25519Signer::from_secret(&TEST_SECRET).unwrap();
+        let runtime_measurements: [&[u8]; 2] = [&[0x03], &[0x04]];
+        let claims = test_claims(&runtime_measurements);
+        let mut token_bytes = [0u8; 64];
+        assert!(matches!(
+            claims.sign(&signer, None, &mut token_bytes),
+            Err(Error::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn reject_long_nonce() {
+        let signer = Ed25519Signer::from_secret(&TEST_SECRET).unwrap();
+        let nonce = [0u8; MAX_EVIDENCE_NONCE_LEN + 1];
+        let claims = EatClaims {
+            nonce: Some(&nonce),
+            ..test_claims(&[])
+        };
+        let mut token_bytes = [0u8; 256];
+        assert!(matches!(
+            claims.sign(&signer, None, &mut token_bytes),
+            Err(Error::InvalidNonce)
+        ));
+    }
+}
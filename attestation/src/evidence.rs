@@ -0,0 +1,266 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use der::asn1::{GeneralizedTime, ObjectIdentifier, OctetStringRef, UtcTime};
+use der::{DateTime, Encode};
+use spki::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+use crate::certificate::{Extension, TbsCertificateBuilder, Time, Validity};
+use crate::dice::{DiceTcbInfo, Fwid, FwidList, TCG_DICE_TCB_INFO_OID};
+use crate::name::{Name, RdnSequence, COMMON_NAME_OID};
+use crate::signer::{CertSigner, Ed25519Signer, ED25519_PUBLIC_KEY_LEN, ED25519_SECRET_KEY_LEN};
+use crate::{request::CertReq, Error, Result, MAX_EVIDENCE_NONCE_LEN};
+
+/// Length of the secret key used to sign evidence.
+pub const EVIDENCE_SECRET_KEY_LEN: usize = ED25519_SECRET_KEY_LEN;
+
+/// Length of the public key evidence is verified with.
+pub const EVIDENCE_PUBLIC_KEY_LEN: usize = ED25519_PUBLIC_KEY_LEN;
+
+// Maximum supported length of the encoded `DiceTcbInfo` extension.
+const MAX_TCB_INFO_LEN: usize = 512;
+
+/// The OID of the certificate extension carrying the nonce passed when issuing evidence. This is
+/// the `id-pkix-ocsp-nonce` OID from [RFC 8954], whose extension value is likewise the nonce
+/// encoded as an OCTET STRING.
+///
+/// [RFC 8954]: https://datatracker.ietf.org/doc/html/rfc8954
+pub const EVIDENCE_NONCE_OID: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1.2");
+
+// Maximum length of the encoded nonce extension value: an OCTET STRING with a short-form length.
+const MAX_NONCE_EXTENSION_LEN: usize = MAX_EVIDENCE_NONCE_LEN + 2;
+
+// The number of bytes of the subject's public key used as the serial number of a certificate
+// issued with `EvidenceSigner::certify()`.
+const KEY_SERIAL_LEN: usize = 16;
+
+/// Issues X.509 evidence certificates, signed with an Ed25519 attestation key.
+pub struct EvidenceSigner {
+    signer: Ed25519Signer,
+    common_name: &'static str,
+}
+
+impl EvidenceSigner {
+    /// Creates an `EvidenceSigner` that signs with the Ed25519 secret key `secret`. Certificates
+    /// issued by the signer name `common_name` as their issuer.
+    pub fn from_secret(
+        secret: &[u8; EVIDENCE_SECRET_KEY_LEN],
+        common_name: &'static str,
+    ) -> Result<'static, Self> {
+        Ok(Self {
+            signer: Ed25519Signer::from_secret(secret)?,
+            common_name,
+        })
+    }
+
+    /// Returns the public key that certificates issued by this signer can be verified with.
+    pub fn public_key(&self) -> [u8; EVIDENCE_PUBLIC_KEY_LEN] {
+        *self.signer.public_key()
+    }
+
+    /// Issues a certificate for the subject and public key of `csr`, which must already have been
+    /// verified. The digests in `measurements`, each paired with the OID of the hash algorithm it
+    /// was made with, are carried in a `DiceTcbInfo` extension. A `nonce` of up to
+    /// `MAX_EVIDENCE_NONCE_LEN` bytes is carried in an extension identified by
+    /// `EVIDENCE_NONCE_OID`. The certificate is DER-encoded to `cert_buf`, and the encoded bytes
+    /// are returned.
+    pub fn issue<'b>(
+        &self,
+        csr: &CertReq,
+        serial: &[u8],
+        measurements: &[(ObjectIdentifier, &[u8])],
+        nonce: Option<&[u8]>,
+        cert_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        self.issue_certificate(
+            csr.info.subject.clone(),
+            csr.info.public_key,
+            serial,
+            measurements,
+            nonce,
+            cert_buf,
+        )
+    }
+
+    /// Issues a certificate for the public key of `subject`, as the next layer of a chain of
+    /// evidence. The digests in `measurements`, each paired with the OID of the hash algorithm it
+    /// was made with, are carried in a `DiceTcbInfo` extension.
+    /// The certificate is DER-encoded to `cert_buf`, and the encoded bytes are returned.
+    pub fn certify<'b>(
+        &self,
+        subject: &EvidenceSigner,
+        measurements: &[(ObjectIdentifier, &[u8])],
+        cert_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        self.issue_certificate(
+            common_name(subject.common_name).map_err(Error::InvalidDer)?,
+            subject.signer.public_key_info(),
+            &subject.signer.public_key()[..KEY_SERIAL_LEN],
+            measurements,
+            None,
+            cert_buf,
+        )
+    }
+
+    fn issue_certificate<'b>(
+        &self,
+        subject: Name,
+        subject_public_key_info: SubjectPublicKeyInfo,
+        serial: &[u8],
+        measurements: &[(ObjectIdentifier, &[u8])],
+        nonce: Option<&[u8]>,
+        cert_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        let mut fwids = FwidList::new();
+        for &(hash_alg, digest) in measurements {
+            let fwid = Fwid {
+                hash_alg,
+                digest: OctetStringRef::new(digest).map_err(Error::InvalidDer)?,
+            };
+            fwids.add(fwid).map_err(Error::InvalidDer)?;
+        }
+        let tcb_info = DiceTcbInfo { fwids: Some(fwids) };
+        let mut tcb_info_bytes = [0u8; MAX_TCB_INFO_LEN];
+        let tcb_info_der = tcb_info
+            .encode_to_slice(&mut tcb_info_bytes)
+            .map_err(Error::InvalidDer)?;
+
+        let mut nonce_bytes = [0u8; MAX_NONCE_EXTENSION_LEN];
+        let mut builder = TbsCertificateBuilder::new(
+            serial,
+            common_name(self.common_name).map_err(Error::InvalidDer)?,
+            validity().map_err(Error::InvalidDer)?,
+            subject,
+            subject_public_key_info,
+        )?
+        .add_extension(Extension {
+            extn_id: TCG_DICE_TCB_INFO_OID,
+            critical: false,
+            extn_value: OctetStringRef::new(tcb_info_der).map_err(Error::InvalidDer)?,
+        })?;
+        if let Some(nonce) = nonce {
+            if nonce.len() > MAX_EVIDENCE_NONCE_LEN {
+                return Err(Error::InvalidNonce);
+            }
+            let nonce_der = OctetStringRef::new(nonce)
+                .map_err(Error::InvalidDer)?
+                .encode_to_slice(&mut nonce_bytes)
+                .map_err(Error::InvalidDer)?;
+            builder = builder.add_extension(Extension {
+                extn_id: EVIDENCE_NONCE_OID,
+                critical: false,
+                extn_value: OctetStringRef::new(nonce_der).map_err(Error::InvalidDer)?,
+            })?;
+        }
+
+        builder.sign(&self.signer, cert_buf)
+    }
+}
+
+impl CertSigner for EvidenceSigner {
+    fn algorithm(&self) -> AlgorithmIdentifier<'static> {
+        self.signer.algorithm()
+    }
+
+    fn public_key_info(&self) -> SubjectPublicKeyInfo<'_> {
+        self.signer.public_key_info()
+    }
+
+    fn sign<'b>(&self, msg: &[u8], sig_buf: &'b mut [u8]) -> Result<'static, &'b [u8]> {
+        self.signer.sign(msg, sig_buf)
+    }
+}
+
+/// Returns the name consisting of just the common name `cn`.
+fn common_name(cn: &'static str) -> der::Result<Name<'static>> {
+    let mut name = RdnSequence::default();
+    name.add_utf8_string(COMMON_NAME_OID, cn)?;
+    Ok(name)
+}
+
+/// Returns the validity period of evidence certificates. There's no trusted time source to bound
+/// the validity with, so certificates are valid from a fixed date in the past and never expire,
+/// using the `GeneralizedTime` value RFC 5280 reserves for certificates with no expiration date.
+fn validity() -> der::Result<Validity> {
+    let not_before = UtcTime::from_date_time(DateTime::new(2018, 3, 22, 23, 59, 59)?)?;
+    let not_after = GeneralizedTime::from_date_time(DateTime::new(9999, 12, 31, 23, 59, 59)?);
+    Ok(Validity {
+        not_before: Time::UtcTime(not_before),
+        not_after: Time::GeneralTime(not_after),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certificate::Certificate;
+    use crate::MAX_CERT_LEN;
+    use const_oid::db::rfc5912::ID_SHA_256;
+
+    // Issues a certificate for `subject`'s key, signed by `issuer`, with `nonce`.
+    fn issue_with_nonce<'b>(
+        issuer: &EvidenceSigner,
+        subject: &EvidenceSigner,
+        nonce: Option<&[u8]>,
+        cert_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        issuer.issue_certificate(
+            common_name(subject.common_name).unwrap(),
+            subject.signer.public_key_info(),
+            &[0x01],
+            &[(ID_SHA_256, &[0xa5u8; 32][..])],
+            nonce,
+            cert_buf,
+        )
+    }
+
+    #[test]
+    fn nonce_extension() {
+        let issuer = EvidenceSigner::from_secret(&[1u8; 32], "Test Issuer").unwrap();
+        let subject = EvidenceSigner::from_secret(&[2u8; 32], "Test Subject").unwrap();
+        let nonce = [0x5au8; MAX_EVIDENCE_NONCE_LEN];
+        let mut cert_bytes = [0u8; MAX_CERT_LEN];
+        let cert_der = issue_with_nonce(&issuer, &subject, Some(&nonce), &mut cert_bytes).unwrap();
+
+        let cert = Certificate::try_from(cert_der).unwrap();
+        let extensions = cert.tbs_certificate.extensions.as_ref().unwrap();
+        let ext = extensions
+            .iter()
+            .find(|e| e.extn_id == EVIDENCE_NONCE_OID)
+            .unwrap();
+        assert!(!ext.critical);
+        // The extension value is the nonce encoded as an OCTET STRING.
+        let value = ext.extn_value.as_bytes();
+        assert_eq!(value[..2], [0x04, MAX_EVIDENCE_NONCE_LEN as u8]);
+        assert_eq!(value[2..], nonce);
+        assert!(extensions
+            .iter()
+            .any(|e| e.extn_id == TCG_DICE_TCB_INFO_OID));
+    }
+
+    #[test]
+    fn no_nonce_extension() {
+        let issuer = EvidenceSigner::from_secret(&[1u8; 32], "Test Issuer").unwrap();
+        let subject = EvidenceSigner::from_secret(&[2u8; 32], "Test Subject").unwrap();
+        let mut cert_bytes = [0u8; MAX_CERT_LEN];
+        let cert_der = issue_with_nonce(&issuer, &subject, None, &mut cert_bytes).unwrap();
+
+        let cert = Certificate::try_from(cert_der).unwrap();
+        let extensions = cert.tbs_certificate.extensions.as_ref().unwrap();
+        assert!(extensions.iter().all(|e| e.extn_id != EVIDENCE_NONCE_OID));
+    }
+
+    #[test]
+    fn reject_long_nonce() {
+        let issuer = EvidenceSigner::from_secret(&[1u8; 32], "Test Issuer").unwrap();
+        let subject = EvidenceSigner::from_secret(&[2u8; 32], "Test Subject").unwrap();
+        let nonce = [0u8; MAX_EVIDENCE_NONCE_LEN + 1];
+        let mut cert_bytes = [0u8; MAX_CERT_LEN];
+        assert!(matches!(
+            issue_with_nonce(&issuer, &subject, Some(&nonce), &mut cert_bytes),
+            Err(Error::InvalidNonce)
+        ));
+    }
+}
@@ -12,6 +12,12 @@ pub const MAX_CERT_LEN: usize = 4096;
 /// Maximum supported length for a CSR
 pub const MAX_CSR_LEN: usize = 4096;
 
+/// Maximum supported length for an evidence token
+pub const MAX_EVIDENCE_TOKEN_LEN: usize = 4096;
+
+/// Maximum supported length for a nonce embedded in evidence
+pub const MAX_EVIDENCE_NONCE_LEN: usize = 64;
+
 pub(crate) const MAX_CSR_ATV: usize = 8;
 pub(crate) const MAX_CSR_ATV_VALUE: usize = 8;
 pub(crate) const MAX_CSR_ATV_VALUE_LEN: usize = 64;
@@ -38,11 +44,26 @@ pub enum Error<'a> {
     /// Invalid public key DER
     InvalidPublicKeyDer(spki::Error),
 
+    /// Invalid secret key bytes
+    InvalidSecretKey,
+
+    /// Evidence nonce longer than `MAX_EVIDENCE_NONCE_LEN`
+    InvalidNonce,
+
+    /// Buffer too small for the encoded data
+    BufferTooSmall,
+
     /// Invalid digital signature
     InvalidSignature,
 
+    /// Failed to make a digital signature
+    SigningFailed,
+
     /// Unsupported signing algorithm
     UnsupportedAlgorithm(spki::AlgorithmIdentifier<'a>),
+
+    /// Unsupported measurement hash algorithm
+    UnsupportedHashAlgorithm,
 }
 
 /// Custom attestation result.
@@ -120,8 +141,20 @@ macro_rules! impl_newtype {
     };
 }
 
-mod attr;
-mod name;
+/// X.501 attribute module
+pub mod attr;
+/// X.509 certificate module
+pub mod certificate;
+/// TCG DICE certificate extensions module
+pub mod dice;
+/// Entity Attestation Token module
+pub mod eat;
+/// Evidence certificate issuing module
+pub mod evidence;
+/// X.501 name module
+pub mod name;
 /// Certificate Signing Resquest module
 pub mod request;
+/// Certificate and CSR signing module
+pub mod signer;
 mod verify;
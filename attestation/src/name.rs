@@ -5,12 +5,15 @@
 
 use arrayvec::ArrayVec;
 use core::fmt;
-use der::asn1::{SequenceOf, SetOf};
-use der::{Decode, Error, ErrorKind, Length};
+use der::asn1::{AnyRef, ObjectIdentifier, SequenceOf, SetOf};
+use der::{Decode, Error, ErrorKind, Length, Tag};
 
 use crate::attr::AttributeTypeAndValue;
 use crate::{MAX_CSR_ATV, MAX_CSR_RDN, MAX_CSR_RDN_LEN, MAX_CSR_RDN_SEQUENCE_LEN};
 
+/// The `id-at-commonName` attribute type OID.
+pub const COMMON_NAME_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+
 /// X.501 Name as defined in [RFC 5280 Section 4.1.2.4]. X.501 Name is used to represent distinguished names.
 ///
 /// ```text
@@ -57,6 +60,20 @@ impl RdnSequence<'_> {
     }
 }
 
+impl<'a> RdnSequence<'a> {
+    /// Appends a RelativeDistinguishedName made of the single attribute `oid`, with `value` as a
+    /// UTF8String. For example, adding `COMMON_NAME_OID` with "Salus" appends `CN=Salus`.
+    pub fn add_utf8_string(&mut self, oid: ObjectIdentifier, value: &'a str) -> Result<(), Error> {
+        let atv = AttributeTypeAndValue {
+            oid,
+            value: AnyRef::new(Tag::Utf8String, value.as_bytes())?,
+        };
+        let mut rdn = RelativeDistinguishedName::default();
+        rdn.0.add(atv)?;
+        self.0.add(rdn)
+    }
+}
+
 /// Serializes the structure according to the rules in [RFC 4514].
 ///
 /// [RFC 4514]: https://datatracker.ietf.org/doc/html/rfc4514
@@ -107,6 +124,13 @@ fn split(s: &str, b: u8) -> impl '_ + Iterator<Item = &'_ str> {
     })
 }
 
+/// X.501 RelativeDistinguishedName as defined in [RFC 5280 Section 4.1.2.4].
+///
+/// ```text
+/// RelativeDistinguishedName ::= SET SIZE (1..MAX) OF AttributeTypeAndValue
+/// ```
+///
+/// [RFC 5280 Section 4.1.2.4]: https://datatracker.ietf.org/doc/html/rfc5280#section-4.1.2.4
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct RelativeDistinguishedName<'a>(pub SetOf<AttributeTypeAndValue<'a>, MAX_CSR_ATV>);
 
@@ -4,10 +4,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use der::asn1::BitStringRef;
-use der::{Decode, Enumerated, Sequence};
+use der::{Decode, Encode, Enumerated, Sequence};
 use spki::{AlgorithmIdentifier, SubjectPublicKeyInfo};
 
-use crate::{attr::Attributes, name::Name, verify::verifier_from_algorithm, Result};
+use crate::attr::{Attribute, Attributes};
+use crate::name::Name;
+use crate::signer::{CertSigner, MAX_SIGNATURE_LEN};
+use crate::{verify::verifier_from_algorithm, Error, Result};
 
 /// Version identifier for certification request information.
 ///
@@ -93,3 +96,113 @@ impl<'a> CertReq<'a> {
         verifier_from_algorithm(self.algorithm)?.verify_csr(self)
     }
 }
+
+/// Builds and signs a `CertReq` without allocating.
+pub struct CertReqBuilder<'a> {
+    subject: Name<'a>,
+    attributes: Attributes<'a>,
+}
+
+impl<'a> CertReqBuilder<'a> {
+    /// Creates a builder for a request for a certificate for `subject`. Use `add_attribute()` to
+    /// add request attributes.
+    pub fn new(subject: Name<'a>) -> Self {
+        Self {
+            subject,
+            attributes: Attributes::new(),
+        }
+    }
+
+    /// Adds `attribute` to the request attributes.
+    pub fn add_attribute(mut self, attribute: Attribute<'a>) -> Result<'static, Self> {
+        self.attributes.add(attribute).map_err(Error::InvalidDer)?;
+        Ok(self)
+    }
+
+    /// Signs the request with `signer`, whose public key is the one the certificate is requested
+    /// for. The `CertReq` is DER-encoded to `csr_buf`, and the encoded bytes are returned.
+    pub fn sign<'b>(
+        self,
+        signer: &dyn CertSigner,
+        csr_buf: &'b mut [u8],
+    ) -> Result<'static, &'b [u8]> {
+        let info = CertReqInfo {
+            version: Version::V1,
+            subject: self.subject,
+            public_key: signer.public_key_info(),
+            attributes: self.attributes,
+        };
+
+        // `csr_buf` holds the encoded `CertReqInfo` until it's signed, and is then overwritten
+        // with the encoded `CertReq`.
+        let mut sig_bytes = [0u8; MAX_SIGNATURE_LEN];
+        let info_der = info.encode_to_slice(csr_buf).map_err(Error::InvalidDer)?;
+        let signature = signer.sign(info_der, &mut sig_bytes)?;
+
+        let csr = CertReq {
+            info,
+            algorithm: signer.algorithm(),
+            signature: BitStringRef::from_bytes(signature).map_err(Error::InvalidDer)?,
+        };
+        csr.encode_to_slice(csr_buf).map_err(Error::InvalidDer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signer::Ed25519Signer;
+    use crate::MAX_CSR_LEN;
+    use const_oid::db::rfc4519::{CN, O};
+    use der::asn1::{AnyRef, SetOf};
+    use der::Tag;
+
+    const TEST_SECRET: [u8; 32] = [7u8; 32];
+
+    #[test]
+    fn build_ed25519_csr() {
+        let signer = Ed25519Signer::from_secret(&TEST_SECRET).unwrap();
+        let mut subject = Name::default();
+        subject.add_utf8_string(CN, "Salus Test TVM").unwrap();
+        subject.add_utf8_string(O, "Rivos").unwrap();
+
+        let mut csr_bytes = [0u8; MAX_CSR_LEN];
+        let csr_der = CertReqBuilder::new(subject.clone())
+            .sign(&signer, &mut csr_bytes)
+            .unwrap();
+
+        let csr = CertReq::try_from(csr_der).unwrap();
+        assert!(csr.verify().is_ok());
+        assert_eq!(csr.algorithm, signer.algorithm());
+        assert_eq!(csr.info.public_key, signer.public_key_info());
+        assert_eq!(csr.info.subject, subject);
+    }
+
+    #[test]
+    fn build_csr_with_attributes() {
+        // The challengePassword attribute from PKCS#9.
+        let challenge_password = der::asn1::ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.7");
+        let mut values = SetOf::new();
+        values
+            .add(AnyRef::new(Tag::Utf8String, b"password").unwrap())
+            .unwrap();
+        let attribute = Attribute {
+            oid: challenge_password,
+            values,
+        };
+
+        let signer = Ed25519Signer::from_secret(&TEST_SECRET).unwrap();
+        let mut subject = Name::default();
+        subject.add_utf8_string(CN, "Salus Test TVM").unwrap();
+        let mut csr_bytes = [0u8; MAX_CSR_LEN];
+        let csr_der = CertReqBuilder::new(subject)
+            .add_attribute(attribute.clone())
+            .unwrap()
+            .sign(&signer, &mut csr_bytes)
+            .unwrap();
+
+        let csr = CertReq::try_from(csr_der).unwrap();
+        assert!(csr.verify().is_ok());
+        assert_eq!(csr.info.attributes.iter().next(), Some(&attribute));
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use spki::{AlgorithmIdentifier, SubjectPublicKeyInfo};
+
+use crate::{Error, Result};
+
+/// Maximum length of a signature made by a `CertSigner`.
+pub const MAX_SIGNATURE_LEN: usize = 128;
+
+/// Length of an Ed25519 secret key.
+pub const ED25519_SECRET_KEY_LEN: usize = ed25519_dalek::SECRET_KEY_LENGTH;
+
+/// Length of an Ed25519 public key.
+pub const ED25519_PUBLIC_KEY_LEN: usize = ed25519_dalek::PUBLIC_KEY_LENGTH;
+
+/// The algorithm identifier for Ed25519 keys and signatures.
+pub const ED25519_ALGORITHM: AlgorithmIdentifier<'static> = AlgorithmIdentifier {
+    oid: ed25519::pkcs8::ALGORITHM_OID,
+    parameters: None,
+};
+
+/// Signs certificate signing requests and certificates.
+pub trait CertSigner {
+    /// Returns the identifier of the algorithm the signer's signatures are made with.
+    fn algorithm(&self) -> AlgorithmIdentifier<'static>;
+
+    /// Returns the public key the signer's signatures can be verified with.
+    fn public_key_info(&self) -> SubjectPublicKeyInfo<'_>;
+
+    /// Signs `msg`, writing the signature to `sig_buf`, which is at least `MAX_SIGNATURE_LEN`
+    /// bytes long, and returning the signature bytes.
+    fn sign<'b>(&self, msg: &[u8], sig_buf: &'b mut [u8]) -> Result<'static, &'b [u8]>;
+}
+
+/// Signs with an Ed25519 key.
+pub struct Ed25519Signer {
+    keypair: Keypair,
+}
+
+impl Ed25519Signer {
+    /// Creates an `Ed25519Signer` that signs with the secret key `secret`.
+    pub fn from_secret(secret: &[u8; ED25519_SECRET_KEY_LEN]) -> Result<'static, Self> {
+        let secret = SecretKey::from_bytes(secret).map_err(|_| Error::InvalidSecretKey)?;
+        let public = PublicKey::from(&secret);
+        Ok(Self {
+            keypair: Keypair { secret, public },
+        })
+    }
+
+    /// Returns the public key the signer's signatures can be verified with.
+    pub fn public_key(&self) -> &[u8; ED25519_PUBLIC_KEY_LEN] {
+        self.keypair.public.as_bytes()
+    }
+}
+
+impl CertSigner for Ed25519Signer {
+    fn algorithm(&self) -> AlgorithmIdentifier<'static> {
+        ED25519_ALGORITHM
+    }
+
+    fn public_key_info(&self) -> SubjectPublicKeyInfo<'_> {
+        SubjectPublicKeyInfo {
+            algorithm: ED25519_ALGORITHM,
+            subject_public_key: self.public_key(),
+        }
+    }
+
+    fn sign<'b>(&self, msg: &[u8], sig_buf: &'b mut [u8]) -> Result<'static, &'b [u8]> {
+        let signature = self.keypair.sign(msg).to_bytes();
+        let sig_buf = sig_buf
+            .get_mut(..signature.len())
+            .ok_or(Error::SigningFailed)?;
+        sig_buf.copy_from_slice(&signature);
+        Ok(sig_buf)
+    }
+}
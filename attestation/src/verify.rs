@@ -2,6 +2,7 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
+use const_oid::db::rfc5912::{ECDSA_WITH_SHA_256, ECDSA_WITH_SHA_384};
 use der::Encode;
 use ed25519::pkcs8::{DecodePublicKey, PublicKeyBytes};
 use ed25519_dalek::{PublicKey, Signature, Verifier};
@@ -24,21 +25,14 @@ impl Ed25519Verifier {
 impl CertVerifier for Ed25519Verifier {
     fn verify_csr(&self, csr: &CertReq) -> Result<()> {
         let mut pub_key_der_bytes = [0u8; Self::PUB_KEY_DER_SIZE];
-        let pub_key_der = csr
-            .info
-            .public_key
-            .encode_to_slice(&mut pub_key_der_bytes)
-            .map_err(Error::InvalidDer)?;
+        let pub_key_der = public_key_der(csr, &mut pub_key_der_bytes)?;
         let pub_key_bytes =
             PublicKeyBytes::from_public_key_der(pub_key_der).map_err(Error::InvalidPublicKeyDer)?;
         let pub_key = PublicKey::from_bytes(&pub_key_bytes.to_bytes())
             .map_err(|_| Error::InvalidPublicKey)?;
 
         let mut csr_info_bytes = [0u8; MAX_CERT_LEN];
-        let csr_info = csr
-            .info
-            .encode_to_slice(&mut csr_info_bytes)
-            .map_err(Error::InvalidCertReq)?;
+        let csr_info = csr_info_der(csr, &mut csr_info_bytes)?;
 
         let sig =
             Signature::try_from(csr.signature.raw_bytes()).map_err(|_| Error::InvalidSignature)?;
@@ -49,6 +43,75 @@ impl CertVerifier for Ed25519Verifier {
     }
 }
 
+/// Verifies ecdsa-with-SHA256 signatures made with a P-256 key.
+pub struct EcdsaP256Verifier {}
+
+impl EcdsaP256Verifier {
+    const PUB_KEY_DER_SIZE: usize = 91;
+}
+
+impl CertVerifier for EcdsaP256Verifier {
+    fn verify_csr(&self, csr: &CertReq) -> Result<()> {
+        let mut pub_key_der_bytes = [0u8; Self::PUB_KEY_DER_SIZE];
+        let pub_key_der = public_key_der(csr, &mut pub_key_der_bytes)?;
+        // Fails unless the key is an id-ecPublicKey on the P-256 curve.
+        let pub_key = p256::ecdsa::VerifyingKey::from_public_key_der(pub_key_der)
+            .map_err(Error::InvalidPublicKeyDer)?;
+
+        let mut csr_info_bytes = [0u8; MAX_CERT_LEN];
+        let csr_info = csr_info_der(csr, &mut csr_info_bytes)?;
+
+        // The signature is a DER-encoded `Ecdsa-Sig-Value`.
+        let sig = p256::ecdsa::Signature::from_der(csr.signature.raw_bytes())
+            .map_err(|_| Error::InvalidSignature)?;
+
+        pub_key
+            .verify(csr_info, &sig)
+            .map_err(|_| Error::InvalidSignature)
+    }
+}
+
+/// Verifies ecdsa-with-SHA384 signatures made with a P-384 key.
+pub struct EcdsaP384Verifier {}
+
+impl EcdsaP384Verifier {
+    const PUB_KEY_DER_SIZE: usize = 120;
+}
+
+impl CertVerifier for EcdsaP384Verifier {
+    fn verify_csr(&self, csr: &CertReq) -> Result<()> {
+        let mut pub_key_der_bytes = [0u8; Self::PUB_KEY_DER_SIZE];
+        let pub_key_der = public_key_der(csr, &mut pub_key_der_bytes)?;
+        // Fails unless the key is an id-ecPublicKey on the P-384 curve.
+        let pub_key = p384::ecdsa::VerifyingKey::from_public_key_der(pub_key_der)
+            .map_err(Error::InvalidPublicKeyDer)?;
+
+        let mut csr_info_bytes = [0u8; MAX_CERT_LEN];
+        let csr_info = csr_info_der(csr, &mut csr_info_bytes)?;
+
+        // The signature is a DER-encoded `Ecdsa-Sig-Value`.
+        let sig = p384::ecdsa::Signature::from_der(csr.signature.raw_bytes())
+            .map_err(|_| Error::InvalidSignature)?;
+
+        pub_key
+            .verify(csr_info, &sig)
+            .map_err(|_| Error::InvalidSignature)
+    }
+}
+
+/// Encodes the subject public key info of `csr` to `buf`, returning the encoded bytes.
+fn public_key_der<'b>(csr: &CertReq, buf: &'b mut [u8]) -> Result<'static, &'b [u8]> {
+    csr.info
+        .public_key
+        .encode_to_slice(buf)
+        .map_err(Error::InvalidDer)
+}
+
+/// Encodes the signed part of `csr` to `buf`, returning the encoded bytes.
+fn csr_info_der<'b>(csr: &CertReq, buf: &'b mut [u8]) -> Result<'static, &'b [u8]> {
+    csr.info.encode_to_slice(buf).map_err(Error::InvalidCertReq)
+}
+
 pub fn verifier_from_algorithm(alg: AlgorithmIdentifier) -> Result<&'static dyn CertVerifier> {
     match alg.oid {
         ed25519::pkcs8::ALGORITHM_OID => {
@@ -58,6 +121,66 @@ pub fn verifier_from_algorithm(alg: AlgorithmIdentifier) -> Result<&'static dyn
             Ok(&*ED25519_V)
         }
 
+        ECDSA_WITH_SHA_256 => {
+            lazy_static! {
+                static ref ECDSA_P256_V: EcdsaP256Verifier = EcdsaP256Verifier {};
+            }
+            Ok(&*ECDSA_P256_V)
+        }
+
+        ECDSA_WITH_SHA_384 => {
+            lazy_static! {
+                static ref ECDSA_P384_V: EcdsaP384Verifier = EcdsaP384Verifier {};
+            }
+            Ok(&*ECDSA_P384_V)
+        }
+
         _ => return Err(Error::UnsupportedAlgorithm(alg)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_P256_CSR: &[u8] = include_bytes!("../../test-workloads/src/bin/test-p256.der");
+    const TEST_P384_CSR: &[u8] = include_bytes!("../../test-workloads/src/bin/test-p384.der");
+
+    #[test]
+    fn verify_p256_csr() {
+        let csr = CertReq::try_from(TEST_P256_CSR).unwrap();
+        assert_eq!(csr.algorithm.oid, ECDSA_WITH_SHA_256);
+        assert!(csr.verify().is_ok());
+    }
+
+    #[test]
+    fn verify_p384_csr() {
+        let csr = CertReq::try_from(TEST_P384_CSR).unwrap();
+        assert_eq!(csr.algorithm.oid, ECDSA_WITH_SHA_384);
+        assert!(csr.verify().is_ok());
+    }
+
+    #[test]
+    fn reject_tampered_csr() {
+        let mut csr_bytes = [0u8; MAX_CERT_LEN];
+        let csr_bytes = &mut csr_bytes[..TEST_P256_CSR.len()];
+        csr_bytes.copy_from_slice(TEST_P256_CSR);
+        // Change the last byte of the common name, which is part of the signed CSR info.
+        let cn = csr_bytes
+            .windows(b"Salus Test TVM".len())
+            .position(|w| w == b"Salus Test TVM")
+            .unwrap();
+        csr_bytes[cn + b"Salus Test TVM".len() - 1] ^= 1;
+        let csr = CertReq::try_from(&csr_bytes[..]).unwrap();
+        assert!(matches!(csr.verify(), Err(Error::InvalidSignature)));
+    }
+
+    #[test]
+    fn reject_mismatched_curve() {
+        let csr = CertReq::try_from(TEST_P384_CSR).unwrap();
+        assert!(matches!(
+            EcdsaP256Verifier {}.verify_csr(&csr),
+            Err(Error::InvalidPublicKeyDer(_))
+        ));
+    }
+}
@@ -2,10 +2,134 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
+use arrayvec::ArrayVec;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::digest_measure::{
+    Sha256Measure, Sha384Measure, Sha512Measure, SHA256_DIGEST_BYTES, SHA384_DIGEST_BYTES,
+    SHA512_DIGEST_BYTES,
+};
+
+/// The maximum number of bytes in a digest made by any of the supported algorithms.
+pub const MAX_DIGEST_BYTES: usize = SHA512_DIGEST_BYTES;
+
+/// A digest made by one of the supported algorithms.
+pub type MeasurementDigest = ArrayVec<u8, MAX_DIGEST_BYTES>;
+
 /// Holds a page measurement and allows updating that measurement when adding pages.
 pub trait DataMeasure {
-    /// Updates the current measurement to include the contents of `page`.
-    fn add_page(&mut self, gpa: u64, page: &[u8]);
+    /// Updates the current measurement to include the contents of `page`. Returns the digest of
+    /// `page` that was folded into the measurement, allowing the addition to be logged and later
+    /// replayed with `add_page_digest()`.
+    fn add_page(&mut self, gpa: u64, page: &[u8]) -> MeasurementDigest;
+    /// Updates the current measurement to include a page at `gpa` whose digest is `page_digest`.
+    fn add_page_digest(&mut self, gpa: u64, page_digest: &[u8]);
+    /// Updates the current measurement to include `data`, which describes part of the measured
+    /// state other than the contents of a page.
+    fn add_data(&mut self, data: &[u8]);
     /// Returns the current measurement.
     fn get_measurement(&self) -> &[u8];
 }
+
+/// The hash algorithms measurements can be made with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    /// Returns the number of bytes in a digest made with this algorithm.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            DigestAlgorithm::Sha256 => SHA256_DIGEST_BYTES,
+            DigestAlgorithm::Sha384 => SHA384_DIGEST_BYTES,
+            DigestAlgorithm::Sha512 => SHA512_DIGEST_BYTES,
+        }
+    }
+
+    /// Returns the digest of the concatenation of `parts`, made with this algorithm.
+    pub fn digest(&self, parts: &[&[u8]]) -> MeasurementDigest {
+        fn digest_with<D: Digest>(parts: &[&[u8]]) -> MeasurementDigest {
+            let mut digest = D::new();
+            for p in parts {
+                digest.update(p);
+            }
+            digest.finalize().into_iter().collect()
+        }
+
+        match self {
+            DigestAlgorithm::Sha256 => digest_with::<Sha256>(parts),
+            DigestAlgorithm::Sha384 => digest_with::<Sha384>(parts),
+            DigestAlgorithm::Sha512 => digest_with::<Sha512>(parts),
+        }
+    }
+}
+
+/// A `DataMeasure` using an algorithm selected at runtime.
+pub enum DynMeasure {
+    /// A Sha256 measurement.
+    Sha256(Sha256Measure),
+    /// A Sha384 measurement.
+    Sha384(Sha384Measure),
+    /// A Sha512 measurement.
+    Sha512(Sha512Measure),
+}
+
+impl DynMeasure {
+    /// Creates a new, zeroed measurement made with `algorithm`.
+    pub fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Sha256 => DynMeasure::Sha256(Sha256Measure::new()),
+            DigestAlgorithm::Sha384 => DynMeasure::Sha384(Sha384Measure::new()),
+            DigestAlgorithm::Sha512 => DynMeasure::Sha512(Sha512Measure::new()),
+        }
+    }
+
+    /// Returns the algorithm the measurement is made with.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        match self {
+            DynMeasure::Sha256(_) => DigestAlgorithm::Sha256,
+            DynMeasure::Sha384(_) => DigestAlgorithm::Sha384,
+            DynMeasure::Sha512(_) => DigestAlgorithm::Sha512,
+        }
+    }
+
+    fn inner(&self) -> &dyn DataMeasure {
+        match self {
+            DynMeasure::Sha256(m) => m,
+            DynMeasure::Sha384(m) => m,
+            DynMeasure::Sha512(m) => m,
+        }
+    }
+
+    fn inner_mut(&mut self) -> &mut dyn DataMeasure {
+        match self {
+            DynMeasure::Sha256(m) => m,
+            DynMeasure::Sha384(m) => m,
+            DynMeasure::Sha512(m) => m,
+        }
+    }
+}
+
+impl DataMeasure for DynMeasure {
+    fn add_page(&mut self, gpa: u64, page: &[u8]) -> MeasurementDigest {
+        self.inner_mut().add_page(gpa, page)
+    }
+
+    fn add_page_digest(&mut self, gpa: u64, page_digest: &[u8]) {
+        self.inner_mut().add_page_digest(gpa, page_digest)
+    }
+
+    fn add_data(&mut self, data: &[u8]) {
+        self.inner_mut().add_data(data)
+    }
+
+    fn get_measurement(&self) -> &[u8] {
+        self.inner().get_measurement()
+    }
+}
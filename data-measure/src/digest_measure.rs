@@ -0,0 +1,150 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use core::marker::PhantomData;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::data_measure::{DataMeasure, MeasurementDigest};
+
+/// The number of bytes in a sha256 digest.
+pub const SHA256_DIGEST_BYTES: usize = 32;
+/// The number of bytes in a sha384 digest.
+pub const SHA384_DIGEST_BYTES: usize = 48;
+/// The number of bytes in a sha512 digest.
+pub const SHA512_DIGEST_BYTES: usize = 64;
+
+/// Maintains a measurement of the pages and other data added, made with the hash `D`. Each page
+/// is folded into the measurement as `D(measurement || gpa || D(page))`, with `gpa` in
+/// little-endian byte order, and other data as `D(measurement || D(data))`.
+pub struct DigestMeasure<D: Digest> {
+    measurement: MeasurementDigest,
+    phantom: PhantomData<D>,
+}
+
+/// A Sha256 measurement.
+pub type Sha256Measure = DigestMeasure<Sha256>;
+/// A Sha384 measurement.
+pub type Sha384Measure = DigestMeasure<Sha384>;
+/// A Sha512 measurement.
+pub type Sha512Measure = DigestMeasure<Sha512>;
+
+impl<D: Digest> DataMeasure for DigestMeasure<D> {
+    fn add_page(&mut self, gpa: u64, bytes: &[u8]) -> MeasurementDigest {
+        let page_digest: MeasurementDigest = D::digest(bytes).into_iter().collect();
+        self.add_page_digest(gpa, &page_digest);
+        page_digest
+    }
+
+    fn add_page_digest(&mut self, gpa: u64, page_digest: &[u8]) {
+        let mut digest = D::new();
+        digest.update(&self.measurement);
+        digest.update(gpa.to_le_bytes());
+        digest.update(page_digest);
+        self.measurement = digest.finalize().into_iter().collect();
+    }
+
+    fn add_data(&mut self, data: &[u8]) {
+        let mut digest = D::new();
+        digest.update(&self.measurement);
+        digest.update(D::digest(data));
+        self.measurement = digest.finalize().into_iter().collect();
+    }
+
+    fn get_measurement(&self) -> &[u8] {
+        &self.measurement
+    }
+}
+
+impl<D: Digest> DigestMeasure<D> {
+    /// Creates a new, zeroed measurement.
+    pub fn new() -> Self {
+        Self {
+            measurement: (0..<D as Digest>::output_size()).map(|_| 0).collect(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<D: Digest> Default for DigestMeasure<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> MeasurementDigest {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Measures a page of 0x5a bytes at 0x8000_0000 followed by the data "abc" with `m`, checking
+    /// the page digest and the measurement after each step against the expected values.
+    fn check_measure<D: Digest>(page_digest: &str, after_page: &str, after_data: &str) {
+        let mut m = DigestMeasure::<D>::new();
+        assert_eq!(m.get_measurement().len(), <D as Digest>::output_size());
+        assert!(m.get_measurement().iter().all(|&b| b == 0));
+        assert_eq!(m.add_page(0x8000_0000, &[0x5a; 4096]), hex(page_digest));
+        assert_eq!(m.get_measurement(), &hex(after_page)[..]);
+        m.add_data(b"abc");
+        assert_eq!(m.get_measurement(), &hex(after_data)[..]);
+
+        // Adding the page by its digest gives the same measurement.
+        let mut replayed = DigestMeasure::<D>::new();
+        replayed.add_page_digest(0x8000_0000, &hex(page_digest));
+        assert_eq!(replayed.get_measurement(), &hex(after_page)[..]);
+    }
+
+    #[test]
+    fn sha256_measure() {
+        assert_eq!(<Sha256 as Digest>::output_size(), SHA256_DIGEST_BYTES);
+        check_measure::<Sha256>(
+            "f302957da5220938a7e3e51a8718c79b9e00dc13ab2119e8cfc978f041720382",
+            "376a44689c1425c6c34b0009c46d4cca144882c1f816b4911fdebb44c92e0277",
+            "0ed82e68da666fe06d407f48afa7d93292491f438a02628498a03853e0502c93",
+        );
+    }
+
+    #[test]
+    fn sha384_measure() {
+        assert_eq!(<Sha384 as Digest>::output_size(), SHA384_DIGEST_BYTES);
+        check_measure::<Sha384>(
+            concat!(
+                "0057381dd99f9b05400d52117455965c1cc0f539df13eb85",
+                "12a1f8bbef0878eb40315edf7deada7cf28b2851fc5cd072"
+            ),
+            concat!(
+                "6f951facfb3e9cb14ac692113788f9c020a7f3a1941bf8e3",
+                "10420a35f2e1e8170a3fc280f22bf740898cec131a3e5dd5"
+            ),
+            concat!(
+                "5ac8aedc39c44800a5036d5b3029119b0a9ddf10323478db",
+                "881d48ac6ad878ae63f9212a08eea9cd47bd47d93265f164"
+            ),
+        );
+    }
+
+    #[test]
+    fn sha512_measure() {
+        assert_eq!(<Sha512 as Digest>::output_size(), SHA512_DIGEST_BYTES);
+        check_measure::<Sha512>(
+            concat!(
+                "417219bb8bfe28035ebf024e5ac4808520670b469785c14d0c32ab8658fbc611",
+                "fbc7292aaeb8b49d9d1328f435d9e8d56f0ad0c91ecc741ff37d9ce74daf22e5"
+            ),
+            concat!(
+                "6f1582da4004d59e5d55005dd3928c857efc5f81054bfecab25f5c00b896e310",
+                "2dd95aea9aa67d10f0af3021a67fd427d78584ec7b2d52a4e53bc5cc94a8de04"
+            ),
+            concat!(
+                "a503fd16cc9ee3a9bacbfd9b176529f548d59d32cda2064c4fea67f8fd95479f",
+                "d1092a789fef66cb85e9f8ff2551d57193c410dec7c5067bf4d70e8db917e92a"
+            ),
+        );
+    }
+}
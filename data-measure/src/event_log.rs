@@ -0,0 +1,321 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A log of the events that make up a VM's measurements, from which a verifier can replay the
+//! measurements. The log is a sequence of TLV-encoded events in the style of the TCG Canonical
+//! Event Log:
+//!
+//! ```text
+//! type:   u8
+//! length: u32 (little-endian), the number of bytes in value
+//! value:  [u8; length]
+//! ```
+//!
+//! All integers in event values are little-endian.
+
+use crate::data_measure::MAX_DIGEST_BYTES;
+
+/// The length of the type and length fields of an encoded event.
+pub const EVENT_HEADER_BYTES: usize = 5;
+
+/// The maximum length of an encoded event.
+pub const MAX_EVENT_BYTES: usize = EVENT_HEADER_BYTES + 16 + MAX_DIGEST_BYTES;
+
+const EVENT_TYPE_MEASURED_PAGE: u8 = 1;
+const EVENT_TYPE_RUNTIME_EXTEND: u8 = 2;
+const EVENT_TYPE_MEASURED_DATA: u8 = 3;
+const EVENT_TYPE_TRUNCATED: u8 = 0xff;
+
+/// An event in a measurement log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeasurementEvent<'a> {
+    /// A page was added to the VM's static measurement with `DataMeasure::add_page()`. Huge pages
+    /// are logged as their constituent 4kB pages, with `page_size` holding the size of the page
+    /// they were part of. `page_size` isn't part of the measurement.
+    ///
+    /// ```text
+    /// type = 1
+    /// value = gpa: u64 || page_size: u64 || digest
+    /// ```
+    MeasuredPage {
+        /// The guest physical address of the page.
+        gpa: u64,
+        /// The size of the page that was mapped, in bytes.
+        page_size: u64,
+        /// The digest of the page contents.
+        digest: &'a [u8],
+    },
+    /// A runtime measurement register was extended with `digest`.
+    ///
+    /// ```text
+    /// type = 2
+    /// value = index: u32 || digest
+    /// ```
+    RuntimeExtend {
+        /// The index of the extended register.
+        index: u32,
+        /// The digest the register was extended with.
+        digest: &'a [u8],
+    },
+    /// `data` was added to the VM's static measurement with `DataMeasure::add_data()`. `data` is
+    /// one of the encodings defined in `crate::initial_state`.
+    ///
+    /// ```text
+    /// type = 3
+    /// value = data
+    /// ```
+    MeasuredData {
+        /// The measured data.
+        data: &'a [u8],
+    },
+    /// The log ran out of space. Events after this one weren't logged, though they were still
+    /// measured.
+    ///
+    /// ```text
+    /// type = 0xff
+    /// value = (empty)
+    /// ```
+    Truncated,
+}
+
+impl<'a> MeasurementEvent<'a> {
+    /// Returns the length of the encoding of this event.
+    pub fn encoded_len(&self) -> usize {
+        use MeasurementEvent::*;
+        EVENT_HEADER_BYTES
+            + match self {
+                MeasuredPage { digest, .. } => 16 + digest.len(),
+                RuntimeExtend { digest, .. } => 4 + digest.len(),
+                MeasuredData { data } => data.len(),
+                Truncated => 0,
+            }
+    }
+
+    /// Encodes this event to the start of `buf`. Returns the encoded bytes, or `None` if `buf` is
+    /// too small to hold the event.
+    pub fn encode<'b>(&self, buf: &'b mut [u8]) -> Option<&'b [u8]> {
+        use MeasurementEvent::*;
+        let len = self.encoded_len();
+        let buf = buf.get_mut(..len)?;
+        let (header, value) = buf.split_at_mut(EVENT_HEADER_BYTES);
+        let event_type = match self {
+            MeasuredPage {
+                gpa,
+                page_size,
+                digest,
+            } => {
+                value[..8].copy_from_slice(&gpa.to_le_bytes());
+                value[8..16].copy_from_slice(&page_size.to_le_bytes());
+                value[16..].copy_from_slice(digest);
+                EVENT_TYPE_MEASURED_PAGE
+            }
+            RuntimeExtend { index, digest } => {
+                value[..4].copy_from_slice(&index.to_le_bytes());
+                value[4..].copy_from_slice(digest);
+                EVENT_TYPE_RUNTIME_EXTEND
+            }
+            MeasuredData { data } => {
+                value.copy_from_slice(data);
+                EVENT_TYPE_MEASURED_DATA
+            }
+            Truncated => EVENT_TYPE_TRUNCATED,
+        };
+        header[0] = event_type;
+        header[1..].copy_from_slice(&((len - EVENT_HEADER_BYTES) as u32).to_le_bytes());
+        Some(buf)
+    }
+
+    /// Decodes the event at the start of `bytes`. Returns the event and the remaining bytes, or
+    /// `None` if `bytes` doesn't start with a valid event. Events with digests longer than
+    /// `MAX_DIGEST_BYTES` are invalid.
+    pub fn decode(bytes: &'a [u8]) -> Option<(Self, &'a [u8])> {
+        use MeasurementEvent::*;
+        let header = bytes.get(..EVENT_HEADER_BYTES)?;
+        let len = u32::from_le_bytes(header[1..].try_into().unwrap()) as usize;
+        let end = EVENT_HEADER_BYTES.checked_add(len)?;
+        let value = bytes.get(EVENT_HEADER_BYTES..end)?;
+        let digest_len_ok = |prefix: usize| len > prefix && len - prefix <= MAX_DIGEST_BYTES;
+        let event = match header[0] {
+            EVENT_TYPE_MEASURED_PAGE if digest_len_ok(16) => MeasuredPage {
+                gpa: u64::from_le_bytes(value[..8].try_into().unwrap()),
+                page_size: u64::from_le_bytes(value[8..16].try_into().unwrap()),
+                digest: &value[16..],
+            },
+            EVENT_TYPE_RUNTIME_EXTEND if digest_len_ok(4) => RuntimeExtend {
+                index: u32::from_le_bytes(value[..4].try_into().unwrap()),
+                digest: &value[4..],
+            },
+            EVENT_TYPE_MEASURED_DATA => MeasuredData { data: value },
+            EVENT_TYPE_TRUNCATED if len == 0 => Truncated,
+            _ => return None,
+        };
+        Some((event, &bytes[end..]))
+    }
+}
+
+/// Iterates over the events in an encoded measurement log. Iteration stops at the end of the log
+/// or at the first invalid event.
+pub struct EventLogIter<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> EventLogIter<'a> {
+    /// Creates an iterator over the events encoded in `bytes`.
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+}
+
+impl<'a> Iterator for EventLogIter<'a> {
+    type Item = MeasurementEvent<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (event, rest) = MeasurementEvent::decode(self.bytes)?;
+        self.bytes = rest;
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayVec;
+
+    const PAGE_DIGEST: [u8; 32] = [0xaa; 32];
+    const EXTEND_DIGEST: [u8; 48] = [0x55; 48];
+
+    fn test_events() -> [MeasurementEvent<'static>; 4] {
+        use MeasurementEvent::*;
+        [
+            MeasuredPage {
+                gpa: 0x8020_0000,
+                page_size: 0x20_0000,
+                digest: &PAGE_DIGEST,
+            },
+            RuntimeExtend {
+                index: 2,
+                digest: &EXTEND_DIGEST,
+            },
+            MeasuredData { data: b"abc" },
+            Truncated,
+        ]
+    }
+
+    // The encodings of `test_events()`, in order.
+    fn golden_encodings() -> [ArrayVec<u8, MAX_EVENT_BYTES>; 4] {
+        let mut page = ArrayVec::new();
+        page.try_extend_from_slice(&[0x01, 0x30, 0x00, 0x00, 0x00])
+            .unwrap();
+        page.try_extend_from_slice(&[0x00, 0x00, 0x20, 0x80, 0x00, 0x00, 0x00, 0x00])
+            .unwrap();
+        page.try_extend_from_slice(&[0x00, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00])
+            .unwrap();
+        page.try_extend_from_slice(&PAGE_DIGEST).unwrap();
+        let mut extend = ArrayVec::new();
+        extend
+            .try_extend_from_slice(&[0x02, 0x34, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00])
+            .unwrap();
+        extend.try_extend_from_slice(&EXTEND_DIGEST).unwrap();
+        let data = [0x03, 0x03, 0x00, 0x00, 0x00, b'a', b'b', b'c'];
+        let truncated = [0xff, 0x00, 0x00, 0x00, 0x00];
+        [
+            page,
+            extend,
+            data.into_iter().collect(),
+            truncated.into_iter().collect(),
+        ]
+    }
+
+    #[test]
+    fn golden_bytes() {
+        for (event, golden) in test_events().iter().zip(golden_encodings()) {
+            let mut buf = [0u8; MAX_EVENT_BYTES];
+            assert_eq!(event.encoded_len(), golden.len());
+            assert_eq!(event.encode(&mut buf).unwrap(), &golden[..]);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut log = [0u8; 4 * MAX_EVENT_BYTES];
+        let mut len = 0;
+        for event in test_events() {
+            len += event.encode(&mut log[len..]).unwrap().len();
+        }
+        for event in test_events() {
+            let mut buf = [0u8; MAX_EVENT_BYTES];
+            let encoded = event.encode(&mut buf).unwrap();
+            assert_eq!(MeasurementEvent::decode(encoded), Some((event, &[][..])));
+        }
+        assert!(EventLogIter::new(&log[..len]).eq(test_events()));
+    }
+
+    #[test]
+    fn encode_too_small() {
+        for event in test_events() {
+            let mut buf = [0u8; MAX_EVENT_BYTES];
+            assert!(event.encode(&mut buf[..event.encoded_len() - 1]).is_none());
+        }
+    }
+
+    #[test]
+    fn reject_short() {
+        for golden in golden_encodings() {
+            for len in 0..golden.len() {
+                assert!(MeasurementEvent::decode(&golden[..len]).is_none());
+            }
+        }
+        // Page and extend events must hold a digest.
+        let mut page = [0u8; EVENT_HEADER_BYTES + 16];
+        page[0] = EVENT_TYPE_MEASURED_PAGE;
+        page[1] = 16;
+        assert!(MeasurementEvent::decode(&page).is_none());
+        let extend = [EVENT_TYPE_RUNTIME_EXTEND, 4, 0, 0, 0, 1, 0, 0, 0];
+        assert!(MeasurementEvent::decode(&extend).is_none());
+    }
+
+    #[test]
+    fn reject_oversized() {
+        // A length that runs past the end of the input.
+        let huge = [EVENT_TYPE_MEASURED_DATA, 0xff, 0xff, 0xff, 0xff, 0];
+        assert!(MeasurementEvent::decode(&huge).is_none());
+        // Digests longer than any supported algorithm's.
+        let mut buf = [0u8; MAX_EVENT_BYTES + 1];
+        let page = MeasurementEvent::MeasuredPage {
+            gpa: 0,
+            page_size: 0x1000,
+            digest: &[0; MAX_DIGEST_BYTES + 1],
+        };
+        assert!(MeasurementEvent::decode(page.encode(&mut buf).unwrap()).is_none());
+        let extend = MeasurementEvent::RuntimeExtend {
+            index: 0,
+            digest: &[0; MAX_DIGEST_BYTES + 1],
+        };
+        assert!(MeasurementEvent::decode(extend.encode(&mut buf).unwrap()).is_none());
+        // Truncation events have no value.
+        let truncated = [EVENT_TYPE_TRUNCATED, 1, 0, 0, 0, 0];
+        assert!(MeasurementEvent::decode(&truncated).is_none());
+    }
+
+    #[test]
+    fn reject_unknown_type() {
+        for event_type in [0, 4, 0x80, 0xfe] {
+            let event = [event_type, 1, 0, 0, 0, 0];
+            assert!(MeasurementEvent::decode(&event).is_none());
+        }
+    }
+
+    #[test]
+    fn iter_stops_at_invalid_event() {
+        let mut log = [0u8; 2 * MAX_EVENT_BYTES];
+        let events = test_events();
+        let mut len = events[2].encode(&mut log).unwrap().len();
+        log[len] = 0x42;
+        len += EVENT_HEADER_BYTES;
+        len += events[3].encode(&mut log[len..]).unwrap().len();
+        let mut iter = EventLogIter::new(&log[..len]);
+        assert_eq!(iter.next(), Some(events[2]));
+        assert_eq!(iter.next(), None);
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Canonical encodings of the parts of a TVM's initial state other than the contents of its
+//! memory. These are added to the TVM's measurement with `DataMeasure::add_data()`. A `PagePerms`
+//! is added each time the permissions of its measured pages are changed, in order with the pages
+//! themselves. When it's finalized, its `TvmConfig` is added, then its `TvmSigner`, then the
+//! `MmioRegion` of each of its emulated MMIO regions in order of address, then the `VcpuState` of
+//! each of its vCPUs in order of vCPU ID.
+//!
+//! Each encoding starts with a `u64` tag identifying what it describes, followed by its fields.
+//! All integers are little-endian.
+
+const TVM_CONFIG_TAG: u64 = 1;
+const VCPU_STATE_TAG: u64 = 2;
+const PAGE_PERMS_TAG: u64 = 3;
+const MMIO_REGION_TAG: u64 = 4;
+const TVM_SIGNER_TAG: u64 = 5;
+
+/// The length of an encoded `TvmConfig`.
+pub const TVM_CONFIG_BYTES: usize = 16;
+
+/// The length of an encoded `VcpuState`.
+pub const VCPU_STATE_BYTES: usize = 32;
+
+/// The length of an encoded `PagePerms`.
+pub const PAGE_PERMS_BYTES: usize = 32;
+
+/// The length of an encoded `MmioRegion`.
+pub const MMIO_REGION_BYTES: usize = 24;
+
+/// The length of the identity of the signer of a TVM's image.
+pub const SIGNER_ID_BYTES: usize = 32;
+
+/// The length of an encoded `TvmSigner`.
+pub const TVM_SIGNER_BYTES: usize = 16 + SIGNER_ID_BYTES;
+
+/// The configuration of a TVM. The host physical addresses used to create the TVM and the number
+/// of vCPUs it has room for don't affect the TVM once it's finalized, so they aren't measured.
+///
+/// ```text
+/// tag = 1: u64 || num_vcpus: u64
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TvmConfig {
+    /// The number of vCPUs that were added to the TVM.
+    pub num_vcpus: u64,
+}
+
+impl TvmConfig {
+    /// Returns the canonical encoding of the configuration.
+    pub fn encode(&self) -> [u8; TVM_CONFIG_BYTES] {
+        let mut bytes = [0u8; TVM_CONFIG_BYTES];
+        bytes[..8].copy_from_slice(&TVM_CONFIG_TAG.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.num_vcpus.to_le_bytes());
+        bytes
+    }
+}
+
+/// The initial register state of a TVM vCPU that the host is allowed to set.
+///
+/// ```text
+/// tag = 2: u64 || vcpu_id: u64 || entry_pc: u64 || entry_arg: u64
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VcpuState {
+    /// The ID of the vCPU, which it's also passed in A0.
+    pub vcpu_id: u64,
+    /// The address the vCPU starts executing at.
+    pub entry_pc: u64,
+    /// The argument passed to the vCPU in A1.
+    pub entry_arg: u64,
+}
+
+impl VcpuState {
+    /// Returns the canonical encoding of the vCPU state.
+    pub fn encode(&self) -> [u8; VCPU_STATE_BYTES] {
+        let mut bytes = [0u8; VCPU_STATE_BYTES];
+        bytes[..8].copy_from_slice(&VCPU_STATE_TAG.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.vcpu_id.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.entry_pc.to_le_bytes());
+        bytes[24..].copy_from_slice(&self.entry_arg.to_le_bytes());
+        bytes
+    }
+}
+
+/// A change to the permissions a TVM's pages are mapped with, made before it was finalized.
+///
+/// ```text
+/// tag = 3: u64 || gpa: u64 || num_pages: u64 || perms: u64
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PagePerms {
+    /// The guest physical address of the first 4kB page in the range.
+    pub gpa: u64,
+    /// The number of 4kB pages in the range.
+    pub num_pages: u64,
+    /// The new permissions of the pages, as the `TsmPagePerms` value passed to `TvmProtectPages`.
+    pub perms: u64,
+}
+
+impl PagePerms {
+    /// Returns the canonical encoding of the permission change.
+    pub fn encode(&self) -> [u8; PAGE_PERMS_BYTES] {
+        let mut bytes = [0u8; PAGE_PERMS_BYTES];
+        bytes[..8].copy_from_slice(&PAGE_PERMS_TAG.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.gpa.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.num_pages.to_le_bytes());
+        bytes[24..].copy_from_slice(&self.perms.to_le_bytes());
+        bytes
+    }
+}
+
+/// A region of a TVM's guest physical address space whose accesses are emulated by the host,
+/// declared before it was finalized.
+///
+/// ```text
+/// tag = 4: u64 || gpa: u64 || num_pages: u64
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MmioRegion {
+    /// The guest physical address of the first 4kB page in the region.
+    pub gpa: u64,
+    /// The number of 4kB pages in the region.
+    pub num_pages: u64,
+}
+
+impl MmioRegion {
+    /// Returns the canonical encoding of the region.
+    pub fn encode(&self) -> [u8; MMIO_REGION_BYTES] {
+        let mut bytes = [0u8; MMIO_REGION_BYTES];
+        bytes[..8].copy_from_slice(&MMIO_REGION_TAG.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.gpa.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.num_pages.to_le_bytes());
+        bytes
+    }
+}
+
+/// The signer of a TVM's image and the security version of the image, as given by the host when
+/// the TVM was created.
+///
+/// ```text
+/// tag = 5: u64 || svn: u64 || signer_id: [u8; 32]
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TvmSigner {
+    /// The security version number of the image.
+    pub svn: u64,
+    /// The identity of the signer, or zero if the image isn't signed.
+    pub signer_id: [u8; SIGNER_ID_BYTES],
+}
+
+impl TvmSigner {
+    /// Returns true if the image has a signer.
+    pub fn is_signed(&self) -> bool {
+        self.signer_id != [0; SIGNER_ID_BYTES]
+    }
+
+    /// Returns the canonical encoding of the signer.
+    pub fn encode(&self) -> [u8; TVM_SIGNER_BYTES] {
+        let mut bytes = [0u8; TVM_SIGNER_BYTES];
+        bytes[..8].copy_from_slice(&TVM_SIGNER_TAG.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.svn.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.signer_id);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tvm_config_golden_bytes() {
+        let config = TvmConfig { num_vcpus: 3 };
+        assert_eq!(
+            config.encode(),
+            [
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // tag
+                0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // num_vcpus
+            ]
+        );
+    }
+
+    #[test]
+    fn vcpu_state_golden_bytes() {
+        let state = VcpuState {
+            vcpu_id: 1,
+            entry_pc: 0x8020_0000,
+            entry_arg: 0x0123_4567_89ab_cdef,
+        };
+        assert_eq!(
+            state.encode(),
+            [
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // tag
+                0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // vcpu_id
+                0x00, 0x00, 0x20, 0x80, 0x00, 0x00, 0x00, 0x00, // entry_pc
+                0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01, // entry_arg
+            ]
+        );
+    }
+
+    #[test]
+    fn page_perms_golden_bytes() {
+        let perms = PagePerms {
+            gpa: 0x8000_1000,
+            num_pages: 16,
+            perms: 2,
+        };
+        assert_eq!(
+            perms.encode(),
+            [
+                0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // tag
+                0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, // gpa
+                0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // num_pages
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // perms
+            ]
+        );
+    }
+
+    #[test]
+    fn mmio_region_golden_bytes() {
+        let region = MmioRegion {
+            gpa: 0x1000_0000,
+            num_pages: 2,
+        };
+        assert_eq!(
+            region.encode(),
+            [
+                0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // tag
+                0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, // gpa
+                0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // num_pages
+            ]
+        );
+    }
+
+    #[test]
+    fn tvm_signer_golden_bytes() {
+        let mut signer_id = [0u8; SIGNER_ID_BYTES];
+        signer_id[0] = 0xaa;
+        signer_id[31] = 0x55;
+        let signer = TvmSigner { svn: 7, signer_id };
+        assert!(signer.is_signed());
+        assert_eq!(
+            signer.encode(),
+            [
+                0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // tag
+                0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // svn
+                0xaa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // signer_id[0..8]
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // signer_id[8..16]
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // signer_id[16..24]
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x55, // signer_id[24..32]
+            ]
+        );
+        let unsigned = TvmSigner {
+            svn: 0,
+            signer_id: [0; SIGNER_ID_BYTES],
+        };
+        assert!(!unsigned.is_signed());
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::digest_measure::SHA256_DIGEST_BYTES;
+use sha2::{Digest, Sha256};
+
+/// The HMAC block size of SHA-256.
+pub const SHA256_BLOCK_BYTES: usize = 64;
+
+/// The maximum number of bytes of output keying material `hkdf_expand()` can produce.
+pub const MAX_HKDF_OUTPUT_BYTES: usize = 255 * SHA256_DIGEST_BYTES;
+
+/// Overwrites `buf` with zeroes in a way the compiler won't elide, for scrubbing secrets that are
+/// no longer needed.
+pub fn zeroize(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        // Safe since `b` is a valid, aligned reference to a byte.
+        unsafe { core::ptr::write_volatile(b, 0) };
+    }
+    core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+}
+
+/// Computes HMAC-SHA256 (RFC 2104) with `key` over the concatenation of `parts`. Keys longer than
+/// the SHA-256 block size are hashed first.
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; SHA256_DIGEST_BYTES] {
+    let mut block_key = [0u8; SHA256_BLOCK_BYTES];
+    if key.len() > SHA256_BLOCK_BYTES {
+        block_key[..SHA256_DIGEST_BYTES].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block_key[..key.len()].copy_from_slice(key);
+    }
+    let mut ipad = [0x36u8; SHA256_BLOCK_BYTES];
+    let mut opad = [0x5cu8; SHA256_BLOCK_BYTES];
+    for (i, k) in block_key.iter().enumerate() {
+        ipad[i] ^= k;
+        opad[i] ^= k;
+    }
+    let mut inner = Sha256::new();
+    inner.update(ipad);
+    for p in parts {
+        inner.update(p);
+    }
+    let mut outer = Sha256::new();
+    outer.update(opad);
+    outer.update(inner.finalize());
+    zeroize(&mut block_key);
+    zeroize(&mut ipad);
+    zeroize(&mut opad);
+    outer.finalize().into()
+}
+
+/// The HKDF-SHA256 (RFC 5869) extract step. Returns a pseudorandom key derived from `ikm` with
+/// `salt`, which may be empty.
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; SHA256_DIGEST_BYTES] {
+    hmac_sha256(salt, &[ikm])
+}
+
+/// The HKDF-SHA256 (RFC 5869) expand step. Fills `okm` with keying material derived from the
+/// pseudorandom key `prk` and `info`. Panics if `okm` is longer than `MAX_HKDF_OUTPUT_BYTES`.
+pub fn hkdf_expand(prk: &[u8], info: &[u8], okm: &mut [u8]) {
+    assert!(okm.len() <= MAX_HKDF_OUTPUT_BYTES);
+    let mut t = [0u8; SHA256_DIGEST_BYTES];
+    for (i, chunk) in okm.chunks_mut(SHA256_DIGEST_BYTES).enumerate() {
+        // The previous block is omitted from the first.
+        let prev: &[u8] = if i == 0 { &[] } else { &t };
+        t = hmac_sha256(prk, &[prev, info, &[i as u8 + 1]]);
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+    zeroize(&mut t);
+}
+
+/// Derives `okm.len()` bytes of keying material from `ikm` using HKDF-SHA256 (RFC 5869) with the
+/// given `salt` and `info`.
+pub fn hkdf_sha256(ikm: &[u8], salt: &[u8], info: &[u8], okm: &mut [u8]) {
+    let mut prk = hkdf_extract(salt, ikm);
+    hkdf_expand(&prk, info, okm);
+    zeroize(&mut prk);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayVec;
+
+    fn hex(s: &str) -> ArrayVec<u8, 256> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn bytes(range: core::ops::Range<u8>) -> ArrayVec<u8, 256> {
+        range.collect()
+    }
+
+    #[test]
+    fn hmac_rfc4231() {
+        // Test cases 1-4, 6 and 7 from RFC 4231. Case 5 covers truncated output, which we don't
+        // support.
+        let long_key = [0xaa; 131];
+        let cases: [(&[u8], &[u8], &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                &bytes(1..26),
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                &long_key,
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                &long_key,
+                b"This is a test using a larger than block-size key and a larger than block-size \
+                  data. The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, data, mac) in cases {
+            assert_eq!(hmac_sha256(key, &[data])[..], hex(mac)[..]);
+        }
+    }
+
+    #[test]
+    fn hmac_parts_concatenated() {
+        let mac = hmac_sha256(b"Jefe", &[b"what do ya ", b"", b"want for nothing?"]);
+        assert_eq!(
+            mac,
+            hmac_sha256(b"Jefe", &[b"what do ya want for nothing?"])
+        );
+    }
+
+    #[test]
+    fn hkdf_rfc5869() {
+        // Test cases 1-3 from RFC 5869.
+        let cases = [
+            (
+                ArrayVec::from_iter([0x0b; 22]),
+                bytes(0x00..0x0d),
+                bytes(0xf0..0xfa),
+                "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5",
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865",
+            ),
+            (
+                bytes(0x00..0x50),
+                bytes(0x60..0xb0),
+                (0xb0..=0xff).collect(),
+                "06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244",
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97c59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db71cc30c58179ec3e87c14c01d5c1f3434f1d87",
+            ),
+            (
+                ArrayVec::from_iter([0x0b; 22]),
+                ArrayVec::new(),
+                ArrayVec::new(),
+                "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04",
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8",
+            ),
+        ];
+        for (ikm, salt, info, prk, okm) in cases {
+            assert_eq!(hkdf_extract(&salt, &ikm)[..], hex(prk)[..]);
+            let expected = hex(okm);
+            let mut out = [0u8; 82];
+            let out = &mut out[..expected.len()];
+            hkdf_sha256(&ikm, &salt, &info, out);
+            assert_eq!(out, &expected[..]);
+        }
+    }
+
+    #[test]
+    fn zeroize_clears() {
+        let mut buf = [0xa5u8; 40];
+        zeroize(&mut buf);
+        assert_eq!(buf, [0; 40]);
+    }
+}
@@ -8,5 +8,13 @@
 
 /// Base trait for measuring pages as they are added to VMs.
 pub mod data_measure;
-/// A Sha256-based implementation of DataMeasure.
-pub mod sha256;
+/// An implementation of DataMeasure generic over the SHA-2 hashes.
+pub mod digest_measure;
+/// Log of the events that make up a measurement.
+pub mod event_log;
+/// Canonical encodings of the non-memory initial state of a TVM.
+pub mod initial_state;
+/// HMAC and HKDF over SHA-256, for deriving keys from measurements.
+pub mod kdf;
+/// Runtime-extended measurement registers.
+pub mod runtime;
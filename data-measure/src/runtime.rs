@@ -0,0 +1,110 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::data_measure::{DigestAlgorithm, MeasurementDigest};
+
+/// The number of runtime measurement registers each VM has.
+pub const NUM_RUNTIME_MEASUREMENT_REGISTERS: usize = 4;
+
+/// A set of measurement registers that are extended with digests at runtime, rather than
+/// measuring the data added when building a VM.
+pub struct RuntimeMeasurements {
+    algorithm: DigestAlgorithm,
+    registers: [MeasurementDigest; NUM_RUNTIME_MEASUREMENT_REGISTERS],
+}
+
+impl RuntimeMeasurements {
+    /// Creates a new set of zeroed registers, extended using `algorithm`.
+    pub fn new(algorithm: DigestAlgorithm) -> Self {
+        let zero: MeasurementDigest = (0..algorithm.digest_len()).map(|_| 0).collect();
+        RuntimeMeasurements {
+            algorithm,
+            registers: [(); NUM_RUNTIME_MEASUREMENT_REGISTERS].map(|_| zero.clone()),
+        }
+    }
+
+    /// Returns the algorithm the registers are extended with.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    /// Extends the register at `index` with `digest`, setting it to the digest of its previous
+    /// value followed by `digest`. Returns `None` if `index` isn't a valid register or `digest`
+    /// isn't the length of a digest made with the registers' algorithm.
+    pub fn extend(&mut self, index: usize, digest: &[u8]) -> Option<()> {
+        if digest.len() != self.algorithm.digest_len() {
+            return None;
+        }
+        let register = self.registers.get_mut(index)?;
+        *register = self.algorithm.digest(&[&register[..], digest]);
+        Some(())
+    }
+
+    /// Returns the current values of the registers.
+    pub fn registers(&self) -> &[MeasurementDigest] {
+        &self.registers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> MeasurementDigest {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn extend_chains() {
+        let mut rm = RuntimeMeasurements::new(DigestAlgorithm::Sha256);
+        assert!(rm.registers().iter().all(|r| r[..] == [0; 32]));
+        assert!(rm.extend(1, &[1; 32]).is_some());
+        assert_eq!(
+            rm.registers()[1],
+            hex("5c85955f709283ecce2b74f1b1552918819f390911816e7bb466805a38ab87f3")
+        );
+        assert!(rm.extend(1, &[2; 32]).is_some());
+        assert_eq!(
+            rm.registers()[1],
+            hex("a7f2fad943905535b10ccf63c832802ed84eaffb15e4fb6bee86a817c35eb833")
+        );
+        // The other registers are unaffected.
+        for i in [0, 2, 3] {
+            assert_eq!(rm.registers()[i][..], [0; 32]);
+        }
+
+        let mut rm = RuntimeMeasurements::new(DigestAlgorithm::Sha384);
+        assert!(rm.extend(3, &[3; 48]).is_some());
+        assert_eq!(
+            rm.registers()[3],
+            hex(concat!(
+                "a99c07d62c77f42baa0b4b4781ef7c1bb1985120f6d1770c",
+                "d01cd96dabc4bdc57f4b6fe2851ce85520dd3b368ef2d088"
+            ))
+        );
+    }
+
+    #[test]
+    fn extend_bad_index() {
+        let mut rm = RuntimeMeasurements::new(DigestAlgorithm::Sha256);
+        assert!(rm
+            .extend(NUM_RUNTIME_MEASUREMENT_REGISTERS, &[1; 32])
+            .is_none());
+        assert!(rm.extend(usize::MAX, &[1; 32]).is_none());
+        assert!(rm.registers().iter().all(|r| r[..] == [0; 32]));
+    }
+
+    #[test]
+    fn extend_wrong_digest_length() {
+        let mut rm = RuntimeMeasurements::new(DigestAlgorithm::Sha384);
+        assert!(rm.extend(0, &[1; 32]).is_none());
+        assert!(rm.extend(0, &[1; 64]).is_none());
+        assert!(rm.extend(0, &[]).is_none());
+        assert!(rm.registers().iter().all(|r| r[..] == [0; 48]));
+        assert!(rm.extend(0, &[1; 48]).is_some());
+    }
+}
@@ -29,13 +29,51 @@ impl CpuId {
     }
 }
 
+/// The virtual memory systems a CPU's MMU can support, as reported by the 'mmu-type' property in
+/// the device-tree. Each mode implies support for the smaller ones, so they're ordered by the size
+/// of the address space they translate.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord)]
+pub enum MmuType {
+    /// 39-bit virtual addressing, and Sv39x4 for guest-stage translation.
+    Sv39,
+    /// 48-bit virtual addressing, and Sv48x4 for guest-stage translation.
+    Sv48,
+    /// 57-bit virtual addressing, and Sv57x4 for guest-stage translation.
+    Sv57,
+}
+
+impl MmuType {
+    /// Returns the `MmuType` for the 'mmu-type' device-tree property value `mmu_string`.
+    fn from_dt_str(mmu_string: &str) -> Option<Self> {
+        match mmu_string {
+            "riscv,sv39" => Some(MmuType::Sv39),
+            "riscv,sv48" => Some(MmuType::Sv48),
+            "riscv,sv57" => Some(MmuType::Sv57),
+            _ => None,
+        }
+    }
+
+    /// Returns the 'mmu-type' device-tree property value for this `MmuType`.
+    fn dt_str(&self) -> &'static str {
+        match self {
+            MmuType::Sv39 => "riscv,sv39",
+            MmuType::Sv48 => "riscv,sv48",
+            MmuType::Sv57 => "riscv,sv57",
+        }
+    }
+}
+
 /// Holds static global information about CPU features and topology.
 #[derive(Debug)]
 pub struct CpuInfo {
     // True if the Sstc extension is supported.
     has_sstc: bool,
+    // True if the Svpbmt extension is supported.
+    has_svpbmt: bool,
     // CPU timer frequency.
     timer_frequency: u32,
+    // The largest virtual memory system supported by the MMU.
+    mmu_type: MmuType,
     // ISA string as reprted in the device-tree. All CPUs are expected to have the same ISA.
     isa_string: ArrayString<MAX_ISA_STRING_LEN>,
     // Mapping of logical CPU index to hart IDs.
@@ -124,8 +162,7 @@ impl CpuInfo {
             .expect("No 'mmu-type' property in device-tree")
             .value_str()
             .unwrap();
-        // All of our memory management currently assumes SV48 compatibility.
-        assert!(mmu_string == "riscv,sv48" || mmu_string == "riscv,sv57");
+        let mmu_type = MmuType::from_dt_str(mmu_string).expect("Unsupported 'mmu-type'");
         // 'interrupt-controller' sub-node indicates if AIA is supported. We rely on AIA support
         // in order to direct interrupts to VS level, so mandate its presence.
         let intc_node = intc_node_from_cpu_node(dt, cpu0);
@@ -150,8 +187,10 @@ impl CpuInfo {
 
         let cpu_info = CpuInfo {
             has_sstc: isa_string.split('_').any(|f| f == "sstc"),
+            has_svpbmt: isa_string.split('_').any(|f| f == "svpbmt"),
             isa_string: ArrayString::from(isa_string).unwrap(),
             timer_frequency,
+            mmu_type,
             hart_ids,
             intc_phandles,
         };
@@ -169,6 +208,16 @@ impl CpuInfo {
         self.has_sstc
     }
 
+    /// Returns true if the Svpbmt extension is supported.
+    pub fn has_svpbmt(&self) -> bool {
+        self.has_svpbmt
+    }
+
+    /// Returns the largest virtual memory system supported by the MMU.
+    pub fn mmu_type(&self) -> MmuType {
+        self.mmu_type
+    }
+
     /// Returns the total number of CPUs.
     pub fn num_cpus(&self) -> usize {
         self.hart_ids.len()
@@ -224,7 +273,9 @@ impl CpuInfo {
             cpu_node.add_prop("device_type")?.set_value_str("cpu")?;
             cpu_node.add_prop("compatible")?.set_value_str("riscv")?;
             cpu_node.add_prop("reg")?.set_value_u32(&[i as u32])?;
-            cpu_node.add_prop("mmu-type")?.set_value_str("riscv,sv48")?;
+            cpu_node
+                .add_prop("mmu-type")?
+                .set_value_str(self.mmu_type.dt_str())?;
             cpu_node
                 .add_prop("riscv,isa")?
                 .set_value_str(self.isa_string.as_str())?;
@@ -19,7 +19,7 @@ pub mod cpu;
 /// Provides the driver for the IMSIC from the AIA spec.
 pub mod imsic;
 
-pub use cpu::{CpuId, CpuInfo, MAX_CPUS};
+pub use cpu::{CpuId, CpuInfo, MmuType, MAX_CPUS};
 pub use imsic::{
     Error as ImsicError, Imsic, ImsicGuestId, ImsicGuestPage, ImsicInterruptId,
     Result as ImsicResult,
@@ -89,7 +89,7 @@ mod tests {
                 .unwrap();
             node.add_prop("riscv,isa")
                 .unwrap()
-                .set_value_str("rv64imafdcvsuh_sstc")
+                .set_value_str("rv64imafdcvsuh_sstc_svpbmt")
                 .unwrap();
             node.add_prop("mmu-type")
                 .unwrap()
@@ -206,6 +206,8 @@ mod tests {
 
         let cpu_info = CpuInfo::get();
         assert!(cpu_info.has_sstc());
+        assert!(cpu_info.has_svpbmt());
+        assert_eq!(cpu_info.mmu_type(), MmuType::Sv48);
         assert_eq!(cpu_info.num_cpus(), 4);
         for i in 0..cpu_info.num_cpus() {
             let hart_id = cpu_info.cpu_to_hart_id(CpuId::new(i)).unwrap();
@@ -29,6 +29,8 @@ extern crate alloc;
 /// `Page`-backed collections resembling those in the standard library.
 pub mod collections;
 mod hw_mem_map;
+/// Tracks the state of regions of a VM's guest physical address space.
+pub mod mem_regions;
 mod page_info;
 /// Implements a linked-list of pages using `PageTracker`.
 pub mod page_list;
@@ -40,6 +42,8 @@ pub mod tlb_version;
 pub use hw_mem_map::Error as MemMapError;
 pub use hw_mem_map::Result as MemMapResult;
 pub use hw_mem_map::{HwMemMap, HwMemMapBuilder, HwMemRegion, HwMemRegionType, HwReservedMemType};
+pub use mem_regions::Error as MemRegionsError;
+pub use mem_regions::{MemoryRegion, MemoryRegions, RegionState, MAX_MEMORY_REGIONS};
 pub use page_info::MAX_PAGE_OWNERS;
 pub use page_list::{LockedPageList, PageList};
 pub use page_tracker::Error as PageTrackingError;
@@ -0,0 +1,462 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use arrayvec::ArrayVec;
+use riscv_pages::{GuestPageAddr, PageSize};
+
+use crate::TlbVersion;
+
+/// Errors related to tracking regions of a VM's address space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// The region would extend past the end of the address space.
+    AddressOverflow,
+    /// The region overlaps one that is already tracked.
+    RegionOverlap(GuestPageAddr),
+    /// There's no space to track another region.
+    TooManyRegions,
+}
+
+/// Holds results for memory region operations.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The maximum number of distinct regions of a VM's address space that may be shared, transitioning
+/// between shared and confidential, pending acceptance, locked, or emulated MMIO at any given time.
+pub const MAX_MEMORY_REGIONS: usize = 32;
+
+/// The state of a region of a VM's address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionState {
+    /// The VM has asked for the region to be shared. The confidential pages that were mapped in the
+    /// region are released once the TLB version at which they were invalidated has been fenced.
+    Sharing(TlbVersion),
+    /// The region is shared and the VM's parent may map shared pages into it.
+    Shared,
+    /// The VM has asked for the region to be made confidential again. The shared pages that were
+    /// mapped in the region are released once the TLB version at which they were invalidated has
+    /// been fenced.
+    Unsharing(TlbVersion),
+    /// The region holds confidential pages that were added after the VM was finalized and which
+    /// the VM has yet to accept.
+    Unaccepted,
+    /// The VM has locked the confidential pages in the region read/execute-only. The region can't
+    /// be shared or converted, and never leaves this state.
+    Locked,
+    /// The region holds no memory. Loads and stores the VM makes to it are emulated by the VM's
+    /// host. Declared before the VM is finalized, and never leaves this state.
+    EmulatedMmio,
+}
+
+impl RegionState {
+    /// Returns the TLB version that must be fenced before a region can leave this state, if any.
+    pub fn pending_version(&self) -> Option<TlbVersion> {
+        use RegionState::*;
+        match self {
+            Sharing(version) | Unsharing(version) => Some(*version),
+            _ => None,
+        }
+    }
+}
+
+/// A range of 4kB pages in a VM's guest physical address space in a particular `RegionState`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryRegion {
+    addr: GuestPageAddr,
+    num_pages: u64,
+    state: RegionState,
+}
+
+impl MemoryRegion {
+    /// Creates a region in `state` covering `num_pages` 4kB pages starting at `addr`.
+    pub fn new(addr: GuestPageAddr, num_pages: u64, state: RegionState) -> Result<Self> {
+        addr.checked_add_pages(num_pages)
+            .ok_or(Error::AddressOverflow)?;
+        Ok(Self {
+            addr,
+            num_pages,
+            state,
+        })
+    }
+
+    /// Returns the address of the first page in this region.
+    pub fn addr(&self) -> GuestPageAddr {
+        self.addr
+    }
+
+    /// Returns the number of 4kB pages in this region.
+    pub fn num_pages(&self) -> u64 {
+        self.num_pages
+    }
+
+    /// Returns the state of this region.
+    pub fn state(&self) -> RegionState {
+        self.state
+    }
+
+    /// Returns a region covering the same range as this one in state `state`.
+    pub fn with_state(&self, state: RegionState) -> Self {
+        Self { state, ..*self }
+    }
+
+    /// Returns the address of the first byte in this region.
+    pub fn start(&self) -> u64 {
+        self.addr.bits()
+    }
+
+    /// Returns the address of the first byte after this region.
+    pub fn end(&self) -> u64 {
+        // Can't overflow since we checked the address range in `new()`.
+        self.start() + self.num_pages * PageSize::Size4k as u64
+    }
+
+    /// Returns if `other` is entirely contained within this region.
+    pub fn contains(&self, other: &MemoryRegion) -> bool {
+        self.start() <= other.start() && other.end() <= self.end()
+    }
+
+    /// Returns if `other` overlaps this region.
+    pub fn overlaps(&self, other: &MemoryRegion) -> bool {
+        self.start() < other.end() && other.start() < self.end()
+    }
+
+    /// Returns the parts of this region that lie before and after `other`, which must be
+    /// contained within this region.
+    fn split_around(&self, other: &MemoryRegion) -> (Option<MemoryRegion>, Option<MemoryRegion>) {
+        let pages_between = |start: u64, end: u64| (end - start) / PageSize::Size4k as u64;
+        let before = (self.start() < other.start()).then(|| MemoryRegion {
+            addr: self.addr,
+            num_pages: pages_between(self.start(), other.start()),
+            state: self.state,
+        });
+        let after = (other.end() < self.end()).then(|| MemoryRegion {
+            // Unwrap ok since `other` is contained within this region.
+            addr: other.addr.checked_add_pages(other.num_pages).unwrap(),
+            num_pages: pages_between(other.end(), self.end()),
+            state: self.state,
+        });
+        (before, after)
+    }
+}
+
+/// The set of regions of a VM's address space that are shared, transitioning between shared and
+/// confidential, pending acceptance, locked, or emulated MMIO. Regions never overlap.
+pub struct MemoryRegions {
+    regions: ArrayVec<MemoryRegion, MAX_MEMORY_REGIONS>,
+}
+
+impl MemoryRegions {
+    /// Creates an empty set of regions.
+    pub fn new() -> Self {
+        Self {
+            regions: ArrayVec::new(),
+        }
+    }
+
+    /// Returns an iterator over the regions in the set, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &MemoryRegion> {
+        self.regions.iter()
+    }
+
+    /// Returns the index of the region that is in the same state as `range` and entirely contains
+    /// it, if there is one.
+    pub fn find(&self, range: &MemoryRegion) -> Option<usize> {
+        self.regions
+            .iter()
+            .position(|r| r.state == range.state && r.contains(range))
+    }
+
+    /// Returns if `range` overlaps any region.
+    pub fn overlaps(&self, range: &MemoryRegion) -> bool {
+        self.regions.iter().any(|r| r.overlaps(range))
+    }
+
+    /// Returns if `range` overlaps a locked region.
+    pub fn overlaps_locked(&self, range: &MemoryRegion) -> bool {
+        self.regions
+            .iter()
+            .any(|r| r.state == RegionState::Locked && r.overlaps(range))
+    }
+
+    /// Returns if any region is waiting on a fence of `version` to leave its current state.
+    pub fn is_pending_at(&self, version: TlbVersion) -> bool {
+        self.regions
+            .iter()
+            .any(|r| r.state.pending_version() == Some(version))
+    }
+
+    /// Checks that `region` can be added to the set.
+    pub fn check_insert(&self, region: &MemoryRegion) -> Result<()> {
+        if self.overlaps(region) {
+            return Err(Error::RegionOverlap(region.addr));
+        }
+        if self.regions.is_full() {
+            return Err(Error::TooManyRegions);
+        }
+        Ok(())
+    }
+
+    /// Adds `region` to the set, provided it doesn't overlap any existing regions.
+    pub fn insert(&mut self, region: MemoryRegion) -> Result<()> {
+        self.check_insert(&region)?;
+        // Unwrap ok since we've checked that there's space.
+        self.regions.try_push(region).unwrap();
+        Ok(())
+    }
+
+    /// Checks that `range` can be carved out of the region at `index` by `carve()`, either keeping
+    /// it as a region in a new state or dropping it from the set.
+    pub fn check_carve(&self, index: usize, range: &MemoryRegion, keep_range: bool) -> Result<()> {
+        let (before, after) = self.regions[index].split_around(range);
+        let added = [before.is_some(), after.is_some(), keep_range]
+            .iter()
+            .filter(|&&b| b)
+            .count();
+        if self.regions.len() - 1 + added > self.regions.capacity() {
+            return Err(Error::TooManyRegions);
+        }
+        Ok(())
+    }
+
+    /// Carves `range` out of the region at `index`, which must contain it. The carved out range
+    /// is either moved to state `to` or dropped from the set if `to` is `None`. The caller must
+    /// have checked that there's space for the resulting regions with `check_carve()`.
+    pub fn carve(&mut self, index: usize, range: &MemoryRegion, to: Option<RegionState>) {
+        let region = self.regions.swap_remove(index);
+        let (before, after) = region.split_around(range);
+        let carved = to.map(|state| range.with_state(state));
+        for r in [before, carved, after].into_iter().flatten() {
+            // Unwrap ok since the caller checked there's space.
+            self.regions.try_push(r).unwrap();
+        }
+    }
+
+    /// Completes the transitions of the regions whose pending TLB version is reported as fenced by
+    /// `is_fenced`, calling `release` on each of them first. Sharing regions become Shared, and
+    /// Unsharing regions are dropped from the set.
+    pub fn complete_fenced<F, R>(&mut self, is_fenced: F, mut release: R)
+    where
+        F: Fn(TlbVersion) -> bool,
+        R: FnMut(&MemoryRegion),
+    {
+        let mut i = 0;
+        while i < self.regions.len() {
+            let region = self.regions[i];
+            if region.state.pending_version().map_or(false, &is_fenced) {
+                release(&region);
+                match region.state {
+                    RegionState::Sharing(_) => self.regions[i].state = RegionState::Shared,
+                    _ => {
+                        self.regions.swap_remove(i);
+                        continue;
+                    }
+                }
+            }
+            i += 1;
+        }
+    }
+}
+
+impl Default for MemoryRegions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use riscv_pages::{PageAddr, PageOwnerId, RawAddr};
+    use std::vec::Vec;
+
+    fn region(addr: u64, num_pages: u64, state: RegionState) -> MemoryRegion {
+        let addr = PageAddr::new(RawAddr::guest(addr, PageOwnerId::host())).unwrap();
+        MemoryRegion::new(addr, num_pages, state).unwrap()
+    }
+
+    fn sorted(regions: &MemoryRegions) -> Vec<MemoryRegion> {
+        let mut v: Vec<MemoryRegion> = regions.iter().copied().collect();
+        v.sort_unstable_by_key(|r| r.start());
+        v
+    }
+
+    #[test]
+    fn region_bounds() {
+        let r = region(0x8000_0000, 4, RegionState::Shared);
+        assert_eq!(r.end(), 0x8000_4000);
+        assert!(r.contains(&region(0x8000_1000, 2, RegionState::Locked)));
+        assert!(r.contains(&r));
+        assert!(!r.contains(&region(0x8000_3000, 2, RegionState::Shared)));
+        assert!(r.overlaps(&region(0x8000_3000, 2, RegionState::Shared)));
+        assert!(r.overlaps(&region(0x7fff_f000, 2, RegionState::Shared)));
+        assert!(!r.overlaps(&region(0x8000_4000, 1, RegionState::Shared)));
+        assert!(!r.overlaps(&region(0x7fff_f000, 1, RegionState::Shared)));
+
+        let top = PageAddr::new(RawAddr::guest(!0xfff, PageOwnerId::host())).unwrap();
+        assert_eq!(
+            MemoryRegion::new(top, 2, RegionState::Shared).err(),
+            Some(Error::AddressOverflow)
+        );
+    }
+
+    #[test]
+    fn insert_and_find() {
+        let mut regions = MemoryRegions::new();
+        let shared = region(0x8000_0000, 4, RegionState::Shared);
+        assert!(regions.insert(shared).is_ok());
+        assert_eq!(
+            regions.insert(region(0x8000_3000, 2, RegionState::Locked)),
+            Err(Error::RegionOverlap(
+                region(0x8000_3000, 2, RegionState::Locked).addr()
+            ))
+        );
+        assert!(regions
+            .insert(region(0x8000_4000, 1, RegionState::Locked))
+            .is_ok());
+
+        assert_eq!(
+            regions.find(&region(0x8000_1000, 2, RegionState::Shared)),
+            Some(0)
+        );
+        // The state must match and the range must be wholly contained.
+        assert_eq!(
+            regions.find(&region(0x8000_1000, 2, RegionState::Unaccepted)),
+            None
+        );
+        assert_eq!(
+            regions.find(&region(0x8000_3000, 2, RegionState::Shared)),
+            None
+        );
+        assert!(regions.overlaps_locked(&region(0x8000_3000, 2, RegionState::Shared)));
+        assert!(!regions.overlaps_locked(&region(0x8000_0000, 4, RegionState::Shared)));
+    }
+
+    #[test]
+    fn insert_full() {
+        let mut regions = MemoryRegions::new();
+        for i in 0..MAX_MEMORY_REGIONS as u64 {
+            assert!(regions
+                .insert(region(0x8000_0000 + i * 0x2000, 1, RegionState::Locked))
+                .is_ok());
+        }
+        assert_eq!(
+            regions.insert(region(0x1000, 1, RegionState::Locked)),
+            Err(Error::TooManyRegions)
+        );
+        // Overlap is reported ahead of capacity.
+        assert!(matches!(
+            regions.check_insert(&region(0x8000_0000, 1, RegionState::Locked)),
+            Err(Error::RegionOverlap(_))
+        ));
+    }
+
+    #[test]
+    fn carve_regions() {
+        let mut regions = MemoryRegions::new();
+        regions
+            .insert(region(0x8000_0000, 8, RegionState::Shared))
+            .unwrap();
+
+        // Carving from the middle leaves regions either side.
+        let range = region(0x8000_2000, 2, RegionState::Shared);
+        let version = TlbVersion::new();
+        let index = regions.find(&range).unwrap();
+        assert!(regions.check_carve(index, &range, true).is_ok());
+        regions.carve(index, &range, Some(RegionState::Unsharing(version)));
+        assert_eq!(
+            sorted(&regions),
+            [
+                region(0x8000_0000, 2, RegionState::Shared),
+                region(0x8000_2000, 2, RegionState::Unsharing(version)),
+                region(0x8000_4000, 4, RegionState::Shared),
+            ]
+        );
+
+        // Carving a whole region out without keeping it drops it from the set.
+        let range = region(0x8000_0000, 2, RegionState::Shared);
+        let index = regions.find(&range).unwrap();
+        regions.carve(index, &range, None);
+        assert_eq!(
+            sorted(&regions),
+            [
+                region(0x8000_2000, 2, RegionState::Unsharing(version)),
+                region(0x8000_4000, 4, RegionState::Shared),
+            ]
+        );
+
+        // Carving from the end leaves the start of the region.
+        let range = region(0x8000_6000, 2, RegionState::Shared);
+        let index = regions.find(&range).unwrap();
+        regions.carve(index, &range, None);
+        assert_eq!(
+            sorted(&regions),
+            [
+                region(0x8000_2000, 2, RegionState::Unsharing(version)),
+                region(0x8000_4000, 2, RegionState::Shared),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_carve_full() {
+        let mut regions = MemoryRegions::new();
+        for i in 0..MAX_MEMORY_REGIONS as u64 {
+            regions
+                .insert(region(0x8000_0000 + i * 0x10000, 4, RegionState::Shared))
+                .unwrap();
+        }
+        let middle = region(0x8000_1000, 2, RegionState::Shared);
+        let index = regions.find(&middle).unwrap();
+        // A full set can't grow, but carving the start of a region in place is fine.
+        assert_eq!(
+            regions.check_carve(index, &middle, false),
+            Err(Error::TooManyRegions)
+        );
+        assert_eq!(
+            regions.check_carve(index, &middle, true),
+            Err(Error::TooManyRegions)
+        );
+        let start = region(0x8000_0000, 2, RegionState::Shared);
+        assert!(regions.check_carve(index, &start, false).is_ok());
+        assert_eq!(
+            regions.check_carve(index, &start, true),
+            Err(Error::TooManyRegions)
+        );
+        let whole = region(0x8000_0000, 4, RegionState::Shared);
+        assert!(regions.check_carve(index, &whole, true).is_ok());
+    }
+
+    #[test]
+    fn complete_fenced_regions() {
+        let mut regions = MemoryRegions::new();
+        let old = TlbVersion::new();
+        let current = old.increment();
+        regions
+            .insert(region(0x8000_0000, 1, RegionState::Sharing(old)))
+            .unwrap();
+        regions
+            .insert(region(0x8000_1000, 1, RegionState::Unsharing(old)))
+            .unwrap();
+        regions
+            .insert(region(0x8000_2000, 1, RegionState::Sharing(current)))
+            .unwrap();
+        regions
+            .insert(region(0x8000_3000, 1, RegionState::Unaccepted))
+            .unwrap();
+        assert!(regions.is_pending_at(current));
+        assert!(!regions.is_pending_at(current.increment()));
+
+        let mut released = Vec::new();
+        regions.complete_fenced(|v| v < current, |r| released.push(r.start()));
+        released.sort_unstable();
+        assert_eq!(released, [0x8000_0000, 0x8000_1000]);
+        assert_eq!(
+            sorted(&regions),
+            [
+                region(0x8000_0000, 1, RegionState::Shared),
+                region(0x8000_2000, 1, RegionState::Sharing(current)),
+                region(0x8000_3000, 1, RegionState::Unaccepted),
+            ]
+        );
+    }
+}
@@ -28,6 +28,11 @@ pub enum PageState {
     /// Page is mapped into the address space of the current owner.
     Mapped,
 
+    /// Page is mapped into the address space of the current owner and is shared with the given
+    /// child VM, which also maps it. Shared pages are non-confidential and can't be converted or
+    /// released until they are unshared.
+    Shared(PageOwnerId),
+
     /// Page is used to store hypervisor-internal state for the current owner, e.g. to back per-VM
     /// data structures or as a page-table page for the VM.
     VmState,
@@ -41,6 +46,15 @@ pub enum PageState {
 
     /// Page has completed the conversion operation and is locked pending assignment or reclaim.
     ConvertedLocked,
+
+    /// Page was released back to the current owner by a child VM, e.g. because the child exited.
+    /// Like a Converted page it's eligible for assignment or to be reclaimed, and the owner may
+    /// also fault it back into its address space. Locking the page clears the distinction.
+    Released,
+
+    /// Page started the conversion operation at the given TLB version and is locked pending
+    /// reclaim. The page can't be assigned since the conversion has yet to be fenced.
+    ConvertingLocked(TlbVersion),
 }
 
 /// The maximum length for an ownership chain. Enough for the host VM to assign to a guest VM
@@ -102,7 +116,8 @@ impl PageInfo {
     pub fn owner(&self) -> Option<PageOwnerId> {
         use PageState::*;
         match self.state {
-            Converting(_) | Converted | ConvertedLocked | Mapped | VmState => {
+            Converting(_) | ConvertingLocked(_) | Converted | ConvertedLocked | Released
+            | Mapped | Shared(_) | VmState => {
                 if !self.owners.is_empty() {
                     Some(self.owners[self.owners.len() - 1])
                 } else {
@@ -118,6 +133,12 @@ impl PageInfo {
         self.state
     }
 
+    /// Returns if the page has completed conversion and is unlocked, including pages released by a
+    /// child VM.
+    pub fn is_converted(&self) -> bool {
+        matches!(self.state, PageState::Converted | PageState::Released)
+    }
+
     /// Returns if the page is free.
     pub fn is_free(&self) -> bool {
         matches!(self.state, PageState::Free)
@@ -134,20 +155,22 @@ impl PageInfo {
         self.mem_type
     }
 
-    /// Pops the current owner if there is one, returning the page to the previous owner.
+    /// Pops the current owner if there is one, returning the page to the previous owner in the
+    /// Released state.
     pub fn release(&mut self) -> PageTrackingResult<PageOwnerId> {
         use PageState::*;
         match self.state {
-            Mapped | VmState | Converted | Converting(_) => {
+            Mapped | VmState | Converted | Converting(_) | Released => {
                 if self.owners.is_empty() {
                     Err(PageTrackingError::OwnerUnderflow) // Can't pop the last owner.
                 } else {
                     let owner = self.owners.pop().unwrap();
-                    self.state = Converted;
+                    self.state = Released;
                     Ok(owner)
                 }
             }
-            ConvertedLocked => Err(PageTrackingError::PageLocked),
+            ConvertedLocked | ConvertingLocked(_) => Err(PageTrackingError::PageLocked),
+            Shared(_) => Err(PageTrackingError::PageShared),
             Reserved => Err(PageTrackingError::ReservedPage),
             Free => Err(PageTrackingError::UnownedPage),
         }
@@ -183,7 +206,7 @@ impl PageInfo {
                 self.state = new_state;
                 Ok(())
             }
-            Converted => Err(PageTrackingError::PageNotLocked),
+            Converted | Released => Err(PageTrackingError::PageNotLocked),
             _ => Err(PageTrackingError::PageNotAssignable),
         }
     }
@@ -226,20 +249,66 @@ impl PageInfo {
         }
     }
 
-    /// Transitions the page to the ConvertedLocked state from Converted, indicating that an exclusive
-    /// reference has been taken to the page in preparation for assignment or reclaim.
+    /// Transitions the page to Shared with `with` if it is currently Mapped.
+    pub fn share(&mut self, with: PageOwnerId) -> PageTrackingResult<()> {
+        use PageState::*;
+        match self.state {
+            Mapped => {
+                self.state = Shared(with);
+                Ok(())
+            }
+            Shared(_) => Err(PageTrackingError::PageShared),
+            _ => Err(PageTrackingError::PageNotShareable),
+        }
+    }
+
+    /// Transitions the page back to Mapped if it is currently shared with `with`.
+    pub fn unshare(&mut self, with: PageOwnerId) -> PageTrackingResult<()> {
+        use PageState::*;
+        match self.state {
+            Shared(id) if id == with => {
+                self.state = Mapped;
+                Ok(())
+            }
+            _ => Err(PageTrackingError::PageNotShared),
+        }
+    }
+
+    /// Transitions the page to the ConvertedLocked state from Converted or Released, indicating
+    /// that an exclusive reference has been taken to the page in preparation for assignment or
+    /// reclaim.
     pub fn lock_for_assignment(&mut self) -> PageTrackingResult<()> {
         use PageState::*;
         match self.state {
-            Converted => {
+            Converted | Released => {
                 self.state = ConvertedLocked;
                 Ok(())
             }
-            ConvertedLocked => Err(PageTrackingError::PageLocked),
+            ConvertedLocked | ConvertingLocked(_) => Err(PageTrackingError::PageLocked),
             _ => Err(PageTrackingError::PageNotAssignable),
         }
     }
 
+    /// Transitions the page to a locked state from Converted, Released or Converting, indicating
+    /// that an exclusive reference has been taken to the page in preparation for reclaim. Pages
+    /// that are still Converting remember their TLB version so that conversion can resume if
+    /// they're unlocked without being reclaimed.
+    pub fn lock_for_reclaim(&mut self) -> PageTrackingResult<()> {
+        use PageState::*;
+        match self.state {
+            Converted | Released => {
+                self.state = ConvertedLocked;
+                Ok(())
+            }
+            Converting(version) => {
+                self.state = ConvertingLocked(version);
+                Ok(())
+            }
+            ConvertedLocked | ConvertingLocked(_) => Err(PageTrackingError::PageLocked),
+            _ => Err(PageTrackingError::PageNotReclaimable),
+        }
+    }
+
     /// Drops the exclusive lock on the a converted page.
     pub fn unlock(&mut self) -> PageTrackingResult<()> {
         use PageState::*;
@@ -248,20 +317,25 @@ impl PageInfo {
                 self.state = Converted;
                 Ok(())
             }
+            ConvertingLocked(version) => {
+                self.state = Converting(version);
+                Ok(())
+            }
             _ => Err(PageTrackingError::PageNotLocked),
         }
     }
 
     /// Reclaims the converted and locked, but unassigned, page as a Mapped page for the current owner.
+    /// Pages locked while still Converting have their conversion rolled back; this is safe without
+    /// a TLB fence since the page never left the owner.
     pub fn reclaim(&mut self) -> PageTrackingResult<()> {
         use PageState::*;
         match self.state {
-            ConvertedLocked => {
+            ConvertedLocked | ConvertingLocked(_) => {
                 self.state = Mapped;
                 Ok(())
             }
-            Converted => Err(PageTrackingError::PageNotLocked),
-            // TODO: Reclaim pages that are converting but not yet fully converted?
+            Converted | Released | Converting(_) => Err(PageTrackingError::PageNotLocked),
             _ => Err(PageTrackingError::PageNotReclaimable),
         }
     }
@@ -648,14 +722,69 @@ mod tests {
         let guest_id = PageOwnerId::new(2).unwrap();
         assert!(page.assign(guest_id, PageState::Mapped).is_ok());
         assert_eq!(page.release().unwrap(), guest_id);
+        assert_eq!(page.state(), PageState::Released);
+        assert_eq!(page.owner().unwrap(), PageOwnerId::host());
+        assert!(page.is_converted());
+        assert!(page.reclaim().is_err());
+        assert!(page.assign(guest_id, PageState::Mapped).is_err());
         assert!(page.lock_for_assignment().is_ok());
         assert_eq!(page.state(), PageState::ConvertedLocked);
         assert!(page.reclaim().is_ok());
 
+        // Conversion can be rolled back before it's been fenced.
+        let version = version.increment();
+        assert!(page.begin_conversion(version).is_ok());
+        assert!(page.reclaim().is_err());
+        assert!(page.lock_for_reclaim().is_ok());
+        assert_eq!(page.state(), PageState::ConvertingLocked(version));
+        assert!(page.lock_for_assignment().is_err());
+        assert!(page.assign(guest_id, PageState::Mapped).is_err());
+        assert!(page.unlock().is_ok());
+        assert_eq!(page.state(), PageState::Converting(version));
+        assert!(page.lock_for_reclaim().is_ok());
+        assert!(page.reclaim().is_ok());
+        assert_eq!(page.state(), PageState::Mapped);
+
         let mut page = PageInfo::new_reserved();
         assert!(!page.is_free());
         assert!(page
             .assign(PageOwnerId::hypervisor(), PageState::Converted)
             .is_err());
     }
+
+    #[test]
+    fn page_sharing() {
+        let mut page = PageInfo::new();
+        assert!(page
+            .assign(PageOwnerId::hypervisor(), PageState::ConvertedLocked)
+            .is_ok());
+        assert!(page.assign(PageOwnerId::host(), PageState::Mapped).is_ok());
+        let guest_id = PageOwnerId::new(2).unwrap();
+        let other_id = PageOwnerId::new(3).unwrap();
+        assert!(page.share(guest_id).is_ok());
+        assert_eq!(page.state(), PageState::Shared(guest_id));
+        assert_eq!(page.owner().unwrap(), PageOwnerId::host());
+        assert_eq!(page.share(other_id), Err(PageTrackingError::PageShared));
+
+        // Shared pages can't be converted or released until they're unshared.
+        let version = TlbVersion::new();
+        assert!(page.begin_conversion(version).is_err());
+        assert_eq!(page.release(), Err(PageTrackingError::PageShared));
+        assert!(page.lock_for_assignment().is_err());
+        assert_eq!(
+            page.unshare(other_id),
+            Err(PageTrackingError::PageNotShared)
+        );
+        assert!(page.unshare(guest_id).is_ok());
+        assert_eq!(page.state(), PageState::Mapped);
+        assert_eq!(
+            page.unshare(guest_id),
+            Err(PageTrackingError::PageNotShared)
+        );
+        assert!(page.begin_conversion(version).is_ok());
+        assert_eq!(
+            page.share(guest_id),
+            Err(PageTrackingError::PageNotShareable)
+        );
+    }
 }
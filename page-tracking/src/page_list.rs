@@ -4,30 +4,40 @@
 
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
-use riscv_pages::{ConvertedPhysPage, PhysPage, SupervisorPageAddr};
+use riscv_pages::{ConvertedPhysPage, PageSize, PhysPage, SupervisorPageAddr};
 
-use crate::{PageTracker, PageTrackingResult};
+use crate::{PageTracker, PageTrackingError, PageTrackingResult};
 
 /// A linked list of exclusively-owned `PhysPages` created using links in the array of `PageInfo`
 /// structs. This list can be used to pass around a list of non-contiguous pages without having
 /// to allocate storage (e.g. in a `Vec<>`). Pages are unlinked from the list by calling `pop()`.
 /// Any pages remaining on the list when the list is dropped are unlinked.
+///
+/// All pages on a list are of the same size. Huge pages are linked through the `PageInfo` of their
+/// first 4kB page.
 pub struct PageList<P: PhysPage> {
     page_tracker: PageTracker,
     head: Option<SupervisorPageAddr>,
     tail: Option<SupervisorPageAddr>,
     len: usize,
+    page_size: PageSize,
     page_state: PhantomData<P>,
 }
 
 impl<P: PhysPage> PageList<P> {
-    /// Creates an empty `PageList`.
+    /// Creates an empty `PageList` of 4kB pages.
     pub fn new(page_tracker: PageTracker) -> Self {
+        Self::new_with_size(page_tracker, PageSize::Size4k)
+    }
+
+    /// Creates an empty `PageList` of `page_size` pages.
+    pub fn new_with_size(page_tracker: PageTracker, page_size: PageSize) -> Self {
         Self {
             page_tracker,
             head: None,
             tail: None,
             len: 0,
+            page_size,
             page_state: PhantomData,
         }
     }
@@ -54,12 +64,17 @@ impl<P: PhysPage> PageList<P> {
             head: Some(head),
             tail: Some(tail),
             len,
+            page_size: PageSize::Size4k,
             page_state: PhantomData,
         }
     }
 
-    /// Appends `page` to the end of the list. Returns an error if `page` is already linked.
+    /// Appends `page` to the end of the list. Returns an error if `page` is already linked or isn't
+    /// the same size as the other pages in the list.
     pub fn push(&mut self, page: P) -> PageTrackingResult<()> {
+        if page.size() != self.page_size {
+            return Err(PageTrackingError::PageSizeMismatch);
+        }
         if let Some(tail_addr) = self.tail {
             self.page_tracker.link_pages(tail_addr, page.addr())?;
             self.tail = Some(page.addr());
@@ -81,7 +96,7 @@ impl<P: PhysPage> PageList<P> {
         }
         self.len -= 1;
         // Safety: This list has unique ownership of the page ever sicne it was pushed.
-        Some(unsafe { P::new(addr) })
+        Some(unsafe { P::new_with_size(addr, self.page_size) })
     }
 
     /// Returns if the list is empty.
@@ -94,6 +109,11 @@ impl<P: PhysPage> PageList<P> {
         self.len
     }
 
+    /// Returns the size of the pages in the list.
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
     /// Returns if the list of pages is contiguous.
     pub fn is_contiguous(&self) -> bool {
         if self.head.is_none() {
@@ -101,7 +121,9 @@ impl<P: PhysPage> PageList<P> {
         }
         let mut prev = self.head.unwrap();
         while let Some(addr) = self.page_tracker.linked_page(prev) {
-            if let Some(next) = prev.checked_add_pages(1) && addr == next {
+            if let Some(next) = prev.checked_add_pages_with_size(1, self.page_size)
+                && addr == next
+            {
                 prev = next;
             } else {
                 return false;
@@ -137,16 +159,22 @@ impl<P: PhysPage> Drop for PageList<P> {
 }
 
 /// Like `PageList`, but for converted pages that are locked for assignemnt or reclaim. Pages are
-/// released back to the "Converted" state when the list is dropped, in addition to unlinking them.
+/// released back to the "Converted" (or "Converting", if they were locked for reclaim before their
+/// conversion completed) state when the list is dropped, in addition to unlinking them.
 pub struct LockedPageList<P: ConvertedPhysPage> {
     inner: PageList<P>,
 }
 
 impl<P: ConvertedPhysPage> LockedPageList<P> {
-    /// Creates an empty `LockedPageList`.
+    /// Creates an empty `LockedPageList` of 4kB pages.
     pub fn new(page_tracker: PageTracker) -> Self {
+        Self::new_with_size(page_tracker, PageSize::Size4k)
+    }
+
+    /// Creates an empty `LockedPageList` of `page_size` pages.
+    pub fn new_with_size(page_tracker: PageTracker, page_size: PageSize) -> Self {
         Self {
-            inner: PageList::new(page_tracker),
+            inner: PageList::new_with_size(page_tracker, page_size),
         }
     }
 }
@@ -170,7 +198,7 @@ impl<P: ConvertedPhysPage> Drop for LockedPageList<P> {
         while let Some(p) = self.inner.pop() {
             // Unwrap ok since pages on the list must be uniquely-owned ConvertedPhysPages to be
             // on the list and all unqiuely-owned ConvertedPhysPages must by definition be in the
-            // "ConvertedLocked" state.
+            // "ConvertedLocked" or "ConvertingLocked" state.
             self.page_tracker.put_converted_page(p).unwrap();
         }
     }
@@ -43,6 +43,14 @@ pub enum Error {
     PageLocked,
     /// Attempt to create a link from a page that is already linked.
     PageAlreadyLinked,
+    /// Attempt to add a page to a list holding pages of a different size.
+    PageSizeMismatch,
+    /// The page is not in a state where it can be shared.
+    PageNotShareable,
+    /// Attempt to unshare a page that isn't shared with the specified VM.
+    PageNotShared,
+    /// Attempt to release or share a page that is already shared.
+    PageShared,
 }
 
 /// Holds the result of page tracking operations.
@@ -63,6 +71,32 @@ impl PageTrackerInner {
     fn get(&mut self, addr: SupervisorPageAddr) -> Result<&PageInfo> {
         self.pages.get(addr).ok_or(Error::InvalidPage(addr))
     }
+
+    /// Calls `f` on the `PageInfo` of each 4kB page making up the `page_size` page at `addr`.
+    ///
+    /// The constituent pages of a huge page may be in different states, e.g. once a huge mapping
+    /// has been split and its 4kB pages transitioned individually, so `f` is first tried on a copy
+    /// of each page's `PageInfo`. No page is updated unless `f` succeeds on every page.
+    fn update_pages<F>(
+        &mut self,
+        addr: SupervisorPageAddr,
+        page_size: PageSize,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&mut PageInfo) -> Result<()>,
+    {
+        let num_pages = PageSize::num_4k_pages(page_size as u64) as usize;
+        for a in addr.iter_from().take(num_pages) {
+            let mut info = self.get(a)?.clone();
+            f(&mut info)?;
+        }
+        for a in addr.iter_from().take(num_pages) {
+            // Unwrap ok since `f` succeeded on an identical copy of the page's state above.
+            f(self.get_mut(a).unwrap()).unwrap();
+        }
+        Ok(())
+    }
 }
 
 /// This struct wraps the list of all memory pages and active guests. It can be cloned and passed to
@@ -187,8 +221,9 @@ impl PageTracker {
         M: MeasureRequirement,
     {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr()).unwrap();
-        info.assign(owner, PageState::Mapped)?;
+        page_tracker.update_pages(page.addr(), page.size(), |info| {
+            info.assign(owner, PageState::Mapped)
+        })?;
         // Safe since we own the page and have updated its state.
         Ok(unsafe { P::MappablePage::new_with_size(page.addr(), page.size()) })
     }
@@ -201,8 +236,9 @@ impl PageTracker {
         owner: PageOwnerId,
     ) -> Result<Page<InternalClean>> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr()).unwrap();
-        info.assign(owner, PageState::VmState)?;
+        page_tracker.update_pages(page.addr(), page.size(), |info| {
+            info.assign(owner, PageState::VmState)
+        })?;
         // Safe since we own the page and have updated its state.
         Ok(unsafe { Page::new_with_size(page.addr(), page.size()) })
     }
@@ -210,21 +246,28 @@ impl PageTracker {
     /// Relases `page` back to its previous owner.
     pub fn release_page<P: PhysPage>(&self, page: P) -> Result<()> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr()).unwrap();
-        info.release()?;
-        Ok(())
+        page_tracker.update_pages(page.addr(), page.size(), |info| {
+            info.release()?;
+            Ok(())
+        })
     }
 
-    /// Releases the page at `addr` back to its previous owner if it's currently owned by `owner`
-    /// and is in a releasable state.
-    pub fn release_page_by_addr(&self, addr: SupervisorPageAddr, owner: PageOwnerId) -> Result<()> {
+    /// Releases the `page_size` page at `addr` back to its previous owner if it's currently owned
+    /// by `owner` and is in a releasable state.
+    pub fn release_page_by_addr(
+        &self,
+        addr: SupervisorPageAddr,
+        page_size: PageSize,
+        owner: PageOwnerId,
+    ) -> Result<()> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(addr)?;
-        if info.owner() != Some(owner) {
-            return Err(Error::OwnerMismatch);
-        }
-        info.release()?;
-        Ok(())
+        page_tracker.update_pages(addr, page_size, |info| {
+            if info.owner() != Some(owner) {
+                return Err(Error::OwnerMismatch);
+            }
+            info.release()?;
+            Ok(())
+        })
     }
 
     /// Marks the invalidated page as having started conversion at `tlb_version`.
@@ -234,51 +277,127 @@ impl PageTracker {
         tlb_version: TlbVersion,
     ) -> Result<()> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr()).unwrap();
-        info.begin_conversion(tlb_version)
+        page_tracker.update_pages(page.addr(), page.size(), |info| {
+            info.begin_conversion(tlb_version)
+        })
     }
 
     /// Reclaims the converted, but unassigned, `page` back to a mapped page for the current owner.
     /// Returns a page that can then be mapped in a page table.
     pub fn reclaim_page<P: ReclaimablePhysPage>(&self, page: P) -> Result<P::MappablePage> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr()).unwrap();
-        info.reclaim()?;
+        page_tracker.update_pages(page.addr(), page.size(), |info| info.reclaim())?;
         // Safe since we own the page and have verified that it can be reclaimed.
         Ok(unsafe { P::MappablePage::new_with_size(page.addr(), page.size()) })
     }
 
-    /// Acquires an exclusive reference to the Converted page at `addr` if it's unassigned and owned
-    /// by `owner`. Completes conversion if the page was Converting at a TLB version older than
-    /// `tlb_version`.
+    /// Acquires an exclusive reference to the Converted `page_size` page at `addr` if it's
+    /// unassigned and owned by `owner`. Completes conversion if the page was Converting at a TLB
+    /// version older than `tlb_version`. For huge pages, every 4kB page in the range must be
+    /// Converted.
     pub fn get_converted_page<P: ConvertedPhysPage>(
         &self,
         addr: SupervisorPageAddr,
+        page_size: PageSize,
         owner: PageOwnerId,
         tlb_version: TlbVersion,
     ) -> Result<P::DirtyPage> {
+        if !addr.is_aligned(page_size) {
+            return Err(Error::PageNotConvertible);
+        }
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(addr)?;
-        if info.owner() != Some(owner)
-            || info.mem_type() != P::mem_type()
-            || (info.state() != PageState::Converted
-                && info.complete_conversion(tlb_version).is_err())
+        // Make sure the whole range is convertible before we start locking pages.
+        for a in addr
+            .iter_from()
+            .take(PageSize::num_4k_pages(page_size as u64) as usize)
         {
-            return Err(Error::PageNotConvertible);
+            let info = page_tracker.get(a)?;
+            if info.owner() != Some(owner)
+                || info.mem_type() != P::mem_type()
+                || (!info.is_converted() && !info.is_convertible(tlb_version))
+            {
+                return Err(Error::PageNotConvertible);
+            }
         }
-        info.lock_for_assignment()?;
+        page_tracker.update_pages(addr, page_size, |info| {
+            if !info.is_converted() {
+                info.complete_conversion(tlb_version)?;
+            }
+            info.lock_for_assignment()
+        })?;
         // Safe since we've taken exclusive ownership of the page, verified its typing, and that it is
         // converted as of `tlb_version`.
-        //
-        // TODO: Page size
-        Ok(unsafe { P::DirtyPage::new(addr) })
+        Ok(unsafe { P::DirtyPage::new_with_size(addr, page_size) })
+    }
+
+    /// Acquires an exclusive reference to the `page_size` page at `addr` for reclaim if it's
+    /// unassigned, owned by `owner`, and either Converted or Converting at any TLB version. Pages
+    /// that are still Converting can't be assigned while locked, only reclaimed.
+    pub fn get_reclaimable_page<P: ConvertedPhysPage>(
+        &self,
+        addr: SupervisorPageAddr,
+        page_size: PageSize,
+        owner: PageOwnerId,
+    ) -> Result<P::DirtyPage> {
+        if !addr.is_aligned(page_size) {
+            return Err(Error::PageNotReclaimable);
+        }
+        let mut page_tracker = self.inner.lock();
+        // Make sure the whole range is reclaimable before we start locking pages.
+        for a in addr
+            .iter_from()
+            .take(PageSize::num_4k_pages(page_size as u64) as usize)
+        {
+            let info = page_tracker.get(a)?;
+            if info.owner() != Some(owner)
+                || info.mem_type() != P::mem_type()
+                || !(info.is_converted() || matches!(info.state(), PageState::Converting(_)))
+            {
+                return Err(Error::PageNotReclaimable);
+            }
+        }
+        page_tracker.update_pages(addr, page_size, |info| info.lock_for_reclaim())?;
+        // Safe since we've taken exclusive ownership of the page and verified its typing. The page
+        // may not have been fenced yet, but it can only be reclaimed by `owner` while locked.
+        Ok(unsafe { P::DirtyPage::new_with_size(addr, page_size) })
+    }
+
+    /// Acquires an exclusive reference to the `page_size` page at `addr` for reclaim if it was
+    /// released back to `owner` by a child VM and hasn't since been locked. For huge pages, every
+    /// 4kB page in the range must be Released.
+    pub fn get_released_page<P: ConvertedPhysPage>(
+        &self,
+        addr: SupervisorPageAddr,
+        page_size: PageSize,
+        owner: PageOwnerId,
+    ) -> Result<P::DirtyPage> {
+        if !addr.is_aligned(page_size) {
+            return Err(Error::PageNotReclaimable);
+        }
+        let mut page_tracker = self.inner.lock();
+        // Make sure the whole range was released before we start locking pages.
+        for a in addr
+            .iter_from()
+            .take(PageSize::num_4k_pages(page_size as u64) as usize)
+        {
+            let info = page_tracker.get(a)?;
+            if info.owner() != Some(owner)
+                || info.mem_type() != P::mem_type()
+                || info.state() != PageState::Released
+            {
+                return Err(Error::PageNotReclaimable);
+            }
+        }
+        page_tracker.update_pages(addr, page_size, |info| info.lock_for_reclaim())?;
+        // Safe since we've taken exclusive ownership of the page and verified its typing. The
+        // page completed conversion before it was assigned to the child that released it.
+        Ok(unsafe { P::DirtyPage::new_with_size(addr, page_size) })
     }
 
     /// Releases an exclusive reference to a converted and locked page.
     pub fn put_converted_page<P: ConvertedPhysPage>(&self, page: P) -> Result<()> {
         let mut page_tracker = self.inner.lock();
-        let info = page_tracker.get_mut(page.addr())?;
-        info.unlock()
+        page_tracker.update_pages(page.addr(), page.size(), |info| info.unlock())
     }
 
     /// Returns true if and only if `addr` is a "Mapped" page owned by `owner` with type `mem_type`.
@@ -298,6 +417,43 @@ impl PageTracker {
         }
     }
 
+    /// Shares the Mapped 4kB page at `addr` owned by `owner` with the child VM `with`, returning a
+    /// page that can then be mapped into the child's page table. The page remains owned, and
+    /// mapped, by `owner`.
+    pub fn share_page(
+        &self,
+        addr: SupervisorPageAddr,
+        owner: PageOwnerId,
+        with: PageOwnerId,
+    ) -> Result<Page<Shared>> {
+        let mut page_tracker = self.inner.lock();
+        let info = page_tracker.get_mut(addr)?;
+        if info.owner() != Some(owner) || info.mem_type() != MemType::Ram {
+            return Err(Error::PageNotShareable);
+        }
+        info.share(with)?;
+        // Safe since we've verified the typing of the page and have updated its state. The page
+        // is never accessed through the returned `Page`, it only serves to map it into `with`.
+        Ok(unsafe { Page::new(addr) })
+    }
+
+    /// Stops sharing the 4kB page at `addr` with the child VM `with`, returning it to the Mapped
+    /// state for its owner.
+    pub fn unshare_page(&self, addr: SupervisorPageAddr, with: PageOwnerId) -> Result<()> {
+        let mut page_tracker = self.inner.lock();
+        page_tracker.get_mut(addr)?.unshare(with)
+    }
+
+    /// Returns true if and only if `addr` is a page that is shared with `with`.
+    pub fn is_shared_page(&self, addr: SupervisorPageAddr, with: PageOwnerId) -> bool {
+        let mut page_tracker = self.inner.lock();
+        if let Ok(info) = page_tracker.get(addr) {
+            info.state() == PageState::Shared(with)
+        } else {
+            false
+        }
+    }
+
     /// Returns true if and only if `addr` is a page owned by `owner` with type `mem_type` and
     /// was converted at a TLB version older than `tlb_version`.
     pub fn is_converted_page(
@@ -312,7 +468,25 @@ impl PageTracker {
             // We lazily update the
             info.owner() == Some(owner)
                 && info.mem_type() == mem_type
-                && (info.state() == PageState::Converted || info.is_convertible(tlb_version))
+                && (info.is_converted() || info.is_convertible(tlb_version))
+        } else {
+            false
+        }
+    }
+
+    /// Returns true if and only if `addr` is a page owned by `owner` with type `mem_type` that has
+    /// started conversion, regardless of whether that conversion has been fenced.
+    pub fn is_reclaimable_page(
+        &self,
+        addr: SupervisorPageAddr,
+        owner: PageOwnerId,
+        mem_type: MemType,
+    ) -> bool {
+        let mut page_tracker = self.inner.lock();
+        if let Ok(info) = page_tracker.get(addr) {
+            info.owner() == Some(owner)
+                && info.mem_type() == mem_type
+                && (info.is_converted() || matches!(info.state(), PageState::Converting(_)))
         } else {
             false
         }
@@ -574,6 +748,63 @@ mod tests {
         assert!((host_pages.len() as u64) < remaining);
     }
 
+    #[test]
+    fn update_diverged_huge_page() {
+        let (page_tracker, mut host_pages) = stub_page_tracker();
+        let id = page_tracker.add_active_guest().unwrap();
+        let base = host_pages
+            .find(|p| p.addr().is_aligned(PageSize::Size2M))
+            .unwrap();
+        let base_addr = base.addr();
+        let mapped = page_tracker.assign_page_for_mapping(base, id).unwrap();
+        // Assign all but the second 4kB page of the 2MB page to the guest.
+        let mut remaining = host_pages.by_ref().take(511);
+        let unassigned = remaining.next().unwrap();
+        for (page, addr) in remaining.zip(base_addr.iter_from().skip(2)) {
+            assert_eq!(page.addr(), addr);
+            page_tracker.assign_page_for_mapping(page, id).unwrap();
+        }
+
+        // Releasing the 2MB page fails on its second 4kB page and leaves the first one untouched.
+        assert!(page_tracker
+            .release_page_by_addr(base_addr, PageSize::Size2M, id)
+            .is_err());
+        assert!(page_tracker.is_mapped_page(mapped.addr(), id, MemType::Ram));
+        assert!(!page_tracker.is_mapped_page(unassigned.addr(), id, MemType::Ram));
+    }
+
+    #[test]
+    fn share_and_unshare_page() {
+        let (page_tracker, mut host_pages) = stub_page_tracker();
+        let owner_id = page_tracker.add_active_guest().unwrap();
+        let child_id = page_tracker.add_active_guest().unwrap();
+        let page = page_tracker
+            .assign_page_for_mapping(host_pages.next().unwrap(), owner_id)
+            .unwrap();
+        let addr = page.addr();
+
+        // Only the page's owner may share it.
+        assert_eq!(
+            page_tracker.share_page(addr, child_id, owner_id).err(),
+            Some(Error::PageNotShareable)
+        );
+        assert!(page_tracker.share_page(addr, owner_id, child_id).is_ok());
+        assert!(page_tracker.is_shared_page(addr, child_id));
+        assert!(!page_tracker.is_mapped_page(addr, owner_id, MemType::Ram));
+        assert_eq!(
+            page_tracker.share_page(addr, owner_id, child_id).err(),
+            Some(Error::PageShared)
+        );
+
+        assert_eq!(
+            page_tracker.unshare_page(addr, owner_id),
+            Err(Error::PageNotShared)
+        );
+        assert!(page_tracker.unshare_page(addr, child_id).is_ok());
+        assert!(!page_tracker.is_shared_page(addr, child_id));
+        assert!(page_tracker.is_mapped_page(addr, owner_id, MemType::Ram));
+    }
+
     #[test]
     fn drop_one_page_tracker_ref() {
         let (page_tracker, _host_mem) = stub_page_tracker();
@@ -13,8 +13,8 @@
 //! - `PlatformPageTable` is a top-level page table structures used to manipulate address translation
 //! and protection.
 //! - `PageTable` provides a generic implementation of a single level of multi-level translation.
-//! - `Sv48x4`, `Sv48`, etc. define standard RISC-V translation modes for 1st or 2nd-stage translation
-//! tables.
+//! - `Sv39x4`, `Sv48x4`, `Sv57x4`, and `Sv48` define standard RISC-V translation modes for 1st or
+//! 2nd-stage translation tables.
 //!
 //! ## Safety
 //!
@@ -34,10 +34,14 @@ extern crate alloc;
 mod page_table;
 /// Provides access to the fields of a riscv PTE.
 mod pte;
+/// Interfaces to build and manage sv39x4 page tables for VMs.
+pub mod sv39x4;
 /// Interfaces to build and manage sv48 page tables for S and U mode access.
 mod sv48;
 /// Interfaces to build and manage sv48x4 page tables for VMs.
 pub mod sv48x4;
+/// Interfaces to build and manage sv57x4 page tables for VMs.
+pub mod sv57x4;
 /// Provides low-level TLB management functions such as fencing.
 pub mod tlb;
 
@@ -46,8 +50,11 @@ pub use page_table::Result as PageTableResult;
 pub use page_table::{
     FirstStagePageTable, GuestStagePageTable, PageTableMapper, PagingMode, PlatformPageTable,
 };
+pub use pte::{PteLeafPerms, PteMemType};
+pub use sv39x4::Sv39x4;
 pub use sv48::Sv48;
 pub use sv48x4::Sv48x4;
+pub use sv57x4::Sv57x4;
 
 #[cfg(test)]
 #[macro_use]
@@ -61,8 +68,10 @@ mod tests {
     use std::{mem, slice};
 
     use super::page_table::*;
+    use super::sv39x4::Sv39x4;
     use super::sv48::Sv48;
     use super::sv48x4::Sv48x4;
+    use super::sv57x4::Sv57x4;
     use super::*;
 
     struct StubState {
@@ -106,6 +115,19 @@ mod tests {
         }
     }
 
+    // Takes a 2MB page made up of contiguous 4kB pages from `host_pages`.
+    fn take_huge_page(host_pages: &mut PageList<Page<ConvertedClean>>) -> Page<ConvertedClean> {
+        let base = host_pages
+            .find(|p| p.addr().is_aligned(PageSize::Size2M))
+            .unwrap()
+            .addr();
+        for (page, addr) in host_pages.take(511).zip(base.iter_from().skip(1)) {
+            assert_eq!(page.addr(), addr);
+        }
+        // Not safe - just a test
+        unsafe { Page::new_with_size(base, PageSize::Size2M) }
+    }
+
     #[test]
     fn map_and_unmap_sv48x4() {
         let state = stub_sys_memory();
@@ -135,11 +157,11 @@ mod tests {
                 slice[0] = 0xdeadbeef;
             }
             let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
-            assert!(mapper.map_page(gpa, mappable).is_ok());
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
         }
         let version = TlbVersion::new();
         guest_page_table
-            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2)
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2, &mut || None)
             .unwrap()
             .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
         let version = version.increment();
@@ -156,6 +178,61 @@ mod tests {
         page_tracker.put_converted_page(clean_page).unwrap();
     }
 
+    // Maps, converts, and reclaims the highest 4kB page of the guest physical address space of a
+    // `T` page table, which is `gpa_bits` wide.
+    fn map_and_unmap_top_page<T: GuestStagePageTable>(gpa_bits: u64) {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<T> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating guest page table");
+
+        let page = host_pages.next().unwrap();
+        let page_addr = page.addr();
+        // Deeper modes need more intermediate tables than `stub_sys_memory()` sets aside.
+        let mut pte_pages = host_pages
+            .by_ref()
+            .take(4)
+            .map(|p| page_tracker.assign_page_for_internal_state(p, id).unwrap());
+        let gpa = PageAddr::new(RawAddr::guest(
+            (1 << gpa_bits) - PageSize::Size4k as u64,
+            PageOwnerId::host(),
+        ))
+        .unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa, PageSize::Size4k, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+        assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        drop(mapper);
+
+        let version = TlbVersion::new();
+        guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 1, &mut || None)
+            .unwrap()
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+        let version = version.increment();
+        let mut converted_pages = guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1, version)
+            .unwrap();
+        let page = converted_pages.next().unwrap();
+        assert_eq!(page.addr(), page_addr);
+        page_tracker.put_converted_page(page).unwrap();
+    }
+
+    #[test]
+    fn map_and_unmap_sv39x4() {
+        map_and_unmap_top_page::<Sv39x4>(41);
+    }
+
+    #[test]
+    fn map_and_unmap_sv57x4() {
+        map_and_unmap_top_page::<Sv57x4>(59);
+    }
+
     #[test]
     fn map_and_unmap_sv48() {
         let state = stub_sys_memory();
@@ -185,11 +262,11 @@ mod tests {
                 slice[0] = 0xdeadbeef;
             }
             let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
-            assert!(mapper.map_page(gpa, mappable).is_ok());
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
         }
         let version = TlbVersion::new();
         guest_page_table
-            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2)
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2, &mut || None)
             .unwrap()
             .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
         let version = version.increment();
@@ -205,4 +282,688 @@ mod tests {
         assert_eq!(clean_page.get_u64(0).unwrap(), 0);
         page_tracker.put_converted_page(clean_page).unwrap();
     }
+
+    #[test]
+    fn convert_huge_page_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let huge_page = take_huge_page(&mut host_pages);
+        let huge_page_addr = huge_page.addr();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size2M, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(huge_page, id).unwrap();
+        assert!(mapper
+            .map_page(gpa_base, mappable, PteLeafPerms::RWX)
+            .is_ok());
+        drop(mapper);
+
+        let version = TlbVersion::new();
+        let invalidated = guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size2M, 1, &mut || None)
+            .unwrap();
+        assert_eq!(invalidated.len(), 512);
+        invalidated
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+        let version = version.increment();
+        let mut converted_pages = guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa_base, PageSize::Size2M, 1, version)
+            .unwrap();
+        let page = converted_pages.next().unwrap();
+        assert_eq!(page.addr(), huge_page_addr);
+        assert_eq!(page.size(), PageSize::Size2M);
+        page_tracker.put_converted_page(page).unwrap();
+    }
+
+    #[test]
+    fn reclaim_huge_page_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let huge_page = take_huge_page(&mut host_pages);
+        let huge_page_addr = huge_page.addr();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size2M, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(huge_page, id).unwrap();
+        mapper
+            .map_page(gpa_base, mappable, PteLeafPerms::RWX)
+            .unwrap();
+        drop(mapper);
+
+        let version = TlbVersion::new();
+        guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size2M, 1, &mut || None)
+            .unwrap()
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+
+        // The page can be reclaimed, without being fenced, and mapped back as a 2MB page.
+        let mut reclaimed_pages = guest_page_table
+            .get_reclaimable_range::<Page<ConvertedDirty>>(gpa_base, PageSize::Size2M, 1)
+            .unwrap();
+        let page = reclaimed_pages.next().unwrap();
+        assert_eq!(page.addr(), huge_page_addr);
+        assert_eq!(page.size(), PageSize::Size2M);
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size2M, 1, &mut || None)
+            .unwrap();
+        let mappable = page_tracker.reclaim_page(page.clean()).unwrap();
+        mapper
+            .map_page(gpa_base, mappable, PteLeafPerms::RWX)
+            .unwrap();
+        drop(mapper);
+        assert!(page_tracker.is_mapped_page(huge_page_addr, id, MemType::Ram));
+        let invalidated = guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size2M, 1, &mut || None)
+            .unwrap();
+        assert_eq!(invalidated.len(), 512);
+    }
+
+    #[test]
+    fn split_huge_page_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let huge_page = take_huge_page(&mut host_pages);
+        let huge_page_addr = huge_page.addr();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size2M, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(huge_page, id).unwrap();
+        assert!(mapper
+            .map_page(gpa_base, mappable, PteLeafPerms::RWX)
+            .is_ok());
+        drop(mapper);
+
+        // Converting a 4kB page in the middle of the 2MB mapping requires a page to split it.
+        let gpa = gpa_base.checked_add_pages(1).unwrap();
+        assert!(guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 1, &mut || None)
+            .is_err());
+        let mut invalidated = guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 1, &mut || {
+                pte_pages.next()
+            })
+            .unwrap();
+        assert_eq!(invalidated.len(), 1);
+        let page = invalidated.next().unwrap();
+        assert_eq!(page.addr(), huge_page_addr.checked_add_pages(1).unwrap());
+        let version = TlbVersion::new();
+        page_tracker.convert_page(page, version).unwrap();
+
+        // The rest of the 2MB page must still be mapped.
+        assert_eq!(
+            guest_page_table
+                .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 1, &mut || None)
+                .unwrap()
+                .len(),
+            1
+        );
+        let version = version.increment();
+        assert!(guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa_base, PageSize::Size2M, 1, version)
+            .is_err());
+        let mut converted_pages = guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1, version)
+            .unwrap();
+        let page = converted_pages.next().unwrap();
+        assert_eq!(page.addr(), huge_page_addr.checked_add_pages(1).unwrap());
+        page_tracker.put_converted_page(page).unwrap();
+    }
+
+    #[test]
+    fn protect_huge_page_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let huge_page = take_huge_page(&mut host_pages);
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size2M, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(huge_page, id).unwrap();
+        assert!(mapper
+            .map_page(gpa_base, mappable, PteLeafPerms::RWX)
+            .is_ok());
+        drop(mapper);
+
+        // The whole 2MB mapping can be protected without splitting it.
+        assert!(guest_page_table
+            .protect_range(gpa_base, 512, PteLeafPerms::RX, &mut || None)
+            .is_ok());
+
+        // Protecting part of it requires a page to split it.
+        let gpa = gpa_base.checked_add_pages(1).unwrap();
+        assert!(guest_page_table
+            .protect_range(gpa, 2, PteLeafPerms::RW, &mut || None)
+            .is_err());
+        assert!(guest_page_table
+            .protect_range(gpa, 2, PteLeafPerms::RW, &mut || pte_pages.next())
+            .is_ok());
+
+        // Unmapped pages can't be protected.
+        let unmapped_gpa = gpa_base.checked_add_pages(511).unwrap();
+        assert!(guest_page_table
+            .protect_range(unmapped_gpa, 2, PteLeafPerms::RX, &mut || pte_pages.next())
+            .is_err());
+
+        // Protected pages remain mapped.
+        assert_eq!(
+            guest_page_table
+                .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 2, &mut || None)
+                .unwrap()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn pte_mem_type() {
+        use pte::PteFieldBits;
+
+        let mut status = PteFieldBits::leaf_with_perms(PteLeafPerms::RW);
+        assert_eq!(status.mem_type(), PteMemType::PMA);
+        status.set_mem_type(PteMemType::IO);
+        assert_eq!(status.mem_type(), PteMemType::IO);
+        assert_eq!(status.bits() >> 61, 2);
+        status.set_mem_type(PteMemType::NC);
+        assert_eq!(status.mem_type(), PteMemType::NC);
+        assert_eq!(status.bits() >> 61, 1);
+        // The permission bits are left untouched.
+        assert_eq!(status.bits() & 0xff, PteLeafPerms::RW as u64);
+    }
+
+    #[test]
+    fn map_non_cacheable_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+        guest_page_table.enable_svpbmt();
+
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size4k, 2, &mut || pte_pages.next())
+            .unwrap();
+        for gpa in gpa_base.iter_from().take(2) {
+            let page = page_tracker
+                .assign_page_for_mapping(host_pages.next().unwrap(), id)
+                .unwrap();
+            assert!(mapper
+                .map_page_with_mem_type(gpa, page, PteLeafPerms::RW, PteMemType::NC)
+                .is_ok());
+        }
+        drop(mapper);
+
+        // Non-cacheable pages are mapped like any other.
+        assert_eq!(
+            guest_page_table
+                .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2, &mut || None)
+                .unwrap()
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn unmapped_range_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        assert!(guest_page_table.is_unmapped_range(gpa_base, 1 << 20));
+        let gpa = gpa_base.checked_add_pages(2).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa, PageSize::Size4k, 1, &mut || pte_pages.next())
+            .unwrap();
+        // Locked PTEs are about to be mapped.
+        assert!(!guest_page_table.is_unmapped_range(gpa_base, 4));
+        let page = page_tracker
+            .assign_page_for_mapping(host_pages.next().unwrap(), id)
+            .unwrap();
+        mapper.map_page(gpa, page, PteLeafPerms::RW).unwrap();
+        drop(mapper);
+
+        assert!(guest_page_table.is_unmapped_range(gpa_base, 2));
+        assert!(!guest_page_table.is_unmapped_range(gpa_base, 3));
+        assert!(!guest_page_table.is_unmapped_range(gpa, 1));
+        assert!(guest_page_table.is_unmapped_range(gpa.checked_add_pages(1).unwrap(), 1 << 20));
+    }
+
+    #[test]
+    fn share_pages_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let pages_to_map = [host_pages.next().unwrap(), host_pages.next().unwrap()];
+        let page_addrs: Vec<SupervisorPageAddr> = pages_to_map.iter().map(|p| p.addr()).collect();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size4k, 2, &mut || pte_pages.next())
+            .unwrap();
+        for (page, gpa) in pages_to_map.into_iter().zip(gpa_base.iter_from()) {
+            let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        }
+        drop(mapper);
+
+        // Build a page table for the child VM the pages will be shared with, using some of the
+        // remaining host pages as its page-table pages.
+        let child_id = page_tracker.add_active_guest().unwrap();
+        let mut child_pages = host_pages.by_ref().take(4).map(|p| {
+            page_tracker
+                .assign_page_for_internal_state(p, child_id)
+                .unwrap()
+        });
+        let child_root = SequentialPages::from(child_pages.next().unwrap());
+        let child_page_table: PlatformPageTable<Sv48> =
+            PlatformPageTable::new(child_root, child_id, page_tracker.clone())
+                .expect("creating sv48");
+
+        // The whole range must be mapped for any of it to be shared.
+        assert!(guest_page_table.share_range(gpa_base, 3, child_id).is_err());
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| !page_tracker.is_shared_page(addr, child_id)));
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| page_tracker.is_mapped_page(addr, id, MemType::Ram)));
+
+        let mut shared_pages = guest_page_table.share_range(gpa_base, 2, child_id).unwrap();
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| page_tracker.is_shared_page(addr, child_id)));
+        // Shared pages can neither be shared again nor converted.
+        assert!(guest_page_table.share_range(gpa_base, 1, child_id).is_err());
+        assert!(guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 1, &mut || None)
+            .is_err());
+
+        let child_gpa = PageAddr::new(RawAddr::supervisor_virt(0x8000_0000)).unwrap();
+        let mapper = child_page_table
+            .map_range(child_gpa, PageSize::Size4k, 1, &mut || child_pages.next())
+            .unwrap();
+        assert!(mapper
+            .map_page(child_gpa, shared_pages.next().unwrap(), PteLeafPerms::RWX)
+            .is_ok());
+        drop(mapper);
+        page_tracker
+            .unshare_page(shared_pages.next().unwrap().addr(), child_id)
+            .unwrap();
+
+        // Tearing down the child's page table stops sharing the pages mapped in it.
+        drop(child_page_table);
+        assert!(!page_tracker.is_shared_page(page_addrs[0], child_id));
+        assert!(!page_tracker.is_shared_page(page_addrs[1], child_id));
+
+        // Unshared pages may be shared again.
+        let shared_pages = guest_page_table.share_range(gpa_base, 2, child_id).unwrap();
+        for page in shared_pages {
+            page_tracker.unshare_page(page.addr(), child_id).unwrap();
+        }
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| page_tracker.is_mapped_page(addr, id, MemType::Ram)));
+        assert_eq!(
+            guest_page_table
+                .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 2, &mut || None)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn share_into_unshared_region_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let pages_to_map = [host_pages.next().unwrap(), host_pages.next().unwrap()];
+        let page_addrs: Vec<SupervisorPageAddr> = pages_to_map.iter().map(|p| p.addr()).collect();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size4k, 2, &mut || pte_pages.next())
+            .unwrap();
+        for (page, gpa) in pages_to_map.into_iter().zip(gpa_base.iter_from()) {
+            let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        }
+        drop(mapper);
+
+        // Build a page table for a child VM which has declared a shared region.
+        let child_id = page_tracker.add_active_guest().unwrap();
+        let root_base = host_pages
+            .find(|p| p.addr().bits() & (Sv48x4::TOP_LEVEL_ALIGN - 1) == 0)
+            .unwrap();
+        let child_root = SequentialPages::from_pages(
+            [root_base]
+                .into_iter()
+                .chain(host_pages.by_ref().take(3))
+                .map(|p| {
+                    page_tracker
+                        .assign_page_for_internal_state(p, child_id)
+                        .unwrap()
+                }),
+        )
+        .unwrap();
+        let child_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(child_root, child_id, page_tracker.clone())
+                .expect("creating sv48x4");
+        let mut child_pages = host_pages.by_ref().take(3).map(|p| {
+            page_tracker
+                .assign_page_for_internal_state(p, child_id)
+                .unwrap()
+        });
+        let child_gpa = PageAddr::new(RawAddr::guest(0x8000_0000, child_id)).unwrap();
+        let child_regions = spin::Mutex::new(MemoryRegions::new());
+        child_regions
+            .lock()
+            .insert(MemoryRegion::new(child_gpa, 2, RegionState::Shared).unwrap())
+            .unwrap();
+
+        // Shares the pages with the child the way the hypervisor does, holding the child's regions
+        // lock from checking that the destination range is shared until the pages have been mapped.
+        let share_with_child = |get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>| {
+            let regions = child_regions.lock();
+            let range = MemoryRegion::new(child_gpa, 2, RegionState::Shared).unwrap();
+            regions.find(&range)?;
+            let mapper = child_page_table
+                .map_range(child_gpa, PageSize::Size4k, 2, get_pte_page)
+                .ok()?;
+            let shared_pages = guest_page_table.share_range(gpa_base, 2, child_id).ok()?;
+            for (page, gpa) in shared_pages.zip(child_gpa.iter_from()) {
+                mapper.map_page(gpa, page, PteLeafPerms::RW).unwrap();
+            }
+            Some(())
+        };
+        assert!(share_with_child(&mut || child_pages.next()).is_some());
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| page_tracker.is_shared_page(addr, child_id)));
+
+        // The child unshares the region, after which its parent can't map pages into it.
+        let version = TlbVersion::new();
+        {
+            let mut regions = child_regions.lock();
+            let range = MemoryRegion::new(child_gpa, 2, RegionState::Shared).unwrap();
+            let index = regions.find(&range).unwrap();
+            assert!(regions.check_carve(index, &range, true).is_ok());
+            assert!(child_page_table
+                .invalidate_mapped_range(child_gpa, 2, &mut || None)
+                .is_ok());
+            regions.carve(index, &range, Some(RegionState::Unsharing(version)));
+        }
+        assert!(share_with_child(&mut || child_pages.next()).is_none());
+
+        // Nothing was mapped into the pending region, so releasing it once fenced stops sharing the
+        // pages and leaves the range unmapped.
+        child_regions.lock().complete_fenced(
+            |v| v == version,
+            |region| {
+                child_page_table
+                    .release_invalidated_range(region.addr(), region.num_pages())
+                    .unwrap();
+            },
+        );
+        assert!(child_page_table.is_unmapped_range(child_gpa, 2));
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| !page_tracker.is_shared_page(addr, child_id)));
+        assert!(page_addrs
+            .iter()
+            .all(|&addr| page_tracker.is_mapped_page(addr, id, MemType::Ram)));
+    }
+
+    #[test]
+    fn invalidate_and_release_range_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        // Map two pages with a hole between them.
+        let pages_to_map = [host_pages.next().unwrap(), host_pages.next().unwrap()];
+        let page_addrs: Vec<SupervisorPageAddr> = pages_to_map.iter().map(|p| p.addr()).collect();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        for (page, gpa) in pages_to_map
+            .into_iter()
+            .zip(gpa_base.iter_from().step_by(2))
+        {
+            let mapper = guest_page_table
+                .map_range(gpa, PageSize::Size4k, 1, &mut || pte_pages.next())
+                .unwrap();
+            let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        }
+
+        guest_page_table
+            .invalidate_mapped_range(gpa_base, 3, &mut || None)
+            .unwrap();
+        // Only invalidated pages can be made valid again.
+        assert!(guest_page_table
+            .validate_range(gpa_base, 3, &mut || None)
+            .is_err());
+        guest_page_table
+            .validate_range(gpa_base, 1, &mut || None)
+            .unwrap();
+        assert!(guest_page_table
+            .release_invalidated_range(gpa_base, 3)
+            .is_err());
+        guest_page_table
+            .invalidate_mapped_range(gpa_base, 1, &mut || None)
+            .unwrap();
+
+        guest_page_table
+            .release_invalidated_range(gpa_base, 3)
+            .unwrap();
+        assert!(page_addrs.iter().all(|&addr| !page_tracker.is_mapped_page(
+            addr,
+            id,
+            MemType::Ram
+        )));
+        // The range is now unmapped.
+        assert!(guest_page_table
+            .map_range(gpa_base, PageSize::Size4k, 3, &mut || None)
+            .is_ok());
+    }
+
+    #[test]
+    fn fault_in_released_page_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let page = host_pages.next().unwrap();
+        let page_addr = page.addr();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa, PageSize::Size4k, 1, &mut || pte_pages.next())
+            .unwrap();
+        let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+        assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        drop(mapper);
+
+        // Nothing to fault in for valid or unmapped addresses.
+        assert!(!guest_page_table.do_fault(RawAddr::from(gpa)));
+        let unmapped_gpa = gpa.checked_add_pages(1).unwrap();
+        assert!(!guest_page_table.do_fault(RawAddr::from(unmapped_gpa)));
+
+        let version = TlbVersion::new();
+        guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 1, &mut || None)
+            .unwrap()
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+        // Pages that are still being converted aren't faulted back in.
+        assert!(!guest_page_table.do_fault(RawAddr::from(gpa)));
+
+        // Nor are pages that completed conversion but were never assigned to a child.
+        let version = version.increment();
+        drop(
+            guest_page_table
+                .get_converted_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1, version)
+                .unwrap(),
+        );
+        assert!(page_tracker.is_converted_page(page_addr, id, MemType::Ram, version));
+        assert!(!guest_page_table.do_fault(RawAddr::from(gpa)));
+
+        // Assign the page to a child, dirty it, and then release it as if the child exited.
+        let child_id = page_tracker.add_active_guest().unwrap();
+        let mut converted_pages = guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1, version)
+            .unwrap();
+        let child_page = page_tracker
+            .assign_page_for_mapping(converted_pages.next().unwrap().clean(), child_id)
+            .unwrap();
+        unsafe {
+            // Not safe - just a test
+            *(page_addr.bits() as *mut u64) = 0xdeadbeef;
+        }
+        page_tracker.release_page(child_page).unwrap();
+        assert!(!page_tracker.is_mapped_page(page_addr, id, MemType::Ram));
+
+        // The parent faults the page back in, cleared.
+        assert!(guest_page_table.do_fault(RawAddr::from(gpa)));
+        assert!(page_tracker.is_mapped_page(page_addr, id, MemType::Ram));
+        assert_eq!(unsafe { *(page_addr.bits() as *const u64) }, 0);
+        assert!(!guest_page_table.do_fault(RawAddr::from(gpa)));
+    }
+
+    #[test]
+    fn reclaim_unfenced_pages_sv48x4() {
+        let state = stub_sys_memory();
+
+        let page_tracker = state.page_tracker;
+        let mut host_pages = state.host_pages;
+        let id = page_tracker.add_active_guest().unwrap();
+        let guest_page_table: PlatformPageTable<Sv48x4> =
+            PlatformPageTable::new(state.root_pages, id, page_tracker.clone())
+                .expect("creating sv48x4");
+
+        let pages_to_map = [host_pages.next().unwrap(), host_pages.next().unwrap()];
+        let page_addrs: Vec<SupervisorPageAddr> = pages_to_map.iter().map(|p| p.addr()).collect();
+        let mut pte_pages = state.pte_pages.into_iter();
+        let gpa_base = PageAddr::new(RawAddr::guest(0x8000_0000, PageOwnerId::host())).unwrap();
+        let mapper = guest_page_table
+            .map_range(gpa_base, PageSize::Size4k, 2, &mut || pte_pages.next())
+            .unwrap();
+        for (page, gpa) in pages_to_map.into_iter().zip(gpa_base.iter_from()) {
+            let mappable = page_tracker.assign_page_for_mapping(page, id).unwrap();
+            assert!(mapper.map_page(gpa, mappable, PteLeafPerms::RWX).is_ok());
+        }
+        drop(mapper);
+
+        // Convert the first page and complete its conversion, but leave the second one unfenced.
+        let version = TlbVersion::new();
+        guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa_base, PageSize::Size4k, 1, &mut || None)
+            .unwrap()
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+        let version = version.increment();
+        let gpa = gpa_base.checked_add_pages(1).unwrap();
+        guest_page_table
+            .invalidate_range::<Page<Invalidated>>(gpa, PageSize::Size4k, 1, &mut || None)
+            .unwrap()
+            .for_each(|invalidated| page_tracker.convert_page(invalidated, version).unwrap());
+        assert!(guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa_base, PageSize::Size4k, 2, version)
+            .is_err());
+
+        // Both pages can be reclaimed regardless of whether they've been fenced.
+        let mut reclaimed_pages = guest_page_table
+            .get_reclaimable_range::<Page<ConvertedDirty>>(gpa_base, PageSize::Size4k, 2)
+            .unwrap();
+        let fenced_page = reclaimed_pages.next().unwrap();
+        assert_eq!(fenced_page.addr(), page_addrs[0]);
+        page_tracker.put_converted_page(fenced_page).unwrap();
+        let unfenced_page = reclaimed_pages.next().unwrap();
+        assert_eq!(unfenced_page.addr(), page_addrs[1]);
+        assert!(guest_page_table
+            .get_converted_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1, version)
+            .is_err());
+        // Dropping the lock leaves the page converting at the same version.
+        page_tracker.put_converted_page(unfenced_page).unwrap();
+        assert!(!page_tracker.is_converted_page(page_addrs[1], id, MemType::Ram, version));
+        assert!(page_tracker.is_converted_page(
+            page_addrs[1],
+            id,
+            MemType::Ram,
+            version.increment()
+        ));
+
+        let mut reclaimed_pages = guest_page_table
+            .get_reclaimable_range::<Page<ConvertedDirty>>(gpa, PageSize::Size4k, 1)
+            .unwrap();
+        let page = reclaimed_pages.next().unwrap().clean();
+        page_tracker.reclaim_page(page).unwrap();
+        assert!(page_tracker.is_mapped_page(page_addrs[1], id, MemType::Ram));
+    }
 }
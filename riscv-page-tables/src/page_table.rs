@@ -7,7 +7,7 @@ use page_tracking::{LockedPageList, PageList, PageTracker, TlbVersion};
 use riscv_pages::*;
 use spin::Mutex;
 
-use crate::pte::{Pte, PteFieldBit, PteFieldBits, PteLeafPerms};
+use crate::pte::{Pte, PteFieldBit, PteFieldBits, PteLeafPerms, PteMemType};
 
 pub(crate) const ENTRIES_PER_PAGE: u64 = 4096 / 8;
 
@@ -38,6 +38,12 @@ pub enum Error {
     PteNotLocked,
     /// The page was not in the range that the `PageTableMapper` covers.
     OutOfMapRange,
+    /// The address isn't aligned to the requested page size.
+    MisalignedAddress,
+    /// The requested range would overflow the mapped address space.
+    AddressOverflow,
+    /// The pages backing a huge page are not physically contiguous and aligned.
+    NonContiguousPages,
 }
 /// Hold the result of page table operations.
 pub type Result<T> = core::result::Result<T, Error>;
@@ -96,6 +102,15 @@ enum TableEntryType<'a, T: PagingMode> {
     Table(PageTablePte<'a, T>),
 }
 
+/// The conversion state required of pages taken from invalidated mappings.
+#[derive(Clone, Copy)]
+enum ConversionState {
+    /// The page must have been converted at a TLB version older than the given version.
+    Fenced(TlbVersion),
+    /// The page may still be converting. Such pages can only be reclaimed.
+    Any,
+}
+
 impl<'a, T: PagingMode> TableEntryType<'a, T> {
     /// Creates a `TableEntryType` by inspecting the passed `pte` and determining its type.
     fn from_pte(pte: &'a mut Pte, level: T::Level) -> Self {
@@ -137,6 +152,39 @@ impl<'a, T: PagingMode, S> TableEntryMut<'a, T, S> {
     fn level(&self) -> T::Level {
         self.level
     }
+
+    /// Replaces this huge page entry with a pointer to a next-level table at `table_paddr`, filling
+    /// the table with entries that cover the same physical range with the same status bits.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that `table_paddr` references a clean page-table page uniquely
+    /// owned by the root `PlatformPageTable`.
+    unsafe fn split(self, table_paddr: SupervisorPageAddr) -> PageTablePte<'a, T> {
+        // Unwrap ok, only entries at non-leaf levels can map huge pages.
+        let next_level = self.level.next().unwrap();
+        let valid = self.pte.valid();
+        let status = self.pte.status();
+        let base_pfn = self.pte.pfn().bits();
+        let pfn_step = PageSize::num_4k_pages(next_level.leaf_page_size() as u64);
+        let mut table = PageTable::<T> {
+            table_addr: table_paddr,
+            level: next_level,
+            phantom: PhantomData,
+        };
+        for index in PageTableIndexIter::<T>::new(next_level) {
+            let pfn = Pfn::supervisor(base_pfn + index.index() * pfn_step);
+            let pte = table.entry_mut(index);
+            pte.set(pfn, &status);
+            if !valid {
+                pte.invalidate();
+            }
+        }
+        // Only point to the new table once it's been fully populated so that the translation of
+        // the range never changes.
+        self.pte.set(table_paddr.pfn(), &PteFieldBits::non_leaf());
+        PageTablePte::new(self.pte, self.level)
+    }
 }
 
 impl<'a, T: PagingMode> UnusedPte<'a, T> {
@@ -172,21 +220,39 @@ impl<'a, T: PagingMode> InvalidatedPte<'a, T> {
         self.pte.lock();
         LockedPte::new(self.pte, self.level)
     }
+
+    /// Marks this PTE as valid, restoring the translation it held before it was invalidated.
+    fn mark_valid(self) -> LeafPte<'a, T> {
+        self.pte.mark_valid();
+        LeafPte::new(self.pte, self.level)
+    }
+
+    /// Clears this PTE, dropping its reference to the page it would map.
+    fn clear(self) -> UnusedPte<'a, T> {
+        self.pte.clear();
+        UnusedPte::new(self.pte, self.level)
+    }
 }
 
 impl<'a, T: PagingMode> LockedPte<'a, T> {
-    /// Marks this PTE as valid and maps it to `paddr` with the specified permissions. Returns this
-    /// entry as a valid leaf entry.
+    /// Marks this PTE as valid and maps it to `paddr` with the specified permissions and memory
+    /// type. Returns this entry as a valid leaf entry.
     ///
     /// # Safety
     ///
     /// The caller must guarantee that `paddr` references a page uniquely owned by the root
     /// `PlatformPageTable`.
-    unsafe fn map_leaf(self, paddr: SupervisorPageAddr, perms: PteLeafPerms) -> LeafPte<'a, T> {
+    unsafe fn map_leaf(
+        self,
+        paddr: SupervisorPageAddr,
+        perms: PteLeafPerms,
+        mem_type: PteMemType,
+    ) -> LeafPte<'a, T> {
         assert!(paddr.is_aligned(self.level.leaf_page_size()));
         let status = {
             let mut s = PteFieldBits::leaf_with_perms(perms);
             s.set_bit(PteFieldBit::User);
+            s.set_mem_type(mem_type);
             s
         };
         self.pte.set(paddr.pfn(), &status);
@@ -217,6 +283,12 @@ impl<'a, T: PagingMode> LeafPte<'a, T> {
         self.pte.invalidate();
         InvalidatedPte::new(self.pte, self.level)
     }
+
+    /// Changes the permissions of the page this PTE maps to `perms`.
+    fn set_perms(self, perms: PteLeafPerms) -> Self {
+        self.pte.set_perms(perms);
+        self
+    }
 }
 
 impl<'a, T: PagingMode> PageTablePte<'a, T> {
@@ -327,6 +399,14 @@ impl<'a, T: PagingMode> PageTable<'a, T> {
                     u.map_table(pt_page.addr())
                 }
             }
+            Invalidated(i) => {
+                // Split the invalidated huge page so that part of it can be mapped.
+                let pt_page = get_pte_page().ok_or(Error::InsufficientPtePages)?;
+                unsafe {
+                    // Safe since we have unique ownership of `pt_page`.
+                    i.split(pt_page.addr())
+                }
+            }
             _ => {
                 return Err(Error::MappingExists);
             }
@@ -353,18 +433,12 @@ impl<'a, T: PagingMode> PageTable<'a, T> {
                     page_tracker.release_page(table_page).unwrap();
                 }
                 Leaf(l) => {
-                    // Unwrap ok since by virtue of being mapped into this page table, we must
-                    // uniquely own the page and it must be in a releasable state.
-                    page_tracker
-                        .release_page_by_addr(l.page_addr(), owner)
-                        .unwrap();
+                    release_leaf_page(&page_tracker, l.page_addr(), l.level(), owner);
                 }
                 Invalidated(i) => {
-                    // Unwrap ok since the only usage of invalid PTEs we currently have is for
-                    // converted pages.
-                    page_tracker
-                        .release_page_by_addr(i.page_addr(), owner)
-                        .unwrap();
+                    // Invalid PTEs either hold converted pages or pages that were unmapped from
+                    // this page table but not yet released, both of which we can release.
+                    release_leaf_page(&page_tracker, i.page_addr(), i.level(), owner);
                 }
                 _ => (),
             }
@@ -372,6 +446,27 @@ impl<'a, T: PagingMode> PageTable<'a, T> {
     }
 }
 
+/// Releases the page at `page_addr` referenced by a leaf PTE at `level` in a page table belonging
+/// to `owner`. Pages shared with `owner` remain owned by their parent, so we just stop sharing
+/// them.
+fn release_leaf_page<L: PageTableLevel>(
+    page_tracker: &PageTracker,
+    page_addr: SupervisorPageAddr,
+    level: L,
+    owner: PageOwnerId,
+) {
+    if page_tracker.is_shared_page(page_addr, owner) {
+        // Unwrap ok since we just checked that the page is shared with us.
+        page_tracker.unshare_page(page_addr, owner).unwrap();
+    } else {
+        // Unwrap ok since by virtue of being referenced by this page table, we must uniquely own
+        // the page and it must be in a releasable state.
+        page_tracker
+            .release_page_by_addr(page_addr, level.leaf_page_size(), owner)
+            .unwrap();
+    }
+}
+
 /// An index to an entry in a page table.
 trait PteIndex {
     /// Returns the offset in bytes of the index
@@ -479,6 +574,8 @@ struct PageTableInner<T: PagingMode> {
     root: SequentialPages<InternalClean>,
     owner: PageOwnerId,
     page_tracker: PageTracker,
+    // True if leaf entries may carry Svpbmt memory types.
+    svpbmt: bool,
     table_type: PhantomData<T>,
 }
 
@@ -504,6 +601,7 @@ impl<T: PagingMode> PageTableInner<T> {
             root,
             owner,
             page_tracker,
+            svpbmt: false,
             table_type: PhantomData,
         })
     }
@@ -519,27 +617,35 @@ impl<T: PagingMode> PageTableInner<T> {
         entry
     }
 
-    /// Creates a translation for `vaddr` to `paddr` with the given permissions.
+    /// Creates a `page_size` translation for `vaddr` to `paddr` with the given permissions and
+    /// memory type, returning the new leaf PTE. The memory type is ignored unless Svpbmt has been
+    /// enabled for this page table.
     ///
     /// # Safety
     ///
     /// The caller must guarantee that `paddr` references a page uniquely owned by the root
     /// `PlatformPageTable`.
-    unsafe fn map_4k_leaf(
+    unsafe fn map_leaf(
         &mut self,
         vaddr: PageAddr<T::MappedAddressSpace>,
         paddr: SupervisorPageAddr,
+        page_size: PageSize,
         perms: PteLeafPerms,
-    ) -> Result<()> {
+        mem_type: PteMemType,
+    ) -> Result<LeafPte<T>> {
+        let mem_type = if self.svpbmt {
+            mem_type
+        } else {
+            PteMemType::PMA
+        };
         let entry = self.walk(RawAddr::from(vaddr));
         use TableEntryType::*;
         match entry {
             Locked(l) => {
-                if !l.level().is_leaf() {
-                    return Err(Error::PageSizeNotSupported(l.level().leaf_page_size()));
+                if l.level().leaf_page_size() != page_size {
+                    return Err(Error::PageSizeNotSupported(page_size));
                 }
-                l.map_leaf(paddr, perms);
-                Ok(())
+                Ok(l.map_leaf(paddr, perms, mem_type))
             }
             Unused(_) | Invalidated(_) => Err(Error::PteNotLocked),
             Leaf(_) => Err(Error::MappingExists),
@@ -547,15 +653,19 @@ impl<T: PagingMode> PageTableInner<T> {
         }
     }
 
-    /// Locks the invalid leaf PTE mapping `vaddr`, filling in any missing intermediate page tables
-    /// using `get_pte_page`.
-    fn lock_4k_leaf_for_mapping(
+    /// Locks the invalid `page_size` leaf PTE mapping `vaddr`, filling in any missing intermediate
+    /// page tables using `get_pte_page`.
+    fn lock_leaf_for_mapping(
         &mut self,
         vaddr: PageAddr<T::MappedAddressSpace>,
+        page_size: PageSize,
         get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
     ) -> Result<()> {
         let mut table = PageTable::from_root(self);
-        while !table.level().is_leaf() {
+        while table.level().leaf_page_size() != page_size {
+            if table.level().is_leaf() {
+                return Err(Error::PageSizeNotSupported(page_size));
+            }
             table = table.next_level_or_fill_fn(RawAddr::from(vaddr), get_pte_page)?;
         }
         let entry = table.entry_for_addr_mut(RawAddr::from(vaddr));
@@ -570,17 +680,21 @@ impl<T: PagingMode> PageTableInner<T> {
                 Ok(())
             }
             Locked(_) => Err(Error::PteLocked),
-            Leaf(_) => Err(Error::MappingExists),
-            Table(_) => unreachable!(),
+            // We can't map a huge page over a range that is already (partially) mapped.
+            Leaf(_) | Table(_) => Err(Error::MappingExists),
         }
     }
 
-    /// Unlocks the leaf PTE mapping `vaddr`.
-    fn unlock_4k_leaf(&mut self, vaddr: PageAddr<T::MappedAddressSpace>) -> Result<()> {
+    /// Unlocks the `page_size` leaf PTE mapping `vaddr`.
+    fn unlock_leaf(
+        &mut self,
+        vaddr: PageAddr<T::MappedAddressSpace>,
+        page_size: PageSize,
+    ) -> Result<()> {
         let entry = self.walk(RawAddr::from(vaddr));
         use TableEntryType::*;
         match entry {
-            Locked(l) => {
+            Locked(l) if l.level().leaf_page_size() == page_size => {
                 l.unlock();
                 Ok(())
             }
@@ -588,9 +702,46 @@ impl<T: PagingMode> PageTableInner<T> {
         }
     }
 
-    /// Returns the valid 4kB leaf PTE mapping `vaddr` if the mapped page matches the specified
-    /// `mem_type`.
-    fn get_mapped_4k_leaf(
+    /// Splits the valid or invalidated huge leaf mapping `vaddr`, if any, until `vaddr` is the base
+    /// address of the leaf that maps it. Page-table pages for the new tables are taken from
+    /// `get_pte_page`.
+    fn split_leaf_at(
+        &mut self,
+        vaddr: PageAddr<T::MappedAddressSpace>,
+        get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
+    ) -> Result<()> {
+        let mut table = PageTable::from_root(self);
+        loop {
+            let aligned = vaddr.is_aligned(table.level().leaf_page_size());
+            use TableEntryType::*;
+            table = match table.entry_for_addr_mut(RawAddr::from(vaddr)) {
+                Table(t) => t.table(),
+                Leaf(l) if !aligned => {
+                    let pt_page = get_pte_page().ok_or(Error::InsufficientPtePages)?;
+                    // Safe since we have unique ownership of `pt_page`.
+                    unsafe { l.split(pt_page.addr()) }.table()
+                }
+                Invalidated(i) if !aligned => {
+                    let pt_page = get_pte_page().ok_or(Error::InsufficientPtePages)?;
+                    // Safe since we have unique ownership of `pt_page`.
+                    unsafe { i.split(pt_page.addr()) }.table()
+                }
+                _ => return Ok(()),
+            };
+        }
+    }
+
+    /// Returns if the page at `page_addr` referenced by a leaf PTE in this page table is either a
+    /// Mapped page owned by us or a page shared with us.
+    fn is_mapped_or_shared_page(&self, page_addr: SupervisorPageAddr) -> bool {
+        self.page_tracker
+            .is_mapped_page(page_addr, self.owner, MemType::Ram)
+            || self.page_tracker.is_shared_page(page_addr, self.owner)
+    }
+
+    /// Returns the valid leaf PTE mapping `vaddr` if the mapped page matches the specified
+    /// `mem_type`. The leaf may map a page of any size.
+    fn get_mapped_leaf(
         &mut self,
         vaddr: PageAddr<T::MappedAddressSpace>,
         mem_type: MemType,
@@ -601,9 +752,6 @@ impl<T: PagingMode> PageTableInner<T> {
         use TableEntryType::*;
         match entry {
             Leaf(l) => {
-                if !l.level().is_leaf() {
-                    return Err(Error::PageSizeNotSupported(l.level().leaf_page_size()));
-                }
                 if !page_tracker.is_mapped_page(l.page_addr(), owner, mem_type) {
                     return Err(Error::PageNotUnmappable);
                 }
@@ -613,27 +761,39 @@ impl<T: PagingMode> PageTableInner<T> {
         }
     }
 
-    /// Returns the invalid 4kB leaf PTE mapping `vaddr` if the PFN the PTE references is a
-    /// page that was converted at a TLB version older than `tlb_version`.
-    fn get_converted_4k_leaf(
+    /// Returns the address of the 4kB page that `vaddr` would translate to if the invalid leaf PTE
+    /// mapping it references a page that is in the conversion state `state`.
+    fn get_converted_page_addr(
         &mut self,
         vaddr: PageAddr<T::MappedAddressSpace>,
         mem_type: MemType,
-        tlb_version: TlbVersion,
-    ) -> Result<InvalidatedPte<T>> {
+        state: ConversionState,
+    ) -> Result<SupervisorPageAddr> {
         let page_tracker = self.page_tracker.clone();
         let owner = self.owner;
         let entry = self.walk(RawAddr::from(vaddr));
         use TableEntryType::*;
         match entry {
             Invalidated(i) => {
-                if !i.level().is_leaf() {
-                    return Err(Error::PageSizeNotSupported(i.level().leaf_page_size()));
-                }
-                if !page_tracker.is_converted_page(i.page_addr(), owner, mem_type, tlb_version) {
+                let leaf_size = i.level().leaf_page_size();
+                let offset = vaddr.bits() - leaf_size.round_down(vaddr.bits());
+                // Unwrap ok since the offset is within the invalidated page.
+                let paddr = i
+                    .page_addr()
+                    .checked_add_pages(PageSize::num_4k_pages(offset))
+                    .unwrap();
+                let converted = match state {
+                    ConversionState::Fenced(tlb_version) => {
+                        page_tracker.is_converted_page(paddr, owner, mem_type, tlb_version)
+                    }
+                    ConversionState::Any => {
+                        page_tracker.is_reclaimable_page(paddr, owner, mem_type)
+                    }
+                };
+                if !converted {
                     return Err(Error::PageNotConverted);
                 }
-                Ok(i)
+                Ok(paddr)
             }
             _ => Err(Error::PageNotConverted),
         }
@@ -661,8 +821,6 @@ impl<T: PagingMode> Drop for PageTableInner<T> {
 }
 
 /// A paging hierarchy for a given addressing type.
-///
-/// TODO: Support non-4k page sizes.
 pub struct PlatformPageTable<T: PagingMode> {
     inner: Mutex<PageTableInner<T>>,
 }
@@ -681,6 +839,13 @@ impl<T: PagingMode> PlatformPageTable<T> {
         })
     }
 
+    /// Allows leaf entries to be mapped with Svpbmt memory types. Until this is called every page
+    /// is mapped with the memory type given by its PMAs, regardless of the memory type requested.
+    /// Must only be called if the CPU supports Svpbmt.
+    pub fn enable_svpbmt(&self) {
+        self.inner.lock().svpbmt = true;
+    }
+
     /// Returns a reference to the systems physical pages map.
     pub fn page_tracker(&self) -> PageTracker {
         self.inner.lock().page_tracker.clone()
@@ -697,10 +862,57 @@ impl<T: PagingMode> PlatformPageTable<T> {
         self.inner.lock().root.base()
     }
 
-    /// Handles a fault from the owner of this page table.
-    pub fn do_fault(&self, _addr: RawAddr<T::MappedAddressSpace>) -> bool {
-        // At the moment we have no reason to take a page fault.
-        false
+    /// Handles a fault from the owner of this page table. Pages that were assigned to a child VM
+    /// are left invalidated in our page table when the child is torn down and are demand-faulted
+    /// back in here: if the invalidated leaf mapping `addr` references a page that the child
+    /// released back to us, the page is cleared and its mapping is restored. Returns true if the
+    /// fault was handled.
+    ///
+    /// Pages we converted but never assigned, or that are still Converting, are never touched.
+    pub fn do_fault(&self, addr: RawAddr<T::MappedAddressSpace>) -> bool {
+        let mut inner = self.inner.lock();
+        let page_tracker = inner.page_tracker.clone();
+        let owner = inner.owner;
+        let entry = inner.walk(addr);
+        use TableEntryType::*;
+        let invalidated = match entry {
+            Invalidated(i) => i,
+            _ => return false,
+        };
+        let page = match page_tracker.get_released_page::<Page<ConvertedDirty>>(
+            invalidated.page_addr(),
+            invalidated.level().leaf_page_size(),
+            owner,
+        ) {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        // Unwrap ok since we hold an exclusive reference to the converted page.
+        page_tracker.reclaim_page(page.clean()).unwrap();
+        invalidated.mark_valid();
+        true
+    }
+
+    /// Returns if none of the `num_pages` 4kB pages starting at `addr` are mapped, or are in the
+    /// process of being mapped or unmapped, in this page table.
+    pub fn is_unmapped_range(&self, addr: PageAddr<T::MappedAddressSpace>, num_pages: u64) -> bool {
+        let end = match addr.checked_add_pages(num_pages) {
+            Some(end) => end,
+            None => return false,
+        };
+        let mut inner = self.inner.lock();
+        let mut a = addr;
+        while a < end {
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                TableEntryType::Unused(u) => u.level().leaf_page_size(),
+                _ => return false,
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        true
     }
 
     /// Prepares for mapping `num_pages` pages of size `page_size` starting at `addr` in the mapped
@@ -714,60 +926,357 @@ impl<T: PagingMode> PlatformPageTable<T> {
         num_pages: u64,
         get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
     ) -> Result<PageTableMapper<T>> {
-        if page_size.is_huge() {
-            return Err(Error::PageSizeNotSupported(page_size));
-        }
-
-        let mut mapper = PageTableMapper::new(self, addr, 0);
+        let page_addrs = addr
+            .iter_from_with_size(page_size)
+            .ok_or(Error::MisalignedAddress)?;
+        let mut mapper = PageTableMapper::new(self, addr, page_size, 0);
         let mut inner = self.inner.lock();
-        for a in addr.iter_from().take(num_pages as usize) {
-            inner.lock_4k_leaf_for_mapping(a, get_pte_page)?;
+        for a in page_addrs.take(num_pages as usize) {
+            inner.lock_leaf_for_mapping(a, page_size, get_pte_page)?;
             mapper.num_pages += 1;
         }
 
         Ok(mapper)
     }
 
-    /// Returns a list of invalidated pages for the given range.
+    /// Invalidates the `num_pages` pages of size `page_size` starting at `addr`, returning a list
+    /// of the invalidated pages. Huge leaf mappings that straddle the boundaries of the range are
+    /// first split using page-table pages from `get_pte_page`. The returned list holds 4kB pages,
+    /// regardless of the size of the leaf mappings that were invalidated.
     pub fn invalidate_range<P: InvalidatedPhysPage>(
         &self,
         addr: PageAddr<T::MappedAddressSpace>,
         page_size: PageSize,
         num_pages: u64,
+        get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
     ) -> Result<PageList<P>> {
-        if page_size.is_huge() {
-            return Err(Error::PageSizeNotSupported(page_size));
+        if !addr.is_aligned(page_size) {
+            return Err(Error::MisalignedAddress);
         }
+        let end = addr
+            .checked_add_pages_with_size(num_pages, page_size)
+            .ok_or(Error::AddressOverflow)?;
 
         let mut inner = self.inner.lock();
         // First make sure the entire range can be unmapped before we start invalidating things.
-        if !addr
-            .iter_from()
-            .take(num_pages as usize)
-            .all(|a| inner.get_mapped_4k_leaf(a, P::mem_type()).is_ok())
-        {
-            return Err(Error::PageNotUnmappable);
+        let mut a = addr;
+        while a < end {
+            let leaf_size = inner
+                .get_mapped_leaf(a, P::mem_type())
+                .map_err(|_| Error::PageNotUnmappable)?
+                .level()
+                .leaf_page_size();
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
         }
 
+        // Break up any huge pages that are only partially covered by the range. Splitting doesn't
+        // change the translation of any address, so there's nothing to undo if this fails.
+        inner.split_leaf_at(addr, get_pte_page)?;
+        inner.split_leaf_at(end, get_pte_page)?;
+
         let mut pages = PageList::new(inner.page_tracker.clone());
-        for a in addr.iter_from().take(num_pages as usize) {
+        let mut a = addr;
+        while a < end {
             // We verified above that we can safely unwrap here.
-            let entry = inner.get_mapped_4k_leaf(a, P::mem_type()).unwrap();
+            let entry = inner.get_mapped_leaf(a, P::mem_type()).unwrap();
+            let leaf_size = entry.level().leaf_page_size();
             let invalidated = entry.invalidate();
-            let page = unsafe {
-                // Safe since we've verified the typing of the page.
-                P::new(invalidated.page_addr())
+            for paddr in invalidated
+                .page_addr()
+                .iter_from()
+                .take(PageSize::num_4k_pages(leaf_size as u64) as usize)
+            {
+                let page = unsafe {
+                    // Safe since we've verified the typing of the page.
+                    P::new(paddr)
+                };
+                // Unwrap ok, a just-invalidated page can't be on any other PageList.
+                pages.push(page).unwrap();
+            }
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
             };
-            // Unwrap ok, a just-invalidated page can't be on any other PageList.
+        }
+
+        Ok(pages)
+    }
+
+    /// Shares the `num_pages` 4kB pages mapped at `addr` with the child VM `with`, returning a list
+    /// of the shared pages. The pages remain mapped in this page table, but may then be mapped into
+    /// the child's page table as well. Only pages mapped with 4kB leaf mappings may be shared.
+    pub fn share_range(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        num_pages: u64,
+        with: PageOwnerId,
+    ) -> Result<PageList<Page<Shared>>> {
+        let mut inner = self.inner.lock();
+        // First make sure the entire range can be shared before we start changing page state.
+        for a in addr.iter_from().take(num_pages as usize) {
+            let leaf_size = inner
+                .get_mapped_leaf(a, MemType::Ram)?
+                .level()
+                .leaf_page_size();
+            if leaf_size != PageSize::Size4k {
+                return Err(Error::PageSizeNotSupported(leaf_size));
+            }
+        }
+
+        let page_tracker = inner.page_tracker.clone();
+        let owner = inner.owner;
+        let mut pages = PageList::new(page_tracker.clone());
+        for a in addr.iter_from().take(num_pages as usize) {
+            // Unwrap ok since we verified above that the page is mapped.
+            let paddr = inner.get_mapped_leaf(a, MemType::Ram).unwrap().page_addr();
+            // Unwrap ok since we own the page and it's in the Mapped state.
+            let page = page_tracker.share_page(paddr, owner, with).unwrap();
+            // Unwrap ok, a just-shared page can't be on any other PageList.
             pages.push(page).unwrap();
         }
 
         Ok(pages)
     }
 
-    /// Returns a list of converted pages that were previously mapped in this page table if they were
-    /// invalidated a TLB version older than `tlb_version`. Guarantees that the full range of pages
-    /// are converted pages.
+    /// Invalidates the leaf mappings in the range of `num_pages` 4kB pages starting at `addr`,
+    /// skipping over any unmapped parts of the range. The range may only map pages that are owned
+    /// by, or shared with, the owner of this page table. Huge leaf mappings that straddle the
+    /// boundaries of the range are first split using page-table pages from `get_pte_page`. The
+    /// pages remain referenced by the invalidated PTEs until they are released with
+    /// `release_invalidated_range()`.
+    pub fn invalidate_mapped_range(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        num_pages: u64,
+        get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
+    ) -> Result<()> {
+        let end = addr
+            .checked_add_pages(num_pages)
+            .ok_or(Error::AddressOverflow)?;
+
+        let mut inner = self.inner.lock();
+        // First make sure the entire range can be unmapped before we start invalidating things.
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let (leaf_size, page_addr) = match inner.walk(RawAddr::from(a)) {
+                Unused(u) => (u.level().leaf_page_size(), None),
+                Leaf(l) => (l.level().leaf_page_size(), Some(l.page_addr())),
+                _ => return Err(Error::PageNotUnmappable),
+            };
+            if let Some(page_addr) = page_addr && !inner.is_mapped_or_shared_page(page_addr) {
+                return Err(Error::PageNotUnmappable);
+            }
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        // Break up any huge pages that are only partially covered by the range. Splitting doesn't
+        // change the translation of any address, so there's nothing to undo if this fails.
+        inner.split_leaf_at(addr, get_pte_page)?;
+        inner.split_leaf_at(end, get_pte_page)?;
+
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Leaf(l) => l.invalidate().level().leaf_page_size(),
+                Unused(u) => u.level().leaf_page_size(),
+                // We verified above that the range only holds leaf and unused entries.
+                _ => unreachable!(),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Releases the pages referenced by the invalidated PTEs in the range of `num_pages` 4kB pages
+    /// starting at `addr`, clearing the PTEs. Pages that are shared with the owner of this page
+    /// table stop being shared, all other pages are returned to their previous owner. The range
+    /// must have previously been invalidated with `invalidate_mapped_range()`.
+    pub fn release_invalidated_range(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        num_pages: u64,
+    ) -> Result<()> {
+        let end = addr
+            .checked_add_pages(num_pages)
+            .ok_or(Error::AddressOverflow)?;
+
+        let mut inner = self.inner.lock();
+        // First make sure that every page in the range can be released.
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let (leaf_size, page_addr) = match inner.walk(RawAddr::from(a)) {
+                Unused(u) => (u.level().leaf_page_size(), None),
+                Invalidated(i) => (i.level().leaf_page_size(), Some(i.page_addr())),
+                _ => return Err(Error::PageNotUnmappable),
+            };
+            if let Some(page_addr) = page_addr {
+                // Invalidated pages must lie entirely within the range.
+                let next = next_page_addr(a, leaf_size);
+                if !a.is_aligned(leaf_size) || next.map_or(true, |n| n > end) {
+                    return Err(Error::MisalignedAddress);
+                }
+                if !inner.is_mapped_or_shared_page(page_addr) {
+                    return Err(Error::PageNotUnmappable);
+                }
+            }
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        let page_tracker = inner.page_tracker.clone();
+        let owner = inner.owner;
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Invalidated(i) => {
+                    release_leaf_page(&page_tracker, i.page_addr(), i.level(), owner);
+                    i.clear().level().leaf_page_size()
+                }
+                Unused(u) => u.level().leaf_page_size(),
+                // We verified above that the range only holds invalidated and unused entries.
+                _ => unreachable!(),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Makes the invalidated leaf mappings in the range of `num_pages` 4kB pages starting at `addr`
+    /// valid again. Every page in the range must be referenced by an invalidated PTE and be owned
+    /// by the owner of this page table. Huge invalidated mappings that straddle the boundaries of
+    /// the range are first split using page-table pages from `get_pte_page`.
+    pub fn validate_range(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        num_pages: u64,
+        get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
+    ) -> Result<()> {
+        let end = addr
+            .checked_add_pages(num_pages)
+            .ok_or(Error::AddressOverflow)?;
+
+        let mut inner = self.inner.lock();
+        let page_tracker = inner.page_tracker.clone();
+        let owner = inner.owner;
+        // First make sure the entire range is backed by invalidated pages that we own.
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Invalidated(i)
+                    if page_tracker.is_mapped_page(i.page_addr(), owner, MemType::Ram) =>
+                {
+                    i.level().leaf_page_size()
+                }
+                _ => return Err(Error::PageNotMapped),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        // Break up any huge pages that are only partially covered by the range.
+        inner.split_leaf_at(addr, get_pte_page)?;
+        inner.split_leaf_at(end, get_pte_page)?;
+
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Invalidated(i) => i.mark_valid().level().leaf_page_size(),
+                // We verified above that the range only holds invalidated entries.
+                _ => unreachable!(),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Changes the permissions of the leaf mappings in the range of `num_pages` 4kB pages starting
+    /// at `addr` to `perms`. Every page in the range must be mapped by a valid PTE and be owned by
+    /// the owner of this page table. Huge mappings that straddle the boundaries of the range are
+    /// first split using page-table pages from `get_pte_page`. The caller is responsible for
+    /// fencing any translations cached with the previous permissions.
+    pub fn protect_range(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        num_pages: u64,
+        perms: PteLeafPerms,
+        get_pte_page: &mut dyn FnMut() -> Option<Page<InternalClean>>,
+    ) -> Result<()> {
+        let end = addr
+            .checked_add_pages(num_pages)
+            .ok_or(Error::AddressOverflow)?;
+
+        let mut inner = self.inner.lock();
+        let page_tracker = inner.page_tracker.clone();
+        let owner = inner.owner;
+        // First make sure the entire range is backed by mapped pages that we own.
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Leaf(l) if page_tracker.is_mapped_page(l.page_addr(), owner, MemType::Ram) => {
+                    l.level().leaf_page_size()
+                }
+                _ => return Err(Error::PageNotMapped),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        // Break up any huge pages that are only partially covered by the range.
+        inner.split_leaf_at(addr, get_pte_page)?;
+        inner.split_leaf_at(end, get_pte_page)?;
+
+        let mut a = addr;
+        while a < end {
+            use TableEntryType::*;
+            let leaf_size = match inner.walk(RawAddr::from(a)) {
+                Leaf(l) => l.set_perms(perms).level().leaf_page_size(),
+                // We verified above that the range only holds leaf entries.
+                _ => unreachable!(),
+            };
+            a = match next_page_addr(a, leaf_size) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Returns a list of converted pages that were previously mapped in this page table if they
+    /// were invalidated a TLB version older than `tlb_version`. Guarantees that the full range of
+    /// pages are converted pages. Huge pages may be assembled from smaller invalidated mappings as
+    /// long as the backing pages are physically contiguous and aligned to `page_size`.
     pub fn get_converted_range<P: ConvertedPhysPage>(
         &self,
         addr: PageAddr<T::MappedAddressSpace>,
@@ -775,21 +1284,69 @@ impl<T: PagingMode> PlatformPageTable<T> {
         num_pages: u64,
         tlb_version: TlbVersion,
     ) -> Result<LockedPageList<P::DirtyPage>> {
-        if page_size.is_huge() {
-            return Err(Error::PageSizeNotSupported(page_size));
-        }
+        self.get_invalidated_range::<P>(
+            addr,
+            page_size,
+            num_pages,
+            ConversionState::Fenced(tlb_version),
+        )
+    }
+
+    /// Returns a list of pages that were previously mapped in this page table and have started
+    /// conversion, whether or not that conversion has been fenced, for reclaim by the owner. Pages
+    /// that are still converting can't be assigned. Guarantees that the full range of pages are
+    /// reclaimable.
+    pub fn get_reclaimable_range<P: ConvertedPhysPage>(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        page_size: PageSize,
+        num_pages: u64,
+    ) -> Result<LockedPageList<P::DirtyPage>> {
+        self.get_invalidated_range::<P>(addr, page_size, num_pages, ConversionState::Any)
+    }
+
+    /// Locks and returns the pages referenced by the invalidated mappings for `num_pages` of size
+    /// `page_size` starting at `addr` if they're all in the conversion state `state`.
+    fn get_invalidated_range<P: ConvertedPhysPage>(
+        &self,
+        addr: PageAddr<T::MappedAddressSpace>,
+        page_size: PageSize,
+        num_pages: u64,
+        state: ConversionState,
+    ) -> Result<LockedPageList<P::DirtyPage>> {
+        let page_addrs = addr
+            .iter_from_with_size(page_size)
+            .ok_or(Error::MisalignedAddress)?;
 
         let mut inner = self.inner.lock();
         let page_tracker = inner.page_tracker.clone();
-        let mut pages = LockedPageList::new(inner.page_tracker.clone());
-        for a in addr.iter_from().take(num_pages as usize) {
-            let paddr = inner
-                .get_converted_4k_leaf(a, P::mem_type(), tlb_version)?
-                .page_addr();
+        let mut pages = LockedPageList::new_with_size(inner.page_tracker.clone(), page_size);
+        for a in page_addrs.take(num_pages as usize) {
+            let paddr = inner.get_converted_page_addr(a, P::mem_type(), state)?;
+            if !paddr.is_aligned(page_size) {
+                return Err(Error::NonContiguousPages);
+            }
+            let num_4k_pages = PageSize::num_4k_pages(page_size as u64) as usize;
+            for (sub_addr, expected) in a
+                .iter_from()
+                .zip(paddr.iter_from())
+                .take(num_4k_pages)
+                .skip(1)
+            {
+                if inner.get_converted_page_addr(sub_addr, P::mem_type(), state)? != expected {
+                    return Err(Error::NonContiguousPages);
+                }
+            }
             // Unwrap ok since we've already verified that this page is owned and converted.
-            let page = page_tracker
-                .get_converted_page::<P>(paddr, inner.owner, tlb_version)
-                .unwrap();
+            let page = match state {
+                ConversionState::Fenced(tlb_version) => {
+                    page_tracker.get_converted_page::<P>(paddr, page_size, inner.owner, tlb_version)
+                }
+                ConversionState::Any => {
+                    page_tracker.get_reclaimable_page::<P>(paddr, page_size, inner.owner)
+                }
+            }
+            .unwrap();
             // Unwrap ok since we have unique ownership of the page and therefore it can't be on
             // any other list.
             pages.push(page).unwrap();
@@ -799,61 +1356,142 @@ impl<T: PagingMode> PlatformPageTable<T> {
     }
 }
 
+/// Returns the address of the page following the `page_size` page containing `addr`, or `None` if
+/// it would overflow the address space.
+fn next_page_addr<AS: AddressSpace>(
+    addr: PageAddr<AS>,
+    page_size: PageSize,
+) -> Option<PageAddr<AS>> {
+    PageAddr::with_round_down(RawAddr::from(addr), page_size)
+        .checked_add_pages_with_size(1, page_size)
+}
+
+/// Returns the memory type pages of type `P` are mapped with by default.
+fn default_mem_type<P: MappablePhysPage<M>, M: MeasureRequirement>() -> PteMemType {
+    match P::mem_type() {
+        MemType::Ram => PteMemType::PMA,
+        MemType::Mmio(_) => PteMemType::IO,
+    }
+}
+
 /// A range of mapped address space that has been locked for mapping. The PTEs are unlocked when
 /// this struct is dropped. Mapping a page in this range is guaranteed to succeed as long as the
 /// address hasn't already been mapped by this `PageTableMapper`.
 pub struct PageTableMapper<'a, T: PagingMode> {
     owner: &'a PlatformPageTable<T>,
     vaddr: PageAddr<T::MappedAddressSpace>,
+    page_size: PageSize,
     num_pages: u64,
 }
 
 impl<'a, T: PagingMode> PageTableMapper<'a, T> {
-    /// Creates a new `PageTableMapper` for `num_pages` starting at `vaddr`.
+    /// Creates a new `PageTableMapper` for `num_pages` of size `page_size` starting at `vaddr`.
     fn new(
         owner: &'a PlatformPageTable<T>,
         vaddr: PageAddr<T::MappedAddressSpace>,
+        page_size: PageSize,
         num_pages: u64,
     ) -> Self {
         Self {
             owner,
             vaddr,
+            page_size,
             num_pages,
         }
     }
 
-    /// Maps `vaddr` to `page_to_map`, consuming `page_to_map`.
-    ///
-    /// TODO: Page permissions.
-    pub fn map_page<P: MappablePhysPage<M>, M: MeasureRequirement>(
+    /// Checks that a page of size `page_size` can be mapped at `vaddr` by this `PageTableMapper`.
+    fn check_page_to_map(
         &self,
         vaddr: PageAddr<T::MappedAddressSpace>,
-        page_to_map: P,
+        page_size: PageSize,
     ) -> Result<()> {
-        if page_to_map.size().is_huge() {
-            return Err(Error::PageSizeNotSupported(page_to_map.size()));
+        if page_size != self.page_size {
+            return Err(Error::PageSizeNotSupported(page_size));
+        }
+        if !vaddr.is_aligned(self.page_size) {
+            return Err(Error::MisalignedAddress);
         }
-        let end_vaddr = self.vaddr.checked_add_pages(self.num_pages).unwrap();
+        let end_vaddr = self
+            .vaddr
+            .checked_add_pages_with_size(self.num_pages, self.page_size)
+            .unwrap();
         if vaddr < self.vaddr || vaddr >= end_vaddr {
             return Err(Error::OutOfMapRange);
         }
+        Ok(())
+    }
+
+    /// Maps `vaddr` to `page_to_map` with the permissions `perms`, consuming `page_to_map`. The
+    /// page must be of the size this `PageTableMapper` was created for. MMIO pages are mapped as
+    /// I/O memory, and RAM pages with the memory type given by their PMAs.
+    pub fn map_page<P: MappablePhysPage<M>, M: MeasureRequirement>(
+        &self,
+        vaddr: PageAddr<T::MappedAddressSpace>,
+        page_to_map: P,
+        perms: PteLeafPerms,
+    ) -> Result<()> {
+        self.map_page_with_mem_type(vaddr, page_to_map, perms, default_mem_type::<P, M>())
+    }
+
+    /// Same as `map_page()`, but maps the page with the memory type `mem_type`.
+    pub fn map_page_with_mem_type<P: MappablePhysPage<M>, M: MeasureRequirement>(
+        &self,
+        vaddr: PageAddr<T::MappedAddressSpace>,
+        page_to_map: P,
+        perms: PteLeafPerms,
+        mem_type: PteMemType,
+    ) -> Result<()> {
+        self.check_page_to_map(vaddr, page_to_map.size())?;
+        let mut inner = self.owner.inner.lock();
+        unsafe {
+            // Safe since we uniquely own page_to_map.
+            inner.map_leaf(vaddr, page_to_map.addr(), self.page_size, perms, mem_type)?;
+        }
+        Ok(())
+    }
 
+    /// Same as `map_page()`, but leaves the PTE mapping `vaddr` invalid. The page is referenced by
+    /// the page table, but can't be accessed until the mapping is made valid with
+    /// `PlatformPageTable::validate_range()`.
+    pub fn map_page_invalidated<P: MappablePhysPage<M>, M: MeasureRequirement>(
+        &self,
+        vaddr: PageAddr<T::MappedAddressSpace>,
+        page_to_map: P,
+        perms: PteLeafPerms,
+    ) -> Result<()> {
+        self.check_page_to_map(vaddr, page_to_map.size())?;
         let mut inner = self.owner.inner.lock();
         unsafe {
             // Safe since we uniquely own page_to_map.
-            inner.map_4k_leaf(vaddr, page_to_map.addr(), PteLeafPerms::RWX)
+            inner
+                .map_leaf(
+                    vaddr,
+                    page_to_map.addr(),
+                    self.page_size,
+                    perms,
+                    default_mem_type::<P, M>(),
+                )?
+                .invalidate();
         }
+        Ok(())
     }
 }
 
 impl<'a, T: PagingMode> Drop for PageTableMapper<'a, T> {
     fn drop(&mut self) {
         let mut inner = self.owner.inner.lock();
-        for a in self.vaddr.iter_from().take(self.num_pages as usize) {
+        // Unwrap ok since the mapper is only created for properly aligned ranges.
+        for a in self
+            .vaddr
+            .iter_from_with_size(self.page_size)
+            .unwrap()
+            .take(self.num_pages as usize)
+        {
             // Ignore the return value since this is expected to fail if the PTE was successfully
             // mapped (which will unlock the PTE), but may succeed if the holder of the PageTableMapper
             // bailed before having filled the entire range (e.g. because of another failure).
-            let _ = inner.unlock_4k_leaf(a);
+            let _ = inner.unlock_leaf(a, self.page_size);
         }
     }
 }
@@ -56,6 +56,7 @@ impl PteFieldBit {
 
 /// Permissions for a leaf page entry.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum PteLeafPerms {
     /// Read only
     R = PteFieldBit::Read.mask() as isize,
@@ -70,6 +71,24 @@ pub enum PteLeafPerms {
         as isize,
 }
 
+/// Page-based memory types for a leaf page entry, as defined by the Svpbmt extension. They override
+/// the memory type given by the physical memory attributes (PMAs) of the mapped page.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PteMemType {
+    /// No override, use the PMAs of the page.
+    #[default]
+    PMA = 0,
+    /// Non-cacheable, idempotent, weakly-ordered main memory.
+    NC = 1,
+    /// Non-cacheable, non-idempotent, strongly-ordered I/O memory.
+    IO = 2,
+}
+
+// Svpbmt keeps the memory type in bits 61 and 62 of leaf PTEs.
+const PBMT_SHIFT: u64 = 61;
+const MASK_PBMT: u64 = 0b11 << PBMT_SHIFT;
+
 const MASK_RWX: u64 = (1 << PteFieldBit::Read.shift())
     | (1 << PteFieldBit::Write.shift())
     | (1 << PteFieldBit::Execute.shift());
@@ -101,7 +120,6 @@ impl Pte {
     }
 
     /// Marks the entry as valid
-    #[allow(dead_code)]
     pub fn mark_valid(&mut self) {
         self.0 |= PteFieldBit::Valid.mask();
     }
@@ -121,6 +139,11 @@ impl Pte {
         self.0 &= !PteFieldBit::Locked.mask()
     }
 
+    /// Replaces the permissions of this leaf entry with `perms`, leaving all other bits unchanged.
+    pub fn set_perms(&mut self, perms: PteLeafPerms) {
+        self.0 = (self.0 & !MASK_RWX) | perms as u64;
+    }
+
     /// Clears everything including valid bit.
     pub fn clear(&mut self) {
         self.0 = 0;
@@ -135,6 +158,13 @@ impl Pte {
     pub fn pfn(&self) -> SupervisorPfn {
         Pfn::supervisor((self.bits() >> PFN_SHIFT) & PFN_MASK)
     }
+
+    /// Returns the status bits of this entry, i.e. everything but the PFN.
+    pub fn status(&self) -> PteFieldBits {
+        PteFieldBits {
+            bits: self.bits() & !(PFN_MASK << PFN_SHIFT),
+        }
+    }
 }
 
 /// The status bits that define PTE state.
@@ -166,6 +196,20 @@ impl PteFieldBits {
         ret
     }
 
+    /// Sets the page-based memory type of a leaf entry to `mem_type`.
+    pub fn set_mem_type(&mut self, mem_type: PteMemType) {
+        self.bits = (self.bits & !MASK_PBMT) | ((mem_type as u64) << PBMT_SHIFT);
+    }
+
+    /// Returns the page-based memory type of a leaf entry.
+    pub fn mem_type(&self) -> PteMemType {
+        match (self.bits & MASK_PBMT) >> PBMT_SHIFT {
+            1 => PteMemType::NC,
+            2 => PteMemType::IO,
+            _ => PteMemType::PMA,
+        }
+    }
+
     /// Creates a new status for a non-leaf entry.
     /// Used for intermeidate levels of page tables.
     pub fn non_leaf() -> Self {
@@ -67,8 +67,42 @@ pub fn hfence_gvma(gaddr: Option<u64>, vmid: Option<u64>) {
     }
 }
 
+/// Executes an HFENCE.VVMA instruction, invalidating VS-stage translations for the VMID in HGATP.
+///
+/// If `vaddr` is not None only translations mapping the specified guest virtual address are
+/// invalidated, otherwise translations for all guest virtual addresses are invalidated.
+///
+/// If 'asid' is not None only translations using the specified ASID are invalidated, otherwise
+/// translations for all ASIDs are invalidated.
+#[cfg(all(target_arch = "riscv64", target_os = "none"))]
+pub fn hfence_vvma(vaddr: Option<u64>, asid: Option<u64>) {
+    // HFENCE.VVMA encoding: 0010001 rs2[4:0] rs1[4:0] 000 00000 1110011
+    match (vaddr, asid) {
+        // Safety: HFENCE.VVMA's behavior is well-defined and its only side effect is to invalidate
+        // address translation caches.
+        (Some(addr), Some(id)) => unsafe {
+            // hfence.vvma a0, a1
+            asm!(".word 0x22b50073", in("a0") addr, in("a1") id);
+        },
+        (Some(addr), None) => unsafe {
+            // hfence.vvma a0, zero
+            asm!(".word 0x22050073", in("a0") addr);
+        },
+        (None, Some(id)) => unsafe {
+            // hfence.vvma zero, a0
+            asm!(".word 0x22a00073", in("a0") id);
+        },
+        (None, None) => unsafe {
+            // hfence.vvma zero, zero
+            asm!(".word 0x22000073");
+        },
+    }
+}
+
 // Make fence instructions a no-op for testing.
 #[cfg(not(any(target_arch = "riscv64", target_os = "none")))]
 pub fn sfence_vma(_vaddr: Option<u64>, _asid: Option<u64>) {}
 #[cfg(not(any(target_arch = "riscv64", target_os = "none")))]
 pub fn hfence_gvma(_gaddr: Option<u64>, _vmid: Option<u64>) {}
+#[cfg(not(any(target_arch = "riscv64", target_os = "none")))]
+pub fn hfence_vvma(_vaddr: Option<u64>, _asid: Option<u64>) {}
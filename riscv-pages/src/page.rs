@@ -27,6 +27,8 @@ pub enum PageSize {
     Size1G = 1024 * 1024 * 1024,
     /// Tera
     Size512G = 512 * 1024 * 1024 * 1024,
+    /// Peta
+    Size256T = 256 * 1024 * 1024 * 1024 * 1024,
 }
 
 impl PageSize {
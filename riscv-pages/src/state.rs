@@ -111,3 +111,10 @@ impl InternalData for InternalDirty {}
 impl Cleanable for InternalDirty {
     type Cleaned = InternalClean;
 }
+
+/// A page that remains owned, and mapped, by its current owner but is also mapped into the address
+/// space of a child VM as shared (non-confidential) memory. Shared pages are never measured.
+#[derive(Debug)]
+pub enum Shared {}
+impl State for Shared {}
+impl Mappable<MeasureOptional> for Shared {}
@@ -150,6 +150,13 @@ register_bitfields![u64,
     ]
 ];
 
+// Timer register.
+register_bitfields![u64,
+    pub time [
+        value OFFSET(0) NUMBITS(64) [],
+    ]
+];
+
 // Supervisor timer compare register.
 register_bitfields![u64,
     pub stimecmp [
@@ -361,6 +368,8 @@ register_bitfields![u64,
     pub henvcfg [
         // Fence of I/O implies memory.
         fiom OFFSET(0) NUMBITS(1) [],
+        // Enable Svpbmt memory types in VS-stage page tables.
+        pbmte OFFSET(62) NUMBITS(1) [],
         // Enable stimecmp in VS.
         stce OFFSET(63) NUMBITS(1) [],
         // TODO: Bits for other extensions we don't care about yet.
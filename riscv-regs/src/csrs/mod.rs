@@ -65,6 +65,8 @@ pub struct CSR {
     pub vstopei: ReadWriteRiscvCsr<stopei::Register, 0x25c>,
     pub vsatp: ReadWriteRiscvCsr<satp::Register, CSR_VSATP>,
     pub vstopi: ReadWriteRiscvCsr<stopi::Register, 0xeb0>,
+
+    pub time: ReadWriteRiscvCsr<time::Register, CSR_TIME>,
 }
 
 // Define the "addresses" of each CSR register.
@@ -114,4 +116,6 @@ pub const CSR: &CSR = &CSR {
     vstopei: ReadWriteRiscvCsr::new(),
     vsatp: ReadWriteRiscvCsr::new(),
     vstopi: ReadWriteRiscvCsr::new(),
+
+    time: ReadWriteRiscvCsr::new(),
 };
@@ -0,0 +1,618 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoding of the subset of RISC-V instructions that the hypervisor needs to emulate or inspect.
+
+use crate::inst::*;
+
+/// Errors resulting from decoding an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The 32-bit instruction is not one that we know how to decode.
+    UnsupportedInstruction(u32),
+    /// The 16-bit compressed instruction is not one that we know how to expand.
+    UnsupportedCompressedInstruction(u16),
+}
+
+pub type DecodeResult<T> = core::result::Result<T, DecodeError>;
+
+/// The width of a memory access.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryWidth {
+    Byte = 1,
+    HalfWord = 2,
+    Word = 4,
+    DoubleWord = 8,
+}
+
+impl MemoryWidth {
+    /// Returns the width in bytes.
+    pub fn bytes(&self) -> u64 {
+        *self as u64
+    }
+}
+
+/// The read-modify-write operation performed by a CSR instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrOp {
+    /// CSRRW(I): Writes the source value to the CSR.
+    Write,
+    /// CSRRS(I): Sets the bits in the CSR that are set in the source value.
+    Set,
+    /// CSRRC(I): Clears the bits in the CSR that are set in the source value.
+    Clear,
+}
+
+/// The source operand of a CSR instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsrSource {
+    /// The source value is held in the register `x<n>`.
+    Register(u32),
+    /// The source value is a 5-bit zero-extended immediate.
+    Immediate(u32),
+}
+
+/// A typed RISC-V instruction. Registers are identified by their architectural number (i.e. `x<n>`)
+/// as encoded in the instruction; see `GprIndex::from_raw()` for converting them to a `GprIndex`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// An integer load: `rd = *(rs1 + offset)`. The loaded value is sign-extended to XLEN if
+    /// `sign_extend` is set and zero-extended otherwise.
+    Load {
+        width: MemoryWidth,
+        sign_extend: bool,
+        rd: u32,
+        rs1: u32,
+        offset: i32,
+    },
+    /// An integer store: `*(rs1 + offset) = rs2`.
+    Store {
+        width: MemoryWidth,
+        rs1: u32,
+        rs2: u32,
+        offset: i32,
+    },
+    /// A CSR access. The previous value of `csr` is written to `rd`.
+    Csr {
+        op: CsrOp,
+        csr: u16,
+        rd: u32,
+        source: CsrSource,
+    },
+    /// Wait for interrupt.
+    Wfi,
+    /// A supervisor address translation fence.
+    SfenceVma { rs1: u32, rs2: u32 },
+}
+
+/// A decoded instruction, along with its encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    raw: u32,
+    len: usize,
+    inst: Instruction,
+}
+
+impl DecodedInstruction {
+    /// Decodes the instruction in `raw`. If the low two bits of `raw` indicate that it is a
+    /// compressed instruction only the low 16 bits are used, and the instruction is expanded to
+    /// its 32-bit equivalent before it is decoded.
+    pub fn from_raw(raw: u32) -> DecodeResult<Self> {
+        if is_compressed(raw) {
+            let expanded = expand_compressed(raw as u16)?;
+            Ok(Self {
+                raw: expanded,
+                len: 2,
+                inst: decode_uncompressed(expanded)?,
+            })
+        } else {
+            Ok(Self {
+                raw,
+                len: 4,
+                inst: decode_uncompressed(raw)?,
+            })
+        }
+    }
+
+    /// Returns the 32-bit encoding of the instruction. For compressed instructions this is the
+    /// encoding of the expanded instruction.
+    pub fn raw(&self) -> u32 {
+        self.raw
+    }
+
+    /// Returns the length of the instruction as it was encoded in memory, in bytes.
+    pub fn length_bytes(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the decoded instruction.
+    pub fn instruction(&self) -> Instruction {
+        self.inst
+    }
+}
+
+/// Returns true if `raw` holds a 16-bit compressed instruction.
+pub fn is_compressed(raw: u32) -> bool {
+    raw & 0x3 != 0x3
+}
+
+/// Returns `len` bits of `raw` starting at bit `start`.
+fn bits(raw: u32, start: u32, len: u32) -> u32 {
+    (raw >> start) & ((1 << len) - 1)
+}
+
+/// Sign-extends the `len`-bit value in `val`.
+fn sext(val: u32, len: u32) -> i32 {
+    let shift = 32 - len;
+    ((val << shift) as i32) >> shift
+}
+
+fn decode_uncompressed(raw: u32) -> DecodeResult<Instruction> {
+    let rd = bits(raw, 7, 5);
+    let rs1 = bits(raw, 15, 5);
+    let rs2 = bits(raw, 20, 5);
+    let load = |width, sign_extend| Instruction::Load {
+        width,
+        sign_extend,
+        rd,
+        rs1,
+        offset: sext_imm_i(raw),
+    };
+    let store = |width| Instruction::Store {
+        width,
+        rs1,
+        rs2,
+        offset: sext(bits(raw, 25, 7) << 5 | bits(raw, 7, 5), 12),
+    };
+    let csr = |op, source| Instruction::Csr {
+        op,
+        csr: bits(raw, 20, 12) as u16,
+        rd,
+        source,
+    };
+
+    use CsrOp::*;
+    use MemoryWidth::*;
+    let inst = match raw {
+        i if i & MASK_LB == MATCH_LB => load(Byte, true),
+        i if i & MASK_LH == MATCH_LH => load(HalfWord, true),
+        i if i & MASK_LW == MATCH_LW => load(Word, true),
+        i if i & MASK_LD == MATCH_LD => load(DoubleWord, true),
+        i if i & MASK_LBU == MATCH_LBU => load(Byte, false),
+        i if i & MASK_LHU == MATCH_LHU => load(HalfWord, false),
+        i if i & MASK_LWU == MATCH_LWU => load(Word, false),
+        i if i & MASK_SB == MATCH_SB => store(Byte),
+        i if i & MASK_SH == MATCH_SH => store(HalfWord),
+        i if i & MASK_SW == MATCH_SW => store(Word),
+        i if i & MASK_SD == MATCH_SD => store(DoubleWord),
+        i if i & MASK_CSRRW == MATCH_CSRRW => csr(Write, CsrSource::Register(rs1)),
+        i if i & MASK_CSRRS == MATCH_CSRRS => csr(Set, CsrSource::Register(rs1)),
+        i if i & MASK_CSRRC == MATCH_CSRRC => csr(Clear, CsrSource::Register(rs1)),
+        i if i & MASK_CSRRWI == MATCH_CSRRWI => csr(Write, CsrSource::Immediate(rs1)),
+        i if i & MASK_CSRRSI == MATCH_CSRRSI => csr(Set, CsrSource::Immediate(rs1)),
+        i if i & MASK_CSRRCI == MATCH_CSRRCI => csr(Clear, CsrSource::Immediate(rs1)),
+        i if i & MASK_WFI == MATCH_WFI => Instruction::Wfi,
+        i if i & MASK_SFENCE_VMA == MATCH_SFENCE_VMA => Instruction::SfenceVma { rs1, rs2 },
+        _ => {
+            return Err(DecodeError::UnsupportedInstruction(raw));
+        }
+    };
+    Ok(inst)
+}
+
+/// Returns the sign-extended immediate of an I-type instruction.
+fn sext_imm_i(raw: u32) -> i32 {
+    sext(bits(raw, 20, 12), 12)
+}
+
+/// Builds an I-type instruction from `base`, which holds its opcode and function fields.
+fn encode_i(base: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    (imm as u32) << 20 | rs1 << 15 | rd << 7 | base
+}
+
+/// Builds an S-type instruction from `base`, which holds its opcode and function fields.
+fn encode_s(base: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bits(imm, 5, 7) << 25 | rs2 << 20 | rs1 << 15 | bits(imm, 0, 5) << 7 | base
+}
+
+/// Builds an R-type instruction from `base`, which holds its opcode and function fields.
+fn encode_r(base: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    rs2 << 20 | rs1 << 15 | rd << 7 | base
+}
+
+/// Builds a B-type instruction from `base`, which holds its opcode and function fields.
+fn encode_b(base: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bits(imm, 12, 1) << 31
+        | bits(imm, 5, 6) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | bits(imm, 1, 4) << 8
+        | bits(imm, 11, 1) << 7
+        | base
+}
+
+/// Builds a U-type instruction from `base`, which holds its opcode. `imm` is the full 32-bit
+/// immediate, the low 12 bits of which must be zero.
+fn encode_u(base: u32, rd: u32, imm: i32) -> u32 {
+    (imm as u32) & 0xffff_f000 | rd << 7 | base
+}
+
+/// Builds a J-type instruction from `base`, which holds its opcode.
+fn encode_j(base: u32, rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    bits(imm, 20, 1) << 31
+        | bits(imm, 1, 10) << 21
+        | bits(imm, 11, 1) << 20
+        | bits(imm, 12, 8) << 12
+        | rd << 7
+        | base
+}
+
+/// Expands the RV64C compressed instruction `raw` to its 32-bit equivalent. Returns an error if
+/// `raw` is reserved or isn't a valid RV64C instruction, including the all-zeroes instruction.
+pub fn expand_compressed(raw: u16) -> DecodeResult<u32> {
+    let c = raw as u32;
+    // Registers in the CIW/CL/CS/CA/CB formats are x8-x15.
+    let rd_prime = bits(c, 2, 3) + 8;
+    let rs1_prime = bits(c, 7, 3) + 8;
+    let rs2_prime = rd_prime;
+    let rd = bits(c, 7, 5);
+    let rs2 = bits(c, 2, 5);
+    const ZERO: u32 = 0;
+    const RA: u32 = 1;
+    const SP: u32 = 2;
+    // Word and double-word offsets in the CL/CS formats.
+    let w_offset = (bits(c, 10, 3) << 3 | bits(c, 6, 1) << 2 | bits(c, 5, 1) << 6) as i32;
+    let d_offset = (bits(c, 10, 3) << 3 | bits(c, 5, 2) << 6) as i32;
+    // Word and double-word offsets from SP in the CI and CSS formats.
+    let lwsp_offset = (bits(c, 12, 1) << 5 | bits(c, 4, 3) << 2 | bits(c, 2, 2) << 6) as i32;
+    let ldsp_offset = (bits(c, 12, 1) << 5 | bits(c, 5, 2) << 3 | bits(c, 2, 3) << 6) as i32;
+    let swsp_offset = (bits(c, 9, 4) << 2 | bits(c, 7, 2) << 6) as i32;
+    let sdsp_offset = (bits(c, 10, 3) << 3 | bits(c, 7, 3) << 6) as i32;
+    // The immediate and shift amount in the CI and CB formats.
+    let imm = sext(bits(c, 12, 1) << 5 | bits(c, 2, 5), 6);
+    let shamt = (bits(c, 12, 1) << 5 | bits(c, 2, 5)) as i32;
+    let addi4spn_imm =
+        (bits(c, 11, 2) << 4 | bits(c, 7, 4) << 6 | bits(c, 6, 1) << 2 | bits(c, 5, 1) << 3) as i32;
+    let addi16sp_imm = sext(
+        bits(c, 12, 1) << 9
+            | bits(c, 6, 1) << 4
+            | bits(c, 5, 1) << 6
+            | bits(c, 3, 2) << 7
+            | bits(c, 2, 1) << 5,
+        10,
+    );
+    let lui_imm = sext(bits(c, 12, 1) << 17 | bits(c, 2, 5) << 12, 18);
+    let jump_offset = sext(
+        bits(c, 12, 1) << 11
+            | bits(c, 11, 1) << 4
+            | bits(c, 9, 2) << 8
+            | bits(c, 8, 1) << 10
+            | bits(c, 7, 1) << 6
+            | bits(c, 6, 1) << 7
+            | bits(c, 3, 3) << 1
+            | bits(c, 2, 1) << 5,
+        12,
+    );
+    let branch_offset = sext(
+        bits(c, 12, 1) << 8
+            | bits(c, 10, 2) << 3
+            | bits(c, 5, 2) << 6
+            | bits(c, 3, 2) << 1
+            | bits(c, 2, 1) << 5,
+        9,
+    );
+
+    // Some encodings are shared between instructions, or are reserved for particular operand
+    // values, so the order of the arms matters.
+    let expanded = match c {
+        // Quadrant 0.
+        i if i & MASK_C_ADDI4SPN == MATCH_C_ADDI4SPN && addi4spn_imm != 0 => {
+            encode_i(MATCH_ADDI, rd_prime, SP, addi4spn_imm)
+        }
+        i if i & MASK_C_FLD == MATCH_C_FLD => encode_i(MATCH_FLD, rd_prime, rs1_prime, d_offset),
+        i if i & MASK_C_LW == MATCH_C_LW => encode_i(MATCH_LW, rd_prime, rs1_prime, w_offset),
+        i if i & MASK_C_LD == MATCH_C_LD => encode_i(MATCH_LD, rd_prime, rs1_prime, d_offset),
+        i if i & MASK_C_FSD == MATCH_C_FSD => encode_s(MATCH_FSD, rs1_prime, rs2_prime, d_offset),
+        i if i & MASK_C_SW == MATCH_C_SW => encode_s(MATCH_SW, rs1_prime, rs2_prime, w_offset),
+        i if i & MASK_C_SD == MATCH_C_SD => encode_s(MATCH_SD, rs1_prime, rs2_prime, d_offset),
+        // Quadrant 1.
+        i if i & MASK_C_ADDI == MATCH_C_ADDI => encode_i(MATCH_ADDI, rd, rd, imm),
+        i if i & MASK_C_ADDIW == MATCH_C_ADDIW && rd != 0 => encode_i(MATCH_ADDIW, rd, rd, imm),
+        i if i & MASK_C_LI == MATCH_C_LI => encode_i(MATCH_ADDI, rd, ZERO, imm),
+        i if i & MASK_C_ADDI16SP == MATCH_C_ADDI16SP && addi16sp_imm != 0 => {
+            encode_i(MATCH_ADDI, SP, SP, addi16sp_imm)
+        }
+        i if i & MASK_C_LUI == MATCH_C_LUI && rd != SP && lui_imm != 0 => {
+            encode_u(MATCH_LUI, rd, lui_imm)
+        }
+        i if i & MASK_C_SRLI == MATCH_C_SRLI => encode_i(MATCH_SRLI, rs1_prime, rs1_prime, shamt),
+        i if i & MASK_C_SRAI == MATCH_C_SRAI => encode_i(MATCH_SRAI, rs1_prime, rs1_prime, shamt),
+        i if i & MASK_C_ANDI == MATCH_C_ANDI => encode_i(MATCH_ANDI, rs1_prime, rs1_prime, imm),
+        i if i & MASK_C_SUB == MATCH_C_SUB => encode_r(MATCH_SUB, rs1_prime, rs1_prime, rs2_prime),
+        i if i & MASK_C_XOR == MATCH_C_XOR => encode_r(MATCH_XOR, rs1_prime, rs1_prime, rs2_prime),
+        i if i & MASK_C_OR == MATCH_C_OR => encode_r(MATCH_OR, rs1_prime, rs1_prime, rs2_prime),
+        i if i & MASK_C_AND == MATCH_C_AND => encode_r(MATCH_AND, rs1_prime, rs1_prime, rs2_prime),
+        i if i & MASK_C_SUBW == MATCH_C_SUBW => {
+            encode_r(MATCH_SUBW, rs1_prime, rs1_prime, rs2_prime)
+        }
+        i if i & MASK_C_ADDW == MATCH_C_ADDW => {
+            encode_r(MATCH_ADDW, rs1_prime, rs1_prime, rs2_prime)
+        }
+        i if i & MASK_C_J == MATCH_C_J => encode_j(MATCH_JAL, ZERO, jump_offset),
+        i if i & MASK_C_BEQZ == MATCH_C_BEQZ => encode_b(MATCH_BEQ, rs1_prime, ZERO, branch_offset),
+        i if i & MASK_C_BNEZ == MATCH_C_BNEZ => encode_b(MATCH_BNE, rs1_prime, ZERO, branch_offset),
+        // Quadrant 2.
+        i if i & MASK_C_SLLI == MATCH_C_SLLI => encode_i(MATCH_SLLI, rd, rd, shamt),
+        i if i & MASK_C_FLDSP == MATCH_C_FLDSP => encode_i(MATCH_FLD, rd, SP, ldsp_offset),
+        i if i & MASK_C_LWSP == MATCH_C_LWSP && rd != 0 => encode_i(MATCH_LW, rd, SP, lwsp_offset),
+        i if i & MASK_C_LDSP == MATCH_C_LDSP && rd != 0 => encode_i(MATCH_LD, rd, SP, ldsp_offset),
+        i if i & MASK_C_EBREAK == MATCH_C_EBREAK => MATCH_EBREAK,
+        i if i & MASK_C_JR == MATCH_C_JR && rd != 0 => encode_i(MATCH_JALR, ZERO, rd, 0),
+        i if i & MASK_C_JALR == MATCH_C_JALR => encode_i(MATCH_JALR, RA, rd, 0),
+        i if i & MASK_C_MV == MATCH_C_MV && rs2 != 0 => encode_r(MATCH_ADD, rd, ZERO, rs2),
+        i if i & MASK_C_ADD == MATCH_C_ADD && rs2 != 0 => encode_r(MATCH_ADD, rd, rd, rs2),
+        i if i & MASK_C_FSDSP == MATCH_C_FSDSP => encode_s(MATCH_FSD, SP, rs2, sdsp_offset),
+        i if i & MASK_C_SWSP == MATCH_C_SWSP => encode_s(MATCH_SW, SP, rs2, swsp_offset),
+        i if i & MASK_C_SDSP == MATCH_C_SDSP => encode_s(MATCH_SD, SP, rs2, sdsp_offset),
+        _ => {
+            return Err(DecodeError::UnsupportedCompressedInstruction(raw));
+        }
+    };
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(raw: u32) -> Instruction {
+        DecodedInstruction::from_raw(raw).unwrap().instruction()
+    }
+
+    #[test]
+    fn loads() {
+        // lw a0, 8(a1)
+        assert_eq!(
+            decode(0x0085a503),
+            Instruction::Load {
+                width: MemoryWidth::Word,
+                sign_extend: true,
+                rd: 10,
+                rs1: 11,
+                offset: 8
+            }
+        );
+        // lb a0, -1(a1)
+        assert_eq!(
+            decode(0xfff58503),
+            Instruction::Load {
+                width: MemoryWidth::Byte,
+                sign_extend: true,
+                rd: 10,
+                rs1: 11,
+                offset: -1
+            }
+        );
+        // lhu t0, 2(s0)
+        assert_eq!(
+            decode(0x00245283),
+            Instruction::Load {
+                width: MemoryWidth::HalfWord,
+                sign_extend: false,
+                rd: 5,
+                rs1: 8,
+                offset: 2
+            }
+        );
+        // ld ra, 8(sp)
+        let inst = DecodedInstruction::from_raw(0x00813083).unwrap();
+        assert_eq!(inst.length_bytes(), 4);
+        assert_eq!(
+            inst.instruction(),
+            Instruction::Load {
+                width: MemoryWidth::DoubleWord,
+                sign_extend: true,
+                rd: 1,
+                rs1: 2,
+                offset: 8
+            }
+        );
+    }
+
+    #[test]
+    fn stores() {
+        // sd ra, 8(sp)
+        assert_eq!(
+            decode(0x00113423),
+            Instruction::Store {
+                width: MemoryWidth::DoubleWord,
+                rs1: 2,
+                rs2: 1,
+                offset: 8
+            }
+        );
+        // sw a1, -4(a0)
+        assert_eq!(
+            decode(0xfeb52e23),
+            Instruction::Store {
+                width: MemoryWidth::Word,
+                rs1: 10,
+                rs2: 11,
+                offset: -4
+            }
+        );
+        // sb zero, 0(a0)
+        assert_eq!(
+            decode(0x00050023),
+            Instruction::Store {
+                width: MemoryWidth::Byte,
+                rs1: 10,
+                rs2: 0,
+                offset: 0
+            }
+        );
+    }
+
+    #[test]
+    fn csr_ops() {
+        // csrr a0, sstatus
+        assert_eq!(
+            decode(0x10002573),
+            Instruction::Csr {
+                op: CsrOp::Set,
+                csr: CSR_SSTATUS,
+                rd: 10,
+                source: CsrSource::Register(0)
+            }
+        );
+        // csrw stvec, a0
+        assert_eq!(
+            decode(0x10551073),
+            Instruction::Csr {
+                op: CsrOp::Write,
+                csr: CSR_STVEC,
+                rd: 0,
+                source: CsrSource::Register(10)
+            }
+        );
+        // csrci sstatus, 2
+        assert_eq!(
+            decode(0x10017073),
+            Instruction::Csr {
+                op: CsrOp::Clear,
+                csr: CSR_SSTATUS,
+                rd: 0,
+                source: CsrSource::Immediate(2)
+            }
+        );
+    }
+
+    #[test]
+    fn system_ops() {
+        assert_eq!(decode(0x10500073), Instruction::Wfi);
+        assert_eq!(
+            decode(0x12000073),
+            Instruction::SfenceVma { rs1: 0, rs2: 0 }
+        );
+        // sfence.vma a0, a1
+        assert_eq!(
+            decode(0x12b50073),
+            Instruction::SfenceVma { rs1: 10, rs2: 11 }
+        );
+    }
+
+    #[test]
+    fn compressed() {
+        // c.lw a0, 4(a1)
+        let inst = DecodedInstruction::from_raw(0x41c8).unwrap();
+        assert_eq!(inst.length_bytes(), 2);
+        assert_eq!(inst.raw(), 0x0045a503);
+        // c.sw a0, 4(a1)
+        assert_eq!(expand_compressed(0xc1c8).unwrap(), 0x00a5a223);
+        // c.ld a5, 120(a4)
+        assert_eq!(expand_compressed(0x7f3c).unwrap(), 0x07873783);
+        // c.sd a5, 120(a4)
+        assert_eq!(expand_compressed(0xff3c).unwrap(), 0x06f73c23);
+        // c.ldsp ra, 8(sp)
+        assert_eq!(expand_compressed(0x60a2).unwrap(), 0x00813083);
+        // c.sdsp ra, 8(sp)
+        assert_eq!(expand_compressed(0xe406).unwrap(), 0x00113423);
+        // c.lwsp a0, 12(sp)
+        assert_eq!(expand_compressed(0x4532).unwrap(), 0x00c12503);
+        // c.swsp a0, 12(sp)
+        assert_eq!(expand_compressed(0xc62a).unwrap(), 0x00a12623);
+        // The upper 16 bits are ignored for compressed instructions.
+        assert_eq!(
+            DecodedInstruction::from_raw(0xdead_60a2).unwrap().raw(),
+            0x00813083
+        );
+    }
+
+    #[test]
+    fn compressed_non_memory() {
+        let expansions = [
+            (0x0808, 0x01010513), // c.addi4spn a0, sp, 16
+            (0x2588, 0x0085b507), // c.fld fa0, 8(a1)
+            (0xa588, 0x00a5b427), // c.fsd fa0, 8(a1)
+            (0x157d, 0xfff50513), // c.addi a0, -1
+            (0x0001, 0x00000013), // c.nop
+            (0x2505, 0x0015051b), // c.addiw a0, 1
+            (0x5781, 0xfe000793), // c.li a5, -32
+            (0x7139, 0xfc010113), // c.addi16sp sp, -64
+            (0x757d, 0xfffff537), // c.lui a0, 0xfffff
+            (0x6305, 0x00001337), // c.lui t1, 1
+            (0x810d, 0x00355513), // c.srli a0, 3
+            (0x95fd, 0x43f5d593), // c.srai a1, 63
+            (0x9a79, 0xffe67613), // c.andi a2, -2
+            (0x8d0d, 0x40b50533), // c.sub a0, a1
+            (0x8d2d, 0x00b54533), // c.xor a0, a1
+            (0x8d4d, 0x00b56533), // c.or a0, a1
+            (0x8d6d, 0x00b57533), // c.and a0, a1
+            (0x9d0d, 0x40b5053b), // c.subw a0, a1
+            (0x9d2d, 0x00b5053b), // c.addw a0, a1
+            (0xb001, 0x801ff06f), // c.j -2048
+            (0xa801, 0x0100006f), // c.j 16
+            (0xd101, 0xf00500e3), // c.beqz a0, -256
+            (0xedfd, 0x0e059f63), // c.bnez a1, 254
+            (0x1082, 0x02009093), // c.slli ra, 32
+            (0x2522, 0x00813507), // c.fldsp fa0, 8(sp)
+            (0xa42a, 0x00a13427), // c.fsdsp fa0, 8(sp)
+            (0x8082, 0x00008067), // c.jr ra
+            (0x9502, 0x000500e7), // c.jalr a0
+            (0x852e, 0x00b00533), // c.mv a0, a1
+            (0x952e, 0x00b50533), // c.add a0, a1
+            (0x9002, 0x00100073), // c.ebreak
+        ];
+        for (compressed, expanded) in expansions {
+            assert_eq!(
+                expand_compressed(compressed),
+                Ok(expanded),
+                "{compressed:#x}"
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_reserved() {
+        // c.addi4spn with a zero immediate, which includes the all-zeroes instruction.
+        assert!(expand_compressed(0x0000).is_err());
+        assert!(expand_compressed(0x0008).is_err());
+        // c.addiw with rd = x0.
+        assert!(expand_compressed(0x2005).is_err());
+        // c.addi16sp and c.lui with a zero immediate.
+        assert!(expand_compressed(0x6101).is_err());
+        assert!(expand_compressed(0x6501).is_err());
+        // c.jr with rs1 = x0.
+        assert!(expand_compressed(0x8002).is_err());
+        // c.subw/c.addw's reserved neighbours.
+        assert!(expand_compressed(0x9d4d).is_err());
+        // Quadrant 0, funct3 = 0b100.
+        assert!(expand_compressed(0x8000).is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        // add a0, a0, a1
+        assert_eq!(
+            DecodedInstruction::from_raw(0x00b50533),
+            Err(DecodeError::UnsupportedInstruction(0x00b50533))
+        );
+        // c.lwsp with rd = x0 is reserved.
+        assert_eq!(
+            expand_compressed(0x4002),
+            Err(DecodeError::UnsupportedCompressedInstruction(0x4002))
+        );
+        // c.addi a0, 1 expands to an instruction that isn't decoded.
+        assert_eq!(
+            DecodedInstruction::from_raw(0x0505),
+            Err(DecodeError::UnsupportedInstruction(0x00150513))
+        );
+        // The all-zeroes instruction is defined to be illegal.
+        assert!(DecodedInstruction::from_raw(0).is_err());
+    }
+}
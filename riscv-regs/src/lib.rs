@@ -10,11 +10,16 @@
 //! inst - auto-generated register definitions
 //! regs - RV64 General Purpose Registers (GPRs), 0-31.
 //! csrs - (H)S-mode CSRs
+//! decode - Decoding of (a subset of) RISC-V instructions.
 
 mod csrs;
+mod decode;
 mod inst;
+mod mmio;
 mod regs;
 
 pub use csrs::*;
+pub use decode::*;
 pub use inst::*;
+pub use mmio::*;
 pub use regs::*;
@@ -0,0 +1,220 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoding of the loads and stores that a guest makes to emulated MMIO regions.
+
+use crate::{DecodedInstruction, GprIndex, Instruction};
+
+/// The direction of an emulated MMIO access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MmioOpcode {
+    /// A load into `rd`. The loaded value is sign-extended from the access width if `signed` is
+    /// set. `rd` is `None` if the destination register is x0.
+    Load { rd: Option<GprIndex>, signed: bool },
+    /// A store of the value in `rs2`. `rs2` is `None` if the source register is x0.
+    Store { rs2: Option<GprIndex> },
+}
+
+/// A load or store by a vCPU to a guest physical address in an emulated MMIO region, and which
+/// must be emulated by the VM's host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MmioOperation {
+    opcode: MmioOpcode,
+    // Width of the access, in bytes.
+    width: u64,
+    // Length of the faulting instruction, in bytes.
+    len: u64,
+}
+
+impl MmioOperation {
+    /// Decodes the load or store instruction in `inst`. Returns `None` if `inst` isn't a load or
+    /// store of an integer register.
+    pub fn decode(inst: u32) -> Option<Self> {
+        let decoded = DecodedInstruction::from_raw(inst).ok()?;
+        let (opcode, width) = match decoded.instruction() {
+            Instruction::Load {
+                width,
+                sign_extend,
+                rd,
+                ..
+            } => (
+                MmioOpcode::Load {
+                    rd: GprIndex::from_raw(rd),
+                    signed: sign_extend,
+                },
+                width,
+            ),
+            Instruction::Store { width, rs2, .. } => (
+                MmioOpcode::Store {
+                    rs2: GprIndex::from_raw(rs2),
+                },
+                width,
+            ),
+            _ => {
+                return None;
+            }
+        };
+        Some(Self {
+            opcode,
+            width: width.bytes(),
+            len: decoded.length_bytes() as u64,
+        })
+    }
+
+    /// Returns this operation with the length of the faulting instruction set to `len` bytes. Used
+    /// when the decoded instruction was the uncompressed equivalent of the one that faulted.
+    pub fn with_length(self, len: u64) -> Self {
+        Self { len, ..self }
+    }
+
+    /// Returns the direction of the access.
+    pub fn opcode(&self) -> MmioOpcode {
+        self.opcode
+    }
+
+    /// Returns the width of the access in bytes.
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+
+    /// Returns the length of the faulting instruction in bytes.
+    pub fn length_bytes(&self) -> u64 {
+        self.len
+    }
+
+    /// Truncates `value` to the width of the access, sign-extending it if this is a signed load.
+    pub fn extend_value(&self, value: u64) -> u64 {
+        if self.width >= 8 {
+            return value;
+        }
+        let bits = self.width * 8;
+        let mask = (1u64 << bits) - 1;
+        let value = value & mask;
+        match self.opcode {
+            MmioOpcode::Load { signed: true, .. } if value & (1 << (bits - 1)) != 0 => {
+                value | !mask
+            }
+            _ => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_loads() {
+        // lw a0, 8(a1)
+        let op = MmioOperation::decode(0x0085a503).unwrap();
+        assert_eq!(
+            op.opcode(),
+            MmioOpcode::Load {
+                rd: Some(GprIndex::A0),
+                signed: true
+            }
+        );
+        assert_eq!(op.width(), 4);
+        assert_eq!(op.length_bytes(), 4);
+        // lhu t0, 2(s0)
+        let op = MmioOperation::decode(0x00245283).unwrap();
+        assert_eq!(
+            op.opcode(),
+            MmioOpcode::Load {
+                rd: Some(GprIndex::T0),
+                signed: false
+            }
+        );
+        assert_eq!(op.width(), 2);
+        // ld zero, 0(a0)
+        let op = MmioOperation::decode(0x00053003).unwrap();
+        assert_eq!(
+            op.opcode(),
+            MmioOpcode::Load {
+                rd: None,
+                signed: true
+            }
+        );
+        assert_eq!(op.width(), 8);
+    }
+
+    #[test]
+    fn decode_stores() {
+        // sd ra, 8(sp)
+        let op = MmioOperation::decode(0x00113423).unwrap();
+        assert_eq!(
+            op.opcode(),
+            MmioOpcode::Store {
+                rs2: Some(GprIndex::RA)
+            }
+        );
+        assert_eq!(op.width(), 8);
+        // sb zero, 0(a0)
+        let op = MmioOperation::decode(0x00050023).unwrap();
+        assert_eq!(op.opcode(), MmioOpcode::Store { rs2: None });
+        assert_eq!(op.width(), 1);
+    }
+
+    #[test]
+    fn decode_compressed() {
+        // c.lw a0, 0(a1)
+        let op = MmioOperation::decode(0x4188).unwrap();
+        assert_eq!(
+            op.opcode(),
+            MmioOpcode::Load {
+                rd: Some(GprIndex::A0),
+                signed: true
+            }
+        );
+        assert_eq!(op.width(), 4);
+        assert_eq!(op.length_bytes(), 2);
+        // The transformed form of the above as reported in HTINST.
+        let op = MmioOperation::decode(0x00002503).unwrap().with_length(2);
+        assert_eq!(op.width(), 4);
+        assert_eq!(op.length_bytes(), 2);
+    }
+
+    #[test]
+    fn decode_rejects_non_memory_ops() {
+        // wfi
+        assert!(MmioOperation::decode(0x10500073).is_none());
+        // csrr a0, sstatus
+        assert!(MmioOperation::decode(0x10002573).is_none());
+    }
+
+    #[test]
+    fn extend_loads() {
+        // lb a0, -1(a1)
+        let lb = MmioOperation::decode(0xfff58503).unwrap();
+        assert_eq!(lb.extend_value(0x7f), 0x7f);
+        assert_eq!(lb.extend_value(0x80), 0xffff_ffff_ffff_ff80);
+        assert_eq!(lb.extend_value(0x1234_5680), 0xffff_ffff_ffff_ff80);
+        // lhu t0, 2(s0)
+        let lhu = MmioOperation::decode(0x00245283).unwrap();
+        assert_eq!(lhu.extend_value(0xdead_8000), 0x8000);
+        // lw a0, 8(a1)
+        let lw = MmioOperation::decode(0x0085a503).unwrap();
+        assert_eq!(lw.extend_value(0x1_8000_0000), 0xffff_ffff_8000_0000);
+        assert_eq!(lw.extend_value(0x1_7fff_ffff), 0x7fff_ffff);
+        // ld ra, 8(sp)
+        let ld = MmioOperation::decode(0x00813083).unwrap();
+        assert_eq!(
+            ld.extend_value(0x8000_0000_0000_0000),
+            0x8000_0000_0000_0000
+        );
+    }
+
+    #[test]
+    fn extend_stores() {
+        // sw a1, -4(a0)
+        let sw = MmioOperation::decode(0xfeb52e23).unwrap();
+        assert_eq!(sw.extend_value(0xffff_ffff_8000_0000), 0x8000_0000);
+        // sb zero, 0(a0)
+        let sb = MmioOperation::decode(0x00050023).unwrap();
+        assert_eq!(sb.extend_value(0x1ff), 0xff);
+        // sd ra, 8(sp)
+        let sd = MmioOperation::decode(0x00113423).unwrap();
+        assert_eq!(sd.extend_value(u64::MAX), u64::MAX);
+    }
+}
@@ -12,6 +12,7 @@
 pub struct GeneralPurposeRegisters([u64; 32]);
 
 /// Index of risc-v general purpose registers in `GeneralPurposeRegisters`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GprIndex {
     RA = 0,
     GP,
@@ -46,6 +47,52 @@ pub enum GprIndex {
     SP,
 }
 
+impl GprIndex {
+    /// Returns the `GprIndex` for the architectural register number `x<raw>` as encoded in an
+    /// instruction. Returns `None` for `x0`, which is hard-wired to zero and has no storage, or
+    /// if `raw` is out of range.
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        use GprIndex::*;
+        let index = match raw {
+            1 => RA,
+            2 => SP,
+            3 => GP,
+            4 => TP,
+            5 => T0,
+            6 => T1,
+            7 => T2,
+            8 => S0,
+            9 => S1,
+            10 => A0,
+            11 => A1,
+            12 => A2,
+            13 => A3,
+            14 => A4,
+            15 => A5,
+            16 => A6,
+            17 => A7,
+            18 => S2,
+            19 => S3,
+            20 => S4,
+            21 => S5,
+            22 => S6,
+            23 => S7,
+            24 => S8,
+            25 => S9,
+            26 => S10,
+            27 => S11,
+            28 => T3,
+            29 => T4,
+            30 => T5,
+            31 => T6,
+            _ => {
+                return None;
+            }
+        };
+        Some(index)
+    }
+}
+
 impl GeneralPurposeRegisters {
     /// Returns the value of the given register.
     pub fn reg(&self, reg_index: GprIndex) -> u64 {
@@ -8,10 +8,23 @@
 pub const EXT_PUT_CHAR: u64 = 0x01;
 pub const EXT_BASE: u64 = 0x10;
 pub const EXT_HART_STATE: u64 = 0x48534D;
+pub const EXT_TIMER: u64 = 0x54494D45; // TIME
+pub const EXT_IPI: u64 = 0x735049; // sPI
+pub const EXT_RFENCE: u64 = 0x52464E43; // RFNC
+pub const EXT_DEBUG_CONSOLE: u64 = 0x4442434E; // DBCN
 pub const EXT_RESET: u64 = 0x53525354;
 pub const EXT_TEE: u64 = 0x41544545;
+pub const EXT_TEE_GUEST: u64 = 0x41544547; // ATEG
 pub const EXT_MEASUREMENT: u64 = 0x5464545;
 // TODO Replace the measurement extension once the `GetEvidence` implementation is complete
 pub const EXT_ATTESTATION: u64 = 0x41545354; // ATST
 
+// Measurement types
+pub const MEASUREMENT_TYPE_STATIC: u64 = 1;
+pub const MEASUREMENT_TYPE_RUNTIME: u64 = 2;
+
+// Sealing key policies
+pub const SEALING_KEY_POLICY_MEASUREMENT: u64 = 0;
+pub const SEALING_KEY_POLICY_SIGNER: u64 = 1;
+
 pub const SBI_SUCCESS: i64 = 0;
@@ -214,6 +214,30 @@ pub enum HartState {
     ResumePending = 6,
 }
 
+/// The types of suspend that can be requested with the HartSuspend SBI call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HartSuspendType {
+    /// The hart's state is preserved while it is suspended, and it resumes execution after the
+    /// HartSuspend call.
+    DefaultRetentive,
+    /// The hart's state is lost while it is suspended, and it resumes execution at the resume
+    /// address passed to the HartSuspend call.
+    DefaultNonRetentive,
+}
+
+impl HartSuspendType {
+    /// Returns the `HartSuspendType` for the `suspend_type` argument of a HartSuspend call.
+    /// Platform-specific suspend types are not supported.
+    pub fn from_raw(suspend_type: u32) -> Result<Self> {
+        use HartSuspendType::*;
+        match suspend_type {
+            0 => Ok(DefaultRetentive),
+            0x8000_0000 => Ok(DefaultNonRetentive),
+            _ => Err(Error::InvalidParam),
+        }
+    }
+}
+
 impl StateFunction {
     /// Attempts to parse `Self` from the passed in `a0-a7`.
     fn from_regs(args: &[u64]) -> Result<Self> {
@@ -300,6 +324,380 @@ impl SbiFunction for StateFunction {
     }
 }
 
+/// Functions defined for the Timer extension
+#[derive(Clone, Copy)]
+pub enum TimerFunction {
+    /// Programs the clock for the next timer event at `stime_value`, in absolute time. Any pending
+    /// timer interrupt is cleared.
+    SetTimer {
+        /// a0 - The time at which the next timer event should occur.
+        stime_value: u64,
+    },
+}
+
+impl TimerFunction {
+    /// Attempts to parse `Self` from the passed in `a0-a7`.
+    fn from_regs(args: &[u64]) -> Result<Self> {
+        use TimerFunction::*;
+        match args[6] {
+            0 => Ok(SetTimer {
+                stime_value: args[0],
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+}
+
+impl SbiFunction for TimerFunction {
+    fn a6(&self) -> u64 {
+        use TimerFunction::*;
+        match self {
+            SetTimer { stime_value: _ } => 0,
+        }
+    }
+
+    fn a0(&self) -> u64 {
+        use TimerFunction::*;
+        match self {
+            SetTimer { stime_value } => *stime_value,
+        }
+    }
+}
+
+/// A set of harts, as passed to the IPI and RFENCE extensions. Harts are selected by the bits set
+/// in `hart_mask`, offset by `hart_mask_base`. A `hart_mask_base` of -1 selects all harts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HartMask {
+    mask: u64,
+    base: u64,
+}
+
+impl HartMask {
+    /// Creates a `HartMask` from the `hart_mask` and `hart_mask_base` arguments of an SBI call.
+    pub fn new(hart_mask: u64, hart_mask_base: u64) -> Self {
+        Self {
+            mask: hart_mask,
+            base: hart_mask_base,
+        }
+    }
+
+    /// Returns if this mask selects all harts.
+    pub fn is_all(&self) -> bool {
+        self.base == u64::MAX
+    }
+
+    /// Returns if the hart with `hart_id` is selected by this mask.
+    pub fn contains(&self, hart_id: u64) -> bool {
+        if self.is_all() {
+            return true;
+        }
+        hart_id
+            .checked_sub(self.base)
+            .filter(|&bit| bit < u64::BITS as u64)
+            .map_or(false, |bit| self.mask & (1 << bit) != 0)
+    }
+
+    /// Returns an iterator over the IDs of the harts selected by this mask. Returns an empty
+    /// iterator if the mask selects all harts, since the set of harts isn't known.
+    pub fn hart_ids(&self) -> impl Iterator<Item = u64> {
+        let (mask, base) = if self.is_all() {
+            (0, 0)
+        } else {
+            (self.mask, self.base)
+        };
+        (0..u64::BITS as u64)
+            .filter(move |bit| mask & (1 << bit) != 0)
+            .filter_map(move |bit| base.checked_add(bit))
+    }
+}
+
+/// Functions defined for the IPI extension
+#[derive(Clone, Copy)]
+pub enum IpiFunction {
+    /// Sends an inter-processor interrupt to the harts in the hart mask. The interrupt is received
+    /// as a supervisor software interrupt.
+    SendIpi {
+        /// a0 - The bitmask of harts to send the IPI to.
+        hart_mask: u64,
+        /// a1 - The hart ID that bit 0 of `hart_mask` corresponds to.
+        hart_mask_base: u64,
+    },
+}
+
+impl IpiFunction {
+    /// Attempts to parse `Self` from the passed in `a0-a7`.
+    fn from_regs(args: &[u64]) -> Result<Self> {
+        use IpiFunction::*;
+        match args[6] {
+            0 => Ok(SendIpi {
+                hart_mask: args[0],
+                hart_mask_base: args[1],
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+
+    /// Returns the set of harts targeted by this function.
+    pub fn hart_mask(&self) -> HartMask {
+        use IpiFunction::*;
+        match self {
+            SendIpi {
+                hart_mask,
+                hart_mask_base,
+            } => HartMask::new(*hart_mask, *hart_mask_base),
+        }
+    }
+}
+
+impl SbiFunction for IpiFunction {
+    fn a6(&self) -> u64 {
+        use IpiFunction::*;
+        match self {
+            SendIpi {
+                hart_mask: _,
+                hart_mask_base: _,
+            } => 0,
+        }
+    }
+
+    fn a0(&self) -> u64 {
+        use IpiFunction::*;
+        match self {
+            SendIpi {
+                hart_mask,
+                hart_mask_base: _,
+            } => *hart_mask,
+        }
+    }
+
+    fn a1(&self) -> u64 {
+        use IpiFunction::*;
+        match self {
+            SendIpi {
+                hart_mask: _,
+                hart_mask_base,
+            } => *hart_mask_base,
+        }
+    }
+}
+
+/// Functions defined for the RFENCE extension
+#[derive(Clone, Copy)]
+pub enum RfenceFunction {
+    /// Executes a FENCE.I instruction on the harts in the hart mask.
+    RemoteFenceI {
+        /// a0 - The bitmask of harts to fence.
+        hart_mask: u64,
+        /// a1 - The hart ID that bit 0 of `hart_mask` corresponds to.
+        hart_mask_base: u64,
+    },
+    /// Executes an SFENCE.VMA covering the given range of virtual addresses on the harts in the
+    /// hart mask.
+    RemoteSFenceVma {
+        /// a0 - The bitmask of harts to fence.
+        hart_mask: u64,
+        /// a1 - The hart ID that bit 0 of `hart_mask` corresponds to.
+        hart_mask_base: u64,
+        /// a2 - The start of the virtual address range to fence.
+        start_addr: u64,
+        /// a3 - The size of the virtual address range to fence.
+        size: u64,
+    },
+    /// Executes an SFENCE.VMA covering the given range of virtual addresses for the given ASID on
+    /// the harts in the hart mask.
+    RemoteSFenceVmaAsid {
+        /// a0 - The bitmask of harts to fence.
+        hart_mask: u64,
+        /// a1 - The hart ID that bit 0 of `hart_mask` corresponds to.
+        hart_mask_base: u64,
+        /// a2 - The start of the virtual address range to fence.
+        start_addr: u64,
+        /// a3 - The size of the virtual address range to fence.
+        size: u64,
+        /// a4 - The ASID to fence.
+        asid: u64,
+    },
+}
+
+impl RfenceFunction {
+    /// Attempts to parse `Self` from the passed in `a0-a7`.
+    fn from_regs(args: &[u64]) -> Result<Self> {
+        use RfenceFunction::*;
+        match args[6] {
+            0 => Ok(RemoteFenceI {
+                hart_mask: args[0],
+                hart_mask_base: args[1],
+            }),
+            1 => Ok(RemoteSFenceVma {
+                hart_mask: args[0],
+                hart_mask_base: args[1],
+                start_addr: args[2],
+                size: args[3],
+            }),
+            2 => Ok(RemoteSFenceVmaAsid {
+                hart_mask: args[0],
+                hart_mask_base: args[1],
+                start_addr: args[2],
+                size: args[3],
+                asid: args[4],
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+
+    /// Returns the set of harts targeted by this function.
+    pub fn hart_mask(&self) -> HartMask {
+        HartMask::new(self.a0(), self.a1())
+    }
+}
+
+impl SbiFunction for RfenceFunction {
+    fn a6(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteFenceI { .. } => 0,
+            RemoteSFenceVma { .. } => 1,
+            RemoteSFenceVmaAsid { .. } => 2,
+        }
+    }
+
+    fn a0(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteFenceI { hart_mask, .. } => *hart_mask,
+            RemoteSFenceVma { hart_mask, .. } => *hart_mask,
+            RemoteSFenceVmaAsid { hart_mask, .. } => *hart_mask,
+        }
+    }
+
+    fn a1(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteFenceI { hart_mask_base, .. } => *hart_mask_base,
+            RemoteSFenceVma { hart_mask_base, .. } => *hart_mask_base,
+            RemoteSFenceVmaAsid { hart_mask_base, .. } => *hart_mask_base,
+        }
+    }
+
+    fn a2(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteSFenceVma { start_addr, .. } => *start_addr,
+            RemoteSFenceVmaAsid { start_addr, .. } => *start_addr,
+            _ => 0,
+        }
+    }
+
+    fn a3(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteSFenceVma { size, .. } => *size,
+            RemoteSFenceVmaAsid { size, .. } => *size,
+            _ => 0,
+        }
+    }
+
+    fn a4(&self) -> u64 {
+        use RfenceFunction::*;
+        match self {
+            RemoteSFenceVmaAsid { asid, .. } => *asid,
+            _ => 0,
+        }
+    }
+}
+
+/// Functions defined for the Debug Console extension
+#[derive(Clone, Copy)]
+pub enum DebugConsoleFunction {
+    /// Writes up to `num_bytes` bytes from the guest physical address `base_addr_lo` (low bits)
+    /// and `base_addr_hi` (high bits) to the console. Returns the number of bytes written, which
+    /// may be less than `num_bytes` if the console can't accept more output.
+    Write {
+        /// a0 - The number of bytes to write.
+        num_bytes: u64,
+        /// a1 - The low XLEN bits of the address of the bytes to write.
+        base_addr_lo: u64,
+        /// a2 - The high XLEN bits of the address of the bytes to write.
+        base_addr_hi: u64,
+    },
+    /// Reads up to `num_bytes` bytes from the console to the guest physical address
+    /// `base_addr_lo` (low bits) and `base_addr_hi` (high bits). Returns the number of bytes read.
+    Read {
+        /// a0 - The maximum number of bytes to read.
+        num_bytes: u64,
+        /// a1 - The low XLEN bits of the address of the destination buffer.
+        base_addr_lo: u64,
+        /// a2 - The high XLEN bits of the address of the destination buffer.
+        base_addr_hi: u64,
+    },
+    /// Writes a single byte to the console.
+    WriteByte {
+        /// a0 - The byte to write.
+        byte: u8,
+    },
+}
+
+impl DebugConsoleFunction {
+    /// Attempts to parse `Self` from the passed in `a0-a7`.
+    fn from_regs(args: &[u64]) -> Result<Self> {
+        use DebugConsoleFunction::*;
+        match args[6] {
+            0 => Ok(Write {
+                num_bytes: args[0],
+                base_addr_lo: args[1],
+                base_addr_hi: args[2],
+            }),
+            1 => Ok(Read {
+                num_bytes: args[0],
+                base_addr_lo: args[1],
+                base_addr_hi: args[2],
+            }),
+            2 => Ok(WriteByte {
+                byte: args[0] as u8,
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+}
+
+impl SbiFunction for DebugConsoleFunction {
+    fn a6(&self) -> u64 {
+        use DebugConsoleFunction::*;
+        match self {
+            Write { .. } => 0,
+            Read { .. } => 1,
+            WriteByte { .. } => 2,
+        }
+    }
+
+    fn a0(&self) -> u64 {
+        use DebugConsoleFunction::*;
+        match self {
+            Write { num_bytes, .. } => *num_bytes,
+            Read { num_bytes, .. } => *num_bytes,
+            WriteByte { byte } => *byte as u64,
+        }
+    }
+
+    fn a1(&self) -> u64 {
+        use DebugConsoleFunction::*;
+        match self {
+            Write { base_addr_lo, .. } => *base_addr_lo,
+            Read { base_addr_lo, .. } => *base_addr_lo,
+            _ => 0,
+        }
+    }
+
+    fn a2(&self) -> u64 {
+        use DebugConsoleFunction::*;
+        match self {
+            Write { base_addr_hi, .. } => *base_addr_hi,
+            Read { base_addr_hi, .. } => *base_addr_hi,
+            _ => 0,
+        }
+    }
+}
+
 /// Functions for the Reset extension
 #[derive(Copy, Clone)]
 pub enum ResetFunction {
@@ -440,6 +838,43 @@ pub enum TvmCpuExitCode {
     /// be safely delegated to the host. The value of the SCAUSE register is stored in `ExitCause0`.
     /// The vCPU is no longer runnable.
     UnhandledException = 6,
+
+    /// The vCPU performed a load from a guest physical address that is not backed by memory. The
+    /// faulting address is stored in `ExitCause0` and the width of the access in bytes is stored
+    /// in `ExitCause1`. The host is expected to emulate the load and write the result to the
+    /// `MmioData` register; the result is written to the destination register of the load and the
+    /// vCPU resumes at the following instruction the next time it is run.
+    MmioLoad = 7,
+
+    /// The vCPU performed a store to a guest physical address that is not backed by memory. The
+    /// faulting address is stored in `ExitCause0`, the width of the access in bytes is stored in
+    /// `ExitCause1`, and the value being stored is held in `MmioData`. The vCPU resumes at the
+    /// following instruction the next time it is run.
+    MmioStore = 8,
+
+    /// The vCPU made a `ShareMemory` TEE-guest call. The base guest physical address of the range
+    /// being shared is stored in `ExitCause0` and its length in bytes is stored in `ExitCause1`.
+    /// Confidential pages that were mapped in the range are returned to the host once the TVM's
+    /// TLBs have been flushed, after which the host may reclaim them and map shared pages into the
+    /// range with `TvmAddSharedPages`.
+    ShareMemory = 9,
+
+    /// The vCPU made an `UnshareMemory` TEE-guest call. The base guest physical address of the
+    /// range is stored in `ExitCause0` and its length in bytes is stored in `ExitCause1`. Shared
+    /// pages that were mapped in the range stop being shared once the TVM's TLBs have been flushed.
+    /// The host may then add confidential pages to the range with `TvmAddZeroPages`.
+    UnshareMemory = 10,
+
+    /// The vCPU made an `AcceptMemory` TEE-guest call. The base guest physical address of the
+    /// accepted range is stored in `ExitCause0` and its length in bytes is stored in `ExitCause1`.
+    AcceptMemory = 11,
+
+    /// The vCPU made a sbi_hart_suspend() call. The suspend type is stored in `ExitCause0` and the
+    /// time at which the vCPU's timer is next due, or `u64::MAX` if its timer isn't armed, is
+    /// stored in `ExitCause1`. The host may deschedule the vCPU until an interrupt is pending for
+    /// it or its timer is due. The vCPU resumes the next time it is run: after the call for a
+    /// retentive suspend, or at its resume address for a non-retentive suspend.
+    HartSuspend = 12,
 }
 
 /// List of registers that can be read or written for a TVM's vCPU.
@@ -460,6 +895,11 @@ pub enum TvmCpuRegister {
 
     /// An additional exit cause register with the same access properties as `ExitCause0`.
     ExitCause1 = 3,
+
+    /// Data register for emulated MMIO accesses. Holds the value being stored after a `MmioStore`
+    /// exit, and is written by the host with the value to be loaded after a `MmioLoad` exit.
+    /// Read-write, and only accessible after the TVM has started.
+    MmioData = 4,
 }
 
 impl TvmCpuRegister {
@@ -471,6 +911,7 @@ impl TvmCpuRegister {
             1 => Ok(EntryArg),
             2 => Ok(ExitCause0),
             3 => Ok(ExitCause1),
+            4 => Ok(MmioData),
             _ => Err(Error::InvalidParam),
         }
     }
@@ -508,6 +949,9 @@ pub struct TsmInfo {
     pub tvm_bytes_per_vcpu: u64,
 }
 
+/// The length of the identity of the signer of a TVM's image.
+pub const TVM_SIGNER_ID_LEN: usize = 32;
+
 /// Parameters used for creating a new confidential VM.
 #[repr(C)]
 pub struct TvmCreateParams {
@@ -524,6 +968,85 @@ pub struct TvmCreateParams {
     /// vCPU state. Must be page-aligned and `TsmInfo::tvm_bytes_per_vcpu` * `tvm_num_vcpus` bytes
     /// in length, rounded up to the nearest multiple of 4kB.
     pub tvm_vcpu_addr: u64,
+    /// The `MeasurementAlgorithm` used to measure the TVM, both for its initial state and its
+    /// runtime measurement registers.
+    pub tvm_measurement_alg: u64,
+    /// The `TvmPagingMode` used for the TVM's guest-stage page table. Must be supported by the
+    /// CPU's MMU.
+    pub tvm_paging_mode: u64,
+    /// The security version number of the TVM's image. Added to the TVM's measurement along with
+    /// `tvm_signer_id`.
+    pub tvm_svn: u64,
+    /// The identity of the signer of the TVM's image, such as a digest of the public key it's
+    /// signed with, or zero if the image isn't signed. It's added to the TVM's measurement, so a
+    /// relying party can check it, and selects the key returned for `SEALING_KEY_POLICY_SIGNER`.
+    /// The TSM doesn't verify the image's signature.
+    pub tvm_signer_id: [u8; TVM_SIGNER_ID_LEN],
+}
+
+/// Guest-stage address translation modes a TVM can be created with.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TvmPagingMode {
+    /// Sv48x4, with a 50-bit guest physical address space.
+    #[default]
+    Sv48x4 = 0,
+    /// Sv39x4, with a 41-bit guest physical address space.
+    Sv39x4 = 1,
+    /// Sv57x4, with a 59-bit guest physical address space.
+    Sv57x4 = 2,
+}
+
+impl TvmPagingMode {
+    /// Attempts to create a paging mode from the given u64 value. Returns an error if the value
+    /// is greater than 2(Sv57x4).
+    pub fn from_reg(reg: u64) -> Result<Self> {
+        use TvmPagingMode::*;
+        match reg {
+            0 => Ok(Sv48x4),
+            1 => Ok(Sv39x4),
+            2 => Ok(Sv57x4),
+            _ => Err(Error::InvalidParam),
+        }
+    }
+}
+
+/// Hash algorithms a TVM can be measured with.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum MeasurementAlgorithm {
+    /// SHA-256, with 32-byte digests.
+    #[default]
+    Sha256 = 0,
+    /// SHA-384, with 48-byte digests.
+    Sha384 = 1,
+    /// SHA-512, with 64-byte digests.
+    Sha512 = 2,
+}
+
+impl MeasurementAlgorithm {
+    /// Attempts to create a measurement algorithm from the given u64 value. Returns an error if
+    /// the value is greater than 2(SHA-512).
+    pub fn from_reg(reg: u64) -> Result<Self> {
+        use MeasurementAlgorithm::*;
+        match reg {
+            0 => Ok(Sha256),
+            1 => Ok(Sha384),
+            2 => Ok(Sha512),
+            _ => Err(Error::InvalidParam),
+        }
+    }
+}
+
+/// Header preceding the digests returned by `GetGuestMeasurement` and `GetSelfMeasurement` when
+/// the measurement version is 2.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MeasurementHeader {
+    /// The `MeasurementAlgorithm` the digests were made with.
+    pub algorithm: u64,
+    /// The number of bytes of digests following the header.
+    pub len: u64,
 }
 
 /// Types of pages allowed to used for creating or managing confidential VMs.
@@ -556,6 +1079,60 @@ impl TsmPageType {
     }
 }
 
+/// Permissions with which a TVM may access confidential pages mapped into its address space.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TsmPagePerms {
+    /// Readable, writable and executable.
+    #[default]
+    ReadWriteExecute = 0,
+    /// Readable and writable.
+    ReadWrite = 1,
+    /// Readable and executable.
+    ReadExecute = 2,
+    /// Read-only.
+    ReadOnly = 3,
+}
+
+impl TsmPagePerms {
+    /// Attempts to create page permissions from the given u64 register value. Returns an error if
+    /// the value is greater than 3(read-only).
+    pub fn from_reg(reg: u64) -> Result<Self> {
+        use TsmPagePerms::*;
+        match reg {
+            0 => Ok(ReadWriteExecute),
+            1 => Ok(ReadWrite),
+            2 => Ok(ReadExecute),
+            3 => Ok(ReadOnly),
+            _ => Err(Error::InvalidParam),
+        }
+    }
+}
+
+/// Memory types with which non-confidential pages may be shared with a TVM.
+#[repr(u64)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum TsmPageMemType {
+    /// Cacheable, as determined by the platform's physical memory attributes.
+    #[default]
+    Cacheable = 0,
+    /// Non-cacheable.
+    NonCacheable = 1,
+}
+
+impl TsmPageMemType {
+    /// Attempts to create a memory type from the given u64 register value. Returns an error if
+    /// the value is greater than 1(non-cacheable).
+    pub fn from_reg(reg: u64) -> Result<Self> {
+        use TsmPageMemType::*;
+        match reg {
+            0 => Ok(Cacheable),
+            1 => Ok(NonCacheable),
+            _ => Err(Error::InvalidParam),
+        }
+    }
+}
+
 /// Functions provided by the TEE extension.
 #[derive(Copy, Clone)]
 pub enum TeeFunction {
@@ -590,7 +1167,9 @@ pub enum TeeFunction {
         num_pages: u64,
     },
     /// Maps `num_pages` zero-filled pages of confidential memory starting at `page_addr` into the
-    /// specified guest's address space at `guest_addr`.
+    /// specified guest's address space at `guest_addr`, accessible with the permissions `perms`.
+    /// Pages added after the guest has been finalized are inaccessible until the guest accepts
+    /// them with the `AcceptMemory` TEE-guest call.
     ///
     /// a6 = 3
     TvmAddZeroPages {
@@ -604,10 +1183,13 @@ pub enum TeeFunction {
         num_pages: u64,
         /// a4 = guest physical address
         guest_addr: u64,
+        /// a5 = page permissions
+        perms: TsmPagePerms,
     },
     /// Copies `num_pages` pages from non-confidential memory at `src_addr` to confidential
     /// memory at `dest_addr`, then measures and maps the pages at `dest_addr` into the specified
-    /// guest's address space at `guest_addr`.
+    /// guest's address space at `guest_addr`. The pages are mapped read/write/execute; use
+    /// `TvmProtectPages` to restrict them.
     ///
     /// a6 = 11
     TvmAddMeasuredPages {
@@ -624,7 +1206,29 @@ pub enum TeeFunction {
         /// a5 = guest physical address
         guest_addr: u64,
     },
-    /// Moves a VM from the initializing state to the Runnable state
+    /// Maps `num_pages` 4kB pages of non-confidential memory starting at `page_addr` into the
+    /// specified guest's address space at `guest_addr`. The pages remain mapped in the caller's
+    /// address space and may not be converted until the guest is destroyed. The destination range
+    /// must lie within a region of guest physical address space the guest has declared as shared.
+    /// Pages may be mapped non-cacheable, e.g. for buffers that devices access without snooping
+    /// the CPU caches, if the platform supports Svpbmt.
+    ///
+    /// a6 = 17
+    TvmAddSharedPages {
+        /// a0 = guest_id
+        guest_id: u64,
+        /// a1 = physical address of the pages to share
+        page_addr: u64,
+        /// a2 = number of pages
+        num_pages: u64,
+        /// a3 = guest physical address
+        guest_addr: u64,
+        /// a4 = memory type of the mapping
+        mem_type: TsmPageMemType,
+    },
+    /// Moves a VM from the initializing state to the Runnable state. The number of vCPUs added to
+    /// the TVM and their `EntryPc` and `EntryArg` registers are added to its measurement, which
+    /// can't change afterwards.
     /// a6 = 4
     Finalize {
         /// a0 = guest id
@@ -639,7 +1243,11 @@ pub enum TeeFunction {
         vcpu_id: u64,
     },
     /// Copies the measurements for the specified guest to the non-confidential physical address
-    /// `dest_addr`. The measurement version and type must be set to 1 for now.
+    /// `dest_addr`. The measurement type selects either the static measurement of the guest's
+    /// initial state (`MEASUREMENT_TYPE_STATIC`) or its runtime measurement registers
+    /// (`MEASUREMENT_TYPE_RUNTIME`). With measurement version 1 only the digests are copied; with
+    /// version 2 they're preceded by a `MeasurementHeader` reporting the algorithm the guest is
+    /// measured with. Returns the number of bytes written to `dest_addr`.
     ///
     /// a6 = 7
     GetGuestMeasurement {
@@ -713,7 +1321,8 @@ pub enum TeeFunction {
         num_pages: u64,
     },
     /// Reclaims `num_pages` of confidential memory starting at `page_addr`. The pages must not
-    /// be currently assigned to an active TVM.
+    /// be currently assigned to an active TVM. Pages that have been converted but not yet fenced
+    /// may also be reclaimed, in which case their conversion is cancelled.
     ///
     /// a6 = 13
     TsmReclaimPages {
@@ -738,6 +1347,80 @@ pub enum TeeFunction {
     ///
     /// a6 = 15
     TsmLocalFence,
+    /// Copies up to `len` bytes of pending console output written by the specified guest with the
+    /// debug console extension to the non-confidential address `dest_addr`. The copied bytes are
+    /// consumed from the guest's console buffer. Returns the number of bytes copied.
+    ///
+    /// a6 = 18
+    TvmReadConsole {
+        /// a0 = guest id
+        guest_id: u64,
+        /// a1 = destination address of the console output
+        dest_addr: u64,
+        /// a2 = maximum number of bytes to copy
+        len: u64,
+    },
+    /// Donates the `num_pages` contiguous 4kB pages starting at `page_addr` to hold the
+    /// measurement event log of the specified guest. Pages must be donated before any measured
+    /// pages are added to the guest, and may only be donated once. Events that don't fit in the
+    /// donated pages aren't logged.
+    ///
+    /// a6 = 19
+    TvmAddMeasurementLogPages {
+        /// a0 = guest id
+        guest_id: u64,
+        /// a1 = address of the first page
+        page_addr: u64,
+        /// a2 = number of pages
+        num_pages: u64,
+    },
+    /// Copies up to `len` bytes of the measurement event log of the specified guest, starting
+    /// `offset` bytes into the log, to the non-confidential address `dest_addr`. Returns the number
+    /// of bytes copied, which is 0 once the end of the log has been reached.
+    ///
+    /// a6 = 20
+    TvmGetMeasurementLog {
+        /// a0 = guest id
+        guest_id: u64,
+        /// a1 = destination address of the log
+        dest_addr: u64,
+        /// a2 = offset into the log
+        offset: u64,
+        /// a3 = maximum number of bytes to copy
+        len: u64,
+    },
+    /// Changes the permissions with which the specified guest may access the `num_pages` 4kB pages
+    /// mapped at `guest_addr` to `perms`, e.g. to map measured code read/execute and data
+    /// read/write. Every page in the range must be mapped. Permissions may only be changed before
+    /// the TVM is finalized, and the change is added to its measurement.
+    ///
+    /// a6 = 21
+    TvmProtectPages {
+        /// a0 = guest id
+        guest_id: u64,
+        /// a1 = guest physical address of the first page
+        guest_addr: u64,
+        /// a2 = number of pages
+        num_pages: u64,
+        /// a3 = page permissions
+        perms: TsmPagePerms,
+    },
+    /// Declares the `num_pages` 4kB pages of guest physical address space starting at `guest_addr`
+    /// as an emulated MMIO region of the specified guest. Loads and stores the guest makes to the
+    /// region are forwarded to the host for emulation; faults on any other unmapped address are
+    /// reported to the host as page faults. The region must not overlap any pages that have been
+    /// added to the guest. Regions may only be added before the TVM is finalized, and are added to
+    /// its measurement.
+    ///
+    /// a6 = 22
+    TvmAddEmulatedMmioRegion {
+        /// a0 = guest id
+        guest_id: u64,
+        /// a1 = guest physical address of the first page
+        guest_addr: u64,
+        /// a2 = number of pages
+        num_pages: u64,
+    },
 }
 
 impl TeeFunction {
@@ -761,6 +1444,7 @@ impl TeeFunction {
                 page_type: TsmPageType::from_reg(args[2])?,
                 num_pages: args[3],
                 guest_addr: args[4],
+                perms: TsmPagePerms::from_reg(args[5])?,
             }),
             4 => Ok(Finalize { guest_id: args[0] }),
             5 => Ok(TvmCpuRun {
@@ -812,6 +1496,40 @@ impl TeeFunction {
                 vcpu_id: args[1],
                 register: TvmCpuRegister::from_reg(args[2])?,
             }),
+            17 => Ok(TvmAddSharedPages {
+                guest_id: args[0],
+                page_addr: args[1],
+                num_pages: args[2],
+                guest_addr: args[3],
+                mem_type: TsmPageMemType::from_reg(args[4])?,
+            }),
+            18 => Ok(TvmReadConsole {
+                guest_id: args[0],
+                dest_addr: args[1],
+                len: args[2],
+            }),
+            19 => Ok(TvmAddMeasurementLogPages {
+                guest_id: args[0],
+                page_addr: args[1],
+                num_pages: args[2],
+            }),
+            20 => Ok(TvmGetMeasurementLog {
+                guest_id: args[0],
+                dest_addr: args[1],
+                offset: args[2],
+                len: args[3],
+            }),
+            21 => Ok(TvmProtectPages {
+                guest_id: args[0],
+                guest_addr: args[1],
+                num_pages: args[2],
+                perms: TsmPagePerms::from_reg(args[3])?,
+            }),
+            22 => Ok(TvmAddEmulatedMmioRegion {
+                guest_id: args[0],
+                guest_addr: args[1],
+                num_pages: args[2],
+            }),
             _ => Err(Error::NotSupported),
         }
     }
@@ -837,6 +1555,7 @@ impl SbiFunction for TeeFunction {
                 page_type: _,
                 num_pages: _,
                 guest_addr: _,
+                perms: _,
             } => 3,
             Finalize { guest_id: _ } => 4,
             TvmCpuRun {
@@ -888,6 +1607,40 @@ impl SbiFunction for TeeFunction {
                 vcpu_id: _,
                 register: _,
             } => 16,
+            TvmAddSharedPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages: _,
+                guest_addr: _,
+                mem_type: _,
+            } => 17,
+            TvmReadConsole {
+                guest_id: _,
+                dest_addr: _,
+                len: _,
+            } => 18,
+            TvmAddMeasurementLogPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages: _,
+            } => 19,
+            TvmGetMeasurementLog {
+                guest_id: _,
+                dest_addr: _,
+                offset: _,
+                len: _,
+            } => 20,
+            TvmProtectPages {
+                guest_id: _,
+                guest_addr: _,
+                num_pages: _,
+                perms: _,
+            } => 21,
+            TvmAddEmulatedMmioRegion {
+                guest_id: _,
+                guest_addr: _,
+                num_pages: _,
+            } => 22,
         }
     }
 
@@ -910,6 +1663,7 @@ impl SbiFunction for TeeFunction {
                 page_type: _,
                 num_pages: _,
                 guest_addr: _,
+                perms: _,
             } => *guest_id,
             Finalize { guest_id } => *guest_id,
             TvmCpuRun {
@@ -956,6 +1710,40 @@ impl SbiFunction for TeeFunction {
                 vcpu_id: _,
                 register: _,
             } => *guest_id,
+            TvmAddSharedPages {
+                guest_id,
+                page_addr: _,
+                num_pages: _,
+                guest_addr: _,
+                mem_type: _,
+            } => *guest_id,
+            TvmReadConsole {
+                guest_id,
+                dest_addr: _,
+                len: _,
+            } => *guest_id,
+            TvmAddMeasurementLogPages {
+                guest_id,
+                page_addr: _,
+                num_pages: _,
+            } => *guest_id,
+            TvmGetMeasurementLog {
+                guest_id,
+                dest_addr: _,
+                offset: _,
+                len: _,
+            } => *guest_id,
+            TvmProtectPages {
+                guest_id,
+                guest_addr: _,
+                num_pages: _,
+                perms: _,
+            } => *guest_id,
+            TvmAddEmulatedMmioRegion {
+                guest_id,
+                guest_addr: _,
+                num_pages: _,
+            } => *guest_id,
             _ => 0,
         }
     }
@@ -978,6 +1766,7 @@ impl SbiFunction for TeeFunction {
                 page_type: _,
                 num_pages: _,
                 guest_addr: _,
+                perms: _,
             } => *page_addr,
             TvmCpuRun {
                 guest_id: _,
@@ -1023,6 +1812,40 @@ impl SbiFunction for TeeFunction {
                 vcpu_id,
                 register: _,
             } => *vcpu_id,
+            TvmAddSharedPages {
+                guest_id: _,
+                page_addr,
+                num_pages: _,
+                guest_addr: _,
+                mem_type: _,
+            } => *page_addr,
+            TvmReadConsole {
+                guest_id: _,
+                dest_addr,
+                len: _,
+            } => *dest_addr,
+            TvmAddMeasurementLogPages {
+                guest_id: _,
+                page_addr,
+                num_pages: _,
+            } => *page_addr,
+            TvmGetMeasurementLog {
+                guest_id: _,
+                dest_addr,
+                offset: _,
+                len: _,
+            } => *dest_addr,
+            TvmProtectPages {
+                guest_id: _,
+                guest_addr,
+                num_pages: _,
+                perms: _,
+            } => *guest_addr,
+            TvmAddEmulatedMmioRegion {
+                guest_id: _,
+                guest_addr,
+                num_pages: _,
+            } => *guest_addr,
             _ => 0,
         }
     }
@@ -1041,6 +1864,7 @@ impl SbiFunction for TeeFunction {
                 page_type,
                 num_pages: _,
                 guest_addr: _,
+                perms: _,
             } => *page_type as u64,
             GetGuestMeasurement {
                 measurement_version: _,
@@ -1077,6 +1901,40 @@ impl SbiFunction for TeeFunction {
                 vcpu_id: _,
                 register,
             } => *register as u64,
+            TvmAddSharedPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages,
+                guest_addr: _,
+                mem_type: _,
+            } => *num_pages,
+            TvmReadConsole {
+                guest_id: _,
+                dest_addr: _,
+                len,
+            } => *len,
+            TvmAddMeasurementLogPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages,
+            } => *num_pages,
+            TvmGetMeasurementLog {
+                guest_id: _,
+                dest_addr: _,
+                offset,
+                len: _,
+            } => *offset,
+            TvmProtectPages {
+                guest_id: _,
+                guest_addr: _,
+                num_pages,
+                perms: _,
+            } => *num_pages,
+            TvmAddEmulatedMmioRegion {
+                guest_id: _,
+                guest_addr: _,
+                num_pages,
+            } => *num_pages,
             _ => 0,
         }
     }
@@ -1090,6 +1948,7 @@ impl SbiFunction for TeeFunction {
                 page_type: _,
                 num_pages,
                 guest_addr: _,
+                perms: _,
             } => *num_pages,
             GetGuestMeasurement {
                 measurement_version: _,
@@ -1111,6 +1970,25 @@ impl SbiFunction for TeeFunction {
                 num_pages: _,
                 guest_addr: _,
             } => *page_type as u64,
+            TvmAddSharedPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages: _,
+                guest_addr,
+                mem_type: _,
+            } => *guest_addr,
+            TvmGetMeasurementLog {
+                guest_id: _,
+                dest_addr: _,
+                offset: _,
+                len,
+            } => *len,
+            TvmProtectPages {
+                guest_id: _,
+                guest_addr: _,
+                num_pages: _,
+                perms,
+            } => *perms as u64,
             _ => 0,
         }
     }
@@ -1124,6 +2002,7 @@ impl SbiFunction for TeeFunction {
                 page_type: _,
                 num_pages: _,
                 guest_addr,
+                perms: _,
             } => *guest_addr,
             TvmAddMeasuredPages {
                 guest_id: _,
@@ -1133,6 +2012,13 @@ impl SbiFunction for TeeFunction {
                 num_pages,
                 guest_addr: _,
             } => *num_pages,
+            TvmAddSharedPages {
+                guest_id: _,
+                page_addr: _,
+                num_pages: _,
+                guest_addr: _,
+                mem_type,
+            } => *mem_type as u64,
             _ => 0,
         }
     }
@@ -1140,6 +2026,14 @@ impl SbiFunction for TeeFunction {
     fn a5(&self) -> u64 {
         use TeeFunction::*;
         match self {
+            TvmAddZeroPages {
+                guest_id: _,
+                page_addr: _,
+                page_type: _,
+                num_pages: _,
+                guest_addr: _,
+                perms,
+            } => *perms as u64,
             TvmAddMeasuredPages {
                 guest_id: _,
                 src_addr: _,
@@ -1153,11 +2047,128 @@ impl SbiFunction for TeeFunction {
     }
 }
 
+/// Functions provided by the TEE-guest extension, used by TVMs to manage the confidentiality of
+/// their address space.
+#[derive(Copy, Clone)]
+pub enum TeeGuestFunction {
+    /// Converts the `len` bytes of guest physical address space starting at `addr` from
+    /// confidential to shared. Any confidential pages mapped in the range are unmapped and returned
+    /// to the host. The range must not overlap a range that is already shared or pending
+    /// acceptance. Both `addr` and `len` must be 4kB-aligned.
+    ///
+    /// a6 = 0
+    ShareMemory {
+        /// a0 = base guest physical address of the range
+        addr: u64,
+        /// a1 = length of the range in bytes
+        len: u64,
+    },
+    /// Converts the `len` bytes of guest physical address space starting at `addr` from shared back
+    /// to confidential. Any shared pages mapped in the range are unmapped. The range must be
+    /// contained within a range previously shared with `ShareMemory`. Both `addr` and `len` must be
+    /// 4kB-aligned.
+    ///
+    /// a6 = 1
+    UnshareMemory {
+        /// a0 = base guest physical address of the range
+        addr: u64,
+        /// a1 = length of the range in bytes
+        len: u64,
+    },
+    /// Accepts the confidential pages in the `len` bytes of guest physical address space starting
+    /// at `addr`. Pages added by the host with `TvmAddZeroPages` after the TVM was finalized are
+    /// inaccessible until they have been accepted. Fails if any page in the range isn't pending
+    /// acceptance. Both `addr` and `len` must be 4kB-aligned.
+    ///
+    /// a6 = 2
+    AcceptMemory {
+        /// a0 = base guest physical address of the range
+        addr: u64,
+        /// a1 = length of the range in bytes
+        len: u64,
+    },
+    /// Permanently makes the confidential pages in the `len` bytes of guest physical address space
+    /// starting at `addr` read/execute-only, e.g. to protect the TVM's kernel text. Every page in
+    /// the range must be mapped and accepted, and the range can't subsequently be shared or
+    /// converted. The TSM fences every vCPU of the TVM before returning, so no vCPU can write to
+    /// the range once the call completes. Both `addr` and `len` must be 4kB-aligned.
+    ///
+    /// a6 = 3
+    LockText {
+        /// a0 = base guest physical address of the range
+        addr: u64,
+        /// a1 = length of the range in bytes
+        len: u64,
+    },
+}
+
+impl TeeGuestFunction {
+    /// Attempts to parse `Self` from the passed in `a0-a7`.
+    fn from_regs(args: &[u64]) -> Result<Self> {
+        use TeeGuestFunction::*;
+        match args[6] {
+            0 => Ok(ShareMemory {
+                addr: args[0],
+                len: args[1],
+            }),
+            1 => Ok(UnshareMemory {
+                addr: args[0],
+                len: args[1],
+            }),
+            2 => Ok(AcceptMemory {
+                addr: args[0],
+                len: args[1],
+            }),
+            3 => Ok(LockText {
+                addr: args[0],
+                len: args[1],
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+}
+
+impl SbiFunction for TeeGuestFunction {
+    fn a6(&self) -> u64 {
+        use TeeGuestFunction::*;
+        match self {
+            ShareMemory { addr: _, len: _ } => 0,
+            UnshareMemory { addr: _, len: _ } => 1,
+            AcceptMemory { addr: _, len: _ } => 2,
+            LockText { addr: _, len: _ } => 3,
+        }
+    }
+
+    fn a0(&self) -> u64 {
+        use TeeGuestFunction::*;
+        match self {
+            ShareMemory { addr, len: _ } => *addr,
+            UnshareMemory { addr, len: _ } => *addr,
+            AcceptMemory { addr, len: _ } => *addr,
+            LockText { addr, len: _ } => *addr,
+        }
+    }
+
+    fn a1(&self) -> u64 {
+        use TeeGuestFunction::*;
+        match self {
+            ShareMemory { addr: _, len } => *len,
+            UnshareMemory { addr: _, len } => *len,
+            AcceptMemory { addr: _, len } => *len,
+            LockText { addr: _, len } => *len,
+        }
+    }
+}
+
 /// Functions provided by the measurement extension.
 #[derive(Copy, Clone)]
 pub enum MeasurementFunction {
     /// Copies the measurements for the current VM to the (guest) physical address in `dest_addr`.
-    /// The measurement version and type must be set to 1 for now.
+    /// The measurement type selects either the static measurement of the VM's initial state
+    /// (`MEASUREMENT_TYPE_STATIC`) or its runtime measurement registers
+    /// (`MEASUREMENT_TYPE_RUNTIME`). With measurement version 1 only the digests are copied; with
+    /// version 2 they're preceded by a `MeasurementHeader`. Returns the number of bytes written to
+    /// `dest_addr`.
     /// a6 = 0
     GetSelfMeasurement {
         /// a0 = measurement version
@@ -1167,6 +2178,18 @@ pub enum MeasurementFunction {
         /// a2 = dest_addr
         dest_addr: u64,
     },
+    /// Copies up to `len` bytes of the measurement event log of the current VM, starting `offset`
+    /// bytes into the log, to the (guest) physical address in `dest_addr`. Returns the number of
+    /// bytes copied, which is 0 once the end of the log has been reached.
+    /// a6 = 1
+    GetSelfMeasurementLog {
+        /// a0 = dest_addr
+        dest_addr: u64,
+        /// a1 = offset into the log
+        offset: u64,
+        /// a2 = maximum number of bytes to copy
+        len: u64,
+    },
 }
 
 impl MeasurementFunction {
@@ -1179,6 +2202,11 @@ impl MeasurementFunction {
                 measurement_type: args[1],
                 dest_addr: args[2],
             }),
+            1 => Ok(GetSelfMeasurementLog {
+                dest_addr: args[0],
+                offset: args[1],
+                len: args[2],
+            }),
             _ => Err(Error::NotSupported),
         }
     }
@@ -1193,6 +2221,11 @@ impl SbiFunction for MeasurementFunction {
                 measurement_type: _,
                 dest_addr: _,
             } => 0,
+            GetSelfMeasurementLog {
+                dest_addr: _,
+                offset: _,
+                len: _,
+            } => 1,
         }
     }
 
@@ -1204,6 +2237,11 @@ impl SbiFunction for MeasurementFunction {
                 measurement_type: _,
                 dest_addr: _,
             } => *measurement_version,
+            GetSelfMeasurementLog {
+                dest_addr,
+                offset: _,
+                len: _,
+            } => *dest_addr,
         }
     }
 
@@ -1215,6 +2253,11 @@ impl SbiFunction for MeasurementFunction {
                 measurement_type,
                 dest_addr: _,
             } => *measurement_type,
+            GetSelfMeasurementLog {
+                dest_addr: _,
+                offset,
+                len: _,
+            } => *offset,
         }
     }
 
@@ -1226,6 +2269,11 @@ impl SbiFunction for MeasurementFunction {
                 measurement_type: _,
                 dest_addr,
             } => *dest_addr,
+            GetSelfMeasurementLog {
+                dest_addr: _,
+                offset: _,
+                len,
+            } => *len,
         }
     }
 }
@@ -1237,12 +2285,20 @@ pub enum AttestationFunction {
     /// The caller passes the CSR and its length through the first 2 arguments.
     /// The third argument is the address where the generated certificate will be placed.
     /// The evidence is formatted an x.509 DiceTcbInfo certificate extension
+    /// The generated certificate is followed by the certificates of the TVM's and Salus'
+    /// DICE identities, forming a DER-encoded chain back to the device identity.
+    /// A nonce of up to 64 bytes may be passed through the last 2 arguments, in which case it's
+    /// embedded in the generated certificate so that a relying party can check the evidence was
+    /// generated in response to its challenge. A nonce length of 0 omits the nonce. Returns the
+    /// total length of the certificate chain.
     ///
     /// a6 = 0
     /// a0 = CSR address
     /// a1 = CSR length
     /// a2 = Generated certificate address
     /// a3 = Reserved length for the generated certificate address
+    /// a4 = Nonce address
+    /// a5 = Nonce length
     GetEvidence {
         /// a0 = CSR address
         csr_addr: u64,
@@ -1252,20 +2308,75 @@ pub enum AttestationFunction {
         cert_addr: u64,
         /// a3 = Reserved length for the generated certificate address
         cert_len: u64,
+        /// a4 = Nonce address
+        nonce_addr: u64,
+        /// a5 = Nonce length
+        nonce_len: u64,
     },
 
-    /// Extend the TCB measurement with an additional measurement.
-    /// TBD: Do we allow for a specific PCR index to be passed, or do we extend
-    /// one dedicated PCR with all runtime extended measurements?
+    /// Extends one of the calling TVM's runtime measurement registers with a digest made with the
+    /// TVM's measurement algorithm. The register is set to the digest of its previous value
+    /// concatenated with the passed in digest, which must be the length of a digest made with that
+    /// algorithm. The runtime measurement registers can be read with `GetSelfMeasurement` using
+    /// `MEASUREMENT_TYPE_RUNTIME`, and are included in the evidence returned by `GetEvidence`.
     ///
-    /// a6 = 0
+    /// a6 = 1
     /// a0 = Measurement entry address
     /// a1 = Measurement entry length
+    /// a2 = Runtime measurement register index
     ExtendMeasurement {
         /// a0 = measurement address
         measurement_addr: u64,
         /// a1 = measurement length
         len: u64,
+        /// a2 = register index
+        index: u64,
+    },
+
+    /// Derives a symmetric key the calling TVM can use to seal data that it persists, and copies
+    /// it to `key_addr`. The key is derived by the TSM from a platform secret and the policy
+    /// selected by `policy`, so that it's only available to TVMs matching that policy on this
+    /// platform: with `SEALING_KEY_POLICY_MEASUREMENT`, only to TVMs with the same initial
+    /// measurement; with `SEALING_KEY_POLICY_SIGNER`, to TVMs created with the same
+    /// `tvm_signer_id` and `tvm_svn` in their `TvmCreateParams`. The TSM returns `NotSupported`
+    /// for policies it can't enforce, such as `SEALING_KEY_POLICY_SIGNER` for an unsigned TVM.
+    /// `key_len` must be at least 32 bytes. Returns the length of the key.
+    ///
+    /// a6 = 2
+    /// a0 = Sealing key policy
+    /// a1 = Key address
+    /// a2 = Reserved length for the key
+    GetSealingKey {
+        /// a0 = sealing key policy
+        policy: u64,
+        /// a1 = key address
+        key_addr: u64,
+        /// a2 = reserved length for the key
+        key_len: u64,
+    },
+
+    /// Gets attestation evidence for the calling TVM as an Entity Attestation Token
+    /// (https://datatracker.ietf.org/doc/html/rfc9711): a CBOR claims set carrying the TVM's
+    /// initial and runtime measurements and the Salus version, wrapped in a COSE_Sign1 structure
+    /// signed by Salus. The certificate of the signing key, which chains back to the device
+    /// identity, is carried in the token's `x5chain` header. A nonce of up to 64 bytes may be
+    /// passed through the first 2 arguments, in which case it's carried in the token's `eat_nonce`
+    /// claim. A nonce length of 0 omits the nonce. Returns the length of the token.
+    ///
+    /// a6 = 3
+    /// a0 = Nonce address
+    /// a1 = Nonce length
+    /// a2 = Token address
+    /// a3 = Reserved length for the token
+    GetEvidenceToken {
+        /// a0 = Nonce address
+        nonce_addr: u64,
+        /// a1 = Nonce length
+        nonce_len: u64,
+        /// a2 = Token address
+        token_addr: u64,
+        /// a3 = Reserved length for the token
+        token_len: u64,
     },
 }
 
@@ -1279,11 +2390,27 @@ impl AttestationFunction {
                 csr_len: args[1],
                 cert_addr: args[2],
                 cert_len: args[3],
+                nonce_addr: args[4],
+                nonce_len: args[5],
             }),
 
             1 => Ok(ExtendMeasurement {
                 measurement_addr: args[0],
                 len: args[1],
+                index: args[2],
+            }),
+
+            2 => Ok(GetSealingKey {
+                policy: args[0],
+                key_addr: args[1],
+                key_len: args[2],
+            }),
+
+            3 => Ok(GetEvidenceToken {
+                nonce_addr: args[0],
+                nonce_len: args[1],
+                token_addr: args[2],
+                token_len: args[3],
             }),
 
             _ => Err(Error::InvalidParam),
@@ -1298,12 +2425,94 @@ impl AttestationFunction {
                 csr_len: _,
                 cert_addr: _,
                 cert_len: _,
+                nonce_addr: _,
+                nonce_len: _,
             } => 0,
 
             ExtendMeasurement {
                 measurement_addr: _,
                 len: _,
+                index: _,
             } => 1,
+
+            GetSealingKey {
+                policy: _,
+                key_addr: _,
+                key_len: _,
+            } => 2,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len: _,
+                token_addr: _,
+                token_len: _,
+            } => 3,
+        }
+    }
+
+    fn a5(&self) -> u64 {
+        use AttestationFunction::*;
+        match self {
+            GetEvidence {
+                csr_addr: _,
+                csr_len: _,
+                cert_addr: _,
+                cert_len: _,
+                nonce_addr: _,
+                nonce_len,
+            } => *nonce_len,
+
+            ExtendMeasurement {
+                measurement_addr: _,
+                len: _,
+                index: _,
+            } => 0,
+
+            GetSealingKey {
+                policy: _,
+                key_addr: _,
+                key_len: _,
+            } => 0,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len: _,
+                token_addr: _,
+                token_len: _,
+            } => 0,
+        }
+    }
+
+    fn a4(&self) -> u64 {
+        use AttestationFunction::*;
+        match self {
+            GetEvidence {
+                csr_addr: _,
+                csr_len: _,
+                cert_addr: _,
+                cert_len: _,
+                nonce_addr,
+                nonce_len: _,
+            } => *nonce_addr,
+
+            ExtendMeasurement {
+                measurement_addr: _,
+                len: _,
+                index: _,
+            } => 0,
+
+            GetSealingKey {
+                policy: _,
+                key_addr: _,
+                key_len: _,
+            } => 0,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len: _,
+                token_addr: _,
+                token_len: _,
+            } => 0,
         }
     }
 
@@ -1315,12 +2524,28 @@ impl AttestationFunction {
                 csr_len: _,
                 cert_addr: _,
                 cert_len,
+                nonce_addr: _,
+                nonce_len: _,
             } => *cert_len,
 
             ExtendMeasurement {
                 measurement_addr: _,
-                len,
-            } => *len,
+                len: _,
+                index: _,
+            } => 0,
+
+            GetSealingKey {
+                policy: _,
+                key_addr: _,
+                key_len: _,
+            } => 0,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len: _,
+                token_addr: _,
+                token_len,
+            } => *token_len,
         }
     }
 
@@ -1332,12 +2557,28 @@ impl AttestationFunction {
                 csr_len: _,
                 cert_addr,
                 cert_len: _,
+                nonce_addr: _,
+                nonce_len: _,
             } => *cert_addr,
 
             ExtendMeasurement {
                 measurement_addr: _,
-                len,
-            } => *len,
+                len: _,
+                index,
+            } => *index,
+
+            GetSealingKey {
+                policy: _,
+                key_addr: _,
+                key_len,
+            } => *key_len,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len: _,
+                token_addr,
+                token_len: _,
+            } => *token_addr,
         }
     }
 
@@ -1349,12 +2590,28 @@ impl AttestationFunction {
                 csr_len,
                 cert_addr: _,
                 cert_len: _,
+                nonce_addr: _,
+                nonce_len: _,
             } => *csr_len,
 
             ExtendMeasurement {
                 measurement_addr: _,
                 len,
+                index: _,
             } => *len,
+
+            GetSealingKey {
+                policy: _,
+                key_addr,
+                key_len: _,
+            } => *key_addr,
+
+            GetEvidenceToken {
+                nonce_addr: _,
+                nonce_len,
+                token_addr: _,
+                token_len: _,
+            } => *nonce_len,
         }
     }
 
@@ -1366,12 +2623,28 @@ impl AttestationFunction {
                 csr_len: _,
                 cert_addr: _,
                 cert_len: _,
+                nonce_addr: _,
+                nonce_len: _,
             } => *csr_addr,
 
             ExtendMeasurement {
                 measurement_addr,
                 len: _,
+                index: _,
             } => *measurement_addr,
+
+            GetSealingKey {
+                policy,
+                key_addr: _,
+                key_len: _,
+            } => *policy,
+
+            GetEvidenceToken {
+                nonce_addr,
+                nonce_len: _,
+                token_addr: _,
+                token_len: _,
+            } => *nonce_addr,
         }
     }
 }
@@ -1443,8 +2716,18 @@ pub enum SbiMessage {
     HartState(StateFunction),
     /// Handles system reset.
     Reset(ResetFunction),
+    /// Programs the timer.
+    Timer(TimerFunction),
+    /// Sends inter-processor interrupts.
+    Ipi(IpiFunction),
+    /// Executes fences on remote harts.
+    Rfence(RfenceFunction),
+    /// Reads and writes the debug console.
+    DebugConsole(DebugConsoleFunction),
     /// Provides capabilities for starting confidential virtual machines.
     Tee(TeeFunction),
+    /// Allows confidential virtual machines to manage the confidentiality of their memory.
+    TeeGuest(TeeGuestFunction),
     /// Allows acquiring measurement of the system that booted the running code.
     Measurement(MeasurementFunction),
     /// The extension for getting attestation evidences and extending measurements.
@@ -1462,7 +2745,14 @@ impl SbiMessage {
             EXT_BASE => BaseFunction::from_regs(gprs.a_regs()).map(SbiMessage::Base),
             EXT_HART_STATE => StateFunction::from_regs(gprs.a_regs()).map(SbiMessage::HartState),
             EXT_RESET => ResetFunction::from_regs(gprs.a_regs()).map(SbiMessage::Reset),
+            EXT_TIMER => TimerFunction::from_regs(gprs.a_regs()).map(SbiMessage::Timer),
+            EXT_IPI => IpiFunction::from_regs(gprs.a_regs()).map(SbiMessage::Ipi),
+            EXT_RFENCE => RfenceFunction::from_regs(gprs.a_regs()).map(SbiMessage::Rfence),
+            EXT_DEBUG_CONSOLE => {
+                DebugConsoleFunction::from_regs(gprs.a_regs()).map(SbiMessage::DebugConsole)
+            }
             EXT_TEE => TeeFunction::from_regs(gprs.a_regs()).map(SbiMessage::Tee),
+            EXT_TEE_GUEST => TeeGuestFunction::from_regs(gprs.a_regs()).map(SbiMessage::TeeGuest),
             EXT_MEASUREMENT => {
                 MeasurementFunction::from_regs(gprs.a_regs()).map(SbiMessage::Measurement)
             }
@@ -1480,7 +2770,12 @@ impl SbiMessage {
             SbiMessage::PutChar(_) => EXT_PUT_CHAR,
             SbiMessage::HartState(_) => EXT_HART_STATE,
             SbiMessage::Reset(_) => EXT_RESET,
+            SbiMessage::Timer(_) => EXT_TIMER,
+            SbiMessage::Ipi(_) => EXT_IPI,
+            SbiMessage::Rfence(_) => EXT_RFENCE,
+            SbiMessage::DebugConsole(_) => EXT_DEBUG_CONSOLE,
             SbiMessage::Tee(_) => EXT_TEE,
+            SbiMessage::TeeGuest(_) => EXT_TEE_GUEST,
             SbiMessage::Measurement(_) => EXT_MEASUREMENT,
             SbiMessage::Attestation(_) => EXT_ATTESTATION,
         }
@@ -1493,7 +2788,12 @@ impl SbiMessage {
             SbiMessage::HartState(f) => f.a6(),
             SbiMessage::PutChar(_) => 0,
             SbiMessage::Reset(_) => 0,
+            SbiMessage::Timer(f) => f.a6(),
+            SbiMessage::Ipi(f) => f.a6(),
+            SbiMessage::Rfence(f) => f.a6(),
+            SbiMessage::DebugConsole(f) => f.a6(),
             SbiMessage::Tee(f) => f.a6(),
+            SbiMessage::TeeGuest(f) => f.a6(),
             SbiMessage::Measurement(f) => f.a6(),
             SbiMessage::Attestation(f) => f.a6(),
         }
@@ -1503,6 +2803,7 @@ impl SbiMessage {
     pub fn a5(&self) -> u64 {
         match self {
             SbiMessage::Tee(f) => f.a5(),
+            SbiMessage::Attestation(f) => f.a5(),
             _ => 0,
         }
     }
@@ -1510,7 +2811,9 @@ impl SbiMessage {
     /// Returns the register value for this `SbiMessage`.
     pub fn a4(&self) -> u64 {
         match self {
+            SbiMessage::Rfence(f) => f.a4(),
             SbiMessage::Tee(f) => f.a4(),
+            SbiMessage::Attestation(f) => f.a4(),
             _ => 0,
         }
     }
@@ -1518,6 +2821,7 @@ impl SbiMessage {
     /// Returns the register value for this `SbiMessage`.
     pub fn a3(&self) -> u64 {
         match self {
+            SbiMessage::Rfence(f) => f.a3(),
             SbiMessage::Tee(f) => f.a3(),
             SbiMessage::Attestation(f) => f.a3(),
             _ => 0,
@@ -1528,6 +2832,8 @@ impl SbiMessage {
     pub fn a2(&self) -> u64 {
         match self {
             SbiMessage::HartState(f) => f.a2(),
+            SbiMessage::Rfence(f) => f.a2(),
+            SbiMessage::DebugConsole(f) => f.a2(),
             SbiMessage::Tee(f) => f.a2(),
             SbiMessage::Measurement(f) => f.a2(),
             SbiMessage::Attestation(f) => f.a2(),
@@ -1540,7 +2846,11 @@ impl SbiMessage {
         match self {
             SbiMessage::Reset(r) => r.a1(),
             SbiMessage::HartState(f) => f.a1(),
+            SbiMessage::Ipi(f) => f.a1(),
+            SbiMessage::Rfence(f) => f.a1(),
+            SbiMessage::DebugConsole(f) => f.a1(),
             SbiMessage::Tee(f) => f.a1(),
+            SbiMessage::TeeGuest(f) => f.a1(),
             SbiMessage::Measurement(f) => f.a1(),
             SbiMessage::Attestation(f) => f.a1(),
             _ => 0,
@@ -1553,7 +2863,12 @@ impl SbiMessage {
             SbiMessage::Reset(r) => r.a0(),
             SbiMessage::PutChar(c) => *c,
             SbiMessage::HartState(f) => f.a0(),
+            SbiMessage::Timer(f) => f.a0(),
+            SbiMessage::Ipi(f) => f.a0(),
+            SbiMessage::Rfence(f) => f.a0(),
+            SbiMessage::DebugConsole(f) => f.a0(),
             SbiMessage::Tee(f) => f.a0(),
+            SbiMessage::TeeGuest(f) => f.a0(),
             SbiMessage::Measurement(f) => f.a0(),
             SbiMessage::Attestation(f) => f.a0(),
             _ => 0,
@@ -1597,3 +2912,149 @@ impl SbiMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the GPRs for an SBI call to extension `ext`, function `fid` with arguments `args`.
+    fn sbi_regs(ext: u64, fid: u64, args: &[u64]) -> GeneralPurposeRegisters {
+        use GprIndex::*;
+        let mut gprs = GeneralPurposeRegisters::default();
+        for (&reg, &val) in [A0, A1, A2, A3, A4, A5].iter().zip(args) {
+            gprs.set_reg(reg, val);
+        }
+        gprs.set_reg(A6, fid);
+        gprs.set_reg(A7, ext);
+        gprs
+    }
+
+    #[test]
+    fn timer_from_regs() {
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_TIMER, 0, &[0x1234_5678])).unwrap();
+        assert!(matches!(
+            msg,
+            SbiMessage::Timer(TimerFunction::SetTimer {
+                stime_value: 0x1234_5678
+            })
+        ));
+        assert_eq!(msg.a7(), EXT_TIMER);
+        assert_eq!(msg.a6(), 0);
+        assert_eq!(msg.a0(), 0x1234_5678);
+        assert_eq!(
+            SbiMessage::from_regs(&sbi_regs(EXT_TIMER, 1, &[])).err(),
+            Some(Error::NotSupported)
+        );
+    }
+
+    #[test]
+    fn ipi_from_regs() {
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_IPI, 0, &[0b101, 4])).unwrap();
+        let SbiMessage::Ipi(f) = msg else {
+            panic!("Not an IPI message");
+        };
+        assert_eq!(f.hart_mask(), HartMask::new(0b101, 4));
+        assert_eq!(msg.a0(), 0b101);
+        assert_eq!(msg.a1(), 4);
+        assert_eq!(
+            SbiMessage::from_regs(&sbi_regs(EXT_IPI, 1, &[])).err(),
+            Some(Error::NotSupported)
+        );
+
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_IPI, 0, &[0, u64::MAX])).unwrap();
+        let SbiMessage::Ipi(f) = msg else {
+            panic!("Not an IPI message");
+        };
+        assert!(f.hart_mask().is_all());
+    }
+
+    #[test]
+    fn rfence_from_regs() {
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_RFENCE, 0, &[0x3, 0])).unwrap();
+        assert!(matches!(
+            msg,
+            SbiMessage::Rfence(RfenceFunction::RemoteFenceI {
+                hart_mask: 0x3,
+                hart_mask_base: 0
+            })
+        ));
+
+        let args = [0x1, 2, 0x8000_0000, 0x2000];
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_RFENCE, 1, &args)).unwrap();
+        assert!(matches!(
+            msg,
+            SbiMessage::Rfence(RfenceFunction::RemoteSFenceVma {
+                hart_mask: 0x1,
+                hart_mask_base: 2,
+                start_addr: 0x8000_0000,
+                size: 0x2000
+            })
+        ));
+        assert_eq!(
+            [msg.a0(), msg.a1(), msg.a2(), msg.a3(), msg.a6()],
+            [0x1, 2, 0x8000_0000, 0x2000, 1]
+        );
+
+        let args = [0x1, u64::MAX, 0x8000_0000, 0x2000, 7];
+        let msg = SbiMessage::from_regs(&sbi_regs(EXT_RFENCE, 2, &args)).unwrap();
+        let SbiMessage::Rfence(f) = msg else {
+            panic!("Not an RFENCE message");
+        };
+        assert!(matches!(
+            f,
+            RfenceFunction::RemoteSFenceVmaAsid {
+                hart_mask: 0x1,
+                hart_mask_base: u64::MAX,
+                start_addr: 0x8000_0000,
+                size: 0x2000,
+                asid: 7
+            }
+        ));
+        assert!(f.hart_mask().is_all());
+        assert_eq!(msg.a4(), 7);
+
+        // The HFENCE functions aren't supported.
+        assert_eq!(
+            SbiMessage::from_regs(&sbi_regs(EXT_RFENCE, 3, &[])).err(),
+            Some(Error::NotSupported)
+        );
+    }
+
+    #[test]
+    fn hart_mask_all() {
+        let mask = HartMask::new(0, u64::MAX);
+        assert!(mask.is_all());
+        assert!(mask.contains(0));
+        assert!(mask.contains(u64::MAX));
+        // The set of harts isn't known, so nothing is enumerated.
+        assert_eq!(mask.hart_ids().count(), 0);
+    }
+
+    #[test]
+    fn hart_mask_bases() {
+        let mask = HartMask::new(0b1001, 8);
+        assert!(!mask.is_all());
+        assert!(mask.contains(8));
+        assert!(!mask.contains(9));
+        assert!(mask.contains(11));
+        // Harts below the base or beyond the width of the mask are never selected.
+        assert!(!mask.contains(0));
+        assert!(!mask.contains(8 + 64));
+        assert!(mask.hart_ids().eq([8, 11]));
+
+        // Bit 63 of the mask may be set.
+        let mask = HartMask::new(1 << 63, 1);
+        assert!(mask.contains(64));
+        assert!(mask.hart_ids().eq([64]));
+
+        // Harts whose IDs would overflow are dropped.
+        let mask = HartMask::new(0b111, u64::MAX - 1);
+        assert!(!mask.is_all());
+        assert!(mask.contains(u64::MAX - 1));
+        assert!(mask.contains(u64::MAX));
+        assert!(!mask.contains(0));
+        assert!(mask.hart_ids().eq([u64::MAX - 1, u64::MAX]));
+        let mask = HartMask::new(u64::MAX, u64::MAX - 1);
+        assert!(mask.hart_ids().eq([u64::MAX - 1, u64::MAX]));
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! DICE-style layering of attestation identities. Each layer of software has a Compound Device
+//! Identifier (CDI) derived from the CDI of the layer below it and the measurement of the layer
+//! itself, and an identity key derived from its CDI. The chain starts with the CDI that firmware
+//! hands to Salus, from which Salus derives its own CDI and then a CDI for each TVM it runs, so
+//! that evidence issued to a TVM chains back to the device through Salus.
+//!
+//! Alongside that chain, each layer has a sealing CDI derived in the same way, from which the keys
+//! it seals data with are derived. Sealing keys are thus independent of the identity keys, and
+//! disclosing one doesn't reveal the CDI the other is derived from.
+
+use arrayvec::ArrayVec;
+use attestation::dice::{SHA256_OID, SHA384_OID, SHA512_OID};
+use attestation::evidence::EvidenceSigner;
+use attestation::MAX_CERT_LEN;
+use core::alloc::Allocator;
+use data_measure::data_measure::{DigestAlgorithm, MeasurementDigest};
+use data_measure::digest_measure::SHA256_DIGEST_BYTES;
+use data_measure::kdf::{hkdf_sha256, zeroize};
+use der::asn1::ObjectIdentifier;
+use device_tree::DeviceTree;
+use spin::Once;
+
+#[cfg(feature = "insecure-test-cdi")]
+use crate::print_util::*;
+#[cfg(feature = "insecure-test-cdi")]
+use crate::println;
+
+/// The length of a Compound Device Identifier.
+pub const CDI_LEN: usize = 32;
+
+/// The length of a sealing key.
+pub const SEALING_KEY_LEN: usize = 32;
+
+// The HKDF info for a key sealed to the signer of a TVM's image, followed by its SVN.
+const SIGNER_SEALING_KEY_INFO: &[u8] = b"Signer Sealing Key";
+
+// The property of the /chosen device-tree node in which firmware passes the CDI to Salus.
+const FIRMWARE_CDI_PROP: &str = "salus,dice-cdi";
+
+// The CDI used in place of a firmware-provided one when built with the `insecure-test-cdi` feature,
+// for platforms whose firmware doesn't provide a CDI. Identities derived from this CDI can't be
+// trusted by a relying party.
+#[cfg(feature = "insecure-test-cdi")]
+const TEST_FIRMWARE_CDI: [u8; CDI_LEN] = *b"salus-test-cdi-not-for-prod-use!";
+
+extern "C" {
+    static _start: u8;
+    static _rodata_end: u8;
+}
+
+/// Derives a key from `ikm` using HKDF-SHA256 with the given `salt` and `info`.
+fn kdf(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; SHA256_DIGEST_BYTES] {
+    let mut okm = [0; SHA256_DIGEST_BYTES];
+    hkdf_sha256(ikm, salt, info, &mut okm);
+    okm
+}
+
+/// Returns the OID identifying `algorithm` in the FWIDs of evidence certificates.
+pub fn digest_algorithm_oid(algorithm: DigestAlgorithm) -> ObjectIdentifier {
+    match algorithm {
+        DigestAlgorithm::Sha256 => SHA256_OID,
+        DigestAlgorithm::Sha384 => SHA384_OID,
+        DigestAlgorithm::Sha512 => SHA512_OID,
+    }
+}
+
+/// A layer in a DICE chain: its attestation and sealing CDIs, the measurement they were derived
+/// from along with the algorithm that measurement was made with, and the identity key used to sign
+/// the evidence it issues.
+pub struct DiceLayer {
+    cdi: [u8; CDI_LEN],
+    seal_cdi: [u8; CDI_LEN],
+    algorithm: DigestAlgorithm,
+    measurement: MeasurementDigest,
+    signer: EvidenceSigner,
+}
+
+impl DiceLayer {
+    /// Creates the layer with the attestation CDI `cdi`, the sealing CDI `seal_cdi` and
+    /// `measurement`, made with `algorithm`, named `common_name` in the certificates it issues.
+    fn new(
+        cdi: [u8; CDI_LEN],
+        seal_cdi: [u8; CDI_LEN],
+        algorithm: DigestAlgorithm,
+        measurement: MeasurementDigest,
+        common_name: &'static str,
+    ) -> Self {
+        let mut key_seed = kdf(&cdi, &[0; SHA256_DIGEST_BYTES], b"Key Pair");
+        // Any 32 bytes make a valid Ed25519 secret key.
+        let signer = EvidenceSigner::from_secret(&key_seed, common_name).unwrap();
+        zeroize(&mut key_seed);
+        Self {
+            cdi,
+            seal_cdi,
+            algorithm,
+            measurement,
+            signer,
+        }
+    }
+
+    /// Derives the next layer of the chain from this one, given the measurement of the next layer
+    /// and the algorithm it was made with.
+    pub fn derive(
+        &self,
+        algorithm: DigestAlgorithm,
+        measurement: &[u8],
+        common_name: &'static str,
+    ) -> Self {
+        let cdi = kdf(&self.cdi, measurement, b"CDI_Attest");
+        let seal_cdi = kdf(&self.seal_cdi, measurement, b"CDI_Seal");
+        Self::new(
+            cdi,
+            seal_cdi,
+            algorithm,
+            measurement.iter().copied().collect(),
+            common_name,
+        )
+    }
+
+    /// Returns the algorithm the measurement of this layer was made with.
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the measurement of this layer.
+    pub fn measurement(&self) -> &[u8] {
+        &self.measurement
+    }
+
+    /// Returns the key this layer can seal data with. As it's derived from the layer's sealing CDI,
+    /// the key is only available to layers with the same measurement on the same platform, running
+    /// on the same layers below.
+    pub fn sealing_key(&self) -> [u8; SEALING_KEY_LEN] {
+        kdf(&self.seal_cdi, &[0; SHA256_DIGEST_BYTES], b"Sealing Key")
+    }
+
+    /// Returns the key that the layers above this one whose images were signed by `signer_id` with
+    /// the security version `svn` can seal data with. Unlike `sealing_key()`, the key doesn't
+    /// depend on the measurement of the layer above, so it stays the same across images from the
+    /// same signer with the same version.
+    pub fn signer_sealing_key(&self, signer_id: &[u8], svn: u64) -> [u8; SEALING_KEY_LEN] {
+        let mut info = [0u8; SIGNER_SEALING_KEY_INFO.len() + 8];
+        info[..SIGNER_SEALING_KEY_INFO.len()].copy_from_slice(SIGNER_SEALING_KEY_INFO);
+        info[SIGNER_SEALING_KEY_INFO.len()..].copy_from_slice(&svn.to_le_bytes());
+        kdf(&self.seal_cdi, signer_id, &info)
+    }
+
+    /// Returns the signer for the evidence issued by this layer.
+    pub fn signer(&self) -> &EvidenceSigner {
+        &self.signer
+    }
+
+    /// Issues a certificate for the identity key of `child`, a layer derived from this one, to
+    /// `cert_buf`. Returns the encoded certificate.
+    pub fn certify<'b>(
+        &self,
+        child: &DiceLayer,
+        cert_buf: &'b mut [u8],
+    ) -> attestation::Result<'static, &'b [u8]> {
+        let fwid = (
+            digest_algorithm_oid(child.algorithm),
+            &child.measurement[..],
+        );
+        self.signer.certify(&child.signer, &[fwid], cert_buf)
+    }
+}
+
+impl Drop for DiceLayer {
+    fn drop(&mut self) {
+        zeroize(&mut self.cdi);
+        zeroize(&mut self.seal_cdi);
+    }
+}
+
+// Salus' layer of the chain, along with its certificate issued by the device layer.
+struct SalusIdentity {
+    layer: DiceLayer,
+    cert: ArrayVec<u8, MAX_CERT_LEN>,
+}
+
+static SALUS_IDENTITY: Once<SalusIdentity> = Once::new();
+
+/// Measures the code and read-only data of the Salus image.
+fn measure_salus() -> MeasurementDigest {
+    // Safe because we trust the linker placed these symbols correctly, and the text and read-only
+    // data of the image are never modified.
+    let image = unsafe {
+        let start = core::ptr::addr_of!(_start);
+        let end = core::ptr::addr_of!(_rodata_end);
+        core::slice::from_raw_parts(start, end as usize - start as usize)
+    };
+    DigestAlgorithm::Sha256.digest(&[image])
+}
+
+/// Initializes Salus' DICE layer from the CDI passed by firmware in the /chosen node of `hyp_dt`.
+/// Panics if firmware didn't provide a CDI, unless built with the `insecure-test-cdi` feature. The
+/// firmware CDI is scrubbed from `hyp_dt` and Salus' copies of it are zeroed once Salus' layer has
+/// been derived. The FDT passed in by firmware, which lies in memory reserved for Salus, still
+/// holds it.
+pub fn init<A: Allocator + Clone>(hyp_dt: &mut DeviceTree<A>) {
+    let mut firmware_cdi = [0; CDI_LEN];
+    let chosen_id = hyp_dt.iter().find(|n| n.name() == "chosen").map(|n| n.id());
+    let cdi_prop = chosen_id.and_then(|id| {
+        hyp_dt
+            .get_mut_node(id)
+            .unwrap()
+            .props_mut()
+            .find(|p| p.name() == FIRMWARE_CDI_PROP)
+    });
+    match cdi_prop {
+        Some(prop) if prop.value_raw().len() == CDI_LEN => {
+            firmware_cdi.copy_from_slice(prop.value_raw());
+            // Overwrite the property's value in place before it's freed.
+            prop.set_value_raw(&[0; CDI_LEN]).unwrap();
+        }
+        Some(_) => panic!("Invalid {} property length", FIRMWARE_CDI_PROP),
+        #[cfg(feature = "insecure-test-cdi")]
+        None => {
+            println!("No CDI from firmware; using an insecure test CDI");
+            firmware_cdi = TEST_FIRMWARE_CDI;
+        }
+        #[cfg(not(feature = "insecure-test-cdi"))]
+        None => panic!("No CDI from firmware in {}", FIRMWARE_CDI_PROP),
+    }
+    if let Some(id) = chosen_id {
+        // Nothing to remove if the CDI wasn't provided.
+        let _ = hyp_dt
+            .get_mut_node(id)
+            .unwrap()
+            .remove_prop(FIRMWARE_CDI_PROP);
+    }
+
+    // Firmware provides a single CDI, from which the device layer's sealing CDI is derived.
+    let mut device_seal_cdi = kdf(&firmware_cdi, &[0; SHA256_DIGEST_BYTES], b"CDI_Seal");
+    let device = DiceLayer::new(
+        firmware_cdi,
+        device_seal_cdi,
+        DigestAlgorithm::Sha256,
+        [0; SHA256_DIGEST_BYTES].into_iter().collect(),
+        "Salus Device",
+    );
+    zeroize(&mut firmware_cdi);
+    zeroize(&mut device_seal_cdi);
+    let layer = device.derive(DigestAlgorithm::Sha256, &measure_salus(), "Salus");
+    let mut cert_bytes = [0u8; MAX_CERT_LEN];
+    let cert = device
+        .certify(&layer, &mut cert_bytes)
+        .expect("Failed to certify the Salus identity");
+    SALUS_IDENTITY.call_once(|| SalusIdentity {
+        layer,
+        cert: cert.try_into().unwrap(),
+    });
+}
+
+/// Returns Salus' layer of the DICE chain.
+pub fn salus_layer() -> &'static DiceLayer {
+    &SALUS_IDENTITY.get().unwrap().layer
+}
+
+/// Returns Salus' certificate, issued by the device layer of the DICE chain.
+pub fn salus_certificate() -> &'static [u8] {
+    &SALUS_IDENTITY.get().unwrap().cert
+}
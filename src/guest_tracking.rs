@@ -6,7 +6,7 @@ use core::marker::PhantomData;
 use core::ops::Deref;
 use page_tracking::collections::{PageArc, PageVec};
 use page_tracking::PageTracker;
-use riscv_page_tables::GuestStagePageTable;
+use riscv_page_tables::{GuestStagePageTable, Sv39x4, Sv48x4, Sv57x4};
 use riscv_pages::{InternalClean, Page, PageOwnerId, SequentialPages};
 use spin::{Mutex, RwLock, RwLockReadGuard};
 
@@ -23,6 +23,20 @@ pub enum Error {
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+/// Evaluates `$body` with `$state` bound to the `GuestState` wrapped by the `Guest` `$guest`,
+/// whichever guest-stage paging mode it uses.
+macro_rules! with_guest_state {
+    ($guest:expr, $state:ident => $body:expr) => {
+        match $guest {
+            $crate::guest_tracking::Guest::Sv39x4($state) => $body,
+            $crate::guest_tracking::Guest::Sv48x4($state) => $body,
+            $crate::guest_tracking::Guest::Sv57x4($state) => $body,
+        }
+    };
+}
+
+pub(crate) use with_guest_state;
+
 /// Wrapper enum for a `Vm<T, S>` for each possible state S so that we can store `Vm`s of any
 /// state in a `PageArc`.
 enum GuestStateInner<T: GuestStagePageTable> {
@@ -154,14 +168,64 @@ impl<T: GuestStagePageTable> GuestState<T> {
     pub fn page_owner_id(&self) -> PageOwnerId {
         self.inner.read().page_owner_id()
     }
+
+    /// Returns the number of outstanding references to the wrapped VM.
+    fn ref_count(&self) -> usize {
+        PageArc::ref_count(&self.inner)
+    }
+
+    /// Converts the wrapped VM from an initializing VM to a finalized VM.
+    fn finalize(&self) -> Result<()> {
+        // Use try_write() here since there shouldn't be any outstanding references to a VM that
+        // we're attempting to finalize. This prevents us from blocking a potentially
+        // long-running operation on a VM that isn't even in the proper state (e.g. a finalized
+        // VM that's running a vCPU).
+        let mut state = self.inner.try_write().ok_or(Error::GuestInUse)?;
+        state.finalize()
+    }
+
+    /// Destroys the wrapped VM.
+    fn destroy(&self) {
+        self.inner.write().destroy();
+    }
+}
+
+/// A reference to a guest VM, tagged with the guest-stage paging mode the VM was created with.
+#[derive(Clone)]
+pub enum Guest {
+    Sv39x4(GuestState<Sv39x4>),
+    Sv48x4(GuestState<Sv48x4>),
+    Sv57x4(GuestState<Sv57x4>),
+}
+
+impl Guest {
+    /// Returns the `PageOwnerId` for the wrapped VM.
+    pub fn page_owner_id(&self) -> PageOwnerId {
+        with_guest_state!(self, state => state.page_owner_id())
+    }
+
+    /// Returns the number of outstanding references to the wrapped VM.
+    fn ref_count(&self) -> usize {
+        with_guest_state!(self, state => state.ref_count())
+    }
+
+    /// Converts the wrapped VM from an initializing VM to a finalized VM.
+    fn finalize(&self) -> Result<()> {
+        with_guest_state!(self, state => state.finalize())
+    }
+
+    /// Destroys the wrapped VM.
+    fn destroy(&self) {
+        with_guest_state!(self, state => state.destroy())
+    }
 }
 
 /// Tracks the guest VMs for a host VM.
-pub struct Guests<T: GuestStagePageTable> {
-    guests: Mutex<PageVec<GuestState<T>>>,
+pub struct Guests {
+    guests: Mutex<PageVec<Guest>>,
 }
 
-impl<T: GuestStagePageTable> Guests<T> {
+impl Guests {
     /// Creates a new `Guests` using `vec_pages` as storage.
     pub fn new(vec_pages: SequentialPages<InternalClean>, page_tracker: PageTracker) -> Self {
         Self {
@@ -170,7 +234,7 @@ impl<T: GuestStagePageTable> Guests<T> {
     }
 
     /// Adds `guest` to this guest tracking table.
-    pub fn add(&self, guest: GuestState<T>) -> Result<()> {
+    pub fn add(&self, guest: Guest) -> Result<()> {
         let mut guests = self.guests.lock();
         guests
             .try_reserve(1)
@@ -180,7 +244,7 @@ impl<T: GuestStagePageTable> Guests<T> {
     }
 
     /// Returns the guest with the given ID.
-    pub fn get(&self, id: PageOwnerId) -> Option<GuestState<T>> {
+    pub fn get(&self, id: PageOwnerId) -> Option<Guest> {
         let guests = self.guests.lock();
         guests.iter().find(|g| g.page_owner_id() == id).cloned()
     }
@@ -198,33 +262,26 @@ impl<T: GuestStagePageTable> Guests<T> {
                 .ok_or(Error::InvalidGuestId)?;
             // This use of ref_count() is sound since we hold the lock on self.guests and no new
             // references can be created if we hold the only reference.
-            if PageArc::ref_count(&guest.inner) != 1 {
+            if guest.ref_count() != 1 {
                 return Err(Error::GuestInUse);
             }
             let last = guest.clone();
             guests.remove(index);
             last
         };
-        guest.inner.write().destroy();
+        guest.destroy();
         Ok(())
     }
 
     /// Finalizes the guest with the given ID, converting the guest from the initializing to the
     /// finalized state. The finalized guest is returned upon success.
-    pub fn finalize(&self, id: PageOwnerId) -> Result<GuestState<T>> {
+    pub fn finalize(&self, id: PageOwnerId) -> Result<Guest> {
         let guests = self.guests.lock();
         let guest = guests
             .iter()
             .find(|g| g.page_owner_id() == id)
             .ok_or(Error::InvalidGuestId)?;
-        {
-            // Use try_write() here since there shouldn't be any outstanding references to a VM that
-            // we're attempting to finalize. This prevents us from blocking a potentially
-            // long-running operation on a VM that isn't even in the proper state (e.g. a finalized
-            // VM that's running a vCPU).
-            let mut state = guest.inner.try_write().ok_or(Error::GuestInUse)?;
-            state.finalize()?;
-        }
+        guest.finalize()?;
         Ok(guest.clone())
     }
 }
@@ -16,23 +16,26 @@
     let_chains
 )]
 
-use core::alloc::{GlobalAlloc, Layout};
+use core::alloc::{Allocator, GlobalAlloc, Layout};
 
 extern crate alloc;
 
 mod asm;
+mod dice;
 mod guest_tracking;
 mod host_vm_loader;
+mod measurement_log;
 mod print_util;
 mod smp;
 mod trap;
 mod vm;
+mod vm_console;
 mod vm_cpu;
 mod vm_id;
 mod vm_pages;
 
 use device_tree::{DeviceTree, Fdt};
-use drivers::{CpuInfo, Imsic};
+use drivers::{CpuInfo, Imsic, MmuType};
 use host_vm_loader::HostVmLoader;
 use hyp_alloc::HypAlloc;
 use page_tracking::*;
@@ -79,8 +82,27 @@ pub fn alloc_error(_layout: Layout) -> ! {
     abort()
 }
 
+/// The host VM, using the guest-stage paging mode that gives it the largest guest physical address
+/// space the CPU supports.
+enum PlatformHostVm {
+    Sv39x4(HostVm<Sv39x4>),
+    Sv48x4(HostVm<Sv48x4>),
+    Sv57x4(HostVm<Sv57x4>),
+}
+
+impl PlatformHostVm {
+    /// Runs the host VM's vCPU `vcpu_id` on this CPU.
+    fn run(&self, vcpu_id: u64) {
+        match self {
+            Self::Sv39x4(vm) => vm.run(vcpu_id),
+            Self::Sv48x4(vm) => vm.run(vcpu_id),
+            Self::Sv57x4(vm) => vm.run(vcpu_id),
+        }
+    }
+}
+
 /// The host VM that all CPUs enter at boot.
-static HOST_VM: Once<HostVm<Sv48x4>> = Once::new();
+static HOST_VM: Once<PlatformHostVm> = Once::new();
 
 /// Builds the hardware memory map from the device-tree. The kernel & initramfs image regions are
 /// aligned to `T::TOP_LEVEL_ALIGN` so that they can be mapped directly into the host VM's guest
@@ -167,6 +189,28 @@ fn build_memory_map<T: GuestStagePageTable>(fdt: &Fdt) -> MemMapResult<HwMemMap>
     Ok(mem_map)
 }
 
+/// Loads the host VM with a `T` guest-stage page table from the kernel & initramfs images loaded
+/// by firmware, mapping the rest of `hyp_mem` into its address space.
+fn load_host_vm<T: GuestStagePageTable, A: Allocator + Clone>(
+    hyp_dt: DeviceTree<A>,
+    host_kernel: HwMemRegion,
+    host_initramfs: Option<HwMemRegion>,
+    guest_ram_base: GuestPhysAddr,
+    guest_phys_size: u64,
+    hyp_mem: HypPageAlloc,
+) -> HostVm<T> {
+    HostVmLoader::new(
+        hyp_dt,
+        host_kernel,
+        host_initramfs,
+        guest_ram_base,
+        guest_phys_size,
+        hyp_mem,
+    )
+    .build_device_tree()
+    .build_address_space()
+}
+
 /// Creates a heapfrom the given `mem_map`, marking the region occupied by the heap as reserved.
 fn create_heap(mem_map: &mut HwMemMap) -> HypAlloc {
     const HEAP_SIZE: u64 = 16 * 1024 * 1024;
@@ -264,6 +308,8 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
     let hyp_fdt =
         unsafe { Fdt::new_from_raw_pointer(fdt_addr as *const u8) }.expect("Failed to read FDT");
 
+    // The paging mode of the host VM isn't known until `CpuInfo` has been parsed, which needs a
+    // heap, but all guest-stage paging modes share the same root alignment.
     let mut mem_map = build_memory_map::<Sv48x4>(&hyp_fdt).expect("Failed to build memory map");
     // Find where QEMU loaded the host kernel image.
     let host_kernel = *mem_map
@@ -278,7 +324,7 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         .cloned();
 
     let heap = create_heap(&mut mem_map);
-    let hyp_dt = DeviceTree::from(&hyp_fdt, &heap).expect("Failed to construct device-tree");
+    let mut hyp_dt = DeviceTree::from(&hyp_fdt, &heap).expect("Failed to construct device-tree");
 
     // Discover supported CPU extensions.
     CpuInfo::parse_from(&hyp_dt);
@@ -289,6 +335,12 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         // don't support the *envcfg registers.
         CSR.henvcfg.modify(henvcfg::stce.val(1));
     }
+    if cpu_info.has_svpbmt() {
+        println!("Svpbmt support present");
+        // The host sees Svpbmt in its ISA string, so let it use memory types in its own page
+        // tables.
+        CSR.henvcfg.modify(henvcfg::pbmte.val(1));
+    }
     println!(
         "{} CPU(s) present. Booting on CPU{} (hart {})",
         cpu_info.num_cpus(),
@@ -309,6 +361,9 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
     );
     Imsic::setup_this_cpu();
 
+    // Derive Salus' attestation identity from the one firmware provides.
+    dice::init(&mut hyp_dt);
+
     // Set up per-CPU memory and boot the secondary CPUs.
     PerCpu::init(hart_id, &mut mem_map);
     smp::start_secondary_cpus();
@@ -326,17 +381,33 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
     // into the host VM.
     let hyp_mem = HypPageAlloc::new(mem_map);
 
-    // Now load the host VM.
-    let host = HostVmLoader::new(
-        hyp_dt,
-        host_kernel,
-        host_initramfs,
-        guest_ram_base,
-        guest_phys_size,
-        hyp_mem,
-    )
-    .build_device_tree()
-    .build_address_space();
+    // Now load the host VM, giving it the largest guest physical address space the CPU supports.
+    let host = match cpu_info.mmu_type() {
+        MmuType::Sv39 => PlatformHostVm::Sv39x4(load_host_vm(
+            hyp_dt,
+            host_kernel,
+            host_initramfs,
+            guest_ram_base,
+            guest_phys_size,
+            hyp_mem,
+        )),
+        MmuType::Sv48 => PlatformHostVm::Sv48x4(load_host_vm(
+            hyp_dt,
+            host_kernel,
+            host_initramfs,
+            guest_ram_base,
+            guest_phys_size,
+            hyp_mem,
+        )),
+        MmuType::Sv57 => PlatformHostVm::Sv57x4(load_host_vm(
+            hyp_dt,
+            host_kernel,
+            host_initramfs,
+            guest_ram_base,
+            guest_phys_size,
+            hyp_mem,
+        )),
+    };
     HOST_VM.call_once(|| host);
 
     let cpu_id = PerCpu::this_cpu().cpu_id();
@@ -350,6 +421,9 @@ extern "C" fn secondary_init(_hart_id: u64) {
     if cpu_info.has_sstc() {
         CSR.henvcfg.modify(henvcfg::stce.val(1));
     }
+    if cpu_info.has_svpbmt() {
+        CSR.henvcfg.modify(henvcfg::pbmte.val(1));
+    }
     Imsic::setup_this_cpu();
 
     let me = PerCpu::this_cpu();
@@ -0,0 +1,77 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use data_measure::event_log::{MeasurementEvent, EVENT_HEADER_BYTES, MAX_EVENT_BYTES};
+use page_tracking::collections::PageVec;
+use page_tracking::PageTracker;
+use riscv_pages::{InternalClean, SequentialPages};
+
+/// Records the events that make up a VM's measurements, in the format defined by
+/// `data_measure::event_log`, so that a verifier can replay them.
+pub struct MeasurementLog {
+    events: Option<PageVec<u8>>,
+    truncated: bool,
+    missed: bool,
+}
+
+impl MeasurementLog {
+    /// Creates a log that records nothing until it's given pages to record events in.
+    pub fn new() -> Self {
+        Self {
+            events: None,
+            truncated: false,
+            missed: false,
+        }
+    }
+
+    /// Returns if the log can be given pages to record events in, which is only the case if it
+    /// hasn't been given any yet and no events have been missed for lack of them.
+    pub fn can_set_pages(&self) -> bool {
+        self.events.is_none() && !self.missed
+    }
+
+    /// Records subsequent events in `pages`.
+    pub fn set_pages(&mut self, pages: SequentialPages<InternalClean>, page_tracker: PageTracker) {
+        self.events = Some(PageVec::new(pages, page_tracker));
+    }
+
+    /// Appends `event` to the log. Space is always left for a `Truncated` event, which is appended
+    /// instead once `event` no longer fits; nothing more is recorded after that.
+    pub fn record(&mut self, event: MeasurementEvent) {
+        if self.truncated {
+            return;
+        }
+        let events = match self.events.as_mut() {
+            Some(events) => events,
+            None => {
+                self.missed = true;
+                return;
+            }
+        };
+        let len = event.encoded_len();
+        let event =
+            if len <= MAX_EVENT_BYTES && events.try_reserve(len + EVENT_HEADER_BYTES).is_ok() {
+                event
+            } else {
+                self.truncated = true;
+                MeasurementEvent::Truncated
+            };
+        let mut buf = [0u8; MAX_EVENT_BYTES];
+        // Unwrap ok: we checked that the event fits in `buf`, and `Truncated` always does.
+        let encoded = event.encode(&mut buf).unwrap();
+        // Unwrap ok: there's always room left for a `Truncated` event.
+        events.try_reserve(encoded.len()).unwrap();
+        for &b in encoded {
+            events.push(b);
+        }
+    }
+
+    /// Returns up to `max_len` bytes of the log, starting `offset` bytes into it.
+    pub fn read(&self, offset: usize, max_len: usize) -> &[u8] {
+        let events = self.events.as_ref().map_or(&[][..], |e| &e[..]);
+        let start = offset.min(events.len());
+        let end = start + max_len.min(events.len() - start);
+        &events[start..end]
+    }
+}
@@ -30,6 +30,17 @@ macro_rules! println {
 
 pub static mut CONSOLE_DRIVER: Once<UartDriver> = Once::new();
 
+/// Writes `bytes` to the console, if one has been initialized.
+pub fn console_write_bytes(bytes: &[u8]) {
+    // Safety: The console driver is only mutated during initialization, and `write_bytes` takes
+    // `&self`.
+    unsafe {
+        if let Some(c) = CONSOLE_DRIVER.get() {
+            c.write_bytes(bytes);
+        }
+    }
+}
+
 /// Driver for a standard UART.
 pub struct UartDriver {
     base_address: Mutex<NonNull<u8>>,
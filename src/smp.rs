@@ -8,7 +8,7 @@ use drivers::{CpuId, CpuInfo, Imsic};
 use page_tracking::{HwMemMap, HwMemRegionType, HwReservedMemType};
 use riscv_pages::{PageSize, RawAddr, SupervisorPageAddr};
 use riscv_regs::{sstatus, ReadWriteable, CSR};
-use sbi::{SbiMessage, StateFunction};
+use sbi::{SbiMessage, StateFunction, TimerFunction};
 use spin::Once;
 
 use crate::vm_id::VmIdTracker;
@@ -27,6 +27,8 @@ pub struct PerCpu {
     cpu_id: CpuId,
     vmid_tracker: RefCell<VmIdTracker>,
     in_guest_copy: RefCell<bool>,
+    timer_deadline: RefCell<u64>,
+    host_timer_deadline: RefCell<u64>,
     online: Once<bool>,
 }
 
@@ -66,6 +68,8 @@ impl PerCpu {
                 cpu_id,
                 vmid_tracker: RefCell::new(VmIdTracker::new()),
                 in_guest_copy: RefCell::new(false),
+                timer_deadline: RefCell::new(u64::MAX),
+                host_timer_deadline: RefCell::new(u64::MAX),
                 online: Once::new(),
             };
             // Safety: ptr is guaranteed to be properly aligned and point to valid memory owned by
@@ -143,6 +147,17 @@ impl PerCpu {
     pub fn in_guest_memcpy(&self) -> bool {
         *self.in_guest_copy.borrow()
     }
+
+    /// Records the time at which the timer of the host VM vCPU last run on this CPU is next due to
+    /// fire, so that it can be honored while running a guest VM on the host's behalf.
+    pub fn set_host_timer_deadline(&self, deadline: u64) {
+        *self.host_timer_deadline.borrow_mut() = deadline;
+    }
+
+    /// Returns the time at which the timer of the host VM vCPU last run on this CPU is due to fire.
+    pub fn host_timer_deadline(&self) -> u64 {
+        *self.host_timer_deadline.borrow()
+    }
 }
 
 /// Halts this CPU until an interrupt (for example, delivered via `kick_cpu()`) is received.
@@ -153,11 +168,34 @@ pub fn wfi() {
     CSR.sstatus.modify(sstatus::sie.val(0));
 }
 
+/// Halts this CPU until an interrupt is pending, without taking the interrupt. The caller is
+/// responsible for checking for and handling the pending interrupt.
+pub fn wfi_masked() {
+    // Safety: WFI behavior is well-defined.
+    unsafe { asm!("wfi", options(nomem, nostack)) };
+}
+
 /// Sends an IPI to `cpu`.
 pub fn send_ipi(cpu: CpuId) {
     Imsic::get().send_ipi(cpu).unwrap();
 }
 
+/// Arms this CPU's supervisor timer to fire at `deadline`. Passing `u64::MAX` disarms the timer
+/// and clears any pending timer interrupt.
+pub fn set_timer(deadline: u64) {
+    // Avoid the trip to firmware if the timer is already armed for this deadline.
+    let mut armed = PerCpu::this_cpu().timer_deadline.borrow_mut();
+    if *armed == deadline {
+        return;
+    }
+    *armed = deadline;
+    let msg = SbiMessage::Timer(TimerFunction::SetTimer {
+        stime_value: deadline,
+    });
+    // Safety: SetTimer doesn't reference memory.
+    unsafe { ecall_send(&msg).unwrap() };
+}
+
 /// Boots secondary CPUs, using the HSM SBI call. Upon return, all secondary CPUs will have
 /// entered secondary_init().
 pub fn start_secondary_cpus() {
@@ -7,11 +7,11 @@ use core::mem::size_of;
 use drivers::{Imsic, ImsicInterruptId};
 use memoffset::offset_of;
 use riscv_regs::{
-    sie, GeneralPurposeRegisters, GprIndex, Interrupt, Readable, Trap, Writeable, CSR,
+    sie, Exception, GeneralPurposeRegisters, GprIndex, Interrupt, Readable, Trap, Writeable, CSR,
 };
 
 use crate::print_util::*;
-use crate::smp::PerCpu;
+use crate::smp::{self, PerCpu};
 use crate::{print, println};
 
 /// Stores the trap context as pushed onto the stack by the trap handler.
@@ -70,7 +70,7 @@ global_asm!(
 );
 
 /// Attempts to handle an interrupt, returning true if the interrupt was successfully handled.
-fn handle_interrupt(irq: Interrupt) -> bool {
+pub fn handle_interrupt(irq: Interrupt) -> bool {
     match irq {
         Interrupt::SupervisorExternal => {
             let mut handled = false;
@@ -84,6 +84,12 @@ fn handle_interrupt(irq: Interrupt) -> bool {
             }
             handled
         }
+        Interrupt::SupervisorTimer => {
+            // The timer is only armed on behalf of a vCPU, which re-arms it the next time the vCPU
+            // runs.
+            smp::set_timer(u64::MAX);
+            true
+        }
         // TODO: Handle supervisor guest external interrupts.
         _ => false,
     }
@@ -110,9 +116,14 @@ extern "C" fn handle_trap(tf_ptr: *mut TrapFrame) {
                 }
             }
             Trap::Exception(e) => {
-                if this_cpu.in_guest_memcpy() && e.is_guest_page_fault() {
+                // Fetching a guest instruction with HLVX goes through VS-stage translation and
+                // may take a VS-level load fault as well.
+                let is_guest_access_fault = e.is_guest_page_fault()
+                    || matches!(e, Exception::LoadPageFault | Exception::LoadFault);
+                if this_cpu.in_guest_memcpy() && is_guest_access_fault {
                     // We took a guest page fault while copying to/from guest memory.
-                    // _copy_{to,from}_guest set T0 to where they want to jump to on a fault.
+                    // _copy_{to,from}_guest and _fetch_guest_instruction set T0 to where they
+                    // want to jump to on a fault.
                     tf.sepc = tf.gprs.reg(GprIndex::T0);
                     return;
                 }
@@ -200,6 +211,8 @@ extern "C" fn handle_trap(tf_ptr: *mut TrapFrame) {
 pub fn install_trap_handler() {
     CSR.stvec.set((_trap_entry as usize).try_into().unwrap());
 
-    // We only expect supervisor-level external interrupts for now.
-    CSR.sie.read_and_set_bits(1 << sie::sext.shift);
+    // We expect supervisor-level external interrupts for IPIs and timer interrupts when emulating
+    // the timers of vCPUs.
+    CSR.sie
+        .read_and_set_bits(1 << sie::sext.shift | 1 << sie::stimer.shift);
 }
@@ -2,25 +2,38 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use attestation::{request::CertReq, MAX_CSR_LEN};
+use arrayvec::ArrayVec;
+use attestation::{eat::EatClaims, request::CertReq, MAX_CERT_LEN, MAX_CSR_LEN};
+use attestation::{MAX_EVIDENCE_NONCE_LEN, MAX_EVIDENCE_TOKEN_LEN};
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::{mem, slice};
-use data_measure::sha256::SHA256_DIGEST_BYTES;
+use data_measure::data_measure::{DigestAlgorithm, MAX_DIGEST_BYTES};
+use data_measure::initial_state::{MmioRegion, PagePerms, TvmConfig, TvmSigner, VcpuState};
+use data_measure::runtime::NUM_RUNTIME_MEASUREMENT_REGISTERS;
 use der::Decode;
-use drivers::{CpuId, CpuInfo, ImsicGuestId, MAX_CPUS};
+use drivers::{CpuId, CpuInfo, ImsicGuestId, MmuType, MAX_CPUS};
 use page_tracking::{HypPageAlloc, PageList, PageTracker};
-use riscv_page_tables::{GuestStagePageTable, PlatformPageTable};
+use riscv_page_tables::{
+    GuestStagePageTable, PlatformPageTable, PteLeafPerms, PteMemType, Sv39x4, Sv48x4, Sv57x4,
+};
 use riscv_pages::*;
-use riscv_regs::GprIndex;
+use riscv_regs::{hie, GprIndex, Interrupt, MmioOpcode, Readable, Trap, Writeable, CSR};
 use s_mode_utils::abort::abort;
 use sbi::Error as SbiError;
 use sbi::*;
 
-use crate::guest_tracking::{GuestState, Guests};
+use crate::dice::{self, DiceLayer};
+use crate::guest_tracking::{with_guest_state, Guest, GuestState, Guests};
 use crate::print_util::*;
-use crate::smp;
-use crate::vm_cpu::{VirtualRegister, VmCpuExit, VmCpuStatus, VmCpus, VM_CPU_BYTES};
-use crate::vm_pages::{self, ActiveVmPages, VmPages, TVM_STATE_PAGES};
-use crate::{print, println};
+use crate::println;
+use crate::smp::{self, PerCpu};
+use crate::trap;
+use crate::vm_console::VmConsole;
+use crate::vm_cpu::{
+    ActiveVmCpu, VirtualRegister, VmCpuExit, VmCpuRequest, VmCpuStatus, VmCpus, VM_CPU_BYTES,
+};
+use crate::vm_pages::Error as VmPagesError;
+use crate::vm_pages::{ActiveVmPages, VmPages, TVM_STATE_PAGES};
 
 const GUEST_ID_SELF_MEASUREMENT: u64 = 0;
 
@@ -28,6 +41,99 @@ const GUEST_ID_SELF_MEASUREMENT: u64 = 0;
 // confuses us with BBL/OpenSBI.
 const SBI_IMPL_ID_SALUS: u64 = 7;
 
+// The maximum length of any measurement returned by `Get{Guest,Self}Measurement`.
+const MAX_MEASUREMENT_BYTES: usize =
+    mem::size_of::<MeasurementHeader>() + NUM_RUNTIME_MEASUREMENT_REGISTERS * MAX_DIGEST_BYTES;
+
+// The number of bytes copied to a VM at a time when reading a measurement log.
+const MEASUREMENT_LOG_COPY_BYTES: usize = 256;
+
+// The number of bytes copied from a VM at a time when writing to its console.
+const CONSOLE_COPY_BYTES: usize = 64;
+
+// The serial number of the next evidence certificate to be issued.
+static NEXT_EVIDENCE_SERIAL: AtomicU64 = AtomicU64::new(1);
+
+/// Copies the measurement of type `measurement_type` for `vm_pages` into `dest`, preceded by a
+/// `MeasurementHeader` if `measurement_version` is 2. Returns the number of bytes copied.
+fn get_measurement_of_type<T: GuestStagePageTable, S>(
+    vm_pages: &VmPages<T, S>,
+    measurement_version: u64,
+    measurement_type: u64,
+    dest: &mut [u8],
+) -> sbi::Result<usize> {
+    let header_len = match measurement_version {
+        1 => 0,
+        2 => mem::size_of::<MeasurementHeader>(),
+        _ => return Err(SbiError::InvalidParam),
+    };
+    let algorithm = vm_pages.measurement_algorithm();
+    let len = match measurement_type {
+        MEASUREMENT_TYPE_STATIC => vm_pages
+            .get_measurement(&mut dest[header_len..])
+            .map(|_| algorithm.digest_len()),
+        MEASUREMENT_TYPE_RUNTIME => vm_pages
+            .get_runtime_measurements(&mut dest[header_len..])
+            .map(|_| NUM_RUNTIME_MEASUREMENT_REGISTERS * algorithm.digest_len()),
+        _ => return Err(SbiError::InvalidParam),
+    }
+    .map_err(|_| SbiError::Failed)?;
+    if header_len != 0 {
+        // Fill in the `algorithm` and `len` fields of the `MeasurementHeader`.
+        let (alg_bytes, len_bytes) = dest[..header_len].split_at_mut(mem::size_of::<u64>());
+        alg_bytes.copy_from_slice(&(sbi_measurement_algorithm(algorithm) as u64).to_le_bytes());
+        len_bytes.copy_from_slice(&(len as u64).to_le_bytes());
+    }
+    Ok(header_len + len)
+}
+
+/// Returns the `DigestAlgorithm` corresponding to the measurement algorithm used in TEE calls.
+fn digest_algorithm_from_sbi(algorithm: sbi::MeasurementAlgorithm) -> DigestAlgorithm {
+    match algorithm {
+        sbi::MeasurementAlgorithm::Sha256 => DigestAlgorithm::Sha256,
+        sbi::MeasurementAlgorithm::Sha384 => DigestAlgorithm::Sha384,
+        sbi::MeasurementAlgorithm::Sha512 => DigestAlgorithm::Sha512,
+    }
+}
+
+/// Returns the measurement algorithm used in TEE calls corresponding to `algorithm`.
+fn sbi_measurement_algorithm(algorithm: DigestAlgorithm) -> sbi::MeasurementAlgorithm {
+    match algorithm {
+        DigestAlgorithm::Sha256 => sbi::MeasurementAlgorithm::Sha256,
+        DigestAlgorithm::Sha384 => sbi::MeasurementAlgorithm::Sha384,
+        DigestAlgorithm::Sha512 => sbi::MeasurementAlgorithm::Sha512,
+    }
+}
+
+/// Returns the MMU type the CPU must support in order to run a TVM using `paging_mode`.
+fn mmu_type_for_paging_mode(paging_mode: sbi::TvmPagingMode) -> MmuType {
+    match paging_mode {
+        sbi::TvmPagingMode::Sv39x4 => MmuType::Sv39,
+        sbi::TvmPagingMode::Sv48x4 => MmuType::Sv48,
+        sbi::TvmPagingMode::Sv57x4 => MmuType::Sv57,
+    }
+}
+
+/// Returns the `PageSize` corresponding to the page type used in TEE calls.
+fn page_size_from_type(page_type: sbi::TsmPageType) -> PageSize {
+    match page_type {
+        sbi::TsmPageType::Page4k => PageSize::Size4k,
+        sbi::TsmPageType::Page2M => PageSize::Size2M,
+        sbi::TsmPageType::Page1G => PageSize::Size1G,
+        sbi::TsmPageType::Page512G => PageSize::Size512G,
+    }
+}
+
+/// Returns the `PteLeafPerms` corresponding to the page permissions used in TEE calls.
+fn leaf_perms_from_page_perms(perms: sbi::TsmPagePerms) -> PteLeafPerms {
+    match perms {
+        sbi::TsmPagePerms::ReadWriteExecute => PteLeafPerms::RWX,
+        sbi::TsmPagePerms::ReadWrite => PteLeafPerms::RW,
+        sbi::TsmPagePerms::ReadExecute => PteLeafPerms::RX,
+        sbi::TsmPagePerms::ReadOnly => PteLeafPerms::R,
+    }
+}
+
 /// Powers off this machine.
 pub fn poweroff() -> ! {
     // Safety: on this platform, a write of 0x5555 to 0x100000 will trigger the platform to
@@ -46,8 +152,15 @@ enum VmExitCause {
     PowerOff(ResetType, ResetReason),
     CpuStart(u64),
     CpuStop,
+    CpuSuspend(u32, u64),
     PageFault(GuestPhysAddr),
     UnhandledTrap(u64),
+    MmioLoad(GuestPhysAddr, u64),
+    MmioStore(GuestPhysAddr, u64),
+    ShareMemory(GuestPageAddr, u64),
+    UnshareMemory(GuestPageAddr, u64),
+    AcceptMemory(GuestPageAddr, u64),
+    HostInterrupt,
 }
 
 impl VmExitCause {
@@ -57,8 +170,15 @@ impl VmExitCause {
             PowerOff(_, _) => TvmCpuExitCode::SystemReset,
             CpuStart(_) => TvmCpuExitCode::HartStart,
             CpuStop => TvmCpuExitCode::HartStop,
+            CpuSuspend(_, _) => TvmCpuExitCode::HartSuspend,
             PageFault(_) => TvmCpuExitCode::GuestPageFault,
             UnhandledTrap(_) => TvmCpuExitCode::UnhandledException,
+            MmioLoad(_, _) => TvmCpuExitCode::MmioLoad,
+            MmioStore(_, _) => TvmCpuExitCode::MmioStore,
+            ShareMemory(_, _) => TvmCpuExitCode::ShareMemory,
+            UnshareMemory(_, _) => TvmCpuExitCode::UnshareMemory,
+            AcceptMemory(_, _) => TvmCpuExitCode::AcceptMemory,
+            HostInterrupt => TvmCpuExitCode::HostInterrupt,
         }
     }
 
@@ -67,8 +187,13 @@ impl VmExitCause {
         match self {
             PowerOff(reset_type, _) => Some(*reset_type as u64),
             CpuStart(hart_id) => Some(*hart_id),
+            CpuSuspend(suspend_type, _) => Some(*suspend_type as u64),
             PageFault(fault_addr) => Some(fault_addr.bits()),
             UnhandledTrap(scause) => Some(*scause),
+            MmioLoad(fault_addr, _) | MmioStore(fault_addr, _) => Some(fault_addr.bits()),
+            ShareMemory(addr, _) | UnshareMemory(addr, _) | AcceptMemory(addr, _) => {
+                Some(addr.bits())
+            }
             _ => None,
         }
     }
@@ -77,6 +202,9 @@ impl VmExitCause {
         use VmExitCause::*;
         match self {
             PowerOff(_, reset_reason) => Some(*reset_reason as u64),
+            CpuSuspend(_, timer_deadline) => Some(*timer_deadline),
+            MmioLoad(_, width) | MmioStore(_, width) => Some(*width),
+            ShareMemory(_, len) | UnshareMemory(_, len) | AcceptMemory(_, len) => Some(*len),
             _ => None,
         }
     }
@@ -94,7 +222,10 @@ enum EcallAction {
 pub struct Vm<T: GuestStagePageTable, S = VmStateFinalized> {
     vcpus: VmCpus,
     vm_pages: VmPages<T, S>,
-    guests: Option<Guests<T>>,
+    guests: Option<Guests>,
+    console: VmConsole,
+    signer: Option<TvmSigner>,
+    identity: Option<DiceLayer>,
 }
 
 impl<T: GuestStagePageTable, S> Vm<T, S> {
@@ -122,9 +253,17 @@ impl<T: GuestStagePageTable> Vm<T, VmStateInitializing> {
             vcpus,
             vm_pages,
             guests: None,
+            console: VmConsole::new(),
+            signer: None,
+            identity: None,
         }
     }
 
+    /// Sets the signer of this VM's image, given by the host when it created this VM.
+    fn set_signer(&mut self, signer: TvmSigner) {
+        self.signer = Some(signer);
+    }
+
     /// `guests`: A vec for storing guest info if "nested" guests will be created. Must have
     /// length zero and capacity limits the number of nested guests.
     fn add_guest_tracking_pages(&mut self, pages: SequentialPages<InternalClean>) {
@@ -175,12 +314,71 @@ impl<T: GuestStagePageTable> Vm<T, VmStateInitializing> {
         Ok(())
     }
 
-    /// Completes intialization of the `Vm`, returning it in a finalized state.
+    /// Adds the configuration of this VM, the signer of its image, its emulated MMIO regions and
+    /// the initial state of its vCPUs to its measurement, so that the host can't change how an
+    /// otherwise identical image is run without changing its measurement.
+    fn measure_initial_state(&self) {
+        let num_vcpus = (0..self.vcpus.num_vcpus())
+            .filter(|&id| self.vcpus.get_vcpu(id).is_ok())
+            .count();
+        let config = TvmConfig {
+            num_vcpus: num_vcpus as u64,
+        };
+        self.vm_pages.add_measured_data(&config.encode());
+        if let Some(signer) = self.signer {
+            self.vm_pages.add_measured_data(&signer.encode());
+        }
+        self.vm_pages
+            .for_each_emulated_mmio_region(|addr, num_pages| {
+                let region = MmioRegion {
+                    gpa: addr.bits(),
+                    num_pages,
+                };
+                self.vm_pages.add_measured_data(&region.encode());
+            });
+        for vcpu_id in 0..self.vcpus.num_vcpus() {
+            // vCPUs that weren't added have no state to measure.
+            let entry_pc = match self.get_vcpu_reg(vcpu_id, TvmCpuRegister::EntryPc) {
+                Ok(entry_pc) => entry_pc,
+                Err(_) => continue,
+            };
+            let state = VcpuState {
+                vcpu_id,
+                entry_pc,
+                entry_arg: self
+                    .get_vcpu_reg(vcpu_id, TvmCpuRegister::EntryArg)
+                    .unwrap(),
+            };
+            self.vm_pages.add_measured_data(&state.encode());
+        }
+    }
+
+    /// Completes intialization of the `Vm`, returning it in a finalized state. The measurements of
+    /// TVMs are completed with their initial vCPU state and configuration, and TVMs are given a
+    /// DICE identity derived from Salus' identity and their initial measurement.
+    ///
+    /// TODO: The identity of a nested TVM should be derived from that of the TVM that created it.
     pub fn finalize(self) -> Vm<T, VmStateFinalized> {
+        let identity = if self.page_owner_id().is_host() {
+            None
+        } else {
+            self.measure_initial_state();
+            let algorithm = self.vm_pages.measurement_algorithm();
+            let mut measurement = [0u8; MAX_DIGEST_BYTES];
+            self.vm_pages.get_measurement(&mut measurement).unwrap();
+            Some(dice::salus_layer().derive(
+                algorithm,
+                &measurement[..algorithm.digest_len()],
+                "Salus TVM",
+            ))
+        };
         Vm {
             vcpus: self.vcpus,
             vm_pages: self.vm_pages.finalize(),
             guests: self.guests,
+            console: self.console,
+            signer: self.signer,
+            identity,
         }
     }
 
@@ -233,6 +431,7 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         let status = match vcpu_status {
             VmCpuStatus::Runnable | VmCpuStatus::Running => HartState::Started,
             VmCpuStatus::PoweredOff => HartState::Stopped,
+            VmCpuStatus::Suspended => HartState::Suspended,
             VmCpuStatus::NotPresent => {
                 return Err(SbiError::InvalidParam);
             }
@@ -240,6 +439,23 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         Ok(status as u64)
     }
 
+    /// Sets a vCPU register.
+    fn set_vcpu_reg(&self, vcpu_id: u64, register: TvmCpuRegister, value: u64) -> sbi::Result<()> {
+        let vcpu = self
+            .vcpus
+            .get_vcpu(vcpu_id)
+            .map_err(|_| SbiError::InvalidParam)?;
+        let mut vcpu = vcpu.lock();
+        use TvmCpuRegister::*;
+        match register {
+            MmioData => vcpu.set_virt_reg(VirtualRegister::MmioData, value),
+            _ => {
+                return Err(SbiError::InvalidParam);
+            }
+        };
+        Ok(())
+    }
+
     /// Gets a vCPU register.
     fn get_vcpu_reg(&self, vcpu_id: u64, register: TvmCpuRegister) -> sbi::Result<u64> {
         let vcpu = self
@@ -251,6 +467,7 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         match register {
             ExitCause0 => Ok(vcpu.get_virt_reg(VirtualRegister::Cause0)),
             ExitCause1 => Ok(vcpu.get_virt_reg(VirtualRegister::Cause1)),
+            MmioData => Ok(vcpu.get_virt_reg(VirtualRegister::MmioData)),
             _ => Err(SbiError::InvalidParam),
         }
     }
@@ -262,6 +479,8 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
             .vcpus
             .take_vcpu(vcpu_id)
             .map_err(|_| SbiError::InvalidParam)?;
+        // Complete any memory share or unshare requests that have been fenced since we last ran.
+        self.vm_pages.release_fenced_regions();
         let exit_code = {
             let mut vcpu = vcpu.lock();
 
@@ -270,12 +489,15 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                 // Activate this vCPU and its address space. We re-activate after every exit (even
                 // if it was handled) so that any pending TLB maintenance can be completed.
                 let mut active_vcpu = vcpu.activate(&self.vm_pages).unwrap();
+                // Now that we're in the VM's address space, act on any requests other vCPUs have
+                // made of us.
+                self.handle_vcpu_requests(vcpu_id, &mut active_vcpu);
 
                 let exit = active_vcpu.run_to_exit();
                 use SbiReturnType::*;
                 match exit {
                     VmCpuExit::Ecall(Some(sbi_msg)) => {
-                        match self.handle_ecall(sbi_msg, active_vcpu.active_pages()) {
+                        match self.handle_ecall(sbi_msg, vcpu_id, &mut active_vcpu) {
                             EcallAction::LegacyOk => {
                                 active_vcpu.set_ecall_result(Legacy(0));
                             }
@@ -299,13 +521,18 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                             .set_ecall_result(Standard(SbiReturn::from(SbiError::NotSupported)));
                     }
                     VmCpuExit::PageFault(addr) => {
-                        if self.handle_guest_fault(addr).is_err() {
-                            break VmExitCause::PageFault(addr);
+                        if let Err(reason) = self.handle_guest_fault(addr, &mut active_vcpu) {
+                            break reason;
                         }
                     }
                     VmCpuExit::DelegatedException(e, stval) => {
                         active_vcpu.inject_exception(e, stval);
                     }
+                    VmCpuExit::HostInterrupt(irq) => {
+                        if let Err(reason) = self.handle_host_interrupt(irq) {
+                            break reason;
+                        }
+                    }
                     VmCpuExit::Other(ref trap_csrs) => {
                         println!("Unhandled guest exit, SCAUSE = 0x{:08x}", trap_csrs.scause);
                         break VmExitCause::UnhandledTrap(trap_csrs.scause);
@@ -328,17 +555,25 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         use TvmCpuExitCode::*;
         if matches!(exit_code, SystemReset | HartStop | UnhandledException) {
             vcpu.power_off();
+        } else if exit_code == HartSuspend {
+            vcpu.suspend();
         }
 
         Ok(exit_code)
     }
 
     /// Handles ecalls from the guest.
-    fn handle_ecall(&self, msg: SbiMessage, active_pages: &ActiveVmPages<T>) -> EcallAction {
+    fn handle_ecall(
+        &self,
+        msg: SbiMessage,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> EcallAction {
         match msg {
             SbiMessage::PutChar(c) => {
-                // put char - legacy command
-                print!("{}", c as u8 as char);
+                // put char - legacy command. There's no way to report a full console buffer, so the
+                // character is dropped in that case.
+                self.write_console(&[c as u8]);
                 EcallAction::LegacyOk
             }
             SbiMessage::Reset(ResetFunction::Reset { reset_type, reason }) => EcallAction::Break(
@@ -346,16 +581,36 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                 SbiReturn::success(0),
             ),
             SbiMessage::Base(base_func) => EcallAction::Continue(self.handle_base_msg(base_func)),
-            SbiMessage::HartState(hsm_func) => self.handle_hart_state_msg(hsm_func),
-            SbiMessage::Tee(tee_func) => {
-                EcallAction::Continue(self.handle_tee_msg(tee_func, active_pages))
+            SbiMessage::HartState(hsm_func) => {
+                self.handle_hart_state_msg(hsm_func, vcpu_id, active_vcpu)
             }
-            SbiMessage::Measurement(measurement_func) => {
-                EcallAction::Continue(self.handle_measurement_msg(measurement_func, active_pages))
+            SbiMessage::Timer(TimerFunction::SetTimer { stime_value }) => {
+                active_vcpu.set_timer(stime_value);
+                EcallAction::Continue(SbiReturn::success(0))
             }
-            SbiMessage::Attestation(attestation_func) => {
-                EcallAction::Continue(self.handle_attestation_msg(attestation_func, active_pages))
+            SbiMessage::Ipi(ipi_func) => EcallAction::Continue(
+                self.post_vcpu_requests(ipi_func.hart_mask(), VmCpuRequest::Ipi)
+                    .map(|_| 0)
+                    .into(),
+            ),
+            SbiMessage::Rfence(rfence_func) => {
+                EcallAction::Continue(self.handle_rfence_msg(rfence_func, vcpu_id, active_vcpu))
+            }
+            SbiMessage::DebugConsole(console_func) => EcallAction::Continue(
+                self.handle_debug_console_msg(console_func, active_vcpu.active_pages()),
+            ),
+            SbiMessage::Tee(tee_func) => {
+                EcallAction::Continue(self.handle_tee_msg(tee_func, active_vcpu.active_pages()))
             }
+            SbiMessage::TeeGuest(guest_func) => {
+                self.handle_tee_guest_msg(guest_func, vcpu_id, active_vcpu)
+            }
+            SbiMessage::Measurement(measurement_func) => EcallAction::Continue(
+                self.handle_measurement_msg(measurement_func, active_vcpu.active_pages()),
+            ),
+            SbiMessage::Attestation(attestation_func) => EcallAction::Continue(
+                self.handle_attestation_msg(attestation_func, active_vcpu.active_pages()),
+            ),
         }
     }
 
@@ -369,9 +624,14 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                 sbi::EXT_PUT_CHAR
                 | sbi::EXT_BASE
                 | sbi::EXT_HART_STATE
+                | sbi::EXT_TIMER
+                | sbi::EXT_IPI
+                | sbi::EXT_RFENCE
+                | sbi::EXT_DEBUG_CONSOLE
                 | sbi::EXT_RESET
                 | sbi::EXT_TEE
                 | sbi::EXT_MEASUREMENT => 1,
+                sbi::EXT_TEE_GUEST if !self.page_owner_id().is_host() => 1,
                 _ => 0,
             },
             // TODO: 0 is valid result for the GetMachine* SBI calls but we should probably
@@ -381,7 +641,12 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         SbiReturn::success(ret)
     }
 
-    fn handle_hart_state_msg(&self, hsm_func: StateFunction) -> EcallAction {
+    fn handle_hart_state_msg(
+        &self,
+        hsm_func: StateFunction,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> EcallAction {
         use StateFunction::*;
         match hsm_func {
             HartStart {
@@ -394,7 +659,64 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
             },
             HartStop => EcallAction::Break(VmExitCause::CpuStop, SbiReturn::success(0)),
             HartStatus { hart_id } => EcallAction::Continue(self.get_vcpu_status(hart_id).into()),
-            _ => EcallAction::Unhandled,
+            HartSuspend {
+                suspend_type,
+                resume_addr,
+                opaque,
+            } => match HartSuspendType::from_raw(suspend_type) {
+                Ok(t) => {
+                    if t == HartSuspendType::DefaultNonRetentive {
+                        active_vcpu.suspend_non_retentive(vcpu_id, resume_addr, opaque);
+                    }
+                    EcallAction::Break(
+                        VmExitCause::CpuSuspend(suspend_type, active_vcpu.timer_deadline()),
+                        SbiReturn::success(0),
+                    )
+                }
+                Err(e) => EcallAction::Continue(SbiReturn::from(e)),
+            },
+        }
+    }
+
+    fn handle_rfence_msg(
+        &self,
+        rfence_func: RfenceFunction,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> SbiReturn {
+        use RfenceFunction::*;
+        let req = match rfence_func {
+            RemoteFenceI { .. } => VmCpuRequest::FenceI,
+            RemoteSFenceVma { .. } | RemoteSFenceVmaAsid { .. } => VmCpuRequest::SFenceVma,
+        };
+        self.fence_vcpus(rfence_func.hart_mask(), req, vcpu_id, active_vcpu)
+            .map(|_| 0)
+            .into()
+    }
+
+    fn handle_debug_console_msg(
+        &self,
+        console_func: DebugConsoleFunction,
+        active_pages: &ActiveVmPages<T>,
+    ) -> SbiReturn {
+        use DebugConsoleFunction::*;
+        match console_func {
+            Write {
+                num_bytes,
+                base_addr_lo,
+                base_addr_hi,
+            } => self
+                .console_write(num_bytes, base_addr_lo, base_addr_hi, active_pages)
+                .into(),
+            // There's no console input to give to VMs.
+            Read { .. } => SbiReturn::success(0),
+            WriteByte { byte } => {
+                if self.write_console(&[byte]) == 0 {
+                    SbiReturn::from(SbiError::Failed)
+                } else {
+                    SbiReturn::success(0)
+                }
+            }
         }
     }
 
@@ -438,8 +760,9 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                 page_type,
                 num_pages,
                 guest_addr,
+                perms,
             } => self
-                .guest_add_zero_pages(guest_id, page_addr, page_type, num_pages, guest_addr)
+                .guest_add_zero_pages(guest_id, page_addr, page_type, num_pages, guest_addr, perms)
                 .into(),
             TvmAddMeasuredPages {
                 guest_id,
@@ -459,6 +782,15 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                     active_pages,
                 )
                 .into(),
+            TvmAddSharedPages {
+                guest_id,
+                page_addr,
+                num_pages,
+                guest_addr,
+                mem_type,
+            } => self
+                .guest_add_shared_pages(guest_id, page_addr, num_pages, guest_addr, mem_type)
+                .into(),
             Finalize { guest_id } => self.guest_finalize(guest_id).into(),
             TvmCpuRun { guest_id, vcpu_id } => self.guest_run_vcpu(guest_id, vcpu_id).into(),
             TvmCpuCreate { guest_id, vcpu_id } => self.guest_add_vcpu(guest_id, vcpu_id).into(),
@@ -489,6 +821,71 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                     active_pages,
                 )
                 .into(),
+            TvmReadConsole {
+                guest_id,
+                dest_addr,
+                len,
+            } => self
+                .guest_read_console(guest_id, dest_addr, len, active_pages)
+                .into(),
+            TvmAddMeasurementLogPages {
+                guest_id,
+                page_addr,
+                num_pages,
+            } => self
+                .guest_add_measurement_log_pages(guest_id, page_addr, num_pages)
+                .into(),
+            TvmGetMeasurementLog {
+                guest_id,
+                dest_addr,
+                offset,
+                len,
+            } => self
+                .guest_get_measurement_log(guest_id, dest_addr, offset, len, active_pages)
+                .into(),
+            TvmProtectPages {
+                guest_id,
+                guest_addr,
+                num_pages,
+                perms,
+            } => self
+                .guest_protect_pages(guest_id, guest_addr, num_pages, perms)
+                .into(),
+            TvmAddEmulatedMmioRegion {
+                guest_id,
+                guest_addr,
+                num_pages,
+            } => self
+                .guest_add_emulated_mmio_region(guest_id, guest_addr, num_pages)
+                .into(),
+        }
+    }
+
+    fn handle_tee_guest_msg(
+        &self,
+        guest_func: TeeGuestFunction,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> EcallAction {
+        if self.page_owner_id().is_host() {
+            // The host has no parent to share memory with.
+            return EcallAction::Unhandled;
+        }
+
+        use TeeGuestFunction::*;
+        let result = match guest_func {
+            ShareMemory { addr, len } => self.share_memory(addr, len),
+            UnshareMemory { addr, len } => self.unshare_memory(addr, len),
+            AcceptMemory { addr, len } => self.accept_memory(addr, len),
+            LockText { addr, len } => {
+                // Nothing for the host to do, so there's no need to exit.
+                let ret = self.lock_text(addr, len, vcpu_id, active_vcpu).map(|_| 0);
+                return EcallAction::Continue(ret.into());
+            }
+        };
+        match result {
+            Ok(cause) => EcallAction::Break(cause, SbiReturn::success(0)),
+            Err(e) => EcallAction::Continue(SbiReturn::from(e)),
         }
     }
 
@@ -512,6 +909,19 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                     active_pages,
                 )
                 .into(),
+            GetSelfMeasurementLog {
+                dest_addr,
+                offset,
+                len,
+            } => self
+                .guest_get_measurement_log(
+                    GUEST_ID_SELF_MEASUREMENT,
+                    dest_addr,
+                    offset,
+                    len,
+                    active_pages,
+                )
+                .into(),
         }
     }
 
@@ -527,12 +937,16 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
                 csr_len,
                 cert_addr,
                 cert_len,
+                nonce_addr,
+                nonce_len,
             } => self
                 .guest_get_evidence(
                     csr_addr,
                     csr_len as usize,
                     cert_addr,
                     cert_len as usize,
+                    nonce_addr,
+                    nonce_len as usize,
                     active_pages,
                 )
                 .into(),
@@ -540,34 +954,201 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
             ExtendMeasurement {
                 measurement_addr,
                 len,
+                index,
             } => self
-                .guest_extend_measurement(measurement_addr, len as usize, active_pages)
+                .guest_extend_measurement(
+                    measurement_addr,
+                    len as usize,
+                    index as usize,
+                    active_pages,
+                )
+                .into(),
+
+            GetSealingKey {
+                policy,
+                key_addr,
+                key_len,
+            } => self
+                .guest_get_sealing_key(policy, key_addr, key_len as usize, active_pages)
+                .into(),
+
+            GetEvidenceToken {
+                nonce_addr,
+                nonce_len,
+                token_addr,
+                token_len,
+            } => self
+                .guest_get_evidence_token(
+                    nonce_addr,
+                    nonce_len as usize,
+                    token_addr,
+                    token_len as usize,
+                    active_pages,
+                )
                 .into(),
         }
     }
 
     // Handle access faults. For example, when a returned page needs to be demand-faulted back to
-    // the page table.
-    fn handle_guest_fault(&self, fault_addr: GuestPhysAddr) -> vm_pages::Result<()> {
-        self.vm_pages.handle_page_fault(fault_addr)?;
-
-        // Get instruction that caused the fault
-        //   - disable ints
-        //   - load hstatus with value from guest
-        //   - set stvec to catch traps during access
-        //   - read instruction using HLV.HU (or tow for 32 bit).
-        //   - reset stvec
-        //   - reset hstatus
-        //   - re-enable ints
-
-        // Determine width of faulting access
-        // determine destination/source register
-        // Check how to service access (device emulation for example) and run.
-        // if load, set destination register
+    // the page table. Loads and stores to the VM's emulated MMIO regions are decoded and forwarded
+    // to the host for emulation; any other fault is reported to the host as a page fault. Returns
+    // the reason to exit to the host if the fault couldn't be handled.
+    fn handle_guest_fault(
+        &self,
+        fault_addr: GuestPhysAddr,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> core::result::Result<(), VmExitCause> {
+        if self.vm_pages.handle_page_fault(fault_addr).is_ok() {
+            return Ok(());
+        }
+        // Never expose the registers of a vCPU that faulted on confidential or shared memory, or on
+        // an address the host has yet to populate.
+        if !self.vm_pages.is_emulated_mmio_addr(fault_addr) {
+            return Err(VmExitCause::PageFault(fault_addr));
+        }
+
+        let mmio_op = active_vcpu
+            .decode_mmio_fault()
+            .ok_or(VmExitCause::PageFault(fault_addr))?;
+        active_vcpu.start_mmio_emulation(mmio_op);
+        let cause = match mmio_op.opcode() {
+            MmioOpcode::Load { .. } => VmExitCause::MmioLoad(fault_addr, mmio_op.width()),
+            MmioOpcode::Store { .. } => VmExitCause::MmioStore(fault_addr, mmio_op.width()),
+        };
+        Err(cause)
+    }
+
+    /// Handles an interrupt taken while running one of this VM's vCPUs. Returns the cause of the
+    /// exit to the VM's host if the interrupt needs the attention of the host.
+    fn handle_host_interrupt(&self, irq: Interrupt) -> core::result::Result<(), VmExitCause> {
+        if !trap::handle_interrupt(irq) {
+            println!("Unhandled interrupt {:?} in guest", irq);
+            return Err(VmExitCause::UnhandledTrap(Trap::Interrupt(irq).to_scause()));
+        }
+        if self.page_owner_id().is_host() {
+            return Ok(());
+        }
+        match irq {
+            // The timer may have been for this vCPU rather than the host. If so, the virtual timer
+            // interrupt is raised when we re-enter the vCPU.
+            Interrupt::SupervisorTimer
+                if CSR.time.get() < PerCpu::this_cpu().host_timer_deadline() =>
+            {
+                Ok(())
+            }
+            // We can't tell if an IPI was for this vCPU or the host, so let the host run in case
+            // it was. Requests posted to this vCPU are handled the next time it's run.
+            _ => Err(VmExitCause::HostInterrupt),
+        }
+    }
+
+    /// Performs any requests that other vCPUs have posted to `active_vcpu`.
+    fn handle_vcpu_requests(&self, vcpu_id: u64, active_vcpu: &mut ActiveVmCpu<T>) {
+        let requests = self.vcpus.pending_requests(vcpu_id);
+        active_vcpu.handle_requests(requests);
+        // Only mark the requests as complete once they've been performed, since vCPUs waiting on
+        // a remote fence are watching for them to be cleared.
+        self.vcpus.complete_requests(vcpu_id, requests);
+    }
+
+    /// Returns an iterator over the IDs of the vCPUs in this VM that are selected by `hart_mask`.
+    fn vcpus_in_mask(&self, hart_mask: HartMask) -> impl Iterator<Item = u64> + '_ {
+        (0..self.vcpus.num_vcpus()).filter(move |&id| {
+            hart_mask.contains(id)
+                && self
+                    .vcpus
+                    .get_vcpu_status(id)
+                    .map_or(false, |s| s != VmCpuStatus::NotPresent)
+        })
+    }
 
+    /// Posts the fence `req` to each of the vCPUs in this VM that are selected by `hart_mask`, and
+    /// waits for the running vCPUs to complete it. vCPUs that aren't running will do so before they
+    /// next enter the VM.
+    fn fence_vcpus(
+        &self,
+        hart_mask: HartMask,
+        req: VmCpuRequest,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> sbi::Result<()> {
+        self.post_vcpu_requests(hart_mask, req)?;
+        // Keep servicing requests made of this vCPU while waiting so that we don't deadlock with a
+        // vCPU that is fencing us.
+        for id in self.vcpus_in_mask(hart_mask) {
+            while self.vcpus.request_in_flight(id, req) {
+                self.handle_vcpu_requests(vcpu_id, active_vcpu);
+                core::hint::spin_loop();
+            }
+        }
+        Ok(())
+    }
+
+    /// Posts `req` to each of the vCPUs in this VM that are selected by `hart_mask`.
+    fn post_vcpu_requests(&self, hart_mask: HartMask, req: VmCpuRequest) -> sbi::Result<()> {
+        // Don't post anything unless every vCPU explicitly named in the mask exists.
+        if hart_mask.hart_ids().any(|id| {
+            self.vcpus
+                .get_vcpu_status(id)
+                .map_or(true, |s| s == VmCpuStatus::NotPresent)
+        }) {
+            return Err(SbiError::InvalidParam);
+        }
+        for id in self.vcpus_in_mask(hart_mask) {
+            self.vcpus
+                .post_request(id, req)
+                .map_err(|_| SbiError::InvalidParam)?;
+        }
         Ok(())
     }
 
+    /// Writes `bytes` to this VM's console. Output from the host goes directly to the UART, while
+    /// output from TVMs is buffered until the host reads it with `TvmReadConsole`. Returns the
+    /// number of bytes written.
+    fn write_console(&self, bytes: &[u8]) -> usize {
+        if self.page_owner_id().is_host() {
+            console_write_bytes(bytes);
+            bytes.len()
+        } else {
+            self.console.write(bytes)
+        }
+    }
+
+    /// Writes up to `num_bytes` from the guest physical address `base_addr_lo` to this VM's
+    /// console. Returns the number of bytes written, which is less than `num_bytes` if the console
+    /// buffer filled up.
+    fn console_write(
+        &self,
+        num_bytes: u64,
+        base_addr_lo: u64,
+        base_addr_hi: u64,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
+        if base_addr_hi != 0 {
+            return Err(SbiError::InvalidParam);
+        }
+        let mut buf = [0u8; CONSOLE_COPY_BYTES];
+        let mut written = 0;
+        while written < num_bytes {
+            let len = core::cmp::min(num_bytes - written, buf.len() as u64) as usize;
+            let src_addr = base_addr_lo
+                .checked_add(written)
+                .ok_or(SbiError::InvalidParam)?;
+            active_pages
+                .copy_from_guest(
+                    &mut buf[..len],
+                    RawAddr::guest(src_addr, self.page_owner_id()),
+                )
+                .map_err(|_| SbiError::InvalidParam)?;
+            let count = self.write_console(&buf[..len]);
+            written += count as u64;
+            if count < len {
+                break;
+            }
+        }
+        Ok(written)
+    }
+
     fn get_tsm_info(
         &self,
         dest_addr: u64,
@@ -596,6 +1177,62 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         Ok(len as u64)
     }
 
+    /// Convenience function to turn the byte-granular range from a TEE-guest call into a
+    /// `GuestPageAddr` and a number of 4kB pages.
+    fn guest_range_from_raw(&self, addr: u64, len: u64) -> sbi::Result<(GuestPageAddr, u64)> {
+        let page_addr = self.guest_addr_from_raw(addr)?;
+        if len == 0 || len % PageSize::Size4k as u64 != 0 {
+            return Err(SbiError::InvalidParam);
+        }
+        Ok((page_addr, len / PageSize::Size4k as u64))
+    }
+
+    fn share_memory(&self, addr: u64, len: u64) -> sbi::Result<VmExitCause> {
+        let (page_addr, num_pages) = self.guest_range_from_raw(addr, len)?;
+        self.vm_pages
+            .share_memory(page_addr, num_pages)
+            .map_err(|_| SbiError::InvalidParam)?;
+        Ok(VmExitCause::ShareMemory(page_addr, len))
+    }
+
+    fn unshare_memory(&self, addr: u64, len: u64) -> sbi::Result<VmExitCause> {
+        let (page_addr, num_pages) = self.guest_range_from_raw(addr, len)?;
+        self.vm_pages
+            .unshare_memory(page_addr, num_pages)
+            .map_err(|_| SbiError::InvalidParam)?;
+        Ok(VmExitCause::UnshareMemory(page_addr, len))
+    }
+
+    fn accept_memory(&self, addr: u64, len: u64) -> sbi::Result<VmExitCause> {
+        let (page_addr, num_pages) = self.guest_range_from_raw(addr, len)?;
+        self.vm_pages
+            .accept_memory(page_addr, num_pages)
+            .map_err(|_| SbiError::InvalidParam)?;
+        Ok(VmExitCause::AcceptMemory(page_addr, len))
+    }
+
+    fn lock_text(
+        &self,
+        addr: u64,
+        len: u64,
+        vcpu_id: u64,
+        active_vcpu: &mut ActiveVmCpu<T>,
+    ) -> sbi::Result<()> {
+        let (page_addr, num_pages) = self.guest_range_from_raw(addr, len)?;
+        active_vcpu
+            .active_pages()
+            .lock_text(page_addr, num_pages)
+            .map_err(|_| SbiError::InvalidParam)?;
+        // Make sure no vCPU can still write to the range through a cached translation once we
+        // return.
+        self.fence_vcpus(
+            HartMask::new(0, u64::MAX),
+            VmCpuRequest::HFenceGvma,
+            vcpu_id,
+            active_vcpu,
+        )
+    }
+
     /// Converts `num_pages` starting at guest physical address `page_addr` to confidential memory.
     fn convert_pages(
         &self,
@@ -603,13 +1240,9 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         page_type: sbi::TsmPageType,
         num_pages: u64,
     ) -> sbi::Result<u64> {
-        if page_type != sbi::TsmPageType::Page4k {
-            // TODO: Support converting hugepages.
-            return Err(SbiError::InvalidParam);
-        }
         let page_addr = self.guest_addr_from_raw(page_addr)?;
         self.vm_pages
-            .convert_pages(page_addr, num_pages)
+            .convert_pages(page_addr, page_size_from_type(page_type), num_pages)
             .map_err(|_| SbiError::InvalidAddress)?;
         Ok(num_pages)
     }
@@ -621,13 +1254,9 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         page_type: sbi::TsmPageType,
         num_pages: u64,
     ) -> sbi::Result<u64> {
-        if page_type != sbi::TsmPageType::Page4k {
-            // TODO: Support converting hugepages.
-            return Err(SbiError::InvalidParam);
-        }
         let page_addr = self.guest_addr_from_raw(page_addr)?;
         self.vm_pages
-            .reclaim_pages(page_addr, num_pages)
+            .reclaim_pages(page_addr, page_size_from_type(page_type), num_pages)
             .map_err(|_| SbiError::InvalidAddress)?;
         Ok(num_pages)
     }
@@ -656,27 +1285,58 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         let params: sbi::TvmCreateParams =
             unsafe { core::ptr::read_unaligned(param_bytes.as_slice().as_ptr().cast()) };
 
+        // The CPU must be able to walk the TVM's guest-stage page table.
+        let paging_mode = sbi::TvmPagingMode::from_reg(params.tvm_paging_mode)?;
+        if mmu_type_for_paging_mode(paging_mode) > CpuInfo::get().mmu_type() {
+            return Err(SbiError::NotSupported);
+        }
+
         // Now create the VM, claiming the pages that the host donated to us.
-        let page_root_addr = self.guest_addr_from_raw(params.tvm_page_directory_addr)?;
-        let state_addr = self.guest_addr_from_raw(params.tvm_state_addr)?;
-        let vcpu_addr = self.guest_addr_from_raw(params.tvm_vcpu_addr)?;
-        let num_vcpu_pages = PageSize::num_4k_pages(params.tvm_num_vcpus * VM_CPU_BYTES);
-        let (guest_vm, state_page) = self
-            .vm_pages
-            .create_guest_vm(page_root_addr, state_addr, vcpu_addr, num_vcpu_pages)
-            .map_err(|_| SbiError::InvalidParam)?;
-        let id = guest_vm.page_owner_id();
+        let guest = match paging_mode {
+            sbi::TvmPagingMode::Sv39x4 => Guest::Sv39x4(self.create_guest::<Sv39x4>(&params)?),
+            sbi::TvmPagingMode::Sv48x4 => Guest::Sv48x4(self.create_guest::<Sv48x4>(&params)?),
+            sbi::TvmPagingMode::Sv57x4 => Guest::Sv57x4(self.create_guest::<Sv57x4>(&params)?),
+        };
+        let id = guest.page_owner_id();
 
-        let guest_state = GuestState::new(guest_vm, state_page);
         self.guests
             .as_ref()
             .unwrap()
-            .add(guest_state)
+            .add(guest)
             .map_err(|_| SbiError::Failed)?;
 
         Ok(id.raw())
     }
 
+    /// Creates an initializing guest VM with a `U` guest-stage page table as described by
+    /// `params`.
+    fn create_guest<U: GuestStagePageTable>(
+        &self,
+        params: &sbi::TvmCreateParams,
+    ) -> sbi::Result<GuestState<U>> {
+        let page_root_addr = self.guest_addr_from_raw(params.tvm_page_directory_addr)?;
+        let state_addr = self.guest_addr_from_raw(params.tvm_state_addr)?;
+        let vcpu_addr = self.guest_addr_from_raw(params.tvm_vcpu_addr)?;
+        let num_vcpu_pages = PageSize::num_4k_pages(params.tvm_num_vcpus * VM_CPU_BYTES);
+        let measurement_algorithm =
+            sbi::MeasurementAlgorithm::from_reg(params.tvm_measurement_alg)?;
+        let (mut guest_vm, state_page) = self
+            .vm_pages
+            .create_guest_vm::<U>(
+                page_root_addr,
+                state_addr,
+                vcpu_addr,
+                num_vcpu_pages,
+                digest_algorithm_from_sbi(measurement_algorithm),
+            )
+            .map_err(|_| SbiError::InvalidParam)?;
+        guest_vm.set_signer(TvmSigner {
+            svn: params.tvm_svn,
+            signer_id: params.tvm_signer_id,
+        });
+        Ok(GuestState::new(guest_vm, state_page))
+    }
+
     fn destroy_guest(&self, guest_id: u64) -> sbi::Result<u64> {
         let guest_id = PageOwnerId::new(guest_id).ok_or(SbiError::InvalidParam)?;
         self.guests
@@ -694,18 +1354,20 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
             .as_ref()
             .and_then(|g| g.finalize(guest_id).ok())
             .ok_or(SbiError::InvalidParam)?;
-        let guest_vm = guest.as_finalized_vm().unwrap();
-
-        // Power on vCPU0 initially. Remaining vCPUs will get powered on by the VM itself via
-        // HSM SBI calls.
-        //
-        // TODO: Should the boot vCPU be specified explicilty?
-        guest_vm.power_on_vcpu(0)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_finalized_vm().unwrap();
+
+            // Power on vCPU0 initially. Remaining vCPUs will get powered on by the VM itself via
+            // HSM SBI calls.
+            //
+            // TODO: Should the boot vCPU be specified explicilty?
+            guest_vm.power_on_vcpu(0)?;
+        });
         Ok(0)
     }
 
     /// Retrieves the guest VM with the ID `guest_id`.
-    fn guest_by_id(&self, guest_id: u64) -> sbi::Result<GuestState<T>> {
+    fn guest_by_id(&self, guest_id: u64) -> sbi::Result<Guest> {
         let guest_id = PageOwnerId::new(guest_id).ok_or(SbiError::InvalidParam)?;
         let guest = self
             .guests
@@ -715,12 +1377,46 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         Ok(guest)
     }
 
+    /// Copies up to `len` bytes of the buffered console output of a guest VM to `dest_addr`.
+    /// Returns the number of bytes copied.
+    fn guest_read_console(
+        &self,
+        guest_id: u64,
+        dest_addr: u64,
+        len: u64,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
+        let guest = self.guest_by_id(guest_id)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+            let mut total = 0;
+            while total < len {
+                let addr = dest_addr.checked_add(total).ok_or(SbiError::InvalidParam)?;
+                let dest_addr = RawAddr::guest(addr, self.page_owner_id());
+                let count = guest_vm
+                    .console
+                    .drain_with((len - total) as usize, |bytes| {
+                        active_pages
+                            .copy_to_guest(dest_addr, bytes)
+                            .map_err(|_| SbiError::InvalidAddress)
+                    })?;
+                if count == 0 {
+                    break;
+                }
+                total += count as u64;
+            }
+            Ok(total)
+        })
+    }
+
     /// Adds a vCPU with `vcpu_id` to a guest VM.
     fn guest_add_vcpu(&self, guest_id: u64, vcpu_id: u64) -> sbi::Result<u64> {
         let guest = self.guest_by_id(guest_id)?;
-        let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-        guest_vm.add_vcpu(vcpu_id)?;
-        Ok(0)
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            guest_vm.add_vcpu(vcpu_id)?;
+            Ok(0)
+        })
     }
 
     /// Sets a register in a guest VM's vCPU.
@@ -732,10 +1428,23 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         value: u64,
     ) -> sbi::Result<u64> {
         let guest = self.guest_by_id(guest_id)?;
-        // The only writeable registers are writeable only during initialization.
-        let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-        guest_vm.set_vcpu_reg(vcpu_id, register, value)?;
-        Ok(0)
+        with_guest_state!(guest, guest => {
+            use TvmCpuRegister::*;
+            match register {
+                EntryArg | EntryPc => {
+                    let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+                    guest_vm.set_vcpu_reg(vcpu_id, register, value)?;
+                }
+                MmioData => {
+                    let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+                    guest_vm.set_vcpu_reg(vcpu_id, register, value)?;
+                }
+                ExitCause0 | ExitCause1 => {
+                    return Err(SbiError::InvalidParam);
+                }
+            };
+            Ok(0)
+        })
     }
 
     /// Gets a register in a guest VM's vCPU.
@@ -746,26 +1455,30 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         register: TvmCpuRegister,
     ) -> sbi::Result<u64> {
         let guest = self.guest_by_id(guest_id)?;
-        use TvmCpuRegister::*;
-        let value = match register {
-            EntryArg | EntryPc => {
-                let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-                guest_vm.get_vcpu_reg(vcpu_id, register)?
-            }
-            ExitCause0 | ExitCause1 => {
-                let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
-                guest_vm.get_vcpu_reg(vcpu_id, register)?
-            }
-        };
-        Ok(value)
+        with_guest_state!(guest, guest => {
+            use TvmCpuRegister::*;
+            let value = match register {
+                EntryArg | EntryPc => {
+                    let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+                    guest_vm.get_vcpu_reg(vcpu_id, register)?
+                }
+                ExitCause0 | ExitCause1 | MmioData => {
+                    let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+                    guest_vm.get_vcpu_reg(vcpu_id, register)?
+                }
+            };
+            Ok(value)
+        })
     }
 
     /// Runs a guest VM's vCPU.
     fn guest_run_vcpu(&self, guest_id: u64, vcpu_id: u64) -> sbi::Result<u64> {
         let guest = self.guest_by_id(guest_id)?;
-        let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
-        let exit_code = guest_vm.run_vcpu(vcpu_id)?;
-        Ok(exit_code as u64)
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+            let exit_code = guest_vm.run_vcpu(vcpu_id)?;
+            Ok(exit_code as u64)
+        })
     }
 
     fn guest_add_page_table_pages(
@@ -776,15 +1489,17 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
     ) -> sbi::Result<u64> {
         let from_page_addr = self.guest_addr_from_raw(from_addr)?;
         let guest = self.guest_by_id(guest_id)?;
-        let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-        self.vm_pages
-            .add_pte_pages_builder(from_page_addr, num_pages, &guest_vm.vm_pages)
-            .map_err(|e| {
-                println!("Salus - pte_pages_builder error {e:?}");
-                SbiError::InvalidAddress
-            })?;
-
-        Ok(0)
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            self.vm_pages
+                .add_pte_pages_builder(from_page_addr, num_pages, &guest_vm.vm_pages)
+                .map_err(|e| {
+                    println!("Salus - pte_pages_builder error {e:?}");
+                    SbiError::InvalidAddress
+                })?;
+
+            Ok(0)
+        })
     }
 
     fn guest_add_zero_pages(
@@ -794,22 +1509,44 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         page_type: sbi::TsmPageType,
         num_pages: u64,
         guest_addr: u64,
+        perms: sbi::TsmPagePerms,
     ) -> sbi::Result<u64> {
-        if page_type != sbi::TsmPageType::Page4k {
-            // TODO - support huge pages.
-            // TODO - need to break up mappings if given address that's part of a huge page.
-            return Err(SbiError::InvalidParam);
-        }
-
         let from_page_addr = self.guest_addr_from_raw(page_addr)?;
+        let page_size = page_size_from_type(page_type);
+        let perms = leaf_perms_from_page_perms(perms);
         let guest = self.guest_by_id(guest_id)?;
-        let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-        let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
-        self.vm_pages
-            .add_zero_pages_builder(from_page_addr, num_pages, &guest_vm.vm_pages, to_page_addr)
-            .map_err(|_| SbiError::InvalidParam)?;
+        with_guest_state!(guest, guest => {
+            if let Some(guest_vm) = guest.as_initializing_vm() {
+                let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+                self.vm_pages
+                    .add_zero_pages_builder(
+                        from_page_addr,
+                        page_size,
+                        num_pages,
+                        &guest_vm.vm_pages,
+                        to_page_addr,
+                        perms,
+                    )
+                    .map_err(|_| SbiError::InvalidParam)?;
+            } else {
+                // Pages added after the guest has been finalized must be accepted by the guest
+                // before they can be used.
+                let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+                let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+                self.vm_pages
+                    .add_unaccepted_zero_pages_builder(
+                        from_page_addr,
+                        page_size,
+                        num_pages,
+                        &guest_vm.vm_pages,
+                        to_page_addr,
+                        perms,
+                    )
+                    .map_err(|_| SbiError::InvalidParam)?;
+            }
 
-        Ok(num_pages)
+            Ok(num_pages)
+        })
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -823,31 +1560,107 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         guest_addr: u64,
         active_pages: &ActiveVmPages<T>,
     ) -> sbi::Result<u64> {
-        if page_type != sbi::TsmPageType::Page4k {
-            // TODO - support huge pages.
-            // TODO - need to break up mappings if given address that's part of a huge page.
-            return Err(SbiError::InvalidParam);
-        }
-
         let src_page_addr = self.guest_addr_from_raw(src_addr)?;
         let from_page_addr = self.guest_addr_from_raw(dest_addr)?;
         let guest = self.guest_by_id(guest_id)?;
-        let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
-        let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
-        active_pages
-            .copy_and_add_data_pages_builder(
-                src_page_addr,
-                from_page_addr,
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+            active_pages
+                .copy_and_add_data_pages_builder(
+                    src_page_addr,
+                    from_page_addr,
+                    page_size_from_type(page_type),
+                    num_pages,
+                    &guest_vm.vm_pages,
+                    to_page_addr,
+                )
+                .map_err(|_| SbiError::InvalidParam)?;
+
+            Ok(num_pages)
+        })
+    }
+
+    fn guest_protect_pages(
+        &self,
+        guest_id: u64,
+        guest_addr: u64,
+        num_pages: u64,
+        perms: sbi::TsmPagePerms,
+    ) -> sbi::Result<u64> {
+        let guest = self.guest_by_id(guest_id)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            let page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+            guest_vm
+                .vm_pages
+                .protect_pages(page_addr, num_pages, leaf_perms_from_page_perms(perms))
+                .map_err(|_| SbiError::InvalidParam)?;
+            let change = PagePerms {
+                gpa: page_addr.bits(),
                 num_pages,
-                &guest_vm.vm_pages,
-                to_page_addr,
-            )
-            .map_err(|_| SbiError::InvalidParam)?;
+                perms: perms as u64,
+            };
+            guest_vm.vm_pages.add_measured_data(&change.encode());
 
-        Ok(num_pages)
+            Ok(0)
+        })
+    }
+
+    fn guest_add_emulated_mmio_region(
+        &self,
+        guest_id: u64,
+        guest_addr: u64,
+        num_pages: u64,
+    ) -> sbi::Result<u64> {
+        if num_pages == 0 {
+            return Err(SbiError::InvalidParam);
+        }
+        let guest = self.guest_by_id(guest_id)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            let page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+            guest_vm
+                .vm_pages
+                .add_emulated_mmio_region(page_addr, num_pages)
+                .map_err(|_| SbiError::InvalidParam)?;
+
+            Ok(0)
+        })
+    }
+
+    fn guest_add_shared_pages(
+        &self,
+        guest_id: u64,
+        page_addr: u64,
+        num_pages: u64,
+        guest_addr: u64,
+        mem_type: sbi::TsmPageMemType,
+    ) -> sbi::Result<u64> {
+        let mem_type = match mem_type {
+            sbi::TsmPageMemType::Cacheable => PteMemType::PMA,
+            sbi::TsmPageMemType::NonCacheable if CpuInfo::get().has_svpbmt() => PteMemType::NC,
+            sbi::TsmPageMemType::NonCacheable => return Err(SbiError::NotSupported),
+        };
+        let from_page_addr = self.guest_addr_from_raw(page_addr)?;
+        let guest = self.guest_by_id(guest_id)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_finalized_vm().ok_or(SbiError::InvalidParam)?;
+            let to_page_addr = guest_vm.guest_addr_from_raw(guest_addr)?;
+            self.vm_pages
+                .add_shared_pages_builder(
+                    from_page_addr,
+                    num_pages,
+                    &guest_vm.vm_pages,
+                    to_page_addr,
+                    mem_type,
+                )
+                .map_err(|_| SbiError::InvalidParam)?;
+
+            Ok(num_pages)
+        })
     }
 
-    // TODO: Add code to return actual measurements
     fn guest_get_measurement(
         &self,
         measurement_version: u64,
@@ -856,50 +1669,171 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
         guest_id: u64,
         active_pages: &ActiveVmPages<T>,
     ) -> sbi::Result<u64> {
-        if (measurement_version != 1) || (measurement_type != 1) {
+        let mut bytes = [0u8; MAX_MEASUREMENT_BYTES];
+        let len = if guest_id == GUEST_ID_SELF_MEASUREMENT {
+            // The guest_id of 0 is a special identifier used to retrieve
+            // measurements for self.
+            get_measurement_of_type(
+                &self.vm_pages,
+                measurement_version,
+                measurement_type,
+                &mut bytes,
+            )
+        } else {
+            let guest = self.guest_by_id(guest_id)?;
+            with_guest_state!(guest, guest => {
+                let result = if let Some(vm) = guest.as_finalized_vm() {
+                    get_measurement_of_type(
+                        &vm.vm_pages,
+                        measurement_version,
+                        measurement_type,
+                        &mut bytes,
+                    )
+                } else {
+                    get_measurement_of_type(
+                        &guest.as_initializing_vm().unwrap().vm_pages,
+                        measurement_version,
+                        measurement_type,
+                        &mut bytes,
+                    )
+                };
+                result
+            })
+        }?;
+
+        let gpa = RawAddr::guest(dest_addr, self.vm_pages.page_owner_id());
+        active_pages
+            .copy_to_guest(gpa, &bytes[..len])
+            .map_err(|_| SbiError::InvalidAddress)?;
+        Ok(len as u64)
+    }
+
+    fn guest_add_measurement_log_pages(
+        &self,
+        guest_id: u64,
+        from_addr: u64,
+        num_pages: u64,
+    ) -> sbi::Result<u64> {
+        if num_pages == 0 {
             return Err(SbiError::InvalidParam);
         }
+        let from_page_addr = self.guest_addr_from_raw(from_addr)?;
+        let guest = self.guest_by_id(guest_id)?;
+        with_guest_state!(guest, guest => {
+            let guest_vm = guest.as_initializing_vm().ok_or(SbiError::InvalidParam)?;
+            self.vm_pages
+                .add_measurement_log_pages_builder(from_page_addr, num_pages, &guest_vm.vm_pages)
+                .map_err(|e| match e {
+                    VmPagesError::MeasurementLogUnavailable => SbiError::InvalidParam,
+                    _ => SbiError::InvalidAddress,
+                })?;
+
+            Ok(0)
+        })
+    }
 
-        // TODO: Define a compile-time constant for the maximum length of any measurement we
-        // would conceivably use.
-        let mut bytes = [0u8; SHA256_DIGEST_BYTES];
+    fn guest_get_measurement_log(
+        &self,
+        guest_id: u64,
+        dest_addr: u64,
+        offset: u64,
+        len: u64,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
         if guest_id == GUEST_ID_SELF_MEASUREMENT {
-            // The guest_id of 0 is a special identifier used to retrieve
-            // measurements for self.
-            self.vm_pages.get_measurement(&mut bytes)
+            self.copy_measurement_log(&self.vm_pages, dest_addr, offset, len, active_pages)
         } else {
-            let guest_id = PageOwnerId::new(guest_id).ok_or(SbiError::InvalidParam)?;
-            let guest = self
-                .guests
-                .as_ref()
-                .and_then(|g| g.get(guest_id))
-                .ok_or(SbiError::InvalidParam)?;
-            let result = if let Some(vm) = guest.as_finalized_vm() {
-                vm.vm_pages.get_measurement(&mut bytes)
-            } else {
-                guest
-                    .as_initializing_vm()
-                    .unwrap()
-                    .vm_pages
-                    .get_measurement(&mut bytes)
-            };
-            result
+            let guest = self.guest_by_id(guest_id)?;
+            with_guest_state!(guest, guest => {
+                let result = if let Some(vm) = guest.as_finalized_vm() {
+                    self.copy_measurement_log(&vm.vm_pages, dest_addr, offset, len, active_pages)
+                } else {
+                    self.copy_measurement_log(
+                        &guest.as_initializing_vm().unwrap().vm_pages,
+                        dest_addr,
+                        offset,
+                        len,
+                        active_pages,
+                    )
+                };
+                result
+            })
         }
-        .map_err(|_| SbiError::Failed)?;
+    }
 
-        let gpa = RawAddr::guest(dest_addr, self.vm_pages.page_owner_id());
+    /// Copies up to `len` bytes of the measurement log of `vm_pages`, starting `offset` bytes
+    /// into the log, to `dest_addr` in this VM's address space. Returns the number of bytes
+    /// copied.
+    fn copy_measurement_log<U: GuestStagePageTable, S>(
+        &self,
+        vm_pages: &VmPages<U, S>,
+        dest_addr: u64,
+        offset: u64,
+        len: u64,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
+        let mut total = 0;
+        while total < len {
+            let mut bytes = [0u8; MEASUREMENT_LOG_COPY_BYTES];
+            let max_len = ((len - total) as usize).min(bytes.len());
+            let log_offset = offset.checked_add(total).ok_or(SbiError::InvalidParam)?;
+            let count = vm_pages.get_measurement_log(log_offset as usize, &mut bytes[..max_len]);
+            if count == 0 {
+                break;
+            }
+            let addr = dest_addr.checked_add(total).ok_or(SbiError::InvalidParam)?;
+            active_pages
+                .copy_to_guest(RawAddr::guest(addr, self.page_owner_id()), &bytes[..count])
+                .map_err(|_| SbiError::InvalidAddress)?;
+            total += count as u64;
+        }
+        Ok(total)
+    }
+
+    /// Reads the `nonce_len`-byte nonce for evidence requested by this VM from `nonce_addr` into
+    /// `nonce_bytes`. Returns the nonce, or `None` if it's empty.
+    fn read_evidence_nonce<'a>(
+        &self,
+        nonce_addr: u64,
+        nonce_len: usize,
+        nonce_bytes: &'a mut [u8; MAX_EVIDENCE_NONCE_LEN],
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<Option<&'a [u8]>> {
+        if nonce_len > MAX_EVIDENCE_NONCE_LEN {
+            return Err(SbiError::InvalidParam);
+        }
+        let nonce_gpa = RawAddr::guest(nonce_addr, self.vm_pages.page_owner_id());
         active_pages
-            .copy_to_guest(gpa, &bytes)
+            .copy_from_guest(&mut nonce_bytes[..nonce_len], nonce_gpa)
             .map_err(|_| SbiError::InvalidAddress)?;
-        Ok(bytes.len() as u64)
+        Ok((nonce_len != 0).then_some(&nonce_bytes[..nonce_len]))
+    }
+
+    /// Reads this VM's runtime measurement registers into `measurement_bytes`, and returns the
+    /// digest held by each register.
+    fn runtime_measurement_registers<'a>(
+        &self,
+        measurement_bytes: &'a mut [u8; MAX_MEASUREMENT_BYTES],
+    ) -> sbi::Result<ArrayVec<&'a [u8], NUM_RUNTIME_MEASUREMENT_REGISTERS>> {
+        self.vm_pages
+            .get_runtime_measurements(measurement_bytes)
+            .map_err(|_| SbiError::Failed)?;
+        let measurement_bytes: &'a [u8] = measurement_bytes;
+        let digest_len = self.vm_pages.measurement_algorithm().digest_len();
+        Ok(measurement_bytes
+            .chunks_exact(digest_len)
+            .take(NUM_RUNTIME_MEASUREMENT_REGISTERS)
+            .collect())
     }
 
     fn guest_get_evidence(
         &self,
         csr_addr: u64,
         csr_len: usize,
-        _cert_addr: u64,
-        _cert_len: usize,
+        cert_addr: u64,
+        cert_len: usize,
+        nonce_addr: u64,
+        nonce_len: usize,
         active_pages: &ActiveVmPages<T>,
     ) -> sbi::Result<u64> {
         if csr_len > MAX_CSR_LEN {
@@ -921,16 +1855,152 @@ impl<T: GuestStagePageTable> Vm<T, VmStateFinalized> {
 
         csr.verify().map_err(|_| SbiError::InvalidParam)?;
 
-        Ok(0)
+        let mut nonce_bytes = [0u8; MAX_EVIDENCE_NONCE_LEN];
+        let nonce =
+            self.read_evidence_nonce(nonce_addr, nonce_len, &mut nonce_bytes, active_pages)?;
+
+        // Only TVMs have an identity to attest to.
+        let identity = self.identity.as_ref().ok_or(SbiError::NotSupported)?;
+        let mut runtime_measurements = [0u8; MAX_MEASUREMENT_BYTES];
+        let registers = self.runtime_measurement_registers(&mut runtime_measurements)?;
+        // The certificate for the CSR carries the TVM's runtime measurements after its initial
+        // measurement, which is also carried by the certificate for the TVM's identity.
+        let hash_alg = dice::digest_algorithm_oid(identity.algorithm());
+        let mut measurements: ArrayVec<_, { NUM_RUNTIME_MEASUREMENT_REGISTERS + 1 }> =
+            ArrayVec::new();
+        measurements.push((hash_alg, identity.measurement()));
+        measurements.extend(registers.iter().map(|&register| (hash_alg, register)));
+        let serial = NEXT_EVIDENCE_SERIAL.fetch_add(1, Ordering::Relaxed);
+        // Build the chain in a single buffer to limit stack usage.
+        let mut chain_bytes = [0u8; MAX_CERT_LEN];
+        let csr_cert_len = identity
+            .signer()
+            .issue(
+                &csr,
+                &serial.to_be_bytes(),
+                &measurements,
+                nonce,
+                &mut chain_bytes,
+            )
+            .map_err(|_| SbiError::Failed)?
+            .len();
+        let tvm_cert_len = dice::salus_layer()
+            .certify(identity, &mut chain_bytes[csr_cert_len..])
+            .map_err(|_| SbiError::Failed)?
+            .len();
+        let salus_cert = dice::salus_certificate();
+        let chain_len = csr_cert_len + tvm_cert_len + salus_cert.len();
+        if chain_len > chain_bytes.len() {
+            return Err(SbiError::Failed);
+        }
+        chain_bytes[csr_cert_len + tvm_cert_len..chain_len].copy_from_slice(salus_cert);
+        if chain_len > cert_len {
+            return Err(SbiError::InvalidParam);
+        }
+
+        let cert_gpa = RawAddr::guest(cert_addr, self.vm_pages.page_owner_id());
+        active_pages
+            .copy_to_guest(cert_gpa, &chain_bytes[..chain_len])
+            .map_err(|_| SbiError::InvalidAddress)?;
+        Ok(chain_len as u64)
+    }
+
+    fn guest_get_evidence_token(
+        &self,
+        nonce_addr: u64,
+        nonce_len: usize,
+        token_addr: u64,
+        token_len: usize,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
+        let mut nonce_bytes = [0u8; MAX_EVIDENCE_NONCE_LEN];
+        let nonce =
+            self.read_evidence_nonce(nonce_addr, nonce_len, &mut nonce_bytes, active_pages)?;
+
+        // Only TVMs have an identity to attest to.
+        let identity = self.identity.as_ref().ok_or(SbiError::NotSupported)?;
+        let mut runtime_measurements = [0u8; MAX_MEASUREMENT_BYTES];
+        let registers = self.runtime_measurement_registers(&mut runtime_measurements)?;
+        let claims = EatClaims {
+            hash_alg: dice::digest_algorithm_oid(identity.algorithm()),
+            tvm_measurement: identity.measurement(),
+            runtime_measurements: &registers,
+            salus_version: env!("CARGO_PKG_VERSION"),
+            nonce,
+        };
+        // Salus attests to the TVM's claims directly, so the token is signed with Salus' key and
+        // carries Salus' certificate.
+        let mut token_bytes = [0u8; MAX_EVIDENCE_TOKEN_LEN];
+        let token = claims
+            .sign(
+                dice::salus_layer().signer(),
+                Some(dice::salus_certificate()),
+                &mut token_bytes,
+            )
+            .map_err(|_| SbiError::Failed)?;
+        if token.len() > token_len {
+            return Err(SbiError::InvalidParam);
+        }
+
+        let token_gpa = RawAddr::guest(token_addr, self.vm_pages.page_owner_id());
+        active_pages
+            .copy_to_guest(token_gpa, token)
+            .map_err(|_| SbiError::InvalidAddress)?;
+        Ok(token.len() as u64)
     }
 
     fn guest_extend_measurement(
         &self,
-        _msmt_addr: u64,
-        _len: usize,
-        _active_pages: &ActiveVmPages<T>,
+        msmt_addr: u64,
+        len: usize,
+        index: usize,
+        active_pages: &ActiveVmPages<T>,
+    ) -> sbi::Result<u64> {
+        if len != self.vm_pages.measurement_algorithm().digest_len() {
+            return Err(SbiError::InvalidParam);
+        }
+
+        let mut digest = [0u8; MAX_DIGEST_BYTES];
+        let msmt_gpa = RawAddr::guest(msmt_addr, self.vm_pages.page_owner_id());
+        active_pages
+            .copy_from_guest(&mut digest[..len], msmt_gpa)
+            .map_err(|_| SbiError::InvalidAddress)?;
+        self.vm_pages
+            .extend_runtime_measurement(index, &digest[..len])
+            .map_err(|_| SbiError::InvalidParam)?;
+        Ok(0)
+    }
+
+    fn guest_get_sealing_key(
+        &self,
+        policy: u64,
+        key_addr: u64,
+        key_len: usize,
+        active_pages: &ActiveVmPages<T>,
     ) -> sbi::Result<u64> {
-        Err(SbiError::NotSupported)
+        // Only TVMs have an identity to derive a sealing key from.
+        let identity = self.identity.as_ref().ok_or(SbiError::NotSupported)?;
+        let key = match policy {
+            SEALING_KEY_POLICY_MEASUREMENT => identity.sealing_key(),
+            SEALING_KEY_POLICY_SIGNER => {
+                // Unsigned TVMs have no signer to seal to.
+                let signer = self
+                    .signer
+                    .filter(TvmSigner::is_signed)
+                    .ok_or(SbiError::NotSupported)?;
+                dice::salus_layer().signer_sealing_key(&signer.signer_id, signer.svn)
+            }
+            _ => return Err(SbiError::InvalidParam),
+        };
+        if key_len < key.len() {
+            return Err(SbiError::InvalidParam);
+        }
+
+        let key_gpa = RawAddr::guest(key_addr, self.vm_pages.page_owner_id());
+        active_pages
+            .copy_to_guest(key_gpa, &key)
+            .map_err(|_| SbiError::InvalidAddress)?;
+        Ok(key.len() as u64)
     }
 
     /// Destroys this `Vm`.
@@ -975,7 +2045,7 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateInitializing> {
         let root =
             PlatformPageTable::new(root_table_pages, PageOwnerId::host(), page_tracker.clone())
                 .unwrap();
-        let vm_pages = VmPages::new(root, 0);
+        let vm_pages = VmPages::new(root, 0, DigestAlgorithm::Sha256);
         for p in pte_pages {
             vm_pages.add_pte_page(p).unwrap();
         }
@@ -1016,7 +2086,7 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateInitializing> {
         let mapper = self
             .inner
             .vm_pages
-            .map_pages(to_addr, pages.len() as u64)
+            .map_pages(to_addr, PageSize::Size4k, pages.len() as u64)
             .unwrap();
         for (page, vm_addr) in pages.zip(to_addr.iter_from()) {
             assert_eq!(page.size(), PageSize::Size4k);
@@ -1043,7 +2113,7 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateInitializing> {
         let mapper = self
             .inner
             .vm_pages
-            .map_pages(to_addr, pages.len() as u64)
+            .map_pages(to_addr, PageSize::Size4k, pages.len() as u64)
             .unwrap();
         for (page, vm_addr) in pages.zip(to_addr.iter_from()) {
             assert_eq!(page.size(), PageSize::Size4k);
@@ -1057,7 +2127,12 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateInitializing> {
             let mappable = page_tracker
                 .assign_page_for_mapping(page, self.inner.page_owner_id())
                 .unwrap();
-            mapper.map_page(vm_addr, mappable).unwrap();
+            // The host's RAM is mapped RWX, but it has no business executing from MMIO regions.
+            let perms = match P::mem_type() {
+                MemType::Ram => PteLeafPerms::RWX,
+                MemType::Mmio(_) => PteLeafPerms::RW,
+            };
+            mapper.map_page(vm_addr, mappable, perms).unwrap();
         }
     }
 
@@ -1103,6 +2178,9 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateFinalized> {
                     HartStop => {
                         break;
                     }
+                    HartSuspend => {
+                        self.wait_for_wakeup();
+                    }
                     _ => {
                         println!("Unhandled host VM exit; shutting down");
                         poweroff();
@@ -1112,6 +2190,26 @@ impl<T: GuestStagePageTable> HostVm<T, VmStateFinalized> {
         }
     }
 
+    /// Parks this CPU until there's an interrupt that should wake up the suspended host vCPU that
+    /// runs on it.
+    fn wait_for_wakeup(&self) {
+        // Wake up for the host's timer, for IPIs, and for interrupts delivered to the host's
+        // interrupt file. VS-level interrupts may be pending even though the host has them masked,
+        // so ignore them while we're parked.
+        smp::set_timer(PerCpu::this_cpu().host_timer_deadline());
+        let prev_hie = CSR.hie.get();
+        CSR.hie.write(hie::sgext.val(1));
+        CSR.hgeie.set(1 << ImsicGuestId::HostVm.to_raw_index());
+
+        // The interrupts are left pending and are handled once we re-enter the vCPU.
+        while CSR.sip.get() & CSR.sie.get() == 0 && CSR.hgeip.get() & CSR.hgeie.get() == 0 {
+            smp::wfi_masked();
+        }
+
+        CSR.hgeie.set(0);
+        CSR.hie.set(prev_hie);
+    }
+
     /// Returns if the vCPU with `vcpu_id` is runnable.
     fn vcpu_is_runnable(&self, vcpu_id: u64) -> bool {
         matches!(
@@ -0,0 +1,83 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use spin::Mutex;
+
+/// The number of bytes of output buffered for each VM's console.
+pub const VM_CONSOLE_BYTES: usize = 1024;
+
+/// A fixed-size ring buffer of console output.
+struct ConsoleRing {
+    buf: [u8; VM_CONSOLE_BYTES],
+    head: usize,
+    len: usize,
+}
+
+impl ConsoleRing {
+    /// Appends as many bytes from `bytes` as there is space for. Returns the number of bytes
+    /// appended.
+    fn push(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(VM_CONSOLE_BYTES - self.len);
+        for (i, &b) in bytes[..count].iter().enumerate() {
+            self.buf[(self.head + self.len + i) % VM_CONSOLE_BYTES] = b;
+        }
+        self.len += count;
+        count
+    }
+
+    /// Returns the longest contiguous run of buffered bytes starting at the oldest byte, limited to
+    /// `max_len` bytes.
+    fn peek(&self, max_len: usize) -> &[u8] {
+        let count = self.len.min(VM_CONSOLE_BYTES - self.head).min(max_len);
+        &self.buf[self.head..self.head + count]
+    }
+
+    /// Discards the oldest `count` bytes.
+    fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.head = (self.head + count) % VM_CONSOLE_BYTES;
+        self.len -= count;
+    }
+}
+
+/// Buffers the console output of a VM until its host reads it.
+pub struct VmConsole {
+    ring: Mutex<ConsoleRing>,
+}
+
+impl VmConsole {
+    /// Creates an empty console buffer.
+    pub fn new() -> Self {
+        Self {
+            ring: Mutex::new(ConsoleRing {
+                buf: [0; VM_CONSOLE_BYTES],
+                head: 0,
+                len: 0,
+            }),
+        }
+    }
+
+    /// Appends as many bytes from `bytes` as will fit in the buffer. Returns the number of bytes
+    /// written, which is less than `bytes.len()` if the buffer is full.
+    pub fn write(&self, bytes: &[u8]) -> usize {
+        self.ring.lock().push(bytes)
+    }
+
+    /// Passes up to `max_len` of the oldest buffered bytes to `f`. The bytes are consumed from the
+    /// buffer only if `f` succeeds. Returns the number of bytes consumed, which is 0 once the
+    /// buffer is empty.
+    pub fn drain_with<E, F>(&self, max_len: usize, f: F) -> Result<usize, E>
+    where
+        F: FnOnce(&[u8]) -> Result<(), E>,
+    {
+        let mut ring = self.ring.lock();
+        let bytes = ring.peek(max_len);
+        let count = bytes.len();
+        if count != 0 {
+            f(bytes)?;
+            ring.consume(count);
+        }
+        Ok(count)
+    }
+}
@@ -2,23 +2,24 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use core::arch::global_asm;
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use core::{mem::size_of, ops::Deref, ops::DerefMut};
 use drivers::{CpuId, CpuInfo, ImsicGuestId};
 use memoffset::offset_of;
 use page_tracking::collections::PageVec;
 use page_tracking::{PageTracker, TlbVersion};
-use riscv_page_tables::GuestStagePageTable;
+use riscv_page_tables::{tlb, GuestStagePageTable};
 use riscv_pages::{GuestPhysAddr, InternalClean, PageOwnerId, RawAddr, SequentialPages};
-use riscv_regs::{hstatus, scounteren, sstatus};
+use riscv_regs::{hstatus, hvip, scounteren, sie, sstatus};
 use riscv_regs::{
-    Exception, FloatingPointRegisters, GeneralPurposeRegisters, GprIndex, LocalRegisterCopy,
-    Readable, Trap, Writeable, CSR,
+    Exception, FloatingPointRegisters, GeneralPurposeRegisters, GprIndex, Interrupt,
+    LocalRegisterCopy, MmioOpcode, MmioOperation, Readable, Trap, Writeable, CSR,
 };
 use sbi::{SbiMessage, SbiReturnType};
 use spin::{Mutex, RwLock, RwLockReadGuard};
 
-use crate::smp::PerCpu;
+use crate::smp::{self, PerCpu};
 use crate::vm_id::VmId;
 use crate::vm_pages::{ActiveVmPages, VmPages};
 
@@ -80,6 +81,7 @@ struct GuestVCpuState {
     vstval: u64,
     vsatp: u64,
     vstimecmp: u64,
+    hvip: u64,
 }
 
 /// CSRs written on an exit from virtualization that are used by the host to determine the cause of
@@ -109,6 +111,11 @@ extern "C" {
     fn _run_guest(g: *mut VmCpuState);
 }
 
+// The guest instruction fetch routine, defined in guest_mem.S.
+extern "C" {
+    fn _fetch_guest_instruction(dest: *mut u8, src_gva: u64) -> usize;
+}
+
 #[allow(dead_code)]
 const fn host_gpr_offset(index: GprIndex) -> usize {
     offset_of!(VmCpuState, host_regs)
@@ -249,6 +256,8 @@ pub enum VmCpuExit {
     PageFault(GuestPhysAddr),
     /// An exception which we expected to handle directly at VS, but trapped to HS instead.
     DelegatedException(Exception, u64),
+    /// An interrupt destined for the hypervisor that was taken while the vCPU was running.
+    HostInterrupt(Interrupt),
     /// Everything else that we currently don't or can't handle.
     Other(VmCpuTrapState),
     // TODO: Add other exit causes as needed.
@@ -263,6 +272,11 @@ pub struct ActiveVmCpu<'vcpu, 'pages, T: GuestStagePageTable> {
 impl<'vcpu, 'pages, T: GuestStagePageTable> ActiveVmCpu<'vcpu, 'pages, T> {
     /// Runs this vCPU until it exits.
     pub fn run_to_exit(&mut self) -> VmCpuExit {
+        // Complete any MMIO load the host emulated for us since we last exited.
+        self.complete_mmio_load();
+        // Start afresh if we're resuming from a non-retentive suspend.
+        self.complete_resume();
+
         // Load the vCPU CSRs. Safe as these don't take effect until V=1.
         CSR.htimedelta.set(self.state.guest_vcpu_csrs.htimedelta);
         CSR.vsstatus.set(self.state.guest_vcpu_csrs.vsstatus);
@@ -276,6 +290,8 @@ impl<'vcpu, 'pages, T: GuestStagePageTable> ActiveVmCpu<'vcpu, 'pages, T> {
         if CpuInfo::get().has_sstc() {
             CSR.vstimecmp.set(self.state.guest_vcpu_csrs.vstimecmp);
         }
+        self.arm_timer();
+        CSR.hvip.set(self.state.guest_vcpu_csrs.hvip);
 
         // TODO, HGEIE programinng:
         //  - Track which guests the host wants interrupts from (by trapping HGEIE accesses from
@@ -312,6 +328,7 @@ impl<'vcpu, 'pages, T: GuestStagePageTable> ActiveVmCpu<'vcpu, 'pages, T> {
         if CpuInfo::get().has_sstc() {
             self.state.guest_vcpu_csrs.vstimecmp = CSR.vstimecmp.get();
         }
+        self.state.guest_vcpu_csrs.hvip = CSR.hvip.get();
 
         // Determine the exit cause from the trap CSRs.
         use Exception::*;
@@ -341,7 +358,68 @@ impl<'vcpu, 'pages, T: GuestStagePageTable> ActiveVmCpu<'vcpu, 'pages, T> {
                     VmCpuExit::Other(self.state.trap_csrs.clone())
                 }
             }
-            _ => VmCpuExit::Other(self.state.trap_csrs.clone()),
+            Trap::Interrupt(i) => VmCpuExit::HostInterrupt(i),
+        }
+    }
+
+    /// Arms the physical timer of this CPU for the next timer event of this vCPU, or of the host
+    /// vCPU on whose behalf this vCPU is being run if that comes first. If this vCPU's timer is
+    /// being emulated rather than provided by Sstc, the virtual timer interrupt is raised or
+    /// cleared as well.
+    fn arm_timer(&mut self) {
+        let has_sstc = CpuInfo::get().has_sstc();
+        let is_host = self.guest_id.is_host();
+        let deadline = self.timer_deadline();
+        let expired = CSR.time.get() >= deadline;
+        let csrs = &mut self.vcpu.state.guest_vcpu_csrs;
+        if !has_sstc {
+            let mut hvip = LocalRegisterCopy::<u64, hvip::Register>::new(csrs.hvip);
+            hvip.modify(hvip::vstimer.val(expired as u64));
+            csrs.hvip = hvip.get();
+        }
+
+        // We only need the physical timer if the timer is emulated and hasn't already fired.
+        let vcpu_deadline = if has_sstc || expired {
+            u64::MAX
+        } else {
+            deadline
+        };
+        let this_cpu = PerCpu::this_cpu();
+        if is_host {
+            // Remember when the host wants its timer interrupt so that we can return to it when
+            // it's due while running a guest VM. There's no point in doing so if the host has the
+            // timer interrupt masked.
+            let vsie = LocalRegisterCopy::<u64, sie::Register>::new(csrs.vsie);
+            this_cpu.set_host_timer_deadline(if vsie.is_set(sie::stimer) {
+                deadline
+            } else {
+                u64::MAX
+            });
+            smp::set_timer(vcpu_deadline);
+        } else {
+            smp::set_timer(vcpu_deadline.min(this_cpu.host_timer_deadline()));
+        }
+    }
+
+    /// Performs the actions in `requests` that were posted to this vCPU by other vCPUs in the VM.
+    pub fn handle_requests(&mut self, requests: VmCpuRequests) {
+        if requests.contains(VmCpuRequest::Ipi) {
+            let mut hvip =
+                LocalRegisterCopy::<u64, hvip::Register>::new(self.state.guest_vcpu_csrs.hvip);
+            hvip.modify(hvip::vssoft.val(1));
+            self.state.guest_vcpu_csrs.hvip = hvip.get();
+        }
+        if requests.contains(VmCpuRequest::FenceI) {
+            // Safety: FENCE.I has no effect other than synchronizing the instruction stream.
+            unsafe { asm!("fence.i") };
+        }
+        if requests.contains(VmCpuRequest::SFenceVma) {
+            // The VM's address space is active so this only affects the VM's VS-stage translations.
+            // We don't track address or ASID ranges, so just flush everything.
+            tlb::hfence_vvma(None, None);
+        }
+        if requests.contains(VmCpuRequest::HFenceGvma) {
+            self.active_pages.hfence_gvma();
         }
     }
 
@@ -349,6 +427,62 @@ impl<'vcpu, 'pages, T: GuestStagePageTable> ActiveVmCpu<'vcpu, 'pages, T> {
     pub fn active_pages(&self) -> &ActiveVmPages<'pages, T> {
         &self.active_pages
     }
+
+    /// Decodes the load or store that caused the last exit, which must have been a G-stage page
+    /// fault. The instruction is taken from HTINST if it was provided, and otherwise read from the
+    /// vCPU's SEPC. Returns `None` if the fault wasn't caused by a load or store instruction.
+    pub fn decode_mmio_fault(&self) -> Option<MmioOperation> {
+        let trap_csrs = &self.state.trap_csrs;
+        let htinst = trap_csrs.htinst as u32;
+        let mmio_op = if htinst & 0x1 != 0 {
+            // A transformed instruction. Bit 1 is cleared if the faulting instruction was a
+            // compressed instruction, but the encoding is otherwise that of the uncompressed
+            // equivalent.
+            let len = if htinst & 0x2 != 0 { 4 } else { 2 };
+            MmioOperation::decode(htinst | 0x2).map(|op| op.with_length(len))
+        } else if htinst == 0 {
+            self.fetch_guest_instruction()
+                .and_then(MmioOperation::decode)
+        } else {
+            // A pseudoinstruction, indicating that the fault happened on an implicit access
+            // during VS-stage address translation.
+            None
+        }?;
+
+        // Make sure the instruction agrees with the type of fault.
+        use Exception::*;
+        match (Trap::from_scause(trap_csrs.scause).ok()?, mmio_op.opcode()) {
+            (Trap::Exception(GuestLoadPageFault), MmioOpcode::Load { .. })
+            | (Trap::Exception(GuestStorePageFault), MmioOpcode::Store { .. }) => Some(mmio_op),
+            _ => None,
+        }
+    }
+
+    /// Reads the instruction at the vCPU's SEPC using HLVX. Returns `None` if a fault was
+    /// encountered while reading the instruction.
+    fn fetch_guest_instruction(&self) -> Option<u32> {
+        // HLVX accesses memory at the privilege level in HSTATUS.SPVP, so use the privilege level
+        // the vCPU trapped from.
+        let guest_hstatus =
+            LocalRegisterCopy::<u64, hstatus::Register>::new(self.state.guest_regs.hstatus);
+        let prev_hstatus = CSR.hstatus.get();
+        CSR.hstatus
+            .modify(hstatus::spvp.val(guest_hstatus.read(hstatus::spvp)));
+
+        let mut inst = 0u32;
+        let this_cpu = PerCpu::this_cpu();
+        this_cpu.enter_guest_memcpy();
+        // Safety: _fetch_guest_instruction writes at most 4 bytes to `inst`, and internally
+        // detects and handles an invalid guest virtual address in SEPC.
+        let bytes = unsafe {
+            _fetch_guest_instruction((&mut inst as *mut u32).cast(), self.state.guest_regs.sepc)
+        };
+        this_cpu.exit_guest_memcpy();
+        CSR.hstatus.set(prev_hstatus);
+
+        let len = if inst & 0x3 == 0x3 { 4 } else { 2 };
+        (bytes == len).then_some(inst)
+    }
 }
 
 impl<'vcpu, 'pages, T: GuestStagePageTable> Deref for ActiveVmCpu<'vcpu, 'pages, T> {
@@ -379,6 +513,8 @@ pub enum VirtualRegister {
     Cause0,
     /// 2nd detailed exit cause register. Usage depends on the exit code.
     Cause1,
+    /// Data register for emulated MMIO loads and stores.
+    MmioData,
 }
 
 /// Virtual register state of a vCPU.
@@ -386,12 +522,24 @@ pub enum VirtualRegister {
 struct VirtualRegisters {
     cause0: u64,
     cause1: u64,
+    mmio_data: u64,
+}
+
+/// The state a vCPU is reset to when it resumes from a non-retentive suspend.
+struct ResumeState {
+    hart_id: u64,
+    resume_addr: u64,
+    opaque: u64,
 }
 
 /// Represents a single virtual CPU of a VM.
 pub struct VmCpu {
     state: VmCpuState,
     virt_regs: VirtualRegisters,
+    // An MMIO load which is waiting on the host to supply the loaded value.
+    pending_mmio_load: Option<MmioOperation>,
+    // Where to resume if the vCPU made a non-retentive suspend.
+    pending_resume: Option<ResumeState>,
     current_cpu: Option<CurrentCpu>,
     // TODO: interrupt_file should really be part of CurrentCpu, but we have no way to migrate it
     // at present.
@@ -420,15 +568,65 @@ impl VmCpu {
         scounteren.modify(scounteren::instret.val(1));
         state.guest_regs.scounteren = scounteren.get();
 
+        // The timer is disabled until the vCPU asks for it.
+        state.guest_vcpu_csrs.vstimecmp = u64::MAX;
+
         Self {
             state,
             virt_regs: VirtualRegisters::default(),
+            pending_mmio_load: None,
+            pending_resume: None,
             current_cpu: None,
             interrupt_file: None,
             guest_id,
         }
     }
 
+    /// Sets the time at which the vCPU's next timer interrupt is due, in its timebase. The timer
+    /// takes effect the next time the vCPU is run.
+    pub fn set_timer(&mut self, stime_value: u64) {
+        self.state.guest_vcpu_csrs.vstimecmp = stime_value;
+    }
+
+    /// Returns the time, in the host's timebase, at which the vCPU's next timer interrupt is due.
+    /// Returns `u64::MAX` if the vCPU's timer isn't armed.
+    pub fn timer_deadline(&self) -> u64 {
+        let csrs = &self.state.guest_vcpu_csrs;
+        if csrs.vstimecmp == u64::MAX {
+            u64::MAX
+        } else {
+            csrs.vstimecmp.wrapping_sub(csrs.htimedelta)
+        }
+    }
+
+    /// Prepares the vCPU for a non-retentive suspend. The next time the vCPU is run it starts
+    /// executing at `resume_addr` with `hart_id` in A0 and `opaque` in A1, as if it had been
+    /// started afresh.
+    pub fn suspend_non_retentive(&mut self, hart_id: u64, resume_addr: u64, opaque: u64) {
+        self.pending_resume = Some(ResumeState {
+            hart_id,
+            resume_addr,
+            opaque,
+        });
+    }
+
+    /// Resets the vCPU to the state it resumes in after a non-retentive suspend, if it was
+    /// suspended.
+    fn complete_resume(&mut self) {
+        if let Some(resume) = self.pending_resume.take() {
+            self.state.guest_regs.sepc = resume.resume_addr;
+            self.set_gpr(GprIndex::A0, resume.hart_id);
+            self.set_gpr(GprIndex::A1, resume.opaque);
+            // The vCPU resumes in S-mode with address translation and interrupts disabled.
+            self.state.guest_vcpu_csrs.vsatp = 0;
+            let mut vsstatus = LocalRegisterCopy::<u64, sstatus::Register>::new(
+                self.state.guest_vcpu_csrs.vsstatus,
+            );
+            vsstatus.modify(sstatus::sie.val(0));
+            self.state.guest_vcpu_csrs.vsstatus = vsstatus.get();
+        }
+    }
+
     /// Sets the `sepc` CSR, or the PC value the vCPU will jump to when it is run.
     pub fn set_sepc(&mut self, sepc: u64) {
         self.state.guest_regs.sepc = sepc;
@@ -459,6 +657,9 @@ impl VmCpu {
             Cause1 => {
                 self.virt_regs.cause1 = value;
             }
+            MmioData => {
+                self.virt_regs.mmio_data = value;
+            }
         }
     }
 
@@ -468,6 +669,7 @@ impl VmCpu {
         match reg {
             Cause0 => self.virt_regs.cause0,
             Cause1 => self.virt_regs.cause1,
+            MmioData => self.virt_regs.mmio_data,
         }
     }
 
@@ -484,6 +686,35 @@ impl VmCpu {
         }
     }
 
+    /// Prepares the vCPU for `mmio_op` to be emulated by the host. The value to be stored, if any,
+    /// is placed in the `MmioData` register and the vCPU is advanced past the faulting instruction.
+    /// For loads, the value in `MmioData` is written to the destination register the next time the
+    /// vCPU is run.
+    pub fn start_mmio_emulation(&mut self, mmio_op: MmioOperation) {
+        match mmio_op.opcode() {
+            MmioOpcode::Load { .. } => {
+                self.virt_regs.mmio_data = 0;
+                self.pending_mmio_load = Some(mmio_op);
+            }
+            MmioOpcode::Store { rs2 } => {
+                let value = rs2.map(|r| self.get_gpr(r)).unwrap_or(0);
+                self.virt_regs.mmio_data = mmio_op.extend_value(value);
+            }
+        }
+        self.state.guest_regs.sepc += mmio_op.length_bytes();
+    }
+
+    /// Writes the value supplied by the host for a pending MMIO load to the load's destination
+    /// register.
+    fn complete_mmio_load(&mut self) {
+        if let Some(mmio_op) = self.pending_mmio_load.take() {
+            if let MmioOpcode::Load { rd: Some(rd), .. } = mmio_op.opcode() {
+                let value = mmio_op.extend_value(self.virt_regs.mmio_data);
+                self.set_gpr(rd, value);
+            }
+        }
+    }
+
     /// Sets the interrupt file for this vCPU.
     pub fn set_interrupt_file(&mut self, interrupt_file: ImsicGuestId) {
         self.interrupt_file = Some(interrupt_file);
@@ -577,12 +808,45 @@ pub enum VmCpuStatus {
     Runnable,
     /// The vCPU has been claimed exclusively for running on a (physical) CPU.
     Running,
+    /// The vCPU has suspended itself and is waiting for an interrupt. It is resumed the next time
+    /// it is run.
+    Suspended,
+}
+
+/// Actions requested of a vCPU by other vCPUs in the same VM, which must be performed on the
+/// physical CPU the vCPU runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmCpuRequest {
+    /// Raise a supervisor software interrupt.
+    Ipi = 1 << 0,
+    /// Synchronize the instruction stream.
+    FenceI = 1 << 1,
+    /// Flush the VS-stage TLB.
+    SFenceVma = 1 << 2,
+    /// Flush the G-stage TLB entries for the VM.
+    HFenceGvma = 1 << 3,
+}
+
+/// A set of `VmCpuRequest`s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VmCpuRequests(u64);
+
+impl VmCpuRequests {
+    /// Returns true if `req` is in the set.
+    pub fn contains(&self, req: VmCpuRequest) -> bool {
+        self.0 & (req as u64) != 0
+    }
 }
 
 struct VmCpusInner {
     // Locking: status must be locked before vcpu.
     status: RwLock<VmCpuStatus>,
     vcpu: Mutex<VmCpu>,
+    // Bitmask of pending `VmCpuRequest`s.
+    requests: AtomicU64,
+    // The physical CPU the vCPU is running on, or last ran on if it is suspended. Only valid while
+    // status is `Running` or `Suspended`.
+    running_cpu: AtomicUsize,
 }
 
 /// A reference to an "Available" (idle) `VmCpu`. The `VmCpu` is guaranteed not to change states
@@ -606,13 +870,18 @@ pub struct RunningVmCpu<'a> {
     parent: &'a VmCpus,
     vcpu: &'a Mutex<VmCpu>,
     id: u64,
-    power_off: bool,
+    exit_status: VmCpuStatus,
 }
 
 impl<'a> RunningVmCpu<'a> {
     /// Mark this vCPU as powered off when it is returned.
     pub fn power_off(&mut self) {
-        self.power_off = true;
+        self.exit_status = VmCpuStatus::PoweredOff;
+    }
+
+    /// Mark this vCPU as suspended when it is returned.
+    pub fn suspend(&mut self) {
+        self.exit_status = VmCpuStatus::Suspended;
     }
 }
 
@@ -629,11 +898,7 @@ impl<'a> Drop for RunningVmCpu<'a> {
         let entry = self.parent.inner.get(self.id as usize).unwrap();
         let mut status = entry.status.write();
         assert_eq!(*status, VmCpuStatus::Running);
-        *status = if self.power_off {
-            VmCpuStatus::PoweredOff
-        } else {
-            VmCpuStatus::Runnable
-        };
+        *status = self.exit_status;
     }
 }
 
@@ -658,6 +923,8 @@ impl VmCpus {
             let entry = VmCpusInner {
                 status: RwLock::new(VmCpuStatus::NotPresent),
                 vcpu: Mutex::new(VmCpu::new(guest_id)),
+                requests: AtomicU64::new(0),
+                running_cpu: AtomicUsize::new(0),
             };
             inner.push(entry);
         }
@@ -684,10 +951,12 @@ impl VmCpus {
         let entry = self.inner.get(vcpu_id as usize).ok_or(Error::BadCpuId)?;
         let status = entry.status.read();
         match *status {
-            VmCpuStatus::PoweredOff | VmCpuStatus::Runnable => Ok(IdleVmCpu {
-                _status: status,
-                vcpu: &entry.vcpu,
-            }),
+            VmCpuStatus::PoweredOff | VmCpuStatus::Runnable | VmCpuStatus::Suspended => {
+                Ok(IdleVmCpu {
+                    _status: status,
+                    vcpu: &entry.vcpu,
+                })
+            }
             VmCpuStatus::Running => Err(Error::VmCpuRunning),
             VmCpuStatus::NotPresent => Err(Error::VmCpuNotFound),
         }
@@ -706,7 +975,9 @@ impl VmCpus {
                     vcpu: &entry.vcpu,
                 })
             }
-            VmCpuStatus::Running | VmCpuStatus::Runnable => Err(Error::VmCpuAlreadyPowered),
+            VmCpuStatus::Running | VmCpuStatus::Runnable | VmCpuStatus::Suspended => {
+                Err(Error::VmCpuAlreadyPowered)
+            }
             VmCpuStatus::NotPresent => Err(Error::VmCpuNotFound),
         }
     }
@@ -717,13 +988,16 @@ impl VmCpus {
         let entry = self.inner.get(vcpu_id as usize).ok_or(Error::BadCpuId)?;
         let mut status = entry.status.write();
         match *status {
-            VmCpuStatus::Runnable => {
+            VmCpuStatus::Runnable | VmCpuStatus::Suspended => {
                 *status = VmCpuStatus::Running;
+                entry
+                    .running_cpu
+                    .store(PerCpu::this_cpu().cpu_id().raw(), Ordering::Release);
                 Ok(RunningVmCpu {
                     parent: self,
                     vcpu: &entry.vcpu,
                     id: vcpu_id,
-                    power_off: false,
+                    exit_status: VmCpuStatus::Runnable,
                 })
             }
             VmCpuStatus::Running => Err(Error::VmCpuRunning),
@@ -737,4 +1011,54 @@ impl VmCpus {
         let entry = self.inner.get(vcpu_id as usize).ok_or(Error::BadCpuId)?;
         Ok(*entry.status.read())
     }
+
+    /// Returns the maximum number of vCPUs in this VM.
+    pub fn num_vcpus(&self) -> u64 {
+        self.inner.len() as u64
+    }
+
+    /// Posts `req` to the vCPU with `vcpu_id`, to be performed the next time it enters the VM. If
+    /// the vCPU is currently running on another physical CPU, or is suspended, the CPU it last ran
+    /// on is interrupted so that the request is acted upon promptly.
+    pub fn post_request(&self, vcpu_id: u64, req: VmCpuRequest) -> Result<()> {
+        let entry = self.inner.get(vcpu_id as usize).ok_or(Error::BadCpuId)?;
+        let status = entry.status.read();
+        if *status == VmCpuStatus::NotPresent {
+            return Err(Error::VmCpuNotFound);
+        }
+        entry.requests.fetch_or(req as u64, Ordering::AcqRel);
+        if matches!(*status, VmCpuStatus::Running | VmCpuStatus::Suspended) {
+            let cpu = CpuId::new(entry.running_cpu.load(Ordering::Acquire));
+            if cpu != PerCpu::this_cpu().cpu_id() {
+                smp::send_ipi(cpu);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the set of requests that have been posted to the vCPU with `vcpu_id`.
+    pub fn pending_requests(&self, vcpu_id: u64) -> VmCpuRequests {
+        self.inner
+            .get(vcpu_id as usize)
+            .map(|entry| VmCpuRequests(entry.requests.load(Ordering::Acquire)))
+            .unwrap_or_default()
+    }
+
+    /// Marks `requests` as having been performed by the vCPU with `vcpu_id`.
+    pub fn complete_requests(&self, vcpu_id: u64, requests: VmCpuRequests) {
+        if let Some(entry) = self.inner.get(vcpu_id as usize) {
+            entry.requests.fetch_and(!requests.0, Ordering::AcqRel);
+        }
+    }
+
+    /// Returns true if `req` has been posted to the vCPU with `vcpu_id` and is yet to be performed
+    /// by it while it's running. Requests posted to a vCPU that isn't running are performed before
+    /// it next enters the VM.
+    pub fn request_in_flight(&self, vcpu_id: u64, req: VmCpuRequest) -> bool {
+        self.inner.get(vcpu_id as usize).map_or(false, |entry| {
+            let status = entry.status.read();
+            *status == VmCpuStatus::Running
+                && entry.requests.load(Ordering::Acquire) & (req as u64) != 0
+        })
+    }
 }
@@ -2,20 +2,26 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
+use arrayvec::ArrayVec;
 use core::arch::global_asm;
 use core::{marker::PhantomData, ops::Deref};
-use data_measure::data_measure::DataMeasure;
-use data_measure::sha256::Sha256Measure;
+use data_measure::data_measure::{DataMeasure, DigestAlgorithm, DynMeasure};
+use data_measure::event_log::MeasurementEvent;
+use data_measure::runtime::{RuntimeMeasurements, NUM_RUNTIME_MEASUREMENT_REGISTERS};
+use drivers::CpuInfo;
 use page_tracking::{
-    LockedPageList, PageList, PageTracker, PageTrackingError, TlbVersion, MAX_PAGE_OWNERS,
+    LockedPageList, MemRegionsError, MemoryRegion, MemoryRegions, PageList, PageTracker,
+    PageTrackingError, RegionState, TlbVersion, MAX_MEMORY_REGIONS, MAX_PAGE_OWNERS,
 };
 use riscv_page_tables::{
-    tlb, GuestStagePageTable, PageTableError, PageTableMapper, PlatformPageTable,
+    tlb, GuestStagePageTable, PageTableError, PageTableMapper, PlatformPageTable, PteLeafPerms,
+    PteMemType,
 };
 use riscv_pages::*;
 use riscv_regs::{hgatp, LocalRegisterCopy, Writeable, CSR};
 use spin::Mutex;
 
+use crate::measurement_log::MeasurementLog;
 use crate::smp::PerCpu;
 use crate::vm::{Vm, VmStateFinalized, VmStateInitializing};
 use crate::vm_cpu::VmCpus;
@@ -32,10 +38,17 @@ pub enum Error {
     UnsupportedPageSize(PageSize),
     NonContiguousPages,
     MeasurementBufferTooSmall,
+    InvalidMeasurementRegister(usize),
+    MeasurementLogUnavailable,
     AddressOverflow,
     TlbCountUnderflow,
     InvalidTlbVersion,
     TlbFenceInProgress,
+    NotSharedRegion(GuestPageAddr),
+    NotUnacceptedRegion(GuestPageAddr),
+    LockedRegion(GuestPageAddr),
+    Regions(MemRegionsError),
+    RegionInUse(GuestPageAddr),
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -138,6 +151,18 @@ impl TlbTracker {
         inner.current.version()
     }
 
+    /// Returns if `version` has been fenced: the TLB version has been incremented past `version`
+    /// and there are no remaining references to `version` or any earlier version.
+    fn is_fenced(&self, version: TlbVersion) -> bool {
+        let inner = self.inner.lock();
+        version < inner.current.version()
+            && inner
+                .prev
+                .as_ref()
+                .filter(|v| v.version() <= version && v.count() != 0)
+                .is_none()
+    }
+
     /// Drops a reference to the given TLB version.
     fn put_version(&self, version: TlbVersion) -> Result<()> {
         let mut inner = self.inner.lock();
@@ -159,28 +184,72 @@ pub struct VmPagesMapper<'a, T: GuestStagePageTable, S> {
 }
 
 impl<'a, T: GuestStagePageTable, S> VmPagesMapper<'a, T, S> {
-    /// Creates a new `VmPagesMapper` for `num_pages` starting at `page_addr`.
-    fn new(vm_pages: &'a VmPages<T, S>, page_addr: GuestPageAddr, num_pages: u64) -> Result<Self> {
+    /// Creates a new `VmPagesMapper` for `num_pages` of size `page_size` starting at `page_addr`.
+    fn new(
+        vm_pages: &'a VmPages<T, S>,
+        page_addr: GuestPageAddr,
+        page_size: PageSize,
+        num_pages: u64,
+    ) -> Result<Self> {
         let inner = vm_pages
             .root
-            .map_range(page_addr, PageSize::Size4k, num_pages, &mut || {
+            .map_range(page_addr, page_size, num_pages, &mut || {
                 vm_pages.pte_pages.pop()
             })
             .map_err(Error::Paging)?;
         Ok(Self { inner, vm_pages })
     }
 
-    /// Maps an unmeasured page into the guest's address space.
-    pub fn map_page<P>(&self, to_addr: GuestPageAddr, page: P) -> Result<()>
+    /// Maps an unmeasured page into the guest's address space with the permissions `perms`.
+    pub fn map_page<P>(&self, to_addr: GuestPageAddr, page: P, perms: PteLeafPerms) -> Result<()>
     where
         P: MappablePhysPage<MeasureOptional>,
     {
-        self.inner.map_page(to_addr, page).map_err(Error::Paging)
+        self.inner
+            .map_page(to_addr, page, perms)
+            .map_err(Error::Paging)
+    }
+
+    /// Same as `map_page()`, but maps the page with the memory type `mem_type`.
+    pub fn map_page_with_mem_type<P>(
+        &self,
+        to_addr: GuestPageAddr,
+        page: P,
+        perms: PteLeafPerms,
+        mem_type: PteMemType,
+    ) -> Result<()>
+    where
+        P: MappablePhysPage<MeasureOptional>,
+    {
+        self.inner
+            .map_page_with_mem_type(to_addr, page, perms, mem_type)
+            .map_err(Error::Paging)
+    }
+}
+
+impl<'a, T: GuestStagePageTable> VmPagesMapper<'a, T, VmStateFinalized> {
+    /// Maps an unmeasured page into the guest's address space with the permissions `perms`,
+    /// leaving it inaccessible to the guest until it's accepted.
+    pub fn map_page_invalidated<P>(
+        &self,
+        to_addr: GuestPageAddr,
+        page: P,
+        perms: PteLeafPerms,
+    ) -> Result<()>
+    where
+        P: MappablePhysPage<MeasureOptional>,
+    {
+        self.inner
+            .map_page_invalidated(to_addr, page, perms)
+            .map_err(Error::Paging)
     }
 }
 
 impl<'a, T: GuestStagePageTable> VmPagesMapper<'a, T, VmStateInitializing> {
-    /// Maps a page into the guest's address space and measures it.
+    /// Maps a page into the guest's address space and measures it. Huge pages are measured as
+    /// their constituent 4kB pages so that the measurement doesn't depend on the page size used.
+    /// The page is mapped read/write/execute; its permissions may be restricted, and the change
+    /// measured, with `VmPages::protect_pages()`.
     pub fn map_page_with_measurement<S, M>(
         &self,
         to_addr: GuestPageAddr,
@@ -192,9 +261,23 @@ impl<'a, T: GuestStagePageTable> VmPagesMapper<'a, T, VmStateInitializing> {
     {
         {
             let mut measurement = self.vm_pages.measurement.lock();
-            measurement.add_page(to_addr.bits(), page.as_bytes());
+            let mut measurement_log = self.vm_pages.measurement_log.lock();
+            for (bytes, addr) in page
+                .as_bytes()
+                .chunks(PageSize::Size4k as usize)
+                .zip(to_addr.iter_from())
+            {
+                let digest = measurement.add_page(addr.bits(), bytes);
+                measurement_log.record(MeasurementEvent::MeasuredPage {
+                    gpa: addr.bits(),
+                    page_size: page.size() as u64,
+                    digest: &digest,
+                });
+            }
         }
-        self.inner.map_page(to_addr, page).map_err(Error::Paging)
+        self.inner
+            .map_page(to_addr, page, PteLeafPerms::RWX)
+            .map_err(Error::Paging)
     }
 }
 
@@ -202,6 +285,7 @@ impl<'a, T: GuestStagePageTable> VmPagesMapper<'a, T, VmStateInitializing> {
 /// when dropped. Used to directly access a guest's memory.
 pub struct ActiveVmPages<'a, T: GuestStagePageTable> {
     prev_hgatp: u64,
+    vmid: u64,
     tlb_version: TlbVersion,
     vm_pages: &'a VmPages<T>,
 }
@@ -251,6 +335,7 @@ impl<'a, T: GuestStagePageTable> ActiveVmPages<'a, T> {
 
         Self {
             prev_hgatp,
+            vmid: vmid.vmid(),
             tlb_version,
             vm_pages,
         }
@@ -261,6 +346,20 @@ impl<'a, T: GuestStagePageTable> ActiveVmPages<'a, T> {
         self.tlb_version
     }
 
+    /// Locks the `num_pages` 4kB pages starting at `addr` read/execute-only (see
+    /// `VmPages::lock_text()`), flushing any writable translations for them cached by this CPU.
+    /// The caller must fence the other CPUs the VM is running on before relying on the change.
+    pub fn lock_text(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        self.vm_pages.lock_text(addr, num_pages)?;
+        self.hfence_gvma();
+        Ok(())
+    }
+
+    /// Flushes the G-stage translations for this VM cached by this CPU.
+    pub fn hfence_gvma(&self) {
+        tlb::hfence_gvma(None, Some(self.vmid));
+    }
+
     /// Copies from `src` to the guest physical address in `dest`. Returns an error if a fault was
     /// encountered while copying.
     pub fn copy_to_guest(&self, dest: GuestPhysAddr, src: &[u8]) -> Result<()> {
@@ -316,22 +415,29 @@ impl<'a, T: GuestStagePageTable> ActiveVmPages<'a, T> {
         Ok(())
     }
 
-    /// Copies `count` pages from `src_addr` in the current guest to the converted pages starting at
-    /// `from_addr`. The pages are then mapped into the child's address space at `to_addr`.
-    pub fn copy_and_add_data_pages_builder(
+    /// Copies `count` pages of size `page_size` from `src_addr` in the current guest to the
+    /// converted pages starting at `from_addr`. The pages are then mapped into the child's address
+    /// space at `to_addr`.
+    pub fn copy_and_add_data_pages_builder<U: GuestStagePageTable>(
         &self,
         src_addr: GuestPageAddr,
         from_addr: GuestPageAddr,
+        page_size: PageSize,
         count: u64,
-        to: &VmPages<T, VmStateInitializing>,
+        to: &VmPages<U, VmStateInitializing>,
         to_addr: GuestPageAddr,
     ) -> Result<u64> {
-        let converted_pages = self.get_converted_pages(from_addr, count)?;
-        let mapper = to.map_pages(to_addr, count)?;
+        let converted_pages = self.get_converted_pages(from_addr, page_size, count)?;
+        let mapper = to.map_pages(to_addr, page_size, count)?;
         let new_owner = to.page_owner_id();
-        for (dirty, (src_addr, to_addr)) in
-            converted_pages.zip(src_addr.iter_from().zip(to_addr.iter_from()))
-        {
+        // The source need only be 4kB-aligned, so step through it in `page_size` increments of
+        // 4kB pages.
+        let src_addrs = src_addr
+            .iter_from()
+            .step_by(PageSize::num_4k_pages(page_size as u64) as usize);
+        // Unwrap ok since `map_pages()` checked that `to_addr` is aligned to `page_size`.
+        let to_addrs = to_addr.iter_from_with_size(page_size).unwrap();
+        for (dirty, (src_addr, to_addr)) in converted_pages.zip(src_addrs.zip(to_addrs)) {
             let initialized = dirty
                 .try_initialize(|bytes| self.copy_from_guest(bytes, src_addr.into()))
                 .map_err(|(e, _)| e)?;
@@ -397,8 +503,11 @@ pub struct VmPages<T: GuestStagePageTable, S = VmStateFinalized> {
     // How many nested TVMs deep this VM is, with 0 being the host.
     nesting: usize,
     root: PlatformPageTable<T>,
-    measurement: Mutex<Sha256Measure>,
+    measurement: Mutex<DynMeasure>,
+    runtime_measurements: Mutex<RuntimeMeasurements>,
+    measurement_log: Mutex<MeasurementLog>,
     pte_pages: PtePagePool,
+    regions: Mutex<MemoryRegions>,
     phantom: PhantomData<S>,
 }
 
@@ -408,6 +517,11 @@ impl<T: GuestStagePageTable, S> VmPages<T, S> {
         self.page_owner_id
     }
 
+    /// Returns the algorithm this guest's measurements are made with.
+    pub fn measurement_algorithm(&self) -> DigestAlgorithm {
+        self.measurement.lock().algorithm()
+    }
+
     /// Copies the measurement for this guest into `dest`.
     pub fn get_measurement(&self, dest: &mut [u8]) -> Result<()> {
         let measurement = self.measurement.lock();
@@ -420,6 +534,32 @@ impl<T: GuestStagePageTable, S> VmPages<T, S> {
         Ok(())
     }
 
+    /// Copies the runtime measurement registers for this guest into `dest`, one after another.
+    pub fn get_runtime_measurements(&self, dest: &mut [u8]) -> Result<()> {
+        let runtime_measurements = self.runtime_measurements.lock();
+        let digest_len = runtime_measurements.algorithm().digest_len();
+        if dest.len() < NUM_RUNTIME_MEASUREMENT_REGISTERS * digest_len {
+            return Err(Error::MeasurementBufferTooSmall);
+        }
+        for (src, dest) in runtime_measurements
+            .registers()
+            .iter()
+            .zip(dest.chunks_exact_mut(digest_len))
+        {
+            dest.copy_from_slice(src);
+        }
+        Ok(())
+    }
+
+    /// Copies the measurement event log for this guest into `dest`, starting `offset` bytes into
+    /// the log. Returns the number of bytes copied, which is 0 at the end of the log.
+    pub fn get_measurement_log(&self, offset: usize, dest: &mut [u8]) -> usize {
+        let measurement_log = self.measurement_log.lock();
+        let src = measurement_log.read(offset, dest.len());
+        dest[..src.len()].copy_from_slice(src);
+        src.len()
+    }
+
     /// Returns the address of the root page table for this VM.
     pub fn root_address(&self) -> SupervisorPageAddr {
         // TODO: Cache this to avoid bouncing off the lock?
@@ -430,36 +570,89 @@ impl<T: GuestStagePageTable, S> VmPages<T, S> {
     pub fn page_tracker(&self) -> PageTracker {
         self.page_tracker.clone()
     }
+
+    /// Returns if `addr` lies within a region this VM has declared as emulated MMIO.
+    pub fn is_emulated_mmio_addr(&self, addr: GuestPhysAddr) -> bool {
+        let page_addr = PageAddr::with_round_down(addr, PageSize::Size4k);
+        MemoryRegion::new(page_addr, 1, RegionState::EmulatedMmio)
+            .map_or(false, |range| self.regions.lock().find(&range).is_some())
+    }
 }
 
 impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
-    /// Returns a list of converted and locked pages created from `num_pages` starting at `page_addr`.
+    /// Extends the runtime measurement register at `index` with `digest`, which must be the length
+    /// of a digest made with this guest's measurement algorithm.
+    pub fn extend_runtime_measurement(&self, index: usize, digest: &[u8]) -> Result<()> {
+        self.runtime_measurements
+            .lock()
+            .extend(index, digest)
+            .ok_or(Error::InvalidMeasurementRegister(index))?;
+        self.measurement_log
+            .lock()
+            .record(MeasurementEvent::RuntimeExtend {
+                index: index as u32,
+                digest,
+            });
+        Ok(())
+    }
+
+    /// Returns a list of converted and locked pages created from `num_pages` of size `page_size`
+    /// starting at `page_addr`.
     fn get_converted_pages(
         &self,
         page_addr: GuestPageAddr,
+        page_size: PageSize,
         num_pages: u64,
     ) -> Result<LockedPageList<Page<ConvertedDirty>>> {
         let version = self.tlb_tracker.current();
         self.root
-            .get_converted_range::<Page<ConvertedDirty>>(
-                page_addr,
-                PageSize::Size4k,
-                num_pages,
-                version,
-            )
+            .get_converted_range::<Page<ConvertedDirty>>(page_addr, page_size, num_pages, version)
+            .map_err(Error::Paging)
+    }
+
+    /// Returns a list of locked pages that have started conversion, whether or not it has been
+    /// fenced, created from `num_pages` of size `page_size` starting at `page_addr`.
+    fn get_reclaimable_pages(
+        &self,
+        page_addr: GuestPageAddr,
+        page_size: PageSize,
+        num_pages: u64,
+    ) -> Result<LockedPageList<Page<ConvertedDirty>>> {
+        self.root
+            .get_reclaimable_range::<Page<ConvertedDirty>>(page_addr, page_size, num_pages)
             .map_err(Error::Paging)
     }
 
-    /// Converts `num_pages` starting at guest physical address `page_addr` to confidential memory.
-    pub fn convert_pages(&self, page_addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+    /// Converts `num_pages` of size `page_size` starting at guest physical address `page_addr` to
+    /// confidential memory. Huge mappings that are only partially covered by the range are split.
+    /// Locked pages can't be converted, since reclaiming them would map them writable again.
+    pub fn convert_pages(
+        &self,
+        page_addr: GuestPageAddr,
+        page_size: PageSize,
+        num_pages: u64,
+    ) -> Result<()> {
         if self.nesting >= MAX_PAGE_OWNERS - 1 {
             // We shouldn't bother converting pages if we won't be able to assign them.
             return Err(Error::NestingTooDeep);
         }
+        let num_4k_pages = num_pages
+            .checked_mul(PageSize::num_4k_pages(page_size as u64))
+            .ok_or(Error::AddressOverflow)?;
+        // Hold the lock until the pages have been invalidated so that the range can't be locked
+        // in the meantime.
+        let regions = self.regions.lock();
+        let range = MemoryRegion::new(page_addr, num_4k_pages, RegionState::Locked)
+            .map_err(Error::Regions)?;
+        if regions.overlaps_locked(&range) {
+            return Err(Error::LockedRegion(page_addr));
+        }
 
         let invalidated_pages = self
             .root
-            .invalidate_range::<Page<Invalidated>>(page_addr, PageSize::Size4k, num_pages)
+            .invalidate_range::<Page<Invalidated>>(page_addr, page_size, num_pages, &mut || {
+                self.pte_pages.pop()
+            })
             .map_err(Error::Paging)?;
         let version = self.tlb_tracker.current();
         for page in invalidated_pages {
@@ -469,21 +662,216 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
         Ok(())
     }
 
-    /// Reclaims `num_pages` of confidential memory starting at guest physical address `page_addr`.
-    pub fn reclaim_pages(&self, page_addr: GuestPageAddr, num_pages: u64) -> Result<()> {
-        // TODO: Support reclaim of converted pages that haven't yet been fenced.
-        let converted_pages = self.get_converted_pages(page_addr, num_pages)?;
-        // Unwrap ok since the PTE for the page must have previously been invalid and all of
-        // the intermediate page-tables must already have been populatd.
-        let mapper = VmPagesMapper::new(self, page_addr, num_pages).unwrap();
-        for (page, addr) in converted_pages.zip(page_addr.iter_from()) {
+    /// Reclaims `num_pages` of size `page_size` of confidential memory starting at guest physical
+    /// address `page_addr`. The reclaimed pages are mapped back with `page_size` mappings if they
+    /// were converted with mappings of that size, and with 4kB mappings otherwise. Pages whose
+    /// conversion hasn't yet been fenced have their conversion rolled back.
+    pub fn reclaim_pages(
+        &self,
+        page_addr: GuestPageAddr,
+        page_size: PageSize,
+        num_pages: u64,
+    ) -> Result<()> {
+        if !page_addr.is_aligned(page_size) {
+            return Err(Error::UnalignedVmPages(page_addr));
+        }
+        // Pages that are still converting never left us, so they don't need a TLB fence before
+        // being mapped back at the same address. Any pages locked here are unlocked again if we
+        // fail to lock the PTEs for mapping.
+        if page_size != PageSize::Size4k
+            && let Ok(converted_pages) = self.get_reclaimable_pages(page_addr, page_size, num_pages)
+            && let Ok(mapper) = VmPagesMapper::new(self, page_addr, page_size, num_pages)
+        {
+            self.map_reclaimed_pages(converted_pages, &mapper, page_addr, page_size);
+            return Ok(());
+        }
+
+        // Fall back to 4kB mappings if the huge mappings have been split, e.g. because only part
+        // of them was converted.
+        let num_4k_pages = num_pages
+            .checked_mul(PageSize::num_4k_pages(page_size as u64))
+            .ok_or(Error::AddressOverflow)?;
+        let converted_pages =
+            self.get_reclaimable_pages(page_addr, PageSize::Size4k, num_4k_pages)?;
+        // The PTEs for the pages must have previously been invalid, but invalidated huge mappings
+        // may need to be split in order to be remapped.
+        let mapper = VmPagesMapper::new(self, page_addr, PageSize::Size4k, num_4k_pages)?;
+        self.map_reclaimed_pages(converted_pages, &mapper, page_addr, PageSize::Size4k);
+        Ok(())
+    }
+
+    /// Maps the `page_size` pages in `converted_pages`, which were just locked for reclaim, back at
+    /// consecutive addresses starting at `page_addr` using `mapper`.
+    fn map_reclaimed_pages(
+        &self,
+        converted_pages: LockedPageList<Page<ConvertedDirty>>,
+        mapper: &VmPagesMapper<T, VmStateFinalized>,
+        page_addr: GuestPageAddr,
+        page_size: PageSize,
+    ) {
+        // Unwrap ok since the caller checked that `page_addr` is aligned to `page_size`.
+        let addrs = page_addr.iter_from_with_size(page_size).unwrap();
+        for (page, addr) in converted_pages.zip(addrs) {
             // Unwrap ok since we know that it's a converted page.
             let mappable = self.page_tracker.reclaim_page(page.clean()).unwrap();
-            mapper.map_page(addr, mappable).unwrap();
+            // Unwrap ok since the address is in range and we haven't mapped it yet.
+            mapper.map_page(addr, mappable, PteLeafPerms::RWX).unwrap();
         }
+    }
+
+    /// Converts the `num_pages` 4kB pages starting at `addr` from confidential to shared. Any
+    /// confidential pages mapped in the range are invalidated and returned to our parent once the
+    /// invalidation has been fenced (see `release_fenced_regions()`). The range must not overlap
+    /// any range that is already shared or pending acceptance.
+    pub fn share_memory(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        let mut regions = self.regions.lock();
+        let range =
+            MemoryRegion::new(addr, num_pages, RegionState::Shared).map_err(Error::Regions)?;
+        regions.check_insert(&range).map_err(Error::Regions)?;
+        self.root
+            .invalidate_mapped_range(addr, num_pages, &mut || self.pte_pages.pop())
+            .map_err(Error::Paging)?;
+        let version = self.tlb_tracker.current();
+        // Unwrap ok since we've checked that the region can be inserted and hold the lock.
+        regions
+            .insert(range.with_state(RegionState::Sharing(version)))
+            .unwrap();
+        // Kick off the fence now if we can, otherwise it's retried when fenced regions are next
+        // released.
+        let _ = self.tlb_tracker.increment();
         Ok(())
     }
 
+    /// Converts the `num_pages` 4kB pages starting at `addr` from shared back to confidential. Any
+    /// shared pages mapped in the range are invalidated and stop being shared once the
+    /// invalidation has been fenced (see `release_fenced_regions()`). The range must lie within a
+    /// single shared region.
+    pub fn unshare_memory(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        let mut regions = self.regions.lock();
+        let range =
+            MemoryRegion::new(addr, num_pages, RegionState::Shared).map_err(Error::Regions)?;
+        let index = regions.find(&range).ok_or(Error::NotSharedRegion(addr))?;
+        regions
+            .check_carve(index, &range, true)
+            .map_err(Error::Regions)?;
+        self.root
+            .invalidate_mapped_range(addr, num_pages, &mut || self.pte_pages.pop())
+            .map_err(Error::Paging)?;
+        let version = self.tlb_tracker.current();
+        regions.carve(index, &range, Some(RegionState::Unsharing(version)));
+        // As above, the fence is retried later if it can't be started now.
+        let _ = self.tlb_tracker.increment();
+        Ok(())
+    }
+
+    /// Accepts the `num_pages` 4kB pages starting at `addr`, making them accessible. The range must
+    /// lie within a single region of pages added after finalization that have yet to be accepted.
+    pub fn accept_memory(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        let mut regions = self.regions.lock();
+        let range =
+            MemoryRegion::new(addr, num_pages, RegionState::Unaccepted).map_err(Error::Regions)?;
+        let index = regions
+            .find(&range)
+            .ok_or(Error::NotUnacceptedRegion(addr))?;
+        regions
+            .check_carve(index, &range, false)
+            .map_err(Error::Regions)?;
+        self.root
+            .validate_range(addr, num_pages, &mut || self.pte_pages.pop())
+            .map_err(Error::Paging)?;
+        regions.carve(index, &range, None);
+        Ok(())
+    }
+
+    /// Permanently makes the `num_pages` 4kB pages starting at `addr` read/execute-only. Every page
+    /// in the range must be mapped and accepted, and the range must not overlap any range that is
+    /// shared or pending acceptance. The caller must fence the translations for the range cached by
+    /// the CPUs running the VM; other CPUs pick up the change once the TLB version has been fenced.
+    pub fn lock_text(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        let mut regions = self.regions.lock();
+        let range =
+            MemoryRegion::new(addr, num_pages, RegionState::Locked).map_err(Error::Regions)?;
+        regions.check_insert(&range).map_err(Error::Regions)?;
+        self.root
+            .protect_range(addr, num_pages, PteLeafPerms::RX, &mut || {
+                self.pte_pages.pop()
+            })
+            .map_err(Error::Paging)?;
+        // Unwrap ok since we've checked that the region can be inserted and hold the lock.
+        regions.insert(range).unwrap();
+        // Kick off a fence so that other CPUs flush their writable translations the next time
+        // they enter the VM. If a fence is already in progress they're flushed after the next one.
+        let _ = self.tlb_tracker.increment();
+        Ok(())
+    }
+
+    /// Completes any pending share or unshare requests whose invalidations have been fenced,
+    /// releasing the pages that were mapped in the affected ranges. Starts a new fence if there are
+    /// requests waiting on the current TLB version.
+    pub fn release_fenced_regions(&self) {
+        let mut regions = self.regions.lock();
+        let current = self.tlb_tracker.current();
+        if regions.is_pending_at(current) {
+            // Nothing to do if there's already a fence in progress; we'll try again next time.
+            let _ = self.tlb_tracker.increment();
+        }
+
+        regions.complete_fenced(
+            |v| self.tlb_tracker.is_fenced(v),
+            |region| {
+                // Unwrap ok since the range was invalidated when the region entered its current
+                // state and nothing can be mapped into a pending region.
+                self.root
+                    .release_invalidated_range(region.addr(), region.num_pages())
+                    .unwrap();
+            },
+        );
+    }
+
+    /// Maps `count` 4kB pages starting at `from_addr` into the shared region of `to` at `to_addr`
+    /// with the memory type `mem_type`. The pages remain mapped in this VM's address space, but
+    /// can't be converted until `to` stops sharing them. Shared pages are never measured, and `to`
+    /// may not execute from them. Locked pages can't be shared, since `to` could then write to
+    /// them.
+    pub fn add_shared_pages_builder<U: GuestStagePageTable>(
+        &self,
+        from_addr: GuestPageAddr,
+        count: u64,
+        to: &VmPages<U, VmStateFinalized>,
+        to_addr: GuestPageAddr,
+        mem_type: PteMemType,
+    ) -> Result<u64> {
+        to.release_fenced_regions();
+        // Hold both locks until the pages have been mapped so that the source range can't be locked
+        // and the destination range can't be unshared in the meantime. The parent's lock is always
+        // taken before the child's.
+        let regions = self.regions.lock();
+        let range =
+            MemoryRegion::new(from_addr, count, RegionState::Locked).map_err(Error::Regions)?;
+        if regions.overlaps_locked(&range) {
+            return Err(Error::LockedRegion(from_addr));
+        }
+        let to_regions = to.regions.lock();
+        MemoryRegion::new(to_addr, count, RegionState::Shared)
+            .ok()
+            .and_then(|range| to_regions.find(&range))
+            .ok_or(Error::NotSharedRegion(to_addr))?;
+        // Lock the destination range first so that there's nothing to undo if it's already
+        // populated.
+        let mapper = VmPagesMapper::new(to, to_addr, PageSize::Size4k, count)?;
+        let shared_pages = self
+            .root
+            .share_range(from_addr, count, to.page_owner_id())
+            .map_err(Error::Paging)?;
+        for (page, guest_addr) in shared_pages.zip(to_addr.iter_from()) {
+            // Unwrap ok since the address is in range and we haven't mapped it yet.
+            mapper
+                .map_page_with_mem_type(guest_addr, page, PteLeafPerms::RW, mem_type)
+                .unwrap();
+        }
+        Ok(count)
+    }
+
     /// Initiates a page conversion fence for this `VmPages` by incrementing the TLB version.
     pub fn initiate_fence(&self) -> Result<()> {
         self.tlb_tracker.increment()
@@ -508,29 +896,31 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
         assigned_pages
     }
 
-    /// Creates a new `Vm` using pages donated by `self`. The returned `Vm` is in the initializing
-    /// state, ready for its address space to be constructed.
-    pub fn create_guest_vm(
+    /// Creates a new `Vm` with a `U` guest-stage page table using pages donated by `self`. The
+    /// returned `Vm` is in the initializing state, ready for its address space to be constructed.
+    pub fn create_guest_vm<U: GuestStagePageTable>(
         &self,
         page_root_addr: GuestPageAddr,
         state_addr: GuestPageAddr,
         vcpus_addr: GuestPageAddr,
         num_vcpu_pages: u64,
-    ) -> Result<(Vm<T, VmStateInitializing>, Page<InternalClean>)> {
-        if (page_root_addr.bits() as *const u64).align_offset(T::TOP_LEVEL_ALIGN as usize) != 0 {
+        measurement_algorithm: DigestAlgorithm,
+    ) -> Result<(Vm<U, VmStateInitializing>, Page<InternalClean>)> {
+        if (page_root_addr.bits() as *const u64).align_offset(U::TOP_LEVEL_ALIGN as usize) != 0 {
             return Err(Error::UnalignedVmPages(page_root_addr));
         }
 
         // Make sure we can grab the pages first before we start wiping and assigning them.
-        let guest_root_pages = self.get_converted_pages(page_root_addr, 4)?;
+        let guest_root_pages = self.get_converted_pages(page_root_addr, PageSize::Size4k, 4)?;
         if !guest_root_pages.is_contiguous() {
             return Err(Error::NonContiguousPages);
         }
-        let state_pages = self.get_converted_pages(state_addr, TVM_STATE_PAGES)?;
+        let state_pages =
+            self.get_converted_pages(state_addr, PageSize::Size4k, TVM_STATE_PAGES)?;
         if !state_pages.is_contiguous() {
             return Err(Error::NonContiguousPages);
         }
-        let vcpu_pages = self.get_converted_pages(vcpus_addr, num_vcpu_pages)?;
+        let vcpu_pages = self.get_converted_pages(vcpus_addr, PageSize::Size4k, num_vcpu_pages)?;
         if !vcpu_pages.is_contiguous() {
             return Err(Error::NonContiguousPages);
         }
@@ -549,21 +939,46 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
 
         Ok((
             Vm::new(
-                VmPages::new(guest_root, self.nesting + 1),
+                VmPages::new(guest_root, self.nesting + 1, measurement_algorithm),
                 VmCpus::new(id, vcpu_pages, self.page_tracker.clone()).unwrap(),
             ),
             state_page,
         ))
     }
 
+    /// Adds the `count` contiguous 4kB pages starting at `from_addr` to hold the measurement event
+    /// log of the given guest.
+    pub fn add_measurement_log_pages_builder<U: GuestStagePageTable>(
+        &self,
+        from_addr: GuestPageAddr,
+        count: u64,
+        to: &VmPages<U, VmStateInitializing>,
+    ) -> Result<()> {
+        if !to.measurement_log.lock().can_set_pages() {
+            return Err(Error::MeasurementLogUnavailable);
+        }
+        let converted_pages = self.get_converted_pages(from_addr, PageSize::Size4k, count)?;
+        if !converted_pages.is_contiguous() {
+            return Err(Error::NonContiguousPages);
+        }
+        let log_pages = SequentialPages::from_pages(
+            self.assign_state_pages_for(converted_pages, to.page_owner_id()),
+        )
+        .unwrap();
+        to.measurement_log
+            .lock()
+            .set_pages(log_pages, self.page_tracker.clone());
+        Ok(())
+    }
+
     /// Adds pages to be used for building page table entries
-    pub fn add_pte_pages_builder(
+    pub fn add_pte_pages_builder<U: GuestStagePageTable>(
         &self,
         from_addr: GuestPageAddr,
         count: u64,
-        to: &VmPages<T, VmStateInitializing>,
+        to: &VmPages<U, VmStateInitializing>,
     ) -> Result<()> {
-        let converted_pages = self.get_converted_pages(from_addr, count)?;
+        let converted_pages = self.get_converted_pages(from_addr, PageSize::Size4k, count)?;
         let new_owner = to.page_owner_id();
         for page in converted_pages {
             // Unwrap ok since we've guaranteed the page is assignable.
@@ -577,26 +992,71 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
         Ok(())
     }
 
-    /// Adds zero-filled pages to the given guest.
-    pub fn add_zero_pages_builder(
+    /// Adds `count` zero-filled pages of size `page_size` to the given guest, mapped with the
+    /// permissions `perms`.
+    pub fn add_zero_pages_builder<U: GuestStagePageTable>(
+        &self,
+        from_addr: GuestPageAddr,
+        page_size: PageSize,
+        count: u64,
+        to: &VmPages<U, VmStateInitializing>,
+        to_addr: GuestPageAddr,
+        perms: PteLeafPerms,
+    ) -> Result<u64> {
+        let converted_pages = self.get_converted_pages(from_addr, page_size, count)?;
+        let mapper = to.map_pages(to_addr, page_size, count)?;
+        let new_owner = to.page_owner_id();
+        // Unwrap ok since `map_pages()` checked that `to_addr` is aligned to `page_size`.
+        let to_addrs = to_addr.iter_from_with_size(page_size).unwrap();
+        for (page, guest_addr) in converted_pages.zip(to_addrs) {
+            // Unwrap ok since we've guaranteed there's space for another owner.
+            let mappable = self
+                .page_tracker
+                .assign_page_for_mapping(page.clean(), new_owner)
+                .unwrap();
+            // Unwrap ok since the address is in range and we haven't mapped it yet.
+            mapper.map_page(guest_addr, mappable, perms).unwrap();
+        }
+        Ok(count)
+    }
+
+    /// Adds `count` zero-filled pages of size `page_size` to the finalized guest `to`, mapped with
+    /// the permissions `perms`. The pages are inaccessible to the guest until it accepts them.
+    pub fn add_unaccepted_zero_pages_builder<U: GuestStagePageTable>(
         &self,
         from_addr: GuestPageAddr,
+        page_size: PageSize,
         count: u64,
-        to: &VmPages<T, VmStateInitializing>,
+        to: &VmPages<U, VmStateFinalized>,
         to_addr: GuestPageAddr,
+        perms: PteLeafPerms,
     ) -> Result<u64> {
-        let converted_pages = self.get_converted_pages(from_addr, count)?;
-        let mapper = to.map_pages(to_addr, count)?;
+        to.release_fenced_regions();
+        let num_4k_pages = count
+            .checked_mul(PageSize::num_4k_pages(page_size as u64))
+            .ok_or(Error::AddressOverflow)?;
+        let range = MemoryRegion::new(to_addr, num_4k_pages, RegionState::Unaccepted)
+            .map_err(Error::Regions)?;
+        let mut regions = to.regions.lock();
+        regions.check_insert(&range).map_err(Error::Regions)?;
+        let converted_pages = self.get_converted_pages(from_addr, page_size, count)?;
+        let mapper = VmPagesMapper::new(to, to_addr, page_size, count)?;
         let new_owner = to.page_owner_id();
-        for (page, guest_addr) in converted_pages.zip(to_addr.iter_from()) {
+        // Unwrap ok since `VmPagesMapper::new()` checked that `to_addr` is aligned to `page_size`.
+        let to_addrs = to_addr.iter_from_with_size(page_size).unwrap();
+        for (page, guest_addr) in converted_pages.zip(to_addrs) {
             // Unwrap ok since we've guaranteed there's space for another owner.
             let mappable = self
                 .page_tracker
                 .assign_page_for_mapping(page.clean(), new_owner)
                 .unwrap();
             // Unwrap ok since the address is in range and we haven't mapped it yet.
-            mapper.map_page(guest_addr, mappable).unwrap();
+            mapper
+                .map_page_invalidated(guest_addr, mappable, perms)
+                .unwrap();
         }
+        // Unwrap ok since we've checked that the region can be inserted and hold the lock.
+        regions.insert(range).unwrap();
         Ok(count)
     }
 
@@ -626,8 +1086,17 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateFinalized> {
 }
 
 impl<T: GuestStagePageTable> VmPages<T, VmStateInitializing> {
-    /// Creates a new `VmPages` from the given root page table.
-    pub fn new(root: PlatformPageTable<T>, nesting: usize) -> Self {
+    /// Creates a new `VmPages` from the given root page table, measured using
+    /// `measurement_algorithm`. Svpbmt memory types are used in the page table if the CPU supports
+    /// them.
+    pub fn new(
+        root: PlatformPageTable<T>,
+        nesting: usize,
+        measurement_algorithm: DigestAlgorithm,
+    ) -> Self {
+        if CpuInfo::get().has_svpbmt() {
+            root.enable_svpbmt();
+        }
         let page_tracker = root.page_tracker();
         Self {
             page_owner_id: root.page_owner_id(),
@@ -635,8 +1104,11 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateInitializing> {
             tlb_tracker: TlbTracker::new(),
             nesting,
             root,
-            measurement: Mutex::new(Sha256Measure::new()),
+            measurement: Mutex::new(DynMeasure::new(measurement_algorithm)),
+            runtime_measurements: Mutex::new(RuntimeMeasurements::new(measurement_algorithm)),
+            measurement_log: Mutex::new(MeasurementLog::new()),
             pte_pages: PtePagePool::new(page_tracker),
+            regions: Mutex::new(MemoryRegions::new()),
             phantom: PhantomData,
         }
     }
@@ -651,14 +1123,80 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateInitializing> {
         Ok(())
     }
 
-    /// Locks `count` 4kB pages starting at `page_addr` for mapping, returning a `VmPagesMapper` that
-    /// can be used to insert (and measure, if necessary) the pages.
+    /// Locks `count` pages of size `page_size` starting at `page_addr` for mapping, returning a
+    /// `VmPagesMapper` that can be used to insert (and measure, if necessary) the pages. The pages
+    /// must not overlap an emulated MMIO region.
     pub fn map_pages(
         &self,
         page_addr: GuestPageAddr,
+        page_size: PageSize,
         count: u64,
     ) -> Result<VmPagesMapper<T, VmStateInitializing>> {
-        VmPagesMapper::new(self, page_addr, count)
+        let num_4k_pages = count
+            .checked_mul(PageSize::num_4k_pages(page_size as u64))
+            .ok_or(Error::AddressOverflow)?;
+        let range = MemoryRegion::new(page_addr, num_4k_pages, RegionState::EmulatedMmio)
+            .map_err(Error::Regions)?;
+        // Hold the lock until the PTEs are locked so that a region can't be declared over them in
+        // the meantime.
+        let regions = self.regions.lock();
+        if regions.overlaps(&range) {
+            return Err(Error::Regions(MemRegionsError::RegionOverlap(page_addr)));
+        }
+        VmPagesMapper::new(self, page_addr, page_size, count)
+    }
+
+    /// Declares the `num_pages` 4kB pages starting at `addr` as an emulated MMIO region. No pages
+    /// may be mapped in the range, and none may be added to it afterwards. Regions are measured
+    /// when the VM is finalized; see `for_each_emulated_mmio_region()`.
+    pub fn add_emulated_mmio_region(&self, addr: GuestPageAddr, num_pages: u64) -> Result<()> {
+        let mut regions = self.regions.lock();
+        let range = MemoryRegion::new(addr, num_pages, RegionState::EmulatedMmio)
+            .map_err(Error::Regions)?;
+        regions.check_insert(&range).map_err(Error::Regions)?;
+        if !self.root.is_unmapped_range(addr, num_pages) {
+            return Err(Error::RegionInUse(addr));
+        }
+        // Unwrap ok since we've checked that the region can be inserted and hold the lock.
+        regions.insert(range).unwrap();
+        Ok(())
+    }
+
+    /// Calls `f` with the address and number of 4kB pages of each emulated MMIO region, in order of
+    /// address.
+    pub fn for_each_emulated_mmio_region<F: FnMut(GuestPageAddr, u64)>(&self, mut f: F) {
+        let regions = self.regions.lock();
+        let mut mmio_regions: ArrayVec<&MemoryRegion, MAX_MEMORY_REGIONS> = regions
+            .iter()
+            .filter(|r| r.state() == RegionState::EmulatedMmio)
+            .collect();
+        mmio_regions.sort_unstable_by_key(|r| r.start());
+        for r in mmio_regions {
+            f(r.addr(), r.num_pages());
+        }
+    }
+
+    /// Changes the permissions of the `num_pages` 4kB pages starting at `addr`, all of which must
+    /// be mapped, to `perms`. The guest hasn't run yet, so there are no translations to fence. The
+    /// caller is responsible for measuring the change with `add_measured_data()`.
+    pub fn protect_pages(
+        &self,
+        addr: GuestPageAddr,
+        num_pages: u64,
+        perms: PteLeafPerms,
+    ) -> Result<()> {
+        self.root
+            .protect_range(addr, num_pages, perms, &mut || self.pte_pages.pop())
+            .map_err(Error::Paging)
+    }
+
+    /// Adds `data`, which describes part of the guest's initial state other than its memory, to
+    /// the guest's measurement.
+    pub fn add_measured_data(&self, data: &[u8]) {
+        self.measurement.lock().add_data(data);
+        self.measurement_log
+            .lock()
+            .record(MeasurementEvent::MeasuredData { data });
     }
 
     /// Consumes this `VmPages`, returning a finalized one.
@@ -670,7 +1208,10 @@ impl<T: GuestStagePageTable> VmPages<T, VmStateInitializing> {
             nesting: self.nesting,
             root: self.root,
             measurement: self.measurement,
+            runtime_measurements: self.runtime_measurements,
+            measurement_log: self.measurement_log,
             pte_pages: self.pte_pages,
+            regions: self.regions,
             phantom: PhantomData,
         }
     }
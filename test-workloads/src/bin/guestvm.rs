@@ -7,12 +7,14 @@
 #![feature(panic_info_message, allocator_api, alloc_error_handler, lang_items)]
 
 use core::alloc::{GlobalAlloc, Layout};
-use core::mem::size_of;
 
 extern crate alloc;
 extern crate test_workloads;
 
-use attestation::{MAX_CERT_LEN, MAX_CSR_LEN};
+use attestation::name::{Name, COMMON_NAME_OID};
+use attestation::request::CertReqBuilder;
+use attestation::signer::Ed25519Signer;
+use attestation::{MAX_CERT_LEN, MAX_CSR_LEN, MAX_EVIDENCE_TOKEN_LEN};
 use s_mode_utils::abort::abort;
 use s_mode_utils::ecall::ecall_send;
 use s_mode_utils::print_sbi::*;
@@ -39,8 +41,14 @@ pub fn alloc_error(_layout: Layout) -> ! {
     abort()
 }
 
-/// Test `CertReq` encoded as ASN.1 DER
-const TEST_CSR: &[u8] = include_bytes!("test-ed25519.der");
+/// Ed25519 secret key for the test CSR
+const TEST_CSR_SECRET: [u8; 32] = [0x5a; 32];
+
+/// Common name the test CSR requests a certificate for
+const TEST_CSR_COMMON_NAME: &str = "Salus Test TVM";
+
+/// Test nonce for the evidence to be bound to
+const TEST_NONCE: &[u8] = b"salus-guestvm-test-nonce";
 
 #[no_mangle]
 #[allow(clippy::zero_ptr)]
@@ -56,23 +64,34 @@ extern "C" fn kernel_init() {
         dest_addr: measurement_page_addr,
     });
 
-    if TEST_CSR.len() > MAX_CSR_LEN as usize {
-        panic!("Test CSR is too large")
-    }
     let csr_addr = measurement_page_addr + PAGE_SIZE_4K;
-    // Align the cert address
-    let csr_size_aligned = (TEST_CSR.len() + size_of::<u64>() - 1) & !(size_of::<u64>() - 1);
-    let cert_addr = csr_addr + csr_size_aligned as u64;
-
-    // Safety: csr_addr is the unique reference to the CSR page.
+    let cert_addr = csr_addr + MAX_CSR_LEN as u64;
+    let nonce_addr = cert_addr + MAX_CERT_LEN as u64;
+
+    // Build and sign the test CSR in place.
+    let signer = Ed25519Signer::from_secret(&TEST_CSR_SECRET).expect("Invalid test CSR key");
+    let mut subject = Name::default();
+    subject
+        .add_utf8_string(COMMON_NAME_OID, TEST_CSR_COMMON_NAME)
+        .expect("Invalid test CSR subject");
+    // Safety: csr_addr is the unique reference to the MAX_CSR_LEN bytes of the CSR pages.
+    let csr_buf = unsafe { core::slice::from_raw_parts_mut(csr_addr as *mut u8, MAX_CSR_LEN) };
+    let csr_len = CertReqBuilder::new(subject)
+        .sign(&signer, csr_buf)
+        .expect("Failed to build test CSR")
+        .len();
+
+    // Safety: nonce_addr is the unique reference to the nonce page.
     unsafe {
-        core::ptr::copy(TEST_CSR.as_ptr(), csr_addr as *mut u8, TEST_CSR.len());
+        core::ptr::copy(TEST_NONCE.as_ptr(), nonce_addr as *mut u8, TEST_NONCE.len());
     }
     let attestation_msg = SbiMessage::Attestation(sbi::AttestationFunction::GetEvidence {
         csr_addr,
-        csr_len: TEST_CSR.len() as u64,
+        csr_len: csr_len as u64,
         cert_addr,
         cert_len: MAX_CERT_LEN as u64,
+        nonce_addr,
+        nonce_len: TEST_NONCE.len() as u64,
     });
 
     println!("*****************************************");
@@ -80,15 +99,48 @@ extern "C" fn kernel_init() {
 
     // Safety: msg contains a unique reference to the measurement page and SBI is safe to write to
     // that page.
-    match unsafe { ecall_send(&msg) } {
+    let measurement_len = match unsafe { ecall_send(&msg) } {
         Err(e) => {
             println!("Guest measurement error {e:?}");
             panic!("Guest measurement call failed");
         }
-        Ok(_) => {
+        Ok(len) => {
             let measurement =
                 unsafe { core::ptr::read_volatile(measurement_page_addr as *const u64) };
             println!("Guest measurement was {measurement:x}");
+            len
+        }
+    };
+
+    // Extend the first runtime measurement register with the static measurement, and read the
+    // runtime measurement registers back.
+    let extend_msg = SbiMessage::Attestation(sbi::AttestationFunction::ExtendMeasurement {
+        measurement_addr: measurement_page_addr,
+        len: measurement_len,
+        index: 0,
+    });
+    // Safety: SBI only reads from the measurement page.
+    if let Err(e) = unsafe { ecall_send(&extend_msg) } {
+        println!("Extend measurement error {e:?}");
+        panic!("Guest extend measurement call failed");
+    }
+    let runtime_measurement_addr = measurement_page_addr + 64;
+    let runtime_msg = SbiMessage::Measurement(sbi::MeasurementFunction::GetSelfMeasurement {
+        measurement_version: 1,
+        measurement_type: sbi::MEASUREMENT_TYPE_RUNTIME,
+        dest_addr: runtime_measurement_addr,
+    });
+    // Safety: msg contains a unique reference to the measurement page and SBI is safe to write to
+    // that page.
+    match unsafe { ecall_send(&runtime_msg) } {
+        Err(e) => {
+            println!("Guest runtime measurement error {e:?}");
+            panic!("Guest runtime measurement call failed");
+        }
+        Ok(_) => {
+            let measurement =
+                unsafe { core::ptr::read_volatile(runtime_measurement_addr as *const u64) };
+            println!("Guest runtime measurement was {measurement:x}");
         }
     }
 
@@ -104,6 +156,65 @@ extern "C" fn kernel_init() {
         }
     }
 
+    let key_addr = nonce_addr + PAGE_SIZE_4K;
+    let sealing_key_msg = SbiMessage::Attestation(sbi::AttestationFunction::GetSealingKey {
+        policy: sbi::SEALING_KEY_POLICY_MEASUREMENT,
+        key_addr,
+        key_len: 32,
+    });
+    // Safety: SBI only writes the key to the page at key_addr, which isn't otherwise used.
+    match unsafe { ecall_send(&sealing_key_msg) } {
+        Err(e) => {
+            println!("Sealing key error {e:?}");
+            panic!("Guest sealing key call failed");
+        }
+        Ok(key_len) => {
+            println!("Sealing key is at 0x{:x} - len {}", key_addr, key_len);
+        }
+    }
+
+    let signer_key_addr = key_addr + 32;
+    let signer_key_msg = SbiMessage::Attestation(sbi::AttestationFunction::GetSealingKey {
+        policy: sbi::SEALING_KEY_POLICY_SIGNER,
+        key_addr: signer_key_addr,
+        key_len: 32,
+    });
+    // Safety: SBI only writes the key to the page at key_addr, after the measurement-bound key.
+    match unsafe { ecall_send(&signer_key_msg) } {
+        Err(e) => {
+            println!("Signer sealing key error {e:?}");
+            panic!("Guest signer sealing key call failed");
+        }
+        Ok(key_len) => {
+            println!(
+                "Signer sealing key is at 0x{:x} - len {}",
+                signer_key_addr, key_len
+            );
+        }
+    }
+
+    let token_addr = key_addr + PAGE_SIZE_4K;
+    let token_msg = SbiMessage::Attestation(sbi::AttestationFunction::GetEvidenceToken {
+        nonce_addr,
+        nonce_len: TEST_NONCE.len() as u64,
+        token_addr,
+        token_len: MAX_EVIDENCE_TOKEN_LEN as u64,
+    });
+    // Safety: SBI only reads the nonce page and writes the token to the page at token_addr, which
+    // isn't otherwise used.
+    match unsafe { ecall_send(&token_msg) } {
+        Err(e) => {
+            println!("Evidence token error {e:?}");
+            panic!("Guest evidence token call failed");
+        }
+        Ok(token_len) => {
+            println!(
+                "Evidence token is at 0x{:x} - len {}",
+                token_addr, token_len
+            );
+        }
+    }
+
     println!("Exiting guest by causing a fault         ");
     println!("*****************************************");
 
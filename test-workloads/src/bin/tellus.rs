@@ -95,6 +95,7 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
     const USABLE_RAM_START_ADDRESS: u64 = 0x8020_0000;
     const NUM_VCPUS: u64 = 1;
     const NUM_TEE_PTE_PAGES: u64 = 10;
+    const NUM_MEASUREMENT_LOG_PAGES: u64 = 1;
     const NUM_GUEST_DATA_PAGES: u64 = 10;
     const NUM_GUEST_ZERO_PAGES: u64 = 10;
     const NUM_GUEST_PAD_PAGES: u64 = 32;
@@ -146,6 +147,10 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         tvm_state_addr,
         tvm_num_vcpus: NUM_VCPUS,
         tvm_vcpu_addr,
+        tvm_measurement_alg: sbi::MeasurementAlgorithm::Sha384 as u64,
+        tvm_paging_mode: sbi::TvmPagingMode::Sv48x4 as u64,
+        tvm_svn: 1,
+        tvm_signer_id: *b"salus-test-tvm-image-signer-id!!",
     };
     let msg = SbiMessage::Tee(sbi::TeeFunction::TvmCreate {
         params_addr: (&tvm_create_params as *const sbi::TvmCreateParams) as u64,
@@ -168,6 +173,17 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
     unsafe { ecall_send(&msg).expect("Tellus - AddPageTablePages returned error") };
     next_page += PAGE_SIZE_4K * NUM_TEE_PTE_PAGES;
 
+    // Add pages for the measurement log.
+    convert_pages(next_page, NUM_MEASUREMENT_LOG_PAGES);
+    let msg = SbiMessage::Tee(sbi::TeeFunction::TvmAddMeasurementLogPages {
+        guest_id: vmid,
+        page_addr: next_page,
+        num_pages: NUM_MEASUREMENT_LOG_PAGES,
+    });
+    // Safety: `TvmAddMeasurementLogPages` only accesses pages that have been previously converted.
+    unsafe { ecall_send(&msg).expect("Tellus - TvmAddMeasurementLogPages returned error") };
+    next_page += PAGE_SIZE_4K * NUM_MEASUREMENT_LOG_PAGES;
+
     // Add vCPU0.
     let msg = SbiMessage::Tee(sbi::TeeFunction::TvmCpuCreate {
         guest_id: vmid,
@@ -253,6 +269,25 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         }
     }
 
+    let mut log_buf = [0u8; 256];
+    let mut log_len = 0;
+    loop {
+        let msg = SbiMessage::Tee(sbi::TeeFunction::TvmGetMeasurementLog {
+            guest_id: vmid,
+            dest_addr: log_buf.as_mut_ptr() as u64,
+            offset: log_len,
+            len: log_buf.len() as u64,
+        });
+        // Safety: The passed buffer is uniquely owned so it's safe to modify in SBI.
+        let len =
+            unsafe { ecall_send(&msg).expect("Tellus - TvmGetMeasurementLog returned error") };
+        if len == 0 {
+            break;
+        }
+        log_len += len;
+    }
+    println!("Guest measurement log is {log_len} bytes");
+
     // Add zeroed (non-measured) pages
     // TODO: Make sure that these guest pages are actually zero
     convert_pages(next_page, NUM_GUEST_ZERO_PAGES);
@@ -262,6 +297,7 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         page_type: sbi::TsmPageType::Page4k,
         num_pages: NUM_GUEST_ZERO_PAGES,
         guest_addr: USABLE_RAM_START_ADDRESS + NUM_GUEST_DATA_PAGES * PAGE_SIZE_4K,
+        perms: sbi::TsmPagePerms::ReadWrite,
     });
     // Safety: `TvmAddZeroPages` only touches pages that we've already converted.
     unsafe {
@@ -301,6 +337,22 @@ extern "C" fn kernel_init(hart_id: u64, fdt_addr: u64) {
         Ok(exit_code) => println!("Tellus - Guest exited with status {:}", exit_code),
     }
 
+    // Print whatever the guest wrote to its console.
+    let mut console_buf = [0u8; 256];
+    loop {
+        let msg = SbiMessage::Tee(sbi::TeeFunction::TvmReadConsole {
+            guest_id: vmid,
+            dest_addr: console_buf.as_mut_ptr() as u64,
+            len: console_buf.len() as u64,
+        });
+        // Safety: The passed buffer is uniquely owned so it's safe to modify in SBI.
+        let len = unsafe { ecall_send(&msg).expect("Tellus - TvmReadConsole returned error") };
+        if len == 0 {
+            break;
+        }
+        console_write_bytes(&console_buf[..len as usize]);
+    }
+
     let msg = SbiMessage::Tee(sbi::TeeFunction::TvmDestroy { guest_id: vmid });
     // Safety: destroying a VM doesn't write to memory that's accessible from the host.
     unsafe {
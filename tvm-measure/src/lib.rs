@@ -0,0 +1,495 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! # TVM measurement precomputation
+//!
+//! Computes the static measurement Salus reports for a TVM, given the image the host loads into
+//! it and a description of how the image is loaded. This lets a verifier know the measurement to
+//! expect before the TVM is launched. The measurement is computed with the same `data_measure`
+//! implementations Salus uses, adding the TVM's pages in the order they're listed in the layout
+//! followed by its initial state as described in `data_measure::initial_state`.
+//!
+//! ## Layout description
+//!
+//! The layout is a text file with one directive per line. Blank lines and anything following a
+//! `#` are ignored, and numbers may be given in decimal or in hex with a `0x` prefix.
+//!
+//! - `algorithm <sha256|sha384|sha512>`: The measurement algorithm selected when creating the
+//!   TVM. Defaults to sha256.
+//! - `segment <image offset> <num pages> <gpa> [perms]`: `num pages` 4kB pages of the image,
+//!   starting at `image offset`, are added with `TvmAddMeasuredPages` at guest physical address
+//!   `gpa`. Any part of the pages past the end of the image is zero. If `perms` is given, one of
+//!   `rwx`, `rw`, `rx` or `r`, the segment's permissions are then set with `TvmProtectPages`.
+//! - `mmio <gpa> <num pages>`: `num pages` 4kB pages of guest physical address space starting at
+//!   `gpa` are declared as an emulated MMIO region with `TvmAddEmulatedMmioRegion`.
+//! - `vcpu <id> <entry pc> <entry arg>`: A vCPU is created with `TvmCpuCreate`, with its
+//!   `EntryPc` and `EntryArg` registers set to the given values.
+
+use std::fmt;
+
+use data_measure::data_measure::{DataMeasure, DigestAlgorithm, DynMeasure, MeasurementDigest};
+use data_measure::initial_state::{MmioRegion, PagePerms, TvmConfig, VcpuState};
+
+/// The size of the pages added to a TVM's measurement.
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Errors resulting from parsing a layout or measuring a TVM.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The line with the given number isn't a valid directive.
+    InvalidDirective(usize),
+    /// The line with the given number contains an invalid number.
+    InvalidNumber(usize),
+    /// The line with the given number names an unknown measurement algorithm.
+    UnknownAlgorithm(usize),
+    /// The line with the given number names unknown page permissions.
+    UnknownPerms(usize),
+    /// A segment is to be loaded at a guest physical address that isn't 4kB-aligned.
+    UnalignedSegment(u64),
+    /// An emulated MMIO region starts at a guest physical address that isn't 4kB-aligned.
+    UnalignedMmioRegion(u64),
+    /// A segment starts past the end of the image.
+    SegmentOutOfBounds(u64),
+    /// More than one vCPU has the given ID.
+    DuplicateVcpu(u64),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            InvalidDirective(line) => write!(f, "line {line}: invalid directive"),
+            InvalidNumber(line) => write!(f, "line {line}: invalid number"),
+            UnknownAlgorithm(line) => write!(f, "line {line}: unknown measurement algorithm"),
+            UnknownPerms(line) => write!(f, "line {line}: unknown page permissions"),
+            UnalignedSegment(gpa) => write!(f, "segment at GPA {gpa:#x} isn't 4kB-aligned"),
+            UnalignedMmioRegion(gpa) => {
+                write!(f, "MMIO region at GPA {gpa:#x} isn't 4kB-aligned")
+            }
+            SegmentOutOfBounds(offset) => {
+                write!(
+                    f,
+                    "segment at image offset {offset:#x} is past the end of the image"
+                )
+            }
+            DuplicateVcpu(id) => write!(f, "vCPU {id} is specified more than once"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Holds results of measurement operations.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// A range of the image loaded into a TVM as measured pages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment {
+    /// The offset of the start of the segment in the image.
+    pub image_offset: u64,
+    /// The number of 4kB pages in the segment.
+    pub num_pages: u64,
+    /// The guest physical address the segment is loaded at.
+    pub gpa: u64,
+    /// The `TsmPagePerms` value the segment's permissions are set to, if they're changed.
+    pub perms: Option<u64>,
+}
+
+/// Describes how an image is loaded into a TVM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// The algorithm the TVM is measured with.
+    pub algorithm: DigestAlgorithm,
+    /// The segments of the image loaded into the TVM, in the order they're added.
+    pub segments: Vec<Segment>,
+    /// The emulated MMIO regions of the TVM, in order of address.
+    pub mmio_regions: Vec<MmioRegion>,
+    /// The initial state of each of the TVM's vCPUs.
+    pub vcpus: Vec<VcpuState>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            algorithm: DigestAlgorithm::Sha256,
+            segments: Vec::new(),
+            mmio_regions: Vec::new(),
+            vcpus: Vec::new(),
+        }
+    }
+}
+
+/// Parses a number in decimal, or in hex if prefixed with `0x`.
+fn parse_number(s: &str, line: usize) -> Result<u64> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16),
+        None => s.replace('_', "").parse(),
+    };
+    parsed.map_err(|_| Error::InvalidNumber(line))
+}
+
+/// Parses page permissions, returning their `TsmPagePerms` value.
+fn parse_perms(s: &str, line: usize) -> Result<u64> {
+    match s {
+        "rwx" => Ok(0),
+        "rw" => Ok(1),
+        "rx" => Ok(2),
+        "r" => Ok(3),
+        _ => Err(Error::UnknownPerms(line)),
+    }
+}
+
+/// Parses the arguments of a directive on line `line` that takes `N` numbers.
+fn parse_numbers<const N: usize>(args: &[&str], line: usize) -> Result<[u64; N]> {
+    if args.len() != N {
+        return Err(Error::InvalidDirective(line));
+    }
+    let mut numbers = [0; N];
+    for (n, arg) in numbers.iter_mut().zip(args) {
+        *n = parse_number(arg, line)?;
+    }
+    Ok(numbers)
+}
+
+impl Layout {
+    /// Parses a layout from its text description.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut layout = Self::default();
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.split('#').next().unwrap();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => (),
+                ["algorithm", name] => {
+                    layout.algorithm = match *name {
+                        "sha256" => DigestAlgorithm::Sha256,
+                        "sha384" => DigestAlgorithm::Sha384,
+                        "sha512" => DigestAlgorithm::Sha512,
+                        _ => return Err(Error::UnknownAlgorithm(line_num)),
+                    };
+                }
+                ["segment", args @ ..] => {
+                    let (args, perms) = match args {
+                        [numbers @ .., perms] if numbers.len() == 3 => {
+                            (numbers, Some(parse_perms(perms, line_num)?))
+                        }
+                        _ => (args, None),
+                    };
+                    let [image_offset, num_pages, gpa] = parse_numbers(args, line_num)?;
+                    if gpa % PAGE_SIZE != 0 {
+                        return Err(Error::UnalignedSegment(gpa));
+                    }
+                    layout.segments.push(Segment {
+                        image_offset,
+                        num_pages,
+                        gpa,
+                        perms,
+                    });
+                }
+                ["mmio", args @ ..] => {
+                    let [gpa, num_pages] = parse_numbers(args, line_num)?;
+                    if gpa % PAGE_SIZE != 0 {
+                        return Err(Error::UnalignedMmioRegion(gpa));
+                    }
+                    layout.mmio_regions.push(MmioRegion { gpa, num_pages });
+                }
+                ["vcpu", args @ ..] => {
+                    let [vcpu_id, entry_pc, entry_arg] = parse_numbers(args, line_num)?;
+                    if layout.vcpus.iter().any(|v| v.vcpu_id == vcpu_id) {
+                        return Err(Error::DuplicateVcpu(vcpu_id));
+                    }
+                    layout.vcpus.push(VcpuState {
+                        vcpu_id,
+                        entry_pc,
+                        entry_arg,
+                    });
+                }
+                _ => return Err(Error::InvalidDirective(line_num)),
+            }
+        }
+        // Salus measures MMIO regions in order of address and vCPUs in order of ID, regardless of
+        // the order they were added in.
+        layout.mmio_regions.sort_by_key(|r| r.gpa);
+        layout.vcpus.sort_by_key(|v| v.vcpu_id);
+        Ok(layout)
+    }
+
+    /// Returns the measurement Salus reports for a TVM with `image` loaded according to this
+    /// layout.
+    pub fn measure(&self, image: &[u8]) -> Result<MeasurementDigest> {
+        let mut measurement = DynMeasure::new(self.algorithm);
+        let mut page = [0u8; PAGE_SIZE as usize];
+        for segment in &self.segments {
+            if segment.image_offset > image.len() as u64 {
+                return Err(Error::SegmentOutOfBounds(segment.image_offset));
+            }
+            let mut offset = segment.image_offset as usize;
+            for gpa in (0..segment.num_pages).map(|i| segment.gpa + i * PAGE_SIZE) {
+                let bytes = &image[offset.min(image.len())..];
+                let len = bytes.len().min(page.len());
+                page[..len].copy_from_slice(&bytes[..len]);
+                page[len..].fill(0);
+                measurement.add_page(gpa, &page);
+                offset += page.len();
+            }
+            if let Some(perms) = segment.perms {
+                let change = PagePerms {
+                    gpa: segment.gpa,
+                    num_pages: segment.num_pages,
+                    perms,
+                };
+                measurement.add_data(&change.encode());
+            }
+        }
+
+        let config = TvmConfig {
+            num_vcpus: self.vcpus.len() as u64,
+        };
+        measurement.add_data(&config.encode());
+        for region in &self.mmio_regions {
+            measurement.add_data(&region.encode());
+        }
+        for vcpu in &self.vcpus {
+            measurement.add_data(&vcpu.encode());
+        }
+        Ok(measurement.get_measurement().iter().copied().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_measure::digest_measure::Sha256Measure;
+    use sha2::{Digest, Sha256};
+
+    fn test_image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    const TEST_LAYOUT: &str = "
+        # The guest image, as loaded by Tellus.
+        segment 0 2 0x8020_0000
+        segment 0x2000 1 0x8030_0000 # The tail of the image.
+        vcpu 0 0x80200000 0x1234
+    ";
+
+    #[test]
+    fn parse_layout() {
+        let layout = Layout::parse(TEST_LAYOUT).unwrap();
+        assert_eq!(layout.algorithm, DigestAlgorithm::Sha256);
+        assert_eq!(
+            layout.segments,
+            vec![
+                Segment {
+                    image_offset: 0,
+                    num_pages: 2,
+                    gpa: 0x8020_0000,
+                    perms: None,
+                },
+                Segment {
+                    image_offset: 0x2000,
+                    num_pages: 1,
+                    gpa: 0x8030_0000,
+                    perms: None,
+                },
+            ]
+        );
+        assert_eq!(
+            layout.vcpus,
+            vec![VcpuState {
+                vcpu_id: 0,
+                entry_pc: 0x8020_0000,
+                entry_arg: 0x1234,
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Layout::parse("algorithm md5"),
+            Err(Error::UnknownAlgorithm(1))
+        );
+        assert_eq!(
+            Layout::parse("\nsegment 0 1"),
+            Err(Error::InvalidDirective(2))
+        );
+        assert_eq!(
+            Layout::parse("segment 0 1 0x8020_0800"),
+            Err(Error::UnalignedSegment(0x8020_0800))
+        );
+        assert_eq!(Layout::parse("vcpu 0 0xg 0"), Err(Error::InvalidNumber(1)));
+        assert_eq!(
+            Layout::parse("vcpu 1 0 0\nvcpu 1 0 0"),
+            Err(Error::DuplicateVcpu(1))
+        );
+        assert_eq!(Layout::parse("load 0 1 0"), Err(Error::InvalidDirective(1)));
+        assert_eq!(
+            Layout::parse("segment 0 1 0 wx"),
+            Err(Error::UnknownPerms(1))
+        );
+    }
+
+    #[test]
+    fn matches_sha256_measure() {
+        // The image ends part-way through the third page, which is zero-filled.
+        let image = test_image(0x2800);
+        let layout = Layout::parse(TEST_LAYOUT).unwrap();
+
+        let mut expected = Sha256Measure::new();
+        expected.add_page(0x8020_0000, &image[..0x1000]);
+        expected.add_page(0x8020_1000, &image[0x1000..0x2000]);
+        let mut tail = [0u8; 0x1000];
+        tail[..0x800].copy_from_slice(&image[0x2000..]);
+        expected.add_page(0x8030_0000, &tail);
+        expected.add_data(&TvmConfig { num_vcpus: 1 }.encode());
+        expected.add_data(&layout.vcpus[0].encode());
+
+        assert_eq!(
+            layout.measure(&image).unwrap().as_slice(),
+            expected.get_measurement()
+        );
+    }
+
+    #[test]
+    fn matches_reference_hashing() {
+        // Recomputes the measurement from the definitions of the encodings, so that a change to
+        // how `Sha256Measure` hashes is caught rather than silently followed.
+        let image = test_image(0x1000);
+        let layout = Layout::parse("segment 0 1 0x1000\nvcpu 0 0x1000 0").unwrap();
+
+        let mut expected = [0u8; 32];
+        let page_digest = Sha256::digest(&image);
+        expected = Sha256::new()
+            .chain_update(expected)
+            .chain_update(0x1000u64.to_le_bytes())
+            .chain_update(page_digest)
+            .finalize()
+            .into();
+        let mut config = Vec::new();
+        config.extend_from_slice(&1u64.to_le_bytes());
+        config.extend_from_slice(&1u64.to_le_bytes());
+        let mut vcpu = Vec::new();
+        for n in [2u64, 0, 0x1000, 0] {
+            vcpu.extend_from_slice(&n.to_le_bytes());
+        }
+        for data in [config, vcpu] {
+            expected = Sha256::new()
+                .chain_update(expected)
+                .chain_update(Sha256::digest(&data))
+                .finalize()
+                .into();
+        }
+
+        assert_eq!(layout.measure(&image).unwrap().as_slice(), &expected);
+    }
+
+    #[test]
+    fn vcpus_measured_in_id_order() {
+        let image = test_image(0x1000);
+        let forward = Layout::parse("segment 0 1 0\nvcpu 0 1 2\nvcpu 1 3 4").unwrap();
+        let reverse = Layout::parse("segment 0 1 0\nvcpu 1 3 4\nvcpu 0 1 2").unwrap();
+        assert_eq!(
+            forward.measure(&image).unwrap(),
+            reverse.measure(&image).unwrap()
+        );
+
+        let changed_pc = Layout::parse("segment 0 1 0\nvcpu 0 5 2\nvcpu 1 3 4").unwrap();
+        assert_ne!(
+            forward.measure(&image).unwrap(),
+            changed_pc.measure(&image).unwrap()
+        );
+    }
+
+    #[test]
+    fn segment_perms_measured() {
+        let image = test_image(0x2000);
+        let layout = Layout::parse("segment 0 1 0x1000 rx\nsegment 0x1000 1 0x2000").unwrap();
+        assert_eq!(layout.segments[0].perms, Some(2));
+        assert_eq!(layout.segments[1].perms, None);
+
+        let mut expected = Sha256Measure::new();
+        expected.add_page(0x1000, &image[..0x1000]);
+        let change = PagePerms {
+            gpa: 0x1000,
+            num_pages: 1,
+            perms: 2,
+        };
+        expected.add_data(&change.encode());
+        expected.add_page(0x2000, &image[0x1000..]);
+        expected.add_data(&TvmConfig { num_vcpus: 0 }.encode());
+        assert_eq!(
+            layout.measure(&image).unwrap().as_slice(),
+            expected.get_measurement()
+        );
+
+        // Permissions that are left unchanged aren't measured.
+        let unprotected = Layout::parse("segment 0 1 0x1000\nsegment 0x1000 1 0x2000").unwrap();
+        assert_ne!(
+            layout.measure(&image).unwrap(),
+            unprotected.measure(&image).unwrap()
+        );
+    }
+
+    #[test]
+    fn mmio_regions_measured_in_address_order() {
+        let image = test_image(0x1000);
+        let layout =
+            Layout::parse("segment 0 1 0\nmmio 0x1000_0000 2\nmmio 0x200_0000 16").unwrap();
+        assert_eq!(
+            layout.mmio_regions,
+            vec![
+                MmioRegion {
+                    gpa: 0x200_0000,
+                    num_pages: 16,
+                },
+                MmioRegion {
+                    gpa: 0x1000_0000,
+                    num_pages: 2,
+                },
+            ]
+        );
+
+        let mut expected = Sha256Measure::new();
+        expected.add_page(0, &image);
+        expected.add_data(&TvmConfig { num_vcpus: 0 }.encode());
+        for region in &layout.mmio_regions {
+            expected.add_data(&region.encode());
+        }
+        assert_eq!(
+            layout.measure(&image).unwrap().as_slice(),
+            expected.get_measurement()
+        );
+
+        let no_mmio = Layout::parse("segment 0 1 0").unwrap();
+        assert_ne!(
+            layout.measure(&image).unwrap(),
+            no_mmio.measure(&image).unwrap()
+        );
+        assert_eq!(
+            Layout::parse("mmio 0x1000_0800 1"),
+            Err(Error::UnalignedMmioRegion(0x1000_0800))
+        );
+    }
+
+    #[test]
+    fn algorithm_selects_digest_length() {
+        let image = test_image(0x1000);
+        for (name, len) in [("sha256", 32), ("sha384", 48), ("sha512", 64)] {
+            let layout = Layout::parse(&format!("algorithm {name}\nsegment 0 1 0")).unwrap();
+            assert_eq!(layout.measure(&image).unwrap().len(), len);
+        }
+    }
+
+    #[test]
+    fn segment_out_of_bounds() {
+        let image = test_image(0x1000);
+        let layout = Layout::parse("segment 0x1001 1 0").unwrap();
+        assert_eq!(
+            layout.measure(&image),
+            Err(Error::SegmentOutOfBounds(0x1001))
+        );
+    }
+}
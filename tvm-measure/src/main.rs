@@ -0,0 +1,38 @@
+// Copyright (c) 2022 by Rivos Inc.
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prints the measurement Salus will report for a TVM. See the `tvm_measure` library for the
+//! format of the layout description.
+
+use std::{env, fs, process};
+
+use tvm_measure::Layout;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: {} <image> <layout>", args[0]);
+        process::exit(1);
+    }
+
+    let image = fs::read(&args[1]).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {e}", args[1]);
+        process::exit(1);
+    });
+    let layout_text = fs::read_to_string(&args[2]).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {e}", args[2]);
+        process::exit(1);
+    });
+    let measurement = Layout::parse(&layout_text)
+        .and_then(|layout| layout.measure(&image))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {e}", args[2]);
+            process::exit(1);
+        });
+
+    for b in measurement {
+        print!("{b:02x}");
+    }
+    println!();
+}